@@ -0,0 +1,16 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use wav_files_tempo::decode::{DecodeLimits, decode_bytes};
+
+fuzz_target!(|data: &[u8]| {
+    // Small limits keep each run fast; errors are fine, panics are not.
+    let limits = DecodeLimits {
+        max_bytes: 1 << 20,
+        ..DecodeLimits::default()
+    };
+    if let Ok(buffer) = decode_bytes(data, &limits) {
+        assert!(buffer.samples.len() <= data.len());
+        assert!(buffer.samples.iter().all(|s| s.is_finite()));
+    }
+});
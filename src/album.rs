@@ -0,0 +1,82 @@
+//! Multi-speed albums for language courses: each input becomes a folder of
+//! chapters from slowest to full speed, with a playlist of them in order.
+
+use std::path::{Path, PathBuf};
+
+/// The speeds of an album's chapters, in playing order.
+pub const TEMPOS: [f32; 4] = [0.6, 0.75, 0.9, 1.0];
+
+/// Name of the playlist in each album's folder.
+pub const INDEX_FILE: &str = "index.m3u";
+
+/// Path of chapter `number` (counting from 1), at `tempo`, of the album made
+/// from `output`: in a folder named after its stem, e.g.
+/// `lesson/01_lesson_0.60x.wav` for `lesson.wav`.
+pub fn chapter_path(output: &Path, number: usize, tempo: f32) -> PathBuf {
+    let stem = output.file_stem().unwrap_or_default().to_string_lossy();
+    let mut name = format!("{number:02}_{stem}_{tempo:.2}x");
+    if let Some(extension) = output.extension() {
+        name.push('.');
+        name.push_str(&extension.to_string_lossy());
+    }
+    output.with_extension("").join(name)
+}
+
+/// One chapter written into an album.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chapter {
+    pub path: PathBuf,
+    pub tempo: f32,
+    pub secs: Option<f64>,
+}
+
+/// An extended M3U playlist of `chapters`, titled `title`, with each
+/// chapter by file name so the folder can move as a whole.
+pub fn playlist(title: &str, chapters: &[Chapter]) -> String {
+    let mut text = format!("#EXTM3U\n#PLAYLIST:{title}\n");
+    for chapter in chapters {
+        // Players read -1 as an unknown length.
+        let secs = chapter.secs.map_or(-1, |secs| secs.round() as i64);
+        let name = chapter.path.file_name().unwrap_or_default();
+        text.push_str(&format!(
+            "#EXTINF:{secs},{title} ({:.0}% speed)\n{}\n",
+            chapter.tempo * 100.0,
+            name.to_string_lossy()
+        ));
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chapters_and_playlist() {
+        let output = Path::new("out/unit1/lesson.flac");
+        let chapters: Vec<Chapter> = TEMPOS
+            .iter()
+            .enumerate()
+            .map(|(k, &tempo)| Chapter {
+                path: chapter_path(output, k + 1, tempo),
+                tempo,
+                secs: Some(60.0 / f64::from(tempo)),
+            })
+            .collect();
+        assert_eq!(
+            chapters[0].path,
+            Path::new("out/unit1/lesson/01_lesson_0.60x.flac")
+        );
+        assert_eq!(
+            chapters[3].path,
+            Path::new("out/unit1/lesson/04_lesson_1.00x.flac")
+        );
+        let text = playlist("lesson", &chapters);
+        assert!(text.starts_with("#EXTM3U\n#PLAYLIST:lesson\n"), "{text}");
+        assert!(
+            text.contains("#EXTINF:100,lesson (60% speed)\n01_lesson_0.60x.flac\n"),
+            "{text}"
+        );
+        assert_eq!(text.lines().count(), 2 + 2 * TEMPOS.len());
+    }
+}
@@ -0,0 +1,635 @@
+//! Musical and speech analysis of decoded audio: tempo (BPM), key, pitch,
+//! and speech rate estimation, plus the tempo curves of syllable-aware
+//! stretching.
+
+use crate::fft::{Complex, fft, hann};
+use crate::resample::resample;
+use std::fmt;
+
+/// Onset envelope frames per second.
+const ENVELOPE_RATE: f32 = 100.0;
+
+/// Tempo search range in BPM.
+const MIN_BPM: f32 = 60.0;
+const MAX_BPM: f32 = 180.0;
+
+/// Tempos near this are preferred when several periodicities fit.
+const PREFERRED_BPM: f32 = 120.0;
+
+/// Shortest input worth estimating a tempo for, in seconds.
+const MIN_TEMPO_SECS: f32 = 3.0;
+
+/// Weakest onsets a tempo is read from: the RMS rise per frequency bin of
+/// the onset envelope. A steady tone only jitters well below this.
+const MIN_ONSET_STRENGTH: f32 = 0.01;
+
+/// How far the chosen beat period's autocorrelation must stand above the
+/// average over the search range, as a fraction of the envelope's energy.
+const MIN_PERIODICITY: f32 = 0.25;
+
+/// Widest frequency bin of the key chromagram, in Hz, so a note's energy
+/// stays in its own pitch class at every sample rate.
+const KEY_BIN_HZ: f32 = 2.7;
+
+/// Correlation by which the best key must beat the next; a lone tone, for
+/// one, fits its major and minor keys almost equally.
+const MIN_KEY_MARGIN: f32 = 0.01;
+
+/// Krumhansl-Kessler key profiles, indexed from the tonic.
+const MAJOR_PROFILE: [f32; 12] = [
+    6.35, 2.23, 3.48, 2.33, 4.38, 4.09, 2.52, 5.19, 2.39, 3.66, 2.29, 2.88,
+];
+const MINOR_PROFILE: [f32; 12] = [
+    6.33, 2.68, 3.52, 5.38, 2.60, 3.53, 2.54, 4.75, 3.98, 2.69, 3.34, 3.17,
+];
+
+/// F0 search range in Hz, covering bass voices through sopranos.
+const MIN_F0: f32 = 50.0;
+const MAX_F0: f32 = 1000.0;
+
+/// Rate pitch tracking runs at; comfortably above twice [`MAX_F0`].
+const PITCH_RATE: u32 = 8000;
+
+/// YIN threshold on the cumulative mean normalized difference.
+const YIN_THRESHOLD: f32 = 0.15;
+
+/// Intensity frames per second for syllable-nucleus detection.
+const INTENSITY_RATE: f32 = 100.0;
+
+/// Syllable nuclei must be within this many dB of the loudest frames...
+const SILENCE_DB: f32 = 25.0;
+
+/// ...and above this level.
+const SILENCE_FLOOR_DBFS: f32 = -60.0;
+
+/// Intensity must dip this many dB below a peak since the last nucleus for
+/// the peak to count as a new one.
+const MIN_DIP_DB: f32 = 2.0;
+
+/// Onset frames averaged into each steadiness value, about 50 ms.
+const STEADINESS_FRAMES: usize = 5;
+
+/// No frame is sped up beyond this many times the uniform tempo.
+const MAX_LOCAL_SPEEDUP: f32 = 4.0;
+
+const PITCH_NAMES: [&str; 12] = [
+    "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B",
+];
+
+/// A musical key: tonic pitch class (0 = C) and mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Key {
+    pub tonic: u8,
+    pub minor: bool,
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mode = if self.minor { "minor" } else { "major" };
+        write!(f, "{} {mode}", PITCH_NAMES[self.tonic as usize % 12])
+    }
+}
+
+/// Fundamental frequency statistics over the voiced frames of a signal.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PitchStats {
+    pub median_hz: f32,
+    /// 5th percentile, so stray octave errors don't set the range.
+    pub low_hz: f32,
+    /// 95th percentile.
+    pub high_hz: f32,
+}
+
+/// Estimated F0 statistics from a YIN-style autocorrelation pitch track.
+///
+/// Returns `None` when no frame is voiced.
+pub fn estimate_pitch(samples: &[f32], sample_rate: u32) -> Option<PitchStats> {
+    let mut track = f0_track(samples, sample_rate);
+    if track.is_empty() {
+        return None;
+    }
+    track.sort_by(f32::total_cmp);
+    let at = |q: f32| track[((track.len() - 1) as f32 * q).round() as usize];
+    Some(PitchStats {
+        median_hz: at(0.5),
+        low_hz: at(0.05),
+        high_hz: at(0.95),
+    })
+}
+
+/// Times in seconds of the syllable nuclei of speech: intensity peaks above
+/// a silence threshold, each separated from the last by a dip, after
+/// de Jong & Wempe (2009).
+pub fn syllable_nuclei(samples: &[f32], sample_rate: u32) -> Vec<f32> {
+    let intensity = intensity_db(samples, sample_rate);
+    if intensity.is_empty() {
+        return Vec::new();
+    }
+    let threshold = silence_threshold(&intensity);
+
+    let mut nuclei: Vec<usize> = Vec::new();
+    // Lowest intensity since the last accepted nucleus.
+    let mut dip = f32::INFINITY;
+    for i in 0..intensity.len() {
+        let db = intensity[i];
+        dip = dip.min(db);
+        let left = if i == 0 {
+            f32::NEG_INFINITY
+        } else {
+            intensity[i - 1]
+        };
+        let right = intensity.get(i + 1).copied().unwrap_or(f32::NEG_INFINITY);
+        if !(db > threshold && db > left && db >= right) {
+            continue;
+        }
+        match nuclei.last_mut() {
+            Some(last) if db - dip < MIN_DIP_DB => {
+                // The same syllable: keep its loudest peak.
+                if db > intensity[*last] {
+                    *last = i;
+                }
+            }
+            _ => {
+                nuclei.push(i);
+                dip = db;
+            }
+        }
+    }
+    nuclei
+        .into_iter()
+        .map(|i| i as f32 / INTENSITY_RATE)
+        .collect()
+}
+
+/// Speech rate in syllables per second, from the first to the last sound
+/// above the silence threshold so leading and trailing pauses don't count.
+///
+/// Returns `None` when no syllable nucleus is found.
+pub fn speech_rate(samples: &[f32], sample_rate: u32) -> Option<f32> {
+    let nuclei = syllable_nuclei(samples, sample_rate);
+    if nuclei.is_empty() {
+        return None;
+    }
+    let intensity = intensity_db(samples, sample_rate);
+    let threshold = silence_threshold(&intensity);
+    let first = intensity.iter().position(|&db| db > threshold)?;
+    let last = intensity.iter().rposition(|&db| db > threshold)?;
+    let span = (last + 1 - first) as f32 / INTENSITY_RATE;
+    Some(nuclei.len() as f32 / span)
+}
+
+/// Per-frame tempos at [`ENVELOPE_RATE`], frame `i` centred on second
+/// `i / ENVELOPE_RATE`, that together take as long as a uniform `tempo`.
+///
+/// The change of duration goes mostly to steady-state frames (vowels,
+/// sustained notes, pauses), while frames with strong spectral flux
+/// (consonant onsets, transients) stay closer to the original speed.
+/// Returns an empty curve for inputs shorter than one analysis frame.
+pub fn local_tempos(samples: &[f32], sample_rate: u32, tempo: f32) -> Vec<f32> {
+    let size = frame_size(sample_rate);
+    if samples.len() < size {
+        return Vec::new();
+    }
+    // Pad by half a frame so frames are centred on their hop.
+    let pad = vec![0.0; size / 2];
+    let padded: Vec<f32> = pad.iter().chain(samples).chain(&pad).copied().collect();
+    let flux = onset_envelope(&padded, sample_rate);
+    let mean_flux = flux.iter().sum::<f32>() / flux.len() as f32;
+    let smoothed: Vec<f32> = (0..flux.len())
+        .map(|i| {
+            let lo = i.saturating_sub(STEADINESS_FRAMES / 2);
+            let hi = (i + STEADINESS_FRAMES / 2 + 1).min(flux.len());
+            flux[lo..hi].iter().sum::<f32>() / (hi - lo) as f32
+        })
+        .collect();
+    // 1 on perfectly steady frames, towards 0 on strong onsets.
+    let steadiness: Vec<f32> = smoothed
+        .iter()
+        .map(|&e| 1.0 / (1.0 + e / mean_flux.max(f32::EPSILON)))
+        .collect();
+    let mean_steadiness = steadiness.iter().sum::<f32>() / steadiness.len() as f32;
+    // Each frame's share of the duration change is proportional to its
+    // steadiness, so the total matches the uniform stretch.
+    let uniform = 1.0 / tempo;
+    let durations: Vec<f32> = steadiness
+        .iter()
+        .map(|&s| (1.0 + (uniform - 1.0) * s / mean_steadiness).max(uniform / MAX_LOCAL_SPEEDUP))
+        .collect();
+    // Clamping may have lengthened the total; scale it back.
+    let scale = uniform * durations.len() as f32 / durations.iter().sum::<f32>();
+    durations.iter().map(|d| 1.0 / (d * scale)).collect()
+}
+
+/// Intensity a frame must exceed to count as sound: [`SILENCE_DB`] below the
+/// 99th percentile, so a few stray peaks don't set it.
+fn silence_threshold(intensity: &[f32]) -> f32 {
+    let mut sorted = intensity.to_vec();
+    sorted.sort_by(f32::total_cmp);
+    let loud = sorted[((sorted.len() - 1) as f32 * 0.99) as usize];
+    (loud - SILENCE_DB).max(SILENCE_FLOOR_DBFS)
+}
+
+/// Frame power in dB over 50 ms windows at [`INTENSITY_RATE`].
+fn intensity_db(samples: &[f32], sample_rate: u32) -> Vec<f32> {
+    let size = (sample_rate as f32 * 0.05) as usize;
+    let hop = (sample_rate as f32 / INTENSITY_RATE).round().max(1.0) as usize;
+    if size == 0 || samples.len() < size {
+        return Vec::new();
+    }
+    (0..=(samples.len() - size) / hop)
+        .map(|f| {
+            let frame = &samples[f * hop..f * hop + size];
+            let power = frame.iter().map(|s| s * s).sum::<f32>() / size as f32;
+            10.0 * (power + 1e-12).log10()
+        })
+        .collect()
+}
+
+/// Estimated tempo in BPM from the periodicity of spectral-flux onsets.
+///
+/// Returns `None` for inputs shorter than a few seconds, and for those
+/// whose onsets are too weak or too irregular to show a beat, such as a
+/// steady tone or noise.
+pub fn estimate_bpm(samples: &[f32], sample_rate: u32) -> Option<f32> {
+    if (samples.len() as f32) < MIN_TEMPO_SECS * sample_rate as f32 {
+        return None;
+    }
+    let envelope = onset_envelope(samples, sample_rate);
+    let frames = envelope.len();
+    let energy: f32 = envelope.iter().map(|e| e * e).sum();
+    let strength = (energy / frames.max(1) as f32).sqrt() / (frame_size(sample_rate) / 2) as f32;
+    if energy <= f32::EPSILON || strength < MIN_ONSET_STRENGTH {
+        return None;
+    }
+
+    let min_lag = (60.0 * ENVELOPE_RATE / MAX_BPM).floor() as usize;
+    let max_lag = (60.0 * ENVELOPE_RATE / MIN_BPM).ceil() as usize;
+    let autocorrelation = |lag: usize| -> f32 {
+        envelope
+            .iter()
+            .zip(&envelope[lag.min(envelope.len())..])
+            .map(|(a, b)| a * b)
+            .sum()
+    };
+    let scores: Vec<f32> = (min_lag..=max_lag + 1)
+        .map(|lag| {
+            let bpm = 60.0 * ENVELOPE_RATE / lag as f32;
+            // Log-Gaussian weighting resolves octave ambiguity toward moderate tempos.
+            let octaves = (bpm / PREFERRED_BPM).log2();
+            autocorrelation(lag) * (-0.5 * (octaves / 0.9).powi(2)).exp()
+        })
+        .collect();
+    let best = (1..scores.len() - 1).max_by(|&a, &b| scores[a].total_cmp(&scores[b]))?;
+    if scores[best] <= 0.0 {
+        return None;
+    }
+    // Per overlapping frame, so long lags are not penalized for their length.
+    let normalized = |lag: usize| {
+        autocorrelation(lag) * frames as f32 / (frames.saturating_sub(lag).max(1) as f32 * energy)
+    };
+    let average = (min_lag..=max_lag).map(normalized).sum::<f32>() / (max_lag - min_lag + 1) as f32;
+    if normalized(min_lag + best) - average < MIN_PERIODICITY {
+        return None;
+    }
+    // Parabolic interpolation between neighbouring lags.
+    let (l, c, r) = (scores[best - 1], scores[best], scores[best + 1]);
+    let denominator = l - 2.0 * c + r;
+    let offset = if denominator.abs() > f32::EPSILON {
+        (0.5 * (l - r) / denominator).clamp(-0.5, 0.5)
+    } else {
+        0.0
+    };
+    let lag = (min_lag + best) as f32 + offset;
+    Some(60.0 * ENVELOPE_RATE / lag)
+}
+
+/// Estimated key from a chromagram correlated against the key profiles.
+///
+/// Returns `None` for silent input, and when no key fits clearly better
+/// than the next.
+pub fn estimate_key(samples: &[f32], sample_rate: u32) -> Option<Key> {
+    let size = ((sample_rate as f32 / KEY_BIN_HZ) as usize).next_power_of_two();
+    let mut chroma = [0.0f32; 12];
+    for frame in chromagram(samples, sample_rate, size, 2 * size) {
+        chroma.iter_mut().zip(frame).for_each(|(c, f)| *c += f);
+    }
+    if chroma.iter().sum::<f32>() <= f32::EPSILON {
+        return None;
+    }
+
+    let mut fits: Vec<(Key, f32)> = (0..12u8)
+        .flat_map(|tonic| [false, true].map(|minor| Key { tonic, minor }))
+        .map(|key| {
+            let profile = if key.minor {
+                &MINOR_PROFILE
+            } else {
+                &MAJOR_PROFILE
+            };
+            let rotated: Vec<f32> = (0..12)
+                .map(|pc| profile[(pc + 12 - key.tonic as usize) % 12])
+                .collect();
+            (key, correlation(&chroma, &rotated))
+        })
+        .collect();
+    fits.sort_by(|a, b| b.1.total_cmp(&a.1));
+    match fits[..] {
+        [(key, best), (_, next), ..] if best - next >= MIN_KEY_MARGIN => Some(key),
+        _ => None,
+    }
+}
+
+/// Per-frame energy in each pitch class (0 = C) between 55 Hz and 2 kHz.
+pub(crate) fn chromagram(
+    samples: &[f32],
+    sample_rate: u32,
+    size: usize,
+    hop: usize,
+) -> Vec<[f32; 12]> {
+    let window = hann(size);
+    let mut buf = vec![Complex::default(); size];
+    let mut frames = Vec::new();
+    let mut start = 0;
+    while start + size <= samples.len() {
+        for ((b, &s), &w) in buf
+            .iter_mut()
+            .zip(&samples[start..start + size])
+            .zip(&window)
+        {
+            *b = Complex::new(s * w, 0.0);
+        }
+        fft(&mut buf, false);
+        let mut chroma = [0.0f32; 12];
+        for (bin, value) in buf.iter().enumerate().take(size / 2).skip(1) {
+            let freq = bin as f32 * sample_rate as f32 / size as f32;
+            if !(55.0..=2000.0).contains(&freq) {
+                continue;
+            }
+            let midi = 69.0 + 12.0 * (freq / 440.0).log2();
+            let class = (midi.round() as i32).rem_euclid(12) as usize;
+            chroma[class] += value.norm().powi(2);
+        }
+        frames.push(chroma);
+        start += hop;
+    }
+    frames
+}
+
+/// F0 of each voiced 40 ms frame (20 ms hop).
+fn f0_track(samples: &[f32], sample_rate: u32) -> Vec<f32> {
+    let (samples, rate) = if sample_rate > PITCH_RATE {
+        (resample(samples, sample_rate, PITCH_RATE), PITCH_RATE)
+    } else {
+        (samples.to_vec(), sample_rate)
+    };
+    let size = (rate as f32 * 0.04) as usize;
+    let min_lag = (rate as f32 / MAX_F0).floor().max(2.0) as usize;
+    let max_lag = (rate as f32 / MIN_F0).ceil() as usize;
+    if size == 0 || samples.len() < size + max_lag + 1 {
+        return Vec::new();
+    }
+
+    let mut track = Vec::new();
+    let mut difference = vec![0.0f32; max_lag + 2];
+    let mut start = 0;
+    while start + size + max_lag + 1 < samples.len() {
+        let frame = &samples[start..start + size + max_lag + 2];
+        let energy = frame[..size].iter().map(|s| s * s).sum::<f32>() / size as f32;
+        start += size / 2;
+        // Skip near-silent frames (below about -50 dBFS).
+        if energy < 1e-5 {
+            continue;
+        }
+        for (lag, d) in difference.iter_mut().enumerate().skip(1) {
+            *d = (0..size).map(|i| (frame[i] - frame[i + lag]).powi(2)).sum();
+        }
+        // Cumulative mean normalized difference.
+        let mut running = 0.0;
+        let mut normalized = vec![1.0f32; max_lag + 2];
+        for lag in 1..=max_lag + 1 {
+            running += difference[lag];
+            normalized[lag] = difference[lag] * lag as f32 / running.max(f32::EPSILON);
+        }
+        let Some(mut lag) = (min_lag..=max_lag).find(|&l| normalized[l] < YIN_THRESHOLD) else {
+            continue;
+        };
+        while lag < max_lag && normalized[lag + 1] < normalized[lag] {
+            lag += 1;
+        }
+        let (l, c, r) = (normalized[lag - 1], normalized[lag], normalized[lag + 1]);
+        let denominator = l - 2.0 * c + r;
+        let offset = if denominator.abs() > f32::EPSILON {
+            (0.5 * (l - r) / denominator).clamp(-0.5, 0.5)
+        } else {
+            0.0
+        };
+        track.push(rate as f32 / (lag as f32 + offset));
+    }
+    track
+}
+
+/// Half-wave rectified spectral flux of log magnitudes, at [`ENVELOPE_RATE`].
+fn onset_envelope(samples: &[f32], sample_rate: u32) -> Vec<f32> {
+    let size = frame_size(sample_rate);
+    let hop = (sample_rate as f32 / ENVELOPE_RATE).round().max(1.0) as usize;
+    let window = hann(size);
+    let mut buf = vec![Complex::default(); size];
+    let mut previous = vec![0.0f32; size / 2];
+    let mut envelope = Vec::new();
+    let mut start = 0;
+    while start + size <= samples.len() {
+        for ((b, &s), &w) in buf
+            .iter_mut()
+            .zip(&samples[start..start + size])
+            .zip(&window)
+        {
+            *b = Complex::new(s * w, 0.0);
+        }
+        fft(&mut buf, false);
+        let mut flux = 0.0;
+        for (prev, value) in previous.iter_mut().zip(&buf) {
+            let magnitude = (1.0 + 100.0 * value.norm()).ln();
+            flux += (magnitude - *prev).max(0.0);
+            *prev = magnitude;
+        }
+        // The first frame has nothing before it to rise from.
+        if start > 0 {
+            envelope.push(flux);
+        }
+        start += hop;
+    }
+    // Subtract the mean so sustained energy does not read as periodicity.
+    let mean = envelope.iter().sum::<f32>() / envelope.len().max(1) as f32;
+    envelope.iter_mut().for_each(|e| *e = (*e - mean).max(0.0));
+    envelope
+}
+
+/// Power-of-two analysis frame of roughly 46 ms.
+fn frame_size(sample_rate: u32) -> usize {
+    ((sample_rate as f32 * 0.046) as usize).next_power_of_two()
+}
+
+fn correlation(a: &[f32], b: &[f32]) -> f32 {
+    let mean = |v: &[f32]| v.iter().sum::<f32>() / v.len() as f32;
+    let (ma, mb) = (mean(a), mean(b));
+    let (mut num, mut da, mut db) = (0.0, 0.0, 0.0);
+    for (&x, &y) in a.iter().zip(b) {
+        num += (x - ma) * (y - mb);
+        da += (x - ma).powi(2);
+        db += (y - mb).powi(2);
+    }
+    num / (da * db).sqrt().max(f32::EPSILON)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::click::click_track;
+
+    fn chord(freqs: &[f32], rate: u32, secs: f32) -> Vec<f32> {
+        (0..(rate as f32 * secs) as usize)
+            .map(|i| {
+                let t = i as f32 / rate as f32;
+                freqs
+                    .iter()
+                    .map(|f| (2.0 * std::f32::consts::PI * f * t).sin())
+                    .sum::<f32>()
+                    / freqs.len() as f32
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_click_track_tempo_is_recovered() {
+        for bpm in [90.0, 128.0] {
+            let track = click_track(16000 * 12, 16000, bpm);
+            let estimate = estimate_bpm(&track, 16000).unwrap();
+            assert!((estimate - bpm).abs() < 2.0, "{bpm} BPM read as {estimate}");
+        }
+        assert_eq!(estimate_bpm(&vec![0.0; 16000 * 5], 16000), None);
+        assert_eq!(estimate_bpm(&click_track(16000, 16000, 120.0), 16000), None);
+    }
+
+    #[test]
+    fn test_steady_sounds_have_no_tempo() {
+        for rate in [16000, 44100] {
+            let tone = chord(&[440.0], rate, 8.0);
+            assert_eq!(estimate_bpm(&tone, rate), None, "{rate}");
+            let mut state = 1u32;
+            let noise: Vec<f32> = (0..rate * 8)
+                .map(|_| {
+                    state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                    (state >> 8) as f32 / (1 << 23) as f32 - 1.0
+                })
+                .collect();
+            assert_eq!(estimate_bpm(&noise, rate), None, "{rate}");
+        }
+    }
+
+    #[test]
+    fn test_pitch_of_harmonic_tones() {
+        for (f0, rate) in [(120.0, 16000), (220.0, 44100)] {
+            // Fundamental plus two weaker harmonics.
+            let tone: Vec<f32> = (0..rate)
+                .map(|i| {
+                    let t = i as f32 / rate as f32;
+                    (1..=3)
+                        .map(|h| {
+                            (2.0 * std::f32::consts::PI * f0 * h as f32 * t).sin() * 0.3 / h as f32
+                        })
+                        .sum()
+                })
+                .collect();
+            let stats = estimate_pitch(&tone, rate as u32).unwrap();
+            assert!(
+                (stats.median_hz - f0).abs() < 2.0,
+                "{f0} Hz read as {stats:?}"
+            );
+            assert!(stats.low_hz <= stats.median_hz && stats.median_hz <= stats.high_hz);
+        }
+        assert_eq!(estimate_pitch(&vec![0.0; 16000], 16000), None);
+    }
+
+    #[test]
+    fn test_local_tempos_slow_steady_frames_most() {
+        // A steady tone with a click every half second.
+        let tone = chord(&[220.0], 16000, 3.0);
+        let clicks = click_track(tone.len(), 16000, 120.0);
+        let input: Vec<f32> = tone.iter().zip(&clicks).map(|(a, b)| 0.3 * a + b).collect();
+        let tempos = local_tempos(&input, 16000, 0.5);
+        assert!(
+            (tempos.len() as i64 - 300).abs() <= 1,
+            "{} frames",
+            tempos.len()
+        );
+        let duration = tempos.iter().map(|t| 1.0 / t).sum::<f32>() / tempos.len() as f32;
+        assert!((duration - 2.0).abs() < 1e-3, "mean duration {duration}");
+        for click in [100, 150, 200] {
+            assert!(
+                tempos[click] > tempos[click + 25] + 0.1,
+                "click {} vs steady {}",
+                tempos[click],
+                tempos[click + 25]
+            );
+        }
+        assert!(local_tempos(&[0.1; 100], 16000, 0.5).is_empty());
+    }
+
+    #[test]
+    fn test_speech_rate_counts_syllables() {
+        let rate = 16000;
+        // Two seconds of 150 ms voiced bursts every 250 ms, padded with silence.
+        let syllables = |per_sec: f32| -> Vec<f32> {
+            let period = rate as f32 / per_sec;
+            let mut samples = vec![0.0; rate as usize / 2];
+            samples.extend((0..2 * rate as usize).map(|i| {
+                let phase = (i as f32 % period) / period;
+                let envelope = (std::f32::consts::PI * phase / 0.6).sin().max(0.0);
+                let t = i as f32 / rate as f32;
+                envelope * 0.4 * (2.0 * std::f32::consts::PI * 180.0 * t).sin()
+            }));
+            samples.extend(vec![0.0; rate as usize]);
+            samples
+        };
+        for per_sec in [3.0, 5.0] {
+            let nuclei = syllable_nuclei(&syllables(per_sec), rate);
+            assert_eq!(nuclei.len(), 2 * per_sec as usize, "{nuclei:?}");
+            let measured = speech_rate(&syllables(per_sec), rate).unwrap();
+            assert!(
+                (measured - per_sec).abs() < 0.5,
+                "{per_sec}/s read as {measured}"
+            );
+        }
+        assert_eq!(speech_rate(&vec![0.0; 16000], rate), None);
+    }
+
+    #[test]
+    fn test_triads_give_their_key() {
+        // C4 E4 G4
+        let c_major = chord(&[261.63, 329.63, 392.0], 16000, 2.0);
+        assert_eq!(
+            estimate_key(&c_major, 16000).unwrap().to_string(),
+            "C major"
+        );
+        // A3 C4 E4
+        let a_minor = chord(&[220.0, 261.63, 329.63], 16000, 2.0);
+        assert_eq!(
+            estimate_key(&a_minor, 16000).unwrap().to_string(),
+            "A minor"
+        );
+        assert_eq!(estimate_key(&vec![0.0; 16000], 16000), None);
+    }
+
+    #[test]
+    fn test_keys_agree_across_sample_rates() {
+        for rate in [16000, 22050, 44100, 48000] {
+            let c_major = chord(&[261.63, 329.63, 392.0], rate, 3.0);
+            let key = estimate_key(&c_major, rate).map(|k| k.to_string());
+            assert_eq!(key.as_deref(), Some("C major"), "{rate}");
+            let a_minor = chord(&[220.0, 261.63, 329.63], rate, 3.0);
+            let key = estimate_key(&a_minor, rate).map(|k| k.to_string());
+            assert_eq!(key.as_deref(), Some("A minor"), "{rate}");
+            // Major or minor: a lone tone doesn't say.
+            assert_eq!(estimate_key(&chord(&[261.63], rate, 3.0), rate), None);
+        }
+    }
+}
@@ -0,0 +1,201 @@
+//! Atomic outputs: each file is written under a temporary name beside its
+//! destination and renamed into place only once it is complete, so a crash
+//! mid-write never leaves a truncated file where an output should be.
+//!
+//! Work run under a [`Claim`] (as [`crate::pipeline::with_deadline`] runs
+//! it) puts its outputs in place only while the claim holds; once the claim
+//! is withdrawn, the outputs already placed are removed and later ones never
+//! appear, so abandoned work can't leave behind a file reported as failed.
+//! The same goes for the [`scratch`] files such work keeps along the way.
+
+use crate::error::{Error, Result};
+use std::cell::RefCell;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// Whether the work a [`Claim`] covers was abandoned, and the outputs it has
+/// put in place.
+type Placed = Arc<Mutex<(bool, Vec<PathBuf>)>>;
+
+thread_local! {
+    static CLAIM: RefCell<Option<Placed>> = const { RefCell::new(None) };
+}
+
+/// The outputs of one piece of work, which can be withdrawn.
+#[derive(Debug, Default, Clone)]
+pub struct Claim {
+    placed: Placed,
+}
+
+impl Claim {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The claim the work on this thread runs under, if any, for threads it
+    /// spawns to [`run`](Self::run) their share of the work under.
+    pub fn current() -> Option<Self> {
+        CLAIM
+            .with(|claim| claim.borrow().clone())
+            .map(|placed| Self { placed })
+    }
+
+    /// Runs `work` on this thread with its outputs under the claim.
+    pub fn run<T>(&self, work: impl FnOnce() -> T) -> T {
+        let previous = CLAIM.with(|claim| claim.replace(Some(Arc::clone(&self.placed))));
+        let value = work();
+        CLAIM.with(|claim| *claim.borrow_mut() = previous);
+        value
+    }
+
+    /// Gives up on the work: removes the outputs it put in place and keeps
+    /// any more from appearing.
+    pub fn withdraw(&self) {
+        let mut placed = self.placed.lock().unwrap_or_else(|e| e.into_inner());
+        placed.0 = true;
+        for path in placed.1.drain(..) {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+/// Where [`write`] puts `path` while it is being written: the same name with
+/// `.partial` before the extension, which format detection still reads.
+pub fn temp_path(path: &Path) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(extension) => format!("{stem}.partial.{}", extension.to_string_lossy()),
+        None => format!("{stem}.partial"),
+    };
+    path.with_file_name(name)
+}
+
+/// Runs `write` on the [`temp_path`] of `path`, then renames the result to
+/// `path`. If writing or renaming fails, the temporary file is removed.
+pub fn write<T>(path: &Path, write: impl FnOnce(&Path) -> Result<T>) -> Result<T> {
+    let temp = temp_path(path);
+    let written = write(&temp).and_then(|value| {
+        place(&temp, path)?;
+        Ok(value)
+    });
+    if written.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    written
+}
+
+/// Creates the scratch file `path`, which the caller removes once done with
+/// it. Under a [`Claim`], withdrawing the claim removes it too, and once the
+/// claim is withdrawn no scratch file can be created.
+pub fn scratch(path: &Path) -> Result<File> {
+    let create = || {
+        File::create(path)
+            .map_err(|e| Error::io(format!("Failed to create scratch file {:?}", path), e))
+    };
+    let Some(placed) = CLAIM.with(|claim| claim.borrow().clone()) else {
+        return create();
+    };
+    // Held across the creation, as in `place`.
+    let mut placed = placed.lock().unwrap_or_else(|e| e.into_inner());
+    if placed.0 {
+        return Err(withdrawn(path));
+    }
+    let file = create()?;
+    placed.1.push(path.to_path_buf());
+    Ok(file)
+}
+
+fn withdrawn(path: &Path) -> Error {
+    Error::invalid(format!(
+        "Output {:?} was withdrawn: its work was abandoned",
+        path
+    ))
+}
+
+/// Renames `temp` to `path`, unless the thread's [`Claim`] was withdrawn.
+fn place(temp: &Path, path: &Path) -> Result<()> {
+    let rename = || {
+        fs::rename(temp, path)
+            .map_err(|e| Error::io(format!("Failed to move output into place at {:?}", path), e))
+    };
+    let Some(placed) = CLAIM.with(|claim| claim.borrow().clone()) else {
+        return rename();
+    };
+    // Held across the rename, so a withdrawal either sees the output or
+    // comes first and stops it.
+    let mut placed = placed.lock().unwrap_or_else(|e| e.into_inner());
+    if placed.0 {
+        return Err(withdrawn(path));
+    }
+    rename()?;
+    placed.1.push(path.to_path_buf());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_outputs_appear_only_when_complete() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("a.w64");
+        assert_eq!(temp_path(&path), dir.path().join("a.partial.w64"));
+        fs::write(&path, b"old").unwrap();
+
+        let err = write(&path, |temp| {
+            fs::write(temp, b"trunc").unwrap();
+            Err::<(), _>(Error::invalid("disk full"))
+        });
+        assert!(err.is_err());
+        assert_eq!(fs::read(&path).unwrap(), b"old");
+        assert!(!temp_path(&path).exists());
+
+        write(&path, |temp| {
+            assert_eq!(fs::read(&path).unwrap(), b"old");
+            fs::write(temp, b"new").map_err(|e| Error::io("write", e))
+        })
+        .unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"new");
+        assert!(!temp_path(&path).exists());
+
+        let (first, second) = (dir.path().join("b.wav"), dir.path().join("c.wav"));
+        let claim = Claim::new();
+        let put = |path: &Path| {
+            write(path, |temp| {
+                fs::write(temp, b"x").map_err(|e| Error::io("write", e))
+            })
+        };
+        claim.run(|| put(&first)).unwrap();
+        assert!(first.exists());
+        claim.withdraw();
+        assert!(!first.exists());
+        assert!(claim.run(|| put(&second)).is_err());
+        assert!(!second.exists() && !temp_path(&second).exists());
+    }
+
+    #[test]
+    fn test_scratch_files_go_with_their_claim() {
+        let dir = tempfile::tempdir().unwrap();
+        let (kept, dropped) = (dir.path().join("a.tmp"), dir.path().join("b.tmp"));
+        scratch(&kept).unwrap();
+        assert!(Claim::current().is_none());
+
+        let claim = Claim::new();
+        // A thread of the work joins in through the claim it runs under.
+        let inner = claim.run(Claim::current).unwrap();
+        std::thread::scope(|s| {
+            s.spawn(|| inner.run(|| scratch(&dropped).map(drop)))
+                .join()
+                .unwrap()
+        })
+        .unwrap();
+        assert!(dropped.exists());
+        claim.withdraw();
+        assert!(!dropped.exists());
+        assert!(claim.run(|| scratch(&dropped)).is_err());
+        assert!(!dropped.exists());
+        assert!(kept.exists());
+    }
+}
@@ -0,0 +1,115 @@
+//! The registry of stretch backends compiled into this build.
+//!
+//! Each backend sits behind a Cargo feature of the same name and adds one
+//! entry to [`BACKENDS`]; [`crate::stretch_channels`] looks the selected
+//! [`Algorithm`] up here, so a build without a backend has no code for it.
+//! Rubber Band and WSOLA backends are not part of the tool yet.
+
+use crate::error::{Error, Result};
+use crate::options::{Algorithm, ProcessOptions};
+
+/// Stretches every channel by the tempo of the options, or by the tempo at
+/// each position of the file (as a fraction of it) when one is given.
+pub(crate) type StretchFn =
+    fn(&[&[f32]], u32, &ProcessOptions, Option<Box<dyn Fn(f32) -> f32>>) -> Vec<Vec<f32>>;
+
+/// One stretch backend.
+pub struct Backend {
+    /// The name `--algorithm` and [`Algorithm::name`] use.
+    pub name: &'static str,
+    /// The Cargo feature that compiles it in.
+    pub feature: &'static str,
+    pub(crate) stretch: StretchFn,
+}
+
+/// The backends in this build, the default first.
+pub static BACKENDS: &[Backend] = &[
+    #[cfg(feature = "ssstretch")]
+    Backend {
+        name: "ssstretch",
+        feature: "ssstretch",
+        stretch: crate::signalsmith,
+    },
+    #[cfg(feature = "vocoder")]
+    Backend {
+        name: "pv",
+        feature: "vocoder",
+        stretch: phase_vocoder,
+    },
+];
+
+/// The backend that runs `algorithm`, if it is compiled in.
+pub fn find(algorithm: Algorithm) -> Option<&'static Backend> {
+    BACKENDS
+        .iter()
+        .find(|backend| backend.name == algorithm.name())
+}
+
+/// Like [`find`], but an algorithm left out of this build is an error.
+pub(crate) fn require(algorithm: Algorithm) -> Result<&'static Backend> {
+    find(algorithm).ok_or_else(|| {
+        Error::Engine(format!(
+            "the {} algorithm is not compiled into this build",
+            algorithm.name()
+        ))
+    })
+}
+
+/// The vocoder stretches a channel in one call, so it is paced per channel,
+/// each counting for its share of the frames.
+#[cfg(feature = "vocoder")]
+fn phase_vocoder(
+    channels: &[&[f32]],
+    sample_rate: u32,
+    options: &ProcessOptions,
+    tempo_at: Option<Box<dyn Fn(f32) -> f32>>,
+) -> Vec<Vec<f32>> {
+    let Algorithm::PhaseVocoder { fft_size, overlap } = options.algorithm else {
+        unreachable!("only the phase vocoder is registered as pv");
+    };
+    let frames = channels.iter().map(|c| c.len()).min().unwrap_or(0);
+    let count = channels.len().max(1);
+    channels
+        .iter()
+        .enumerate()
+        .map(|(i, c)| {
+            let stretched = match &tempo_at {
+                Some(tempo_at) => crate::vocoder::stretch(c, fft_size, overlap, tempo_at),
+                None => crate::vocoder::stretch(c, fft_size, overlap, |_| options.tempo),
+            };
+            let share = frames * (i + 1) / count - frames * i / count;
+            crate::pace(options, share, sample_rate);
+            stretched
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_registry_matches_features() {
+        let names: Vec<&str> = BACKENDS.iter().map(|backend| backend.name).collect();
+        let expected = [
+            cfg!(feature = "ssstretch").then_some("ssstretch"),
+            cfg!(feature = "vocoder").then_some("pv"),
+        ];
+        assert_eq!(names, expected.into_iter().flatten().collect::<Vec<_>>());
+        assert_eq!(
+            find(Algorithm::Signalsmith).is_some(),
+            cfg!(feature = "ssstretch")
+        );
+        let vocoder = Algorithm::PhaseVocoder {
+            fft_size: 2048,
+            overlap: 4,
+        };
+        assert_eq!(
+            find(vocoder).map(|backend| backend.feature),
+            cfg!(feature = "vocoder").then_some("vocoder")
+        );
+        if let Some(first) = BACKENDS.first() {
+            assert_eq!(first.name, Algorithm::default().name());
+        }
+    }
+}
@@ -0,0 +1,87 @@
+//! Metronome overlay for audibly verifying a stretch hit its target BPM.
+
+use crate::format::AudioBuffer;
+use std::f32::consts::PI;
+use std::path::{Path, PathBuf};
+
+/// Pitch of each click.
+const CLICK_HZ: f32 = 1000.0;
+
+/// Length of each click in seconds.
+const CLICK_SECS: f32 = 0.015;
+
+/// Peak level of each click, loud enough to hear over full-scale program.
+const CLICK_LEVEL: f32 = 0.5;
+
+/// Decaying sine bursts every beat of `bpm`, starting at the first sample.
+pub fn click_track(frames: usize, sample_rate: u32, bpm: f32) -> Vec<f32> {
+    let mut track = vec![0.0; frames];
+    if !(bpm.is_finite() && bpm > 0.0) {
+        return track;
+    }
+    let rate = sample_rate as f32;
+    let beat = 60.0 * rate / bpm;
+    let len = (CLICK_SECS * rate) as usize;
+    let mut onset = 0.0f32;
+    while (onset as usize) < frames {
+        let start = onset as usize;
+        for (i, s) in track[start..].iter_mut().take(len).enumerate() {
+            let t = i as f32 / rate;
+            *s = (2.0 * PI * CLICK_HZ * t).sin() * CLICK_LEVEL * (-t / (CLICK_SECS / 5.0)).exp();
+        }
+        onset += beat;
+    }
+    track
+}
+
+/// A copy of `buffer` with the click track mixed into every channel.
+pub fn overlay(buffer: &AudioBuffer, bpm: f32) -> AudioBuffer {
+    let channels = buffer.format.channels.max(1) as usize;
+    let track = click_track(buffer.frames(), buffer.format.sample_rate, bpm);
+    let samples = buffer
+        .samples
+        .iter()
+        .enumerate()
+        .map(|(i, &s)| (s + track.get(i / channels).copied().unwrap_or(0.0)).clamp(-1.0, 1.0))
+        .collect();
+    AudioBuffer::new(buffer.format, samples)
+}
+
+/// Where the verification copy of `output` is written: `name.click.wav`.
+pub fn verification_path(output: &Path) -> PathBuf {
+    output.with_extension("click.wav")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::AudioFormat;
+
+    #[test]
+    fn test_clicks_fall_on_beats() {
+        // 120 BPM at 16 kHz is one click every 8000 samples.
+        let track = click_track(24000, 16000, 120.0);
+        let onsets: Vec<usize> = (1..track.len())
+            .filter(|&i| track[i] != 0.0 && track[i - 1] == 0.0)
+            .collect();
+        assert_eq!(onsets, vec![1, 8001, 16001]);
+        assert!(track.iter().all(|s| s.abs() <= CLICK_LEVEL));
+    }
+
+    #[test]
+    fn test_overlay_mixes_into_each_channel() {
+        let format = AudioFormat {
+            channels: 2,
+            ..AudioFormat::default()
+        };
+        let buffer = AudioBuffer::new(format, vec![0.9; 2000]);
+        let mixed = overlay(&buffer, 60.0);
+        assert_eq!(mixed.samples.len(), 2000);
+        assert_eq!(mixed.samples[2], mixed.samples[3]);
+        assert!(mixed.samples.iter().all(|s| s.abs() <= 1.0));
+        assert_eq!(
+            verification_path(Path::new("out/a.wav")),
+            PathBuf::from("out/a.click.wav")
+        );
+    }
+}
@@ -0,0 +1,303 @@
+//! Comparing two runs' JSON reports, for a quick answer to "did the new
+//! settings change anything unexpected?": counts, audio, and timing side by
+//! side, then the files whose outcome got worse or changed.
+
+use crate::error::{Error, Result};
+use crate::json::Json;
+use crate::run;
+use crate::units::{format_bytes, format_duration};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+/// Relative rise in wall or CPU time past which a run counts as slower.
+pub const SLOWER: f64 = 0.1;
+
+/// Change in a file's output duration, in seconds, worth listing.
+const DURATION_SLACK: f64 = 0.001;
+
+/// Files listed per section before the rest are only counted.
+const LISTED: usize = 20;
+
+/// One file as a report recorded it.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FileStats {
+    pub status: String,
+    pub error: Option<String>,
+    pub input_secs: Option<f64>,
+    pub output_secs: Option<f64>,
+    pub clipped_samples: Option<f64>,
+}
+
+/// What a report says about its run.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RunStats {
+    /// Every file, by input path.
+    pub files: BTreeMap<String, FileStats>,
+    /// From the report's `started` and `finished` stamps, to the second.
+    pub wall_secs: Option<f64>,
+    pub cpu_secs: Option<f64>,
+    pub peak_rss_bytes: Option<f64>,
+}
+
+impl RunStats {
+    /// Reads the JSON report at `path`.
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| Error::io(format!("Failed to read report {:?}", path), e))?;
+        let report =
+            Json::parse(&text).map_err(|e| Error::invalid(format!("Report {:?}: {e}", path)))?;
+        Self::from_json(&report)
+            .ok_or_else(|| Error::invalid(format!("Report {:?} has no \"files\" list", path)))
+    }
+
+    /// The stats of a parsed report, if it lists its files.
+    pub fn from_json(report: &Json) -> Option<Self> {
+        let Some(Json::Array(entries)) = report.get("files") else {
+            return None;
+        };
+        let number = |json: &Json, key| json.get(key).and_then(Json::as_f64);
+        let text = |json: &Json, key| json.get(key).and_then(Json::as_str).map(str::to_string);
+        let mut files = BTreeMap::new();
+        for entry in entries {
+            let Some(input) = text(entry, "input") else {
+                continue;
+            };
+            let file = FileStats {
+                status: text(entry, "status").unwrap_or_default(),
+                error: text(entry, "error"),
+                input_secs: number(entry, "input_duration_s"),
+                output_secs: number(entry, "output_duration_s"),
+                clipped_samples: number(entry, "output_clipped_samples"),
+            };
+            files.insert(input, file);
+        }
+        let stamp = |key| text(report, key).and_then(|t| run::parse_timestamp(&t));
+        let wall_secs = match (stamp("started"), stamp("finished")) {
+            (Some(started), Some(finished)) => Some(finished.saturating_sub(started) as f64),
+            _ => None,
+        };
+        let resources = report.get("resources");
+        Some(Self {
+            files,
+            wall_secs,
+            cpu_secs: resources.and_then(|r| number(r, "cpu_secs")),
+            peak_rss_bytes: resources.and_then(|r| number(r, "peak_rss_bytes")),
+        })
+    }
+
+    fn count(&self, status: &str) -> usize {
+        self.files.values().filter(|f| f.status == status).count()
+    }
+
+    fn total(&self, secs: fn(&FileStats) -> Option<f64>) -> f64 {
+        self.files.values().filter_map(secs).sum()
+    }
+}
+
+/// How a new run differs from an old one.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Comparison {
+    /// `(what, old, new, change)` rows of the side-by-side table.
+    pub rows: Vec<[String; 4]>,
+    /// Files that failed, went missing, or started clipping, and a run
+    /// that got slower.
+    pub regressions: Vec<String>,
+    /// Everything else that changed: fixed and new files, and outputs whose
+    /// duration moved.
+    pub changes: Vec<String>,
+}
+
+/// Compares `new` against `old`.
+pub fn compare(old: &RunStats, new: &RunStats) -> Comparison {
+    let mut comparison = Comparison::default();
+    let count = |what: &str, old: usize, new: usize| {
+        let change = match new as i64 - old as i64 {
+            0 => String::new(),
+            delta => format!("{delta:+}"),
+        };
+        [what.to_string(), old.to_string(), new.to_string(), change]
+    };
+    let measure = |what: &str, old: Option<f64>, new: Option<f64>, show: &dyn Fn(f64) -> String| {
+        let change = match (old, new) {
+            (Some(old), Some(new)) if old > 0.0 && new != old => {
+                format!("{:+.1}%", (new / old - 1.0) * 100.0)
+            }
+            _ => String::new(),
+        };
+        let show = |value: Option<f64>| value.map(show).unwrap_or_else(|| "-".to_string());
+        [what.to_string(), show(old), show(new), change]
+    };
+    let secs = |secs: f64| format_duration(secs);
+    let bytes = |bytes: f64| format_bytes(bytes as u64);
+    comparison.rows = vec![
+        count("files", old.files.len(), new.files.len()),
+        count("ok", old.count("ok"), new.count("ok")),
+        count("skipped", old.count("skipped"), new.count("skipped")),
+        count("failed", old.count("failed"), new.count("failed")),
+        measure(
+            "input audio",
+            Some(old.total(|f| f.input_secs)),
+            Some(new.total(|f| f.input_secs)),
+            &secs,
+        ),
+        measure(
+            "output audio",
+            Some(old.total(|f| f.output_secs)),
+            Some(new.total(|f| f.output_secs)),
+            &secs,
+        ),
+        measure("wall time", old.wall_secs, new.wall_secs, &secs),
+        measure("CPU time", old.cpu_secs, new.cpu_secs, &secs),
+        measure("peak RSS", old.peak_rss_bytes, new.peak_rss_bytes, &bytes),
+    ];
+
+    let (regressions, changes) = (&mut comparison.regressions, &mut comparison.changes);
+    for (input, before) in &old.files {
+        let Some(after) = new.files.get(input) else {
+            regressions.push(format!("{input}: missing from the new run"));
+            continue;
+        };
+        if after.status == "failed" && before.status != "failed" {
+            let error = after.error.as_deref().unwrap_or("no error recorded");
+            regressions.push(format!("{input}: failed (was {}): {error}", before.status));
+        } else if after.status != before.status {
+            changes.push(format!("{input}: {} (was {})", after.status, before.status));
+        }
+        let clips = |f: &FileStats| f.clipped_samples.unwrap_or(0.0);
+        if clips(after) > 0.0 && clips(before) == 0.0 && before.clipped_samples.is_some() {
+            regressions.push(format!(
+                "{input}: output clips ({} samples at full scale, was none)",
+                clips(after)
+            ));
+        }
+        if let (Some(was), Some(now)) = (before.output_secs, after.output_secs)
+            && (now - was).abs() > DURATION_SLACK
+        {
+            changes.push(format!(
+                "{input}: output {} -> {}",
+                format_duration(was),
+                format_duration(now)
+            ));
+        }
+    }
+    for input in new
+        .files
+        .keys()
+        .filter(|input| !old.files.contains_key(*input))
+    {
+        changes.push(format!("{input}: new in this run"));
+    }
+    let timings = [
+        ("wall time", old.wall_secs, new.wall_secs),
+        ("CPU time", old.cpu_secs, new.cpu_secs),
+    ];
+    for (what, was, now) in timings {
+        if let (Some(was), Some(now)) = (was, now)
+            && was > 0.0
+            && now > was * (1.0 + SLOWER)
+            // Short runs are too noisy to time, and wall times are whole seconds.
+            && now - was > 1.0
+        {
+            regressions.push(format!(
+                "{what} up {:.0}%: {} -> {}",
+                (now / was - 1.0) * 100.0,
+                format_duration(was),
+                format_duration(now)
+            ));
+        }
+    }
+    comparison
+}
+
+impl Comparison {
+    /// The table, then each section, for printing.
+    pub fn to_text(&self) -> String {
+        let mut text = format!("{:<14}{:>12}{:>12}{:>10}\n", "", "old", "new", "change");
+        for [what, old, new, change] in &self.rows {
+            let row = format!("{what:<14}{old:>12}{new:>12}{change:>10}");
+            text.push_str(row.trim_end());
+            text.push('\n');
+        }
+        for (title, lines) in [
+            ("Regressions", &self.regressions),
+            ("Changes", &self.changes),
+        ] {
+            if lines.is_empty() {
+                text.push_str(&format!("\nNo {}\n", title.to_lowercase()));
+                continue;
+            }
+            text.push_str(&format!("\n{title} ({}):\n", lines.len()));
+            for line in lines.iter().take(LISTED) {
+                text.push_str(&format!("  {line}\n"));
+            }
+            if lines.len() > LISTED {
+                text.push_str(&format!("  ... and {} more\n", lines.len() - LISTED));
+            }
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compare_lists_regressions_and_changes() {
+        let report = |files: &str, finished: &str, cpu: f64| {
+            let text = format!(
+                "{{\"started\": \"2024-03-01T12:00:00Z\", \"finished\": \"{finished}\", \
+                 \"resources\": {{\"cpu_secs\": {cpu}}}, \"files\": [{files}]}}"
+            );
+            RunStats::from_json(&Json::parse(&text).unwrap()).unwrap()
+        };
+        let ok = |input: &str, secs: f64| {
+            format!(
+                "{{\"input\": \"{input}\", \"status\": \"ok\", \"output_duration_s\": {secs}, \
+                 \"output_clipped_samples\": 0}}"
+            )
+        };
+        let old = report(
+            &[ok("a.wav", 1.0), ok("b.wav", 2.0), ok("c.wav", 3.0)].join(","),
+            "2024-03-01T12:00:10Z",
+            8.0,
+        );
+        let failed = "{\"input\": \"b.wav\", \"status\": \"failed\", \"error\": \"bad header\"}";
+        let new = report(
+            &[ok("a.wav", 1.25), failed.to_string(), ok("d.wav", 4.0)].join(","),
+            "2024-03-01T12:00:20Z",
+            8.4,
+        );
+        assert_eq!(new.wall_secs, Some(20.0));
+
+        let comparison = compare(&old, &new);
+        assert_eq!(
+            comparison.regressions,
+            [
+                "b.wav: failed (was ok): bad header",
+                "c.wav: missing from the new run",
+                "wall time up 100%: 10s -> 20s",
+            ]
+        );
+        assert_eq!(
+            comparison.changes,
+            ["a.wav: output 1s -> 1.25s", "d.wav: new in this run"]
+        );
+        let text = comparison.to_text();
+        assert!(
+            text.contains("failed                   0           1        +1\n"),
+            "{text}"
+        );
+        assert!(
+            text.contains("CPU time                8s        8.4s     +5.0%\n"),
+            "{text}"
+        );
+
+        assert!(
+            compare(&old, &old)
+                .to_text()
+                .contains("No regressions\n\nNo changes\n")
+        );
+    }
+}
@@ -0,0 +1,339 @@
+//! Config files: defaults plus named `[profile.NAME]` presets.
+//!
+//! The format is the subset of TOML the options need: `key = value` pairs
+//! with string, integer, float, boolean, or flat array values, `#` comments,
+//! and `[profile.NAME]` section headers. Keys before the first section are
+//! defaults; a selected profile overrides them.
+
+use crate::error::{Error, Result};
+use crate::json::quote;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+/// A config value.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    String(String),
+    Integer(i64),
+    Float(f64),
+    Bool(bool),
+    Array(Vec<Value>),
+}
+
+impl fmt::Display for Value {
+    /// The value as it would be written on the command line.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::String(s) => f.write_str(s),
+            Self::Integer(n) => write!(f, "{n}"),
+            Self::Float(x) => write!(f, "{x}"),
+            Self::Bool(b) => write!(f, "{b}"),
+            Self::Array(items) => {
+                let items: Vec<String> = items.iter().map(ToString::to_string).collect();
+                f.write_str(&items.join(","))
+            }
+        }
+    }
+}
+
+impl Value {
+    /// Best-effort typed value of a raw command-line argument.
+    pub fn infer(raw: &str) -> Self {
+        match raw {
+            "true" => return Self::Bool(true),
+            "false" => return Self::Bool(false),
+            _ => {}
+        }
+        if let Ok(n) = raw.parse::<i64>() {
+            return Self::Integer(n);
+        }
+        match raw.parse::<f64>() {
+            Ok(x) if x.is_finite() => Self::Float(x),
+            _ => Self::String(raw.to_string()),
+        }
+    }
+
+    /// The value as a TOML literal.
+    pub fn to_toml(&self) -> String {
+        match self {
+            Self::String(s) => quote(s),
+            Self::Float(x) => format!("{x:?}"),
+            Self::Array(items) => {
+                let items: Vec<String> = items.iter().map(Self::to_toml).collect();
+                format!("[{}]", items.join(", "))
+            }
+            other => other.to_string(),
+        }
+    }
+
+    /// The value as a JSON literal.
+    pub fn to_json(&self) -> String {
+        // The two formats agree on every value this type can hold.
+        self.to_toml()
+    }
+}
+
+/// Ordered `key = value` pairs of one section.
+pub type Table = Vec<(String, Value)>;
+
+/// A parsed config file.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Config {
+    pub defaults: Table,
+    pub profiles: Vec<(String, Table)>,
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| Error::io(format!("Failed to read config {:?}", path), e))?;
+        Self::parse(&text).map_err(|e| Error::invalid(format!("Invalid config {:?}: {e}", path)))
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut config = Self::default();
+        let mut profile: Option<usize> = None;
+        for (number, raw) in text.lines().enumerate() {
+            let line = strip_comment(raw).trim();
+            if line.is_empty() {
+                continue;
+            }
+            let at = |message: String| Error::invalid(format!("line {}: {message}", number + 1));
+            if let Some(header) = line.strip_prefix('[') {
+                let name = header
+                    .strip_suffix(']')
+                    .and_then(|h| h.trim().strip_prefix("profile."))
+                    .filter(|n| !n.is_empty())
+                    .ok_or_else(|| at(format!("expected [profile.NAME], found {line}")))?;
+                if config.profiles.iter().any(|(n, _)| n == name) {
+                    return Err(at(format!("profile {name:?} defined twice")));
+                }
+                config.profiles.push((name.to_string(), Table::new()));
+                profile = Some(config.profiles.len() - 1);
+                continue;
+            }
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| at(format!("expected key = value, found {line}")))?;
+            let key = key.trim().trim_matches('"').replace('_', "-");
+            let value = parse_value(value.trim()).map_err(at)?;
+            let table = match profile {
+                Some(i) => &mut config.profiles[i].1,
+                None => &mut config.defaults,
+            };
+            if table.iter().any(|(k, _)| *k == key) {
+                return Err(at(format!("key {key:?} set twice")));
+            }
+            table.push((key, value));
+        }
+        Ok(config)
+    }
+
+    /// Profile names in file order.
+    pub fn profile_names(&self) -> impl Iterator<Item = &str> {
+        self.profiles.iter().map(|(n, _)| n.as_str())
+    }
+
+    /// Defaults overridden by the named profile, if any.
+    pub fn resolve(&self, profile: Option<&str>) -> Result<Table> {
+        let mut table = self.defaults.clone();
+        let Some(name) = profile else {
+            return Ok(table);
+        };
+        let (_, overrides) = self
+            .profiles
+            .iter()
+            .find(|(n, _)| n == name)
+            .ok_or_else(|| {
+                let known: Vec<&str> = self.profile_names().collect();
+                Error::invalid(format!(
+                    "Unknown profile {name:?} (known: {})",
+                    known.join(", ")
+                ))
+            })?;
+        for (key, value) in overrides {
+            match table.iter_mut().find(|(k, _)| k == key) {
+                Some(entry) => entry.1 = value.clone(),
+                None => table.push((key.clone(), value.clone())),
+            }
+        }
+        Ok(table)
+    }
+}
+
+/// The `known` key closest to a misspelled `key`, if any is close enough to
+/// suggest (at most a third of its characters edited).
+pub fn closest<'a>(key: &str, known: impl IntoIterator<Item = &'a str>) -> Option<&'a str> {
+    let limit = (key.chars().count() / 3).max(1);
+    known
+        .into_iter()
+        .map(|candidate| (edit_distance(key, candidate), candidate))
+        .filter(|&(distance, _)| distance <= limit)
+        .min_by_key(|&(distance, _)| distance)
+        .map(|(_, candidate)| candidate)
+}
+
+/// Levenshtein distance between `a` and `b`, in characters.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, &cb) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(ca != cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
+
+/// Drops a trailing `#` comment that is not inside a string.
+fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            '#' if !quoted => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+fn parse_value(text: &str) -> std::result::Result<Value, String> {
+    if let Some(inner) = text.strip_prefix('[') {
+        let inner = inner
+            .strip_suffix(']')
+            .ok_or_else(|| format!("unterminated array {text}"))?;
+        return split_items(inner)
+            .into_iter()
+            .map(parse_value)
+            .collect::<std::result::Result<_, _>>()
+            .map(Value::Array);
+    }
+    if let Some(inner) = text.strip_prefix('"') {
+        let inner = inner
+            .strip_suffix('"')
+            .ok_or_else(|| format!("unterminated string {text}"))?;
+        if inner.contains(['"', '\\']) {
+            return Err(format!("escapes are not supported in {text}"));
+        }
+        return Ok(Value::String(inner.to_string()));
+    }
+    match text {
+        "true" => return Ok(Value::Bool(true)),
+        "false" => return Ok(Value::Bool(false)),
+        _ => {}
+    }
+    let number = text.replace('_', "");
+    if let Ok(n) = number.parse::<i64>() {
+        return Ok(Value::Integer(n));
+    }
+    match number.parse::<f64>() {
+        Ok(x) if x.is_finite() => Ok(Value::Float(x)),
+        _ => Err(format!("invalid value {text} (quote strings)")),
+    }
+}
+
+/// Splits array items on commas outside strings, ignoring a trailing comma.
+fn split_items(inner: &str) -> Vec<&str> {
+    let mut items = Vec::new();
+    let (mut start, mut quoted) = (0, false);
+    for (i, c) in inner.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ',' if !quoted => {
+                items.push(inner[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    items.push(inner[start..].trim());
+    items.retain(|item| !item.is_empty());
+    items
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str = r#"
+# Shared defaults
+tempo = 1.1
+input_dir = ["raw/a", "raw/b"]
+
+[profile.podcast]
+rate = 44_100
+bits = 16
+normalize = "-16LUFS"   # EBU-ish
+tempo = 1.25
+
+[profile.asr]
+declip = true
+"#;
+
+    #[test]
+    fn test_profile_overrides_defaults() {
+        let config = Config::parse(SAMPLE).unwrap();
+        assert_eq!(
+            config.profile_names().collect::<Vec<_>>(),
+            ["podcast", "asr"]
+        );
+        let table = config.resolve(Some("podcast")).unwrap();
+        assert_eq!(
+            table,
+            vec![
+                ("tempo".to_string(), Value::Float(1.25)),
+                (
+                    "input-dir".to_string(),
+                    Value::Array(vec![
+                        Value::String("raw/a".into()),
+                        Value::String("raw/b".into())
+                    ])
+                ),
+                ("rate".to_string(), Value::Integer(44100)),
+                ("bits".to_string(), Value::Integer(16)),
+                ("normalize".to_string(), Value::String("-16LUFS".into())),
+            ]
+        );
+        assert_eq!(config.resolve(None).unwrap().len(), 2);
+        assert!(config.resolve(Some("radio")).is_err());
+    }
+
+    #[test]
+    fn test_parse_errors_name_the_line() {
+        let err = Config::parse("tempo = 1.0\n[output]\n").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "line 2: expected [profile.NAME], found [output]"
+        );
+        assert!(Config::parse("normalize = -16LUFS").is_err());
+        assert!(Config::parse("tempo = 1\ntempo = 2").is_err());
+
+        let known = ["tempo", "tempo-end", "output-dir", "declip"];
+        assert_eq!(closest("tempo-ned", known), Some("tempo-end"));
+        assert_eq!(closest("ouput-dir", known), Some("output-dir"));
+        assert_eq!(closest("loudness", known), None);
+    }
+
+    #[test]
+    fn test_inferred_values_render_as_literals() {
+        let values: Vec<Value> = ["1.0", "44100", "true", "-16LUFS", "C:\\a \"b\""]
+            .into_iter()
+            .map(Value::infer)
+            .collect();
+        let literals: Vec<String> = values.iter().map(Value::to_toml).collect();
+        assert_eq!(
+            literals,
+            ["1.0", "44100", "true", "\"-16LUFS\"", r#""C:\\a \"b\"""#]
+        );
+        assert_eq!(Value::Array(values[..2].to_vec()).to_json(), "[1.0, 44100]");
+        // Printed configs read back in.
+        let config = Config::parse(&format!("tempo = {}", values[0].to_toml())).unwrap();
+        assert_eq!(config.defaults[0].1, Value::Float(1.0));
+    }
+}
@@ -0,0 +1,394 @@
+//! Hardened WAV decoding for untrusted input.
+//!
+//! Header fields are validated before anything is allocated, sample storage
+//! is bounded by the bytes actually present, and no input can panic.
+
+use crate::error::{Error, Result};
+use crate::format::{AudioBuffer, AudioFormat, SampleType, int_to_f32};
+use crate::riff;
+use crate::units::{format_bytes, format_duration};
+use std::fs;
+use std::ops::Range;
+use std::path::Path;
+
+const FORMAT_PCM: u16 = 1;
+const FORMAT_FLOAT: u16 = 3;
+const FORMAT_EXTENSIBLE: u16 = 0xFFFE;
+
+/// Upper bounds on what a decoded file may claim or contain.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DecodeLimits {
+    /// Largest file accepted, checked before reading it.
+    pub max_bytes: u64,
+    pub max_channels: u16,
+    pub max_sample_rate: u32,
+    /// Longest audio accepted in seconds, judged from the data present.
+    pub max_duration: Option<f64>,
+}
+
+impl Default for DecodeLimits {
+    fn default() -> Self {
+        Self {
+            max_bytes: 4 << 30,
+            max_channels: 64,
+            max_sample_rate: 768_000,
+            max_duration: None,
+        }
+    }
+}
+
+/// A validated WAV file whose samples are still the raw little-endian bytes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RawAudio {
+    pub format: AudioFormat,
+    /// The `fmt ` chunk body exactly as read.
+    pub fmt: Vec<u8>,
+    /// The sample data, cut to whole frames.
+    pub data: Vec<u8>,
+    /// Ids of the metadata chunks, in file order.
+    pub metadata: Vec<[u8; 4]>,
+    /// What was repaired to read a damaged file, for the report.
+    pub repairs: Vec<String>,
+}
+
+impl RawAudio {
+    pub fn frames(&self) -> usize {
+        let frame = (self.format.sample_type.bits() / 8) as usize * self.format.channels as usize;
+        self.data.len() / frame.max(1)
+    }
+
+    pub fn duration_secs(&self) -> f64 {
+        self.frames() as f64 / self.format.sample_rate as f64
+    }
+
+    /// Converts the samples to `f32`, normalized to [-1.0, 1.0]. Non-finite
+    /// float samples become 0.
+    pub fn into_buffer(self) -> AudioBuffer {
+        // Collected straight from the bytes, so only the `f32`s are allocated.
+        let samples = self.samples().collect();
+        AudioBuffer::new(self.format, samples)
+    }
+
+    /// The samples as [`RawAudio::into_buffer`] converts them, one at a time,
+    /// for a single pass that needs no copy of the whole file.
+    pub fn samples(&self) -> impl ExactSizeIterator<Item = f32> + '_ {
+        let format = self.format;
+        let width = (format.sample_type.bits() / 8) as usize;
+        self.data
+            .chunks_exact(width)
+            .map(move |b| match format.sample_type {
+                SampleType::Int(8) => (b[0] as f32 - 128.0) / 128.0,
+                SampleType::Int(bits) => {
+                    let mut raw = [0u8; 4];
+                    raw[4 - width..].copy_from_slice(b);
+                    // Left-aligned in an i32, so the arithmetic shift sign-extends.
+                    int_to_f32(i32::from_le_bytes(raw) >> (32 - bits), bits)
+                }
+                SampleType::Float(_) => {
+                    let s = f32::from_le_bytes([b[0], b[1], b[2], b[3]]);
+                    if s.is_finite() { s } else { 0.0 }
+                }
+            })
+    }
+}
+
+/// Reads and decodes the WAV file at `path` within `limits`.
+pub fn decode_file(path: &Path, limits: &DecodeLimits) -> Result<AudioBuffer> {
+    decode_raw_file(path, limits).map(RawAudio::into_buffer)
+}
+
+/// Like [`decode_file`], but leaves the samples as raw bytes.
+pub fn decode_raw_file(path: &Path, limits: &DecodeLimits) -> Result<RawAudio> {
+    let len = fs::metadata(path)
+        .map_err(|e| Error::io("Failed to open input WAV", e))?
+        .len();
+    if len > limits.max_bytes {
+        return Err(Error::LimitExceeded(format!(
+            "input is {} (max {})",
+            format_bytes(len),
+            format_bytes(limits.max_bytes)
+        )));
+    }
+    let bytes = fs::read(path).map_err(|e| Error::io("Failed to open input WAV", e))?;
+    decode_raw_vec(bytes, limits)
+}
+
+/// Like [`decode_raw`], but takes ownership of `bytes` and keeps the samples
+/// in them.
+pub fn decode_raw_vec(mut bytes: Vec<u8>, limits: &DecodeLimits) -> Result<RawAudio> {
+    let (mut raw, data) = validate(&bytes, limits)?;
+    // The samples stay in the buffer read from disk rather than being copied
+    // out of it, so decoding never holds the file twice.
+    bytes.truncate(data.end);
+    bytes.drain(..data.start);
+    raw.data = bytes;
+    Ok(raw)
+}
+
+/// Decodes an in-memory WAV file, normalizing samples to [-1.0, 1.0].
+///
+/// A data chunk claiming more bytes than present is truncated to what is
+/// there, as are trailing partial frames. Non-finite float samples become 0.
+pub fn decode_bytes(bytes: &[u8], limits: &DecodeLimits) -> Result<AudioBuffer> {
+    decode_raw(bytes, limits).map(RawAudio::into_buffer)
+}
+
+/// Validates an in-memory WAV file like [`decode_bytes`] without converting
+/// its samples.
+pub fn decode_raw(bytes: &[u8], limits: &DecodeLimits) -> Result<RawAudio> {
+    let (mut raw, data) = validate(bytes, limits)?;
+    raw.data = bytes[data].to_vec();
+    Ok(raw)
+}
+
+/// Everything [`decode_raw`] returns but the sample data, whose range in
+/// `bytes` comes alongside.
+fn validate(bytes: &[u8], limits: &DecodeLimits) -> Result<(RawAudio, Range<usize>)> {
+    if bytes.len() as u64 > limits.max_bytes {
+        return Err(Error::LimitExceeded(format!(
+            "input is {} (max {})",
+            format_bytes(bytes.len() as u64),
+            format_bytes(limits.max_bytes)
+        )));
+    }
+    let chunks = riff::container_spans(bytes)?;
+    let find = |id: &[u8; 4]| chunks.iter().find(|(c, _)| c == id).map(|(_, r)| r.clone());
+    let fmt = &bytes[find(b"fmt ").ok_or_else(|| malformed("missing fmt chunk"))?];
+    let format = parse_fmt(fmt, limits)?;
+    let data = find(b"data").ok_or_else(|| malformed("missing data chunk"))?;
+
+    let width = (format.sample_type.bits() / 8) as usize;
+    let frame = width * format.channels as usize;
+    let usable = data.len() - data.len() % frame;
+    let mut repairs = Vec::new();
+    if let Some((id, size)) = riff::overrun(bytes) {
+        repairs.push(format!(
+            "truncated {:?} chunk: {size} bytes declared, the file ends first",
+            String::from_utf8_lossy(&id)
+        ));
+    }
+    if usable < data.len() {
+        repairs.push(format!(
+            "dropped a partial trailing frame of {} bytes",
+            data.len() - usable
+        ));
+    }
+    if let Some(max) = limits.max_duration {
+        let duration = (usable / frame) as f64 / format.sample_rate as f64;
+        if duration > max {
+            return Err(Error::LimitExceeded(format!(
+                "duration {} (max {})",
+                format_duration(duration),
+                format_duration(max)
+            )));
+        }
+    }
+    let raw = RawAudio {
+        format,
+        fmt: fmt.to_vec(),
+        data: Vec::new(),
+        metadata: chunks
+            .iter()
+            .filter(|(id, _)| riff::is_metadata(id))
+            .map(|(id, _)| *id)
+            .collect(),
+        repairs,
+    };
+    Ok((raw, data.start..data.start + usable))
+}
+
+/// Validates a `fmt ` chunk body and turns it into a format descriptor.
+fn parse_fmt(data: &[u8], limits: &DecodeLimits) -> Result<AudioFormat> {
+    if data.len() < 16 {
+        return Err(malformed("fmt chunk too short"));
+    }
+    let u16_at = |i: usize| u16::from_le_bytes([data[i], data[i + 1]]);
+    let u32_at = |i: usize| u32::from_le_bytes([data[i], data[i + 1], data[i + 2], data[i + 3]]);
+
+    let mut tag = u16_at(0);
+    let channels = u16_at(2);
+    let sample_rate = u32_at(4);
+    let block_align = u16_at(12);
+    let bits = u16_at(14);
+    if tag == FORMAT_EXTENSIBLE {
+        if data.len() < 26 {
+            return Err(malformed("extensible fmt chunk too short"));
+        }
+        // The first two bytes of the sub-format GUID carry the real tag.
+        tag = u16_at(24);
+    }
+
+    let sample_type = match (tag, bits) {
+        (FORMAT_PCM, 8 | 16 | 24 | 32) => SampleType::Int(bits),
+        (FORMAT_FLOAT, 32) => SampleType::Float(bits),
+        _ => return Err(malformed("unsupported sample encoding")),
+    };
+    if channels == 0 || channels > limits.max_channels {
+        return Err(Error::LimitExceeded(format!(
+            "{channels} channels (max {})",
+            limits.max_channels
+        )));
+    }
+    if sample_rate == 0 || sample_rate > limits.max_sample_rate {
+        return Err(Error::LimitExceeded(format!(
+            "sample rate {sample_rate} Hz (max {} Hz)",
+            limits.max_sample_rate
+        )));
+    }
+    if block_align as u32 != channels as u32 * (bits / 8) as u32 {
+        return Err(malformed(
+            "block align does not match channels and bit depth",
+        ));
+    }
+    Ok(AudioFormat {
+        sample_rate,
+        channels,
+        sample_type,
+    })
+}
+
+fn malformed(reason: &'static str) -> Error {
+    Error::decode(
+        "Failed to open input WAV",
+        hound::Error::FormatError(reason),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hound::{SampleFormat, WavSpec, WavWriter};
+    use std::io::Cursor;
+
+    fn wav(spec: WavSpec, samples: &[i32]) -> Vec<u8> {
+        let mut cursor = Cursor::new(Vec::new());
+        let mut writer = WavWriter::new(&mut cursor, spec).unwrap();
+        for &s in samples {
+            writer.write_sample(s).unwrap();
+        }
+        writer.finalize().unwrap();
+        cursor.into_inner()
+    }
+
+    #[test]
+    fn test_decodes_every_integer_depth() {
+        for bits in [8, 16, 24, 32] {
+            let spec = WavSpec {
+                channels: 2,
+                sample_rate: 16000,
+                bits_per_sample: bits,
+                sample_format: SampleFormat::Int,
+            };
+            let half = 1i32 << (bits - 2);
+            let buffer = decode_bytes(
+                &wav(spec, &[half, -half, 0, -2 * half]),
+                &DecodeLimits::default(),
+            )
+            .unwrap();
+            assert_eq!(buffer.format, AudioFormat::from(spec));
+            assert_eq!(buffer.samples, vec![0.5, -0.5, 0.0, -1.0], "{bits}-bit");
+        }
+    }
+
+    #[test]
+    fn test_file_decodes_like_bytes() {
+        let spec = WavSpec {
+            channels: 2,
+            sample_rate: 16000,
+            bits_per_sample: 24,
+            sample_format: SampleFormat::Int,
+        };
+        let mut bytes = wav(spec, &[1 << 22, -(1 << 20), 3, -4]);
+        // Metadata after the samples must not end up in them.
+        bytes.extend_from_slice(b"LIST\x04\0\0\0INFO");
+        let riff_size = (bytes.len() as u32 - 8).to_le_bytes();
+        bytes[4..8].copy_from_slice(&riff_size);
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("in.wav");
+        fs::write(&path, &bytes).unwrap();
+        let limits = DecodeLimits::default();
+        let raw = decode_raw_file(&path, &limits).unwrap();
+        assert_eq!(raw, decode_raw(&bytes, &limits).unwrap());
+        assert_eq!(raw.data.len(), 12);
+        assert_eq!(raw.metadata, vec![*b"LIST"]);
+        let samples: Vec<f32> = raw.samples().collect();
+        assert_eq!(raw.into_buffer().samples, samples);
+        assert_eq!(samples[0], 0.5);
+    }
+
+    #[test]
+    fn test_size_and_duration_limits() {
+        let spec = WavSpec {
+            channels: 1,
+            sample_rate: 16000,
+            bits_per_sample: 16,
+            sample_format: SampleFormat::Int,
+        };
+        let bytes = wav(spec, &vec![0; 16000]);
+        let limits = DecodeLimits {
+            max_duration: Some(0.5),
+            ..DecodeLimits::default()
+        };
+        assert!(matches!(
+            decode_bytes(&bytes, &limits),
+            Err(Error::LimitExceeded(_))
+        ));
+        let limits = DecodeLimits {
+            max_bytes: 1000,
+            ..DecodeLimits::default()
+        };
+        assert!(matches!(
+            decode_bytes(&bytes, &limits),
+            Err(Error::LimitExceeded(_))
+        ));
+    }
+
+    #[test]
+    fn test_hostile_headers_fail_cleanly() {
+        let spec = WavSpec {
+            channels: 1,
+            sample_rate: 16000,
+            bits_per_sample: 16,
+            sample_format: SampleFormat::Int,
+        };
+        let good = wav(spec, &[1, 2, 3]);
+        let limits = DecodeLimits::default();
+
+        // A data chunk claiming 4 GiB only yields the samples present.
+        let mut huge = good.clone();
+        huge[40..44].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert_eq!(decode_bytes(&huge, &limits).unwrap().samples.len(), 3);
+        assert_eq!(
+            decode_raw(&huge[..huge.len() - 1], &limits)
+                .unwrap()
+                .repairs,
+            vec![
+                format!(
+                    "truncated \"data\" chunk: {} bytes declared, the file ends first",
+                    u32::MAX
+                ),
+                "dropped a partial trailing frame of 1 bytes".to_string(),
+            ]
+        );
+
+        let mut channels = good.clone();
+        channels[22..24].copy_from_slice(&0u16.to_le_bytes());
+        assert!(matches!(
+            decode_bytes(&channels, &limits),
+            Err(Error::LimitExceeded(_))
+        ));
+
+        // Every single-byte corruption of the header and every truncation
+        // either decodes or errors; none may panic.
+        for i in 0..44 {
+            for value in [0x00, 0x01, 0x7F, 0x80, 0xFF] {
+                let mut bytes = good.clone();
+                bytes[i] = value;
+                let _ = decode_bytes(&bytes, &limits);
+            }
+        }
+        for len in 0..good.len() {
+            let _ = decode_bytes(&good[..len], &limits);
+        }
+    }
+}
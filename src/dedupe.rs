@@ -0,0 +1,259 @@
+//! Replacing outputs whose audio exactly repeats another output with links.
+//!
+//! Outputs are compared by their decoded samples and format, so files that
+//! differ only in headers or metadata chunks still count as duplicates. A
+//! [`MAPPING_FILE`] in the output directory records every output's hash and,
+//! for duplicates, the output it now links to; later runs load it, so an
+//! output can be linked to one written by an earlier run.
+
+use crate::error::{Error, Result};
+use crate::format::AudioBuffer;
+use crate::{checksum, decode, read_back_limits};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Name of the mapping file, holding `hash,path,original` rows with paths
+/// relative to the output directory; `original` is empty for kept files.
+pub const MAPPING_FILE: &str = "dedupe.csv";
+
+#[derive(Debug, Clone, PartialEq)]
+struct Entry {
+    hash: u64,
+    /// The kept output this one is a link to, if it is a duplicate.
+    original: Option<PathBuf>,
+}
+
+/// Every deduplicated output under one directory.
+#[derive(Debug, Default)]
+pub struct Deduper {
+    root: PathBuf,
+    entries: BTreeMap<PathBuf, Entry>,
+}
+
+impl Deduper {
+    /// The outputs under `root` recorded by earlier runs, if any.
+    pub fn load(root: &Path) -> Result<Self> {
+        let mut deduper = Self {
+            root: root.to_path_buf(),
+            entries: BTreeMap::new(),
+        };
+        let path = root.join(MAPPING_FILE);
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(deduper),
+            Err(e) => return Err(Error::io(format!("Failed to read {:?}", path), e)),
+        };
+        for (number, line) in text.lines().enumerate().skip(1) {
+            let fields = split_csv(line);
+            let parsed = match fields.as_slice() {
+                [hash, file, original] => u64::from_str_radix(hash, 16).ok().map(|hash| {
+                    let original = (!original.is_empty()).then(|| PathBuf::from(original));
+                    (PathBuf::from(file), Entry { hash, original })
+                }),
+                _ => None,
+            };
+            let (file, entry) = parsed.ok_or_else(|| {
+                Error::invalid(format!("Invalid {:?}: line {}", path, number + 1))
+            })?;
+            // Entries for files removed since are forgotten.
+            if root.join(&file).is_file() {
+                deduper.entries.insert(file, entry);
+            }
+        }
+        Ok(deduper)
+    }
+
+    /// Unlinks each of `outputs` that shares its file with another output, so
+    /// rewriting it leaves the others' audio alone, and forgets its entry.
+    pub fn prepare(&mut self, outputs: &[PathBuf]) -> Result<()> {
+        for output in outputs {
+            let Some(file) = self.relative(output) else {
+                continue;
+            };
+            let Some(entry) = self.entries.remove(&file) else {
+                continue;
+            };
+            let dependents: Vec<PathBuf> = self
+                .entries
+                .iter()
+                .filter(|(_, e)| e.original.as_ref() == Some(&file))
+                .map(|(f, _)| f.clone())
+                .collect();
+            if entry.original.is_some() || !dependents.is_empty() {
+                fs::remove_file(output)
+                    .map_err(|e| Error::io(format!("Failed to unlink {:?}", output), e))?;
+            }
+            // The first dependent keeps the old audio for the rest.
+            if let Some((first, rest)) = dependents.split_first() {
+                for f in rest {
+                    self.entries.get_mut(f).unwrap().original = Some(first.clone());
+                }
+                self.entries.get_mut(first).unwrap().original = None;
+            }
+        }
+        Ok(())
+    }
+
+    /// Hashes the audio of `output` and, when an earlier output holds the
+    /// very same audio, replaces `output` with a hard link to it. Returns the
+    /// output linked to, if any.
+    pub fn add(&mut self, output: &Path) -> Result<Option<PathBuf>> {
+        let file = self.relative(output).ok_or_else(|| {
+            Error::invalid(format!("{:?} is outside the output directory", output))
+        })?;
+        let audio = decode::decode_file(output, &read_back_limits())?;
+        let hash = content_hash(&audio);
+        let candidates: Vec<PathBuf> = self
+            .entries
+            .iter()
+            .filter(|(f, e)| e.hash == hash && e.original.is_none() && **f != file)
+            .map(|(f, _)| f.clone())
+            .collect();
+        for candidate in candidates {
+            let path = self.root.join(&candidate);
+            // A matching hash alone isn't proof; only identical audio is linked.
+            if decode::decode_file(&path, &read_back_limits())
+                .ok()
+                .as_ref()
+                != Some(&audio)
+            {
+                continue;
+            }
+            fs::remove_file(output)
+                .and_then(|()| fs::hard_link(&path, output))
+                .map_err(|e| Error::io(format!("Failed to link {:?} to {:?}", output, path), e))?;
+            self.entries.insert(
+                file,
+                Entry {
+                    hash,
+                    original: Some(candidate.clone()),
+                },
+            );
+            return Ok(Some(path));
+        }
+        self.entries.insert(
+            file,
+            Entry {
+                hash,
+                original: None,
+            },
+        );
+        Ok(None)
+    }
+
+    /// Writes the [`MAPPING_FILE`].
+    pub fn save(&self) -> Result<()> {
+        let mut text = String::from("hash,path,original\n");
+        for (file, entry) in &self.entries {
+            let original = entry.original.as_deref().unwrap_or(Path::new(""));
+            text.push_str(&format!(
+                "{:016x},{},{}\n",
+                entry.hash,
+                csv_field(&file.display().to_string()),
+                csv_field(&original.display().to_string())
+            ));
+        }
+        let path = self.root.join(MAPPING_FILE);
+        fs::write(&path, text).map_err(|e| Error::io(format!("Failed to write {:?}", path), e))
+    }
+
+    fn relative(&self, output: &Path) -> Option<PathBuf> {
+        output.strip_prefix(&self.root).ok().map(Path::to_path_buf)
+    }
+}
+
+/// Hash of the decoded samples of `audio` and the format they're in.
+pub fn content_hash(audio: &AudioBuffer) -> u64 {
+    let format = audio.format;
+    let header = [
+        format.sample_rate as f32,
+        f32::from(format.channels),
+        f32::from(format.sample_type.bits()),
+    ];
+    checksum(header.into_iter().chain(audio.samples.iter().copied()))
+}
+
+/// Quotes a CSV field when it contains a delimiter, quote, or line break;
+/// [`split_csv`] reads it back.
+pub fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// The fields of one CSV line, with `"quoted"` fields unescaped.
+pub(crate) fn split_csv(line: &str) -> Vec<String> {
+    let mut fields = vec![String::new()];
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                fields.last_mut().unwrap().push('"');
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(String::new()),
+            c => fields.last_mut().unwrap().push(c),
+        }
+    }
+    fields
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::AudioFormat;
+
+    #[test]
+    fn test_duplicates_link_across_runs() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let root = dir.path();
+        let tone = AudioBuffer::new(AudioFormat::default(), vec![0.25, -0.5, 0.125, 0.0]);
+        let other = AudioBuffer::new(AudioFormat::default(), vec![0.5; 4]);
+        let (a, b, c) = (
+            root.join("a.wav"),
+            root.join("b, slow.wav"),
+            root.join("c.wav"),
+        );
+        crate::write_wav(&a, &tone)?;
+        crate::write_wav(&b, &other)?;
+        let mut first = Deduper::load(root)?;
+        assert_eq!(first.add(&a)?, None);
+        assert_eq!(first.add(&b)?, None);
+        first.save()?;
+
+        // A later run writes the same audio again, under another name.
+        crate::write_wav(&c, &tone)?;
+        let mut second = Deduper::load(root)?;
+        assert_eq!(second.add(&c)?, Some(a.clone()));
+        second.save()?;
+        let third = Deduper::load(root)?;
+        assert_eq!(third.entries, second.entries);
+        assert_eq!(
+            third.entries[Path::new("c.wav")].original.as_deref(),
+            Some(Path::new("a.wav"))
+        );
+
+        // Rewriting the kept file leaves its duplicate's audio alone.
+        let mut fourth = Deduper::load(root)?;
+        fourth.prepare(std::slice::from_ref(&a))?;
+        crate::write_wav(&a, &other)?;
+        assert_eq!(crate::read_wav(&c)?, tone);
+        assert_eq!(fourth.add(&a)?, Some(b.clone()));
+        assert_eq!(fourth.entries[Path::new("c.wav")].original, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_csv_fields_round_trip() {
+        let fields = ["plain.wav", "a, b.wav", "say \"hi\".wav", "two\nlines", ""];
+        assert_eq!(csv_field("plain.wav"), "plain.wav");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        let line: Vec<String> = fields.iter().map(|f| csv_field(f)).collect();
+        assert_eq!(split_csv(&line.join(",")), fields);
+    }
+}
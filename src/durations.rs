@@ -0,0 +1,135 @@
+//! Listings of how long each output of a batch is, so dataset pipelines
+//! needn't sweep the outputs with `soxi` or `ffprobe` afterwards: Kaldi's
+//! `utt2dur`, and JSON.
+
+use crate::error::{Error, Result};
+use crate::json::Json;
+use crate::paths::{self, PathStyle};
+use crate::report::Report;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// One file a batch wrote, and its duration.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Output {
+    pub path: PathBuf,
+    pub secs: f64,
+}
+
+/// Every output of the files `report` lists as processed, with the segments
+/// of a split file in its place. Outputs of unknown duration are left out.
+pub fn outputs(report: &Report) -> Vec<Output> {
+    let mut outputs = Vec::new();
+    for file in report.files.iter().filter(|f| f.status() == "ok") {
+        if file.segments.is_empty() {
+            outputs.extend(file.output_secs.map(|secs| Output {
+                path: file.output.clone(),
+                secs,
+            }));
+        } else {
+            outputs.extend(
+                file.segments
+                    .iter()
+                    .zip(&file.segment_secs)
+                    .map(|(path, &secs)| Output {
+                        path: path.clone(),
+                        secs,
+                    }),
+            );
+        }
+    }
+    outputs
+}
+
+/// The Kaldi utterance ID of `path`: its path under `output_dir` without the
+/// extension, with `-` between directories and `_` for whitespace, which
+/// IDs can't hold.
+pub fn utterance_id(path: &Path, output_dir: &Path) -> String {
+    let relative = path.strip_prefix(output_dir).unwrap_or(path);
+    let relative = relative.with_extension("");
+    let parts: Vec<String> = relative
+        .iter()
+        .map(|part| part.to_string_lossy().replace(char::is_whitespace, "_"))
+        .collect();
+    parts.join("-")
+}
+
+/// `outputs` as a Kaldi `utt2dur` file: `<utterance-id> <seconds>` lines,
+/// sorted by ID as Kaldi's tools expect.
+pub fn utt2dur(outputs: &[Output], output_dir: &Path) -> String {
+    let mut lines: Vec<(String, f64)> = outputs
+        .iter()
+        .map(|output| (utterance_id(&output.path, output_dir), output.secs))
+        .collect();
+    lines.sort_by(|a, b| a.0.cmp(&b.0));
+    lines
+        .iter()
+        .map(|(id, secs)| format!("{id} {secs:.3}\n"))
+        .collect()
+}
+
+/// `outputs` as JSON, with paths in `style`: the count, the total seconds,
+/// and each file's path and duration.
+pub fn to_json(outputs: &[Output], style: PathStyle) -> String {
+    let secs = |secs: f64| Json::Number((secs * 1000.0).round() / 1000.0);
+    let files: Vec<String> = outputs
+        .iter()
+        .map(|output| {
+            let entry = Json::Object(vec![
+                (
+                    "path".to_string(),
+                    paths::to_text(&output.path, style).into(),
+                ),
+                ("duration_s".to_string(), secs(output.secs)),
+            ]);
+            format!("    {entry}")
+        })
+        .collect();
+    let total = outputs.iter().map(|output| output.secs).sum();
+    format!(
+        "{{\n  \"count\": {},\n  \"total_duration_s\": {},\n  \"files\": [\n{}\n  ]\n}}\n",
+        outputs.len(),
+        secs(total),
+        files.join(",\n")
+    )
+}
+
+/// Writes `text`, a listing, to `path`.
+pub fn write(path: &Path, text: &str) -> Result<()> {
+    fs::write(path, text).map_err(|e| Error::io(format!("Failed to write durations {:?}", path), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::FileReport;
+
+    #[test]
+    fn test_listings_of_outputs_and_segments() {
+        let out = Path::new("out");
+        let mut long = FileReport::new(Path::new("in/long.wav"), &out.join("b/long.wav"));
+        long.segments = vec![out.join("b/long_001.wav"), out.join("b/long_002.wav")];
+        long.segment_secs = vec![30.0, 12.5];
+        let short = FileReport {
+            output_secs: Some(1.0 / 3.0),
+            ..FileReport::new(Path::new("in/a b.wav"), &out.join("a b.wav"))
+        };
+        let failed = FileReport::failed(Path::new("in/x.wav"), &out.join("x.wav"), "bad");
+        let outputs = outputs(&Report::new(vec![long, short, failed]));
+        assert_eq!(outputs.len(), 3);
+
+        assert_eq!(
+            utt2dur(&outputs, out),
+            "a_b 0.333\nb-long_001 30.000\nb-long_002 12.500\n"
+        );
+        let json = to_json(&outputs, PathStyle::Posix);
+        assert!(
+            json.starts_with("{\n  \"count\": 3,\n  \"total_duration_s\": 42.833,\n"),
+            "{json}"
+        );
+        assert!(
+            json.contains("{\"path\": \"out/b/long_002.wav\", \"duration_s\": 12.5}"),
+            "{json}"
+        );
+    }
+}
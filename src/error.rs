@@ -0,0 +1,138 @@
+//! Typed errors for library consumers.
+
+use crate::format::AudioFormat;
+use crate::validate::Profile;
+use std::fmt;
+use std::io;
+use std::time::Duration;
+
+/// Result alias used throughout the library.
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Everything that can go wrong while processing audio.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// The input's format is outside what the pipeline accepts.
+    UnsupportedFormat {
+        found: AudioFormat,
+        expected: AudioFormat,
+    },
+    /// Reading or writing a file failed.
+    Io { context: String, source: io::Error },
+    /// The input is not a well-formed WAV file.
+    Decode {
+        context: String,
+        source: hound::Error,
+    },
+    /// Writing the output WAV failed for a reason other than I/O.
+    Encode {
+        context: String,
+        source: hound::Error,
+    },
+    /// The stretch engine could not process the buffer.
+    Engine(String),
+    /// The requested options or paths are inconsistent.
+    InvalidOptions(String),
+    /// The input exceeds a configured size or header limit.
+    LimitExceeded(String),
+    /// Processing one file took longer than the configured timeout.
+    Timeout(Duration),
+    /// A written output did not read back as what was written.
+    Verification(String),
+    /// A bug: processing panicked on an internal invariant.
+    Internal(String),
+}
+
+impl Error {
+    pub(crate) fn io(context: impl Into<String>, source: io::Error) -> Self {
+        Self::Io {
+            context: context.into(),
+            source,
+        }
+    }
+
+    /// Wraps a hound error from the read side, keeping I/O failures distinct.
+    pub(crate) fn decode(context: impl Into<String>, source: hound::Error) -> Self {
+        match source {
+            hound::Error::IoError(e) => Self::io(context, e),
+            source => Self::Decode {
+                context: context.into(),
+                source,
+            },
+        }
+    }
+
+    pub(crate) fn invalid(message: impl Into<String>) -> Self {
+        Self::InvalidOptions(message.into())
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnsupportedFormat { found, expected } => {
+                let list: Vec<String> = Profile::from(*expected)
+                    .violations(found, 0.0)
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect();
+                write!(f, "Unsupported format: {}", list.join(", "))
+            }
+            Self::Io { context, source } => write!(f, "{context}: {source}"),
+            Self::Decode { context, source } | Self::Encode { context, source } => {
+                write!(f, "{context}: {source}")
+            }
+            Self::Engine(message) => write!(f, "Stretch engine error: {message}"),
+            Self::InvalidOptions(message) => f.write_str(message),
+            Self::LimitExceeded(message) => write!(f, "Input rejected: {message}"),
+            Self::Timeout(limit) => write!(f, "Timed out after {limit:?}"),
+            Self::Verification(message) => write!(f, "Output verification failed: {message}"),
+            Self::Internal(message) => write!(f, "Internal error: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io { source, .. } => Some(source),
+            Self::Decode { source, .. } | Self::Encode { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_keeps_io_errors_distinct() {
+        let err = Error::decode(
+            "Failed to open input WAV",
+            hound::Error::IoError(io::Error::from(io::ErrorKind::NotFound)),
+        );
+        assert!(matches!(err, Error::Io { .. }));
+        let err = Error::decode("Failed to open input WAV", hound::Error::FormatError("bad"));
+        assert!(matches!(err, Error::Decode { .. }));
+        assert_eq!(
+            err.to_string(),
+            "Failed to open input WAV: Ill-formed WAVE file: bad"
+        );
+    }
+
+    #[test]
+    fn test_unsupported_format_lists_mismatches() {
+        let expected = AudioFormat::default();
+        let found = AudioFormat {
+            channels: 2,
+            ..expected
+        };
+        let err = Error::UnsupportedFormat { found, expected };
+        assert_eq!(
+            err.to_string(),
+            "Unsupported format: 2 channels (expected 1)"
+        );
+    }
+}
@@ -0,0 +1,139 @@
+//! Minimal radix-2 FFT used by the spectral effects.
+
+use std::f32::consts::PI;
+use std::ops::{Add, Mul, Sub};
+
+/// Complex number in rectangular form.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Complex {
+    pub re: f32,
+    pub im: f32,
+}
+
+impl Complex {
+    pub fn new(re: f32, im: f32) -> Self {
+        Self { re, im }
+    }
+
+    /// Builds a complex number from magnitude and phase.
+    pub fn from_polar(magnitude: f32, phase: f32) -> Self {
+        Self::new(magnitude * phase.cos(), magnitude * phase.sin())
+    }
+
+    pub fn norm(self) -> f32 {
+        self.re.hypot(self.im)
+    }
+
+    /// Phase angle in radians.
+    pub fn arg(self) -> f32 {
+        self.im.atan2(self.re)
+    }
+
+    pub fn scale(self, factor: f32) -> Self {
+        Self::new(self.re * factor, self.im * factor)
+    }
+}
+
+impl Add for Complex {
+    type Output = Self;
+    fn add(self, rhs: Self) -> Self {
+        Self::new(self.re + rhs.re, self.im + rhs.im)
+    }
+}
+
+impl Sub for Complex {
+    type Output = Self;
+    fn sub(self, rhs: Self) -> Self {
+        Self::new(self.re - rhs.re, self.im - rhs.im)
+    }
+}
+
+impl Mul for Complex {
+    type Output = Self;
+    fn mul(self, rhs: Self) -> Self {
+        Self::new(
+            self.re * rhs.re - self.im * rhs.im,
+            self.re * rhs.im + self.im * rhs.re,
+        )
+    }
+}
+
+/// In-place iterative FFT; `buf.len()` must be a power of two.
+///
+/// The inverse transform is scaled by `1 / n` so a round trip is lossless.
+pub fn fft(buf: &mut [Complex], inverse: bool) {
+    let n = buf.len();
+    assert!(n.is_power_of_two(), "FFT size must be a power of two");
+    if n < 2 {
+        return;
+    }
+
+    // Bit-reversal permutation.
+    let bits = n.trailing_zeros();
+    for i in 0..n {
+        let j = i.reverse_bits() >> (usize::BITS - bits);
+        if i < j {
+            buf.swap(i, j);
+        }
+    }
+
+    let sign = if inverse { 1.0 } else { -1.0 };
+    let mut len = 2;
+    while len <= n {
+        let step = Complex::from_polar(1.0, sign * 2.0 * PI / len as f32);
+        for chunk in buf.chunks_mut(len) {
+            let mut w = Complex::new(1.0, 0.0);
+            let (lo, hi) = chunk.split_at_mut(len / 2);
+            for (a, b) in lo.iter_mut().zip(hi.iter_mut()) {
+                let t = *b * w;
+                *b = *a - t;
+                *a = *a + t;
+                w = w * step;
+            }
+        }
+        len <<= 1;
+    }
+
+    if inverse {
+        let scale = 1.0 / n as f32;
+        buf.iter_mut().for_each(|c| *c = c.scale(scale));
+    }
+}
+
+/// Periodic Hann window of length `n`, suited to overlap-add.
+pub fn hann(n: usize) -> Vec<f32> {
+    (0..n)
+        .map(|i| 0.5 - 0.5 * (2.0 * PI * i as f32 / n as f32).cos())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fft_round_trip() {
+        let input: Vec<Complex> = (0..64)
+            .map(|i| Complex::new((i as f32 * 0.3).sin(), 0.0))
+            .collect();
+        let mut buf = input.clone();
+        fft(&mut buf, false);
+        fft(&mut buf, true);
+        for (a, b) in input.iter().zip(&buf) {
+            assert!((a.re - b.re).abs() < 1e-4 && b.im.abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn test_fft_finds_tone_bin() {
+        let n = 256;
+        let mut buf: Vec<Complex> = (0..n)
+            .map(|i| Complex::new((2.0 * PI * 8.0 * i as f32 / n as f32).cos(), 0.0))
+            .collect();
+        fft(&mut buf, false);
+        let peak = (0..n / 2)
+            .max_by(|&a, &b| buf[a].norm().total_cmp(&buf[b].norm()))
+            .unwrap();
+        assert_eq!(peak, 8);
+    }
+}
@@ -0,0 +1,135 @@
+//! Compact acoustic fingerprints for deduplicating and matching files.
+//!
+//! Each analysis frame is reduced to 60 pairwise pitch-class comparisons
+//! from its chromagram, and every (comparison, outcome) pair over the file
+//! is folded into a 64-bit SimHash. Identical audio always gets the same
+//! fingerprint; re-encoded, resampled, or tempo-stretched copies land within
+//! a small Hamming distance, since the comparisons depend neither on file
+//! names and levels nor on frame timing.
+
+use crate::analysis::chromagram;
+use crate::format::AudioBuffer;
+use std::fmt;
+
+/// Pitch-class offsets compared within each frame.
+const INTERVALS: usize = 5;
+
+/// A 64-bit acoustic fingerprint, displayed as 16 hex digits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Fingerprint(pub u64);
+
+impl Fingerprint {
+    /// Fingerprint of mono `samples`, or `None` for silence or inputs
+    /// shorter than one analysis frame.
+    pub fn of(samples: &[f32], sample_rate: u32) -> Option<Self> {
+        // Roughly 0.4 s frames at any rate keep the pitch resolution fine
+        // enough to separate semitones down to 55 Hz.
+        let size = ((sample_rate as f32 * 0.37) as usize).next_power_of_two();
+        // How often each comparison came out true, over the non-silent frames.
+        let mut wins = [0i64; 12 * INTERVALS];
+        let mut frames = 0;
+        for chroma in chromagram(samples, sample_rate, size, size / 2) {
+            if chroma.iter().sum::<f32>() <= f32::EPSILON {
+                continue;
+            }
+            for (i, &energy) in chroma.iter().enumerate() {
+                for d in 1..=INTERVALS {
+                    wins[i * INTERVALS + d - 1] += i64::from(energy > chroma[(i + d) % 12]);
+                }
+            }
+            frames += 1;
+        }
+        if frames == 0 {
+            return None;
+        }
+        let mut counts = [0i64; 64];
+        for (feature, &won) in wins.iter().enumerate() {
+            let outcomes = [
+                (mix(2 * feature as u64), frames - won),
+                (mix(2 * feature as u64 + 1), won),
+            ];
+            for (hash, weight) in outcomes {
+                for (bit, count) in counts.iter_mut().enumerate() {
+                    *count += if hash >> bit & 1 == 1 {
+                        weight
+                    } else {
+                        -weight
+                    };
+                }
+            }
+        }
+        let bits = counts
+            .iter()
+            .enumerate()
+            .fold(0u64, |acc, (bit, &count)| acc | u64::from(count > 0) << bit);
+        Some(Self(bits))
+    }
+
+    /// Fingerprint of `buffer` downmixed to mono.
+    pub fn of_buffer(buffer: &AudioBuffer) -> Option<Self> {
+        Self::of(&buffer.mono(), buffer.format.sample_rate)
+    }
+
+    /// Number of differing bits; small distances mean similar audio.
+    pub fn distance(self, other: Self) -> u32 {
+        (self.0 ^ other.0).count_ones()
+    }
+}
+
+impl fmt::Display for Fingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:016x}", self.0)
+    }
+}
+
+/// SplitMix64 finalizer: a pseudo-random 64-bit pattern per feature.
+fn mix(mut x: u64) -> u64 {
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::PI;
+
+    /// A melody of one-second notes, each a two-partial tone.
+    fn melody(notes: &[f32], rate: u32, note_secs: f32) -> Vec<f32> {
+        let per_note = (rate as f32 * note_secs) as usize;
+        (0..per_note * notes.len())
+            .map(|i| {
+                let f = notes[i / per_note];
+                let t = i as f32 / rate as f32;
+                0.3 * (2.0 * PI * f * t).sin() + 0.1 * (4.0 * PI * f * t).sin()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_same_audio_matches_across_rates_and_levels() {
+        let notes = [261.63, 329.63, 392.0, 440.0, 349.23, 293.66];
+        let a = Fingerprint::of(&melody(&notes, 16000, 1.0), 16000).unwrap();
+        assert_eq!(Fingerprint::of(&melody(&notes, 16000, 1.0), 16000), Some(a));
+
+        let quieter: Vec<f32> = melody(&notes, 44100, 1.0).iter().map(|s| s * 0.5).collect();
+        let resampled = Fingerprint::of(&quieter, 44100).unwrap();
+        let slower = Fingerprint::of(&melody(&notes, 16000, 1.25), 16000).unwrap();
+        let other = Fingerprint::of(&melody(&[466.16, 277.18, 311.13], 16000, 2.0), 16000).unwrap();
+        assert!(
+            a.distance(resampled) < a.distance(other),
+            "{a} {resampled} {other}"
+        );
+        assert!(
+            a.distance(slower) < a.distance(other),
+            "{a} {slower} {other}"
+        );
+    }
+
+    #[test]
+    fn test_silence_has_no_fingerprint() {
+        assert_eq!(Fingerprint::of(&vec![0.0; 32000], 16000), None);
+        assert_eq!(Fingerprint::of(&[0.5; 100], 16000), None);
+        assert_eq!(Fingerprint(0xab).to_string(), "00000000000000ab");
+    }
+}
@@ -0,0 +1,461 @@
+//! A small lossless FLAC encoder for `--also-output flac`.
+//!
+//! Every channel is coded independently, each block with whichever of a
+//! constant, a verbatim, or the best fixed-predictor (order 0-4) subframe
+//! is smallest, residuals Rice-coded in a single partition. That gets most
+//! of the way to the reference encoder on speech without its search. The
+//! STREAMINFO MD5 is left zero, which the format allows for "not computed".
+
+use crate::error::{Error, Result};
+use crate::format::{self, AudioBuffer, SampleType};
+use std::fs;
+use std::path::Path;
+
+/// Frames per FLAC block, as the reference encoder uses by default.
+const BLOCK_SIZE: usize = 4096;
+
+/// Largest Rice parameter written; 15 would be the escape code.
+const MAX_RICE_PARAMETER: u32 = 14;
+
+/// Highest sample rate STREAMINFO's 20-bit field holds, in Hz.
+const MAX_SAMPLE_RATE: u32 = (1 << 20) - 1;
+
+/// Bits per sample written for `sample_type`: integer depths up to 24 as
+/// they are, and 24 for deeper or float samples, which hold no more
+/// precision than that once in memory as f32.
+pub fn bits_for(sample_type: SampleType) -> u16 {
+    match sample_type {
+        SampleType::Int(bits) if bits <= 24 => bits,
+        _ => 24,
+    }
+}
+
+/// Writes `buffer` as FLAC at the depth [`bits_for`] its sample type, only
+/// appearing at `path` once complete.
+pub fn write_flac(path: &Path, buffer: &AudioBuffer) -> Result<()> {
+    let bytes = encode(buffer)?;
+    crate::atomic::write(path, |temp| {
+        fs::write(temp, &bytes).map_err(|e| Error::io("Failed to write output FLAC", e))
+    })
+}
+
+/// The FLAC file for `buffer`.
+pub fn encode(buffer: &AudioBuffer) -> Result<Vec<u8>> {
+    let format = buffer.format;
+    if format.sample_rate == 0 || format.sample_rate > MAX_SAMPLE_RATE {
+        return Err(Error::invalid(format!(
+            "FLAC cannot hold a sample rate of {} Hz",
+            format.sample_rate
+        )));
+    }
+    if !(1..=8).contains(&format.channels) {
+        return Err(Error::invalid(format!(
+            "FLAC cannot hold {} channels",
+            format.channels
+        )));
+    }
+    let bits = bits_for(format.sample_type);
+    let channels: Vec<Vec<i64>> = buffer
+        .channels()
+        .into_iter()
+        .map(|c| {
+            c.into_iter()
+                .map(|s| i64::from(format::f32_to_int(s, bits)))
+                .collect()
+        })
+        .collect();
+    let frames = channels[0].len();
+
+    let mut body = Vec::new();
+    let (mut min_frame, mut max_frame) = (u32::MAX, 0);
+    for (number, start) in (0..frames).step_by(BLOCK_SIZE).enumerate() {
+        let len = BLOCK_SIZE.min(frames - start);
+        let blocks: Vec<&[i64]> = channels.iter().map(|c| &c[start..start + len]).collect();
+        let frame = encode_frame(number as u64, &blocks, format.sample_rate, bits);
+        min_frame = min_frame.min(frame.len() as u32);
+        max_frame = max_frame.max(frame.len() as u32);
+        body.extend(frame);
+    }
+
+    let mut out = b"fLaC".to_vec();
+    let mut info = BitWriter::default();
+    // The last-block flag, then STREAMINFO's type (0) and length (34).
+    info.put(1, 1);
+    info.put(0, 7);
+    info.put(34, 24);
+    let block = BLOCK_SIZE.min(frames).max(16) as u64;
+    info.put(block, 16);
+    info.put(block, 16);
+    info.put(if frames == 0 { 0 } else { u64::from(min_frame) }, 24);
+    info.put(u64::from(max_frame), 24);
+    info.put(u64::from(format.sample_rate), 20);
+    info.put(u64::from(format.channels - 1), 3);
+    info.put(u64::from(bits - 1), 5);
+    info.put(frames as u64, 36);
+    for _ in 0..4 {
+        info.put(0, 32);
+    }
+    out.extend(info.finish());
+    out.extend(body);
+    Ok(out)
+}
+
+/// One frame holding a block of each channel, header and CRCs included.
+fn encode_frame(number: u64, blocks: &[&[i64]], sample_rate: u32, bits: u16) -> Vec<u8> {
+    let len = blocks[0].len();
+    let mut header = BitWriter::default();
+    // Sync code, reserved bit, fixed block size.
+    header.put(0b11_1111_1111_1110, 14);
+    header.put(0, 2);
+    // Block size as a 16-bit value after the header, then the rate and
+    // depth codes (zero for "as in STREAMINFO"), for independent channels.
+    header.put(0b0111, 4);
+    header.put(rate_code(sample_rate), 4);
+    header.put(blocks.len() as u64 - 1, 4);
+    header.put(depth_code(bits), 3);
+    header.put(0, 1);
+    header.put_utf8(number);
+    header.put(len as u64 - 1, 16);
+    let mut frame = header.finish();
+    frame.push(crc8(&frame));
+
+    let mut subframes = BitWriter::from_bytes(frame);
+    for block in blocks {
+        write_subframe(&mut subframes, block, u32::from(bits));
+    }
+    let mut frame = subframes.finish();
+    let crc = crc16(&frame);
+    frame.extend_from_slice(&crc.to_be_bytes());
+    frame
+}
+
+/// The frame header code of the common rates, which decoders of the
+/// streamable subset expect there rather than only in STREAMINFO.
+fn rate_code(sample_rate: u32) -> u64 {
+    match sample_rate {
+        88_200 => 0b0011,
+        176_400 => 0b0001,
+        192_000 => 0b0010,
+        8_000 => 0b0100,
+        16_000 => 0b0101,
+        22_050 => 0b0110,
+        24_000 => 0b0111,
+        32_000 => 0b1000,
+        44_100 => 0b1001,
+        48_000 => 0b1010,
+        96_000 => 0b1011,
+        _ => 0b0000,
+    }
+}
+
+/// The frame header code of the common depths, like [`rate_code`].
+fn depth_code(bits: u16) -> u64 {
+    match bits {
+        8 => 0b001,
+        12 => 0b010,
+        16 => 0b100,
+        20 => 0b101,
+        24 => 0b110,
+        _ => 0b000,
+    }
+}
+
+/// The smallest of a constant, verbatim, or fixed-predictor subframe.
+fn write_subframe(out: &mut BitWriter, block: &[i64], bits: u32) {
+    if block.iter().all(|&s| s == block[0]) {
+        out.put(0b0000_0000, 8);
+        out.put_signed(block[0], bits);
+        return;
+    }
+    let verbatim = block.len() as u64 * u64::from(bits);
+    let best = (0..=4usize.min(block.len() - 1))
+        .map(|order| {
+            let residual = fixed_residual(block, order);
+            let (parameter, cost) = rice_parameter(&residual);
+            let total = 6 + order as u64 * u64::from(bits) + 4 + cost;
+            (total, order, parameter, residual)
+        })
+        .min_by_key(|(total, ..)| *total);
+    match best {
+        Some((total, order, parameter, residual)) if total < verbatim => {
+            out.put(0b0001_0000 | (order as u64) << 1, 8);
+            for &s in &block[..order] {
+                out.put_signed(s, bits);
+            }
+            // Rice coding with 4-bit parameters, one partition.
+            out.put(0b00, 2);
+            out.put(0, 4);
+            out.put(u64::from(parameter), 4);
+            for &r in &residual {
+                let folded = fold(r);
+                out.put_unary(folded >> parameter);
+                out.put(folded, parameter);
+            }
+        }
+        _ => {
+            out.put(0b0000_0010, 8);
+            for &s in block {
+                out.put_signed(s, bits);
+            }
+        }
+    }
+}
+
+/// What the fixed predictor of `order` leaves unpredicted after the warm-up.
+fn fixed_residual(block: &[i64], order: usize) -> Vec<i64> {
+    (order..block.len())
+        .map(|i| {
+            let s = |back: usize| block[i - back];
+            match order {
+                0 => s(0),
+                1 => s(0) - s(1),
+                2 => s(0) - 2 * s(1) + s(2),
+                3 => s(0) - 3 * s(1) + 3 * s(2) - s(3),
+                _ => s(0) - 4 * s(1) + 6 * s(2) - 4 * s(3) + s(4),
+            }
+        })
+        .collect()
+}
+
+/// Signed residuals mapped onto unsigned ones, 0, -1, 1, -2, ... to 0, 1, 2, 3, ...
+fn fold(r: i64) -> u64 {
+    ((r << 1) ^ (r >> 63)) as u64
+}
+
+/// The cheapest Rice parameter for `residual` and its cost in bits.
+fn rice_parameter(residual: &[i64]) -> (u32, u64) {
+    let folded: Vec<u64> = residual.iter().map(|&r| fold(r)).collect();
+    (0..=MAX_RICE_PARAMETER)
+        .map(|k| {
+            let quotients: u64 = folded.iter().map(|u| u >> k).sum();
+            (k, quotients + folded.len() as u64 * u64::from(k + 1))
+        })
+        .min_by_key(|&(_, cost)| cost)
+        .unwrap_or((0, 0))
+}
+
+/// CRC-8 with polynomial 0x07, as frame headers use.
+fn crc8(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0u8, |mut crc, &b| {
+        crc ^= b;
+        for _ in 0..8 {
+            crc = if crc & 0x80 != 0 {
+                (crc << 1) ^ 0x07
+            } else {
+                crc << 1
+            };
+        }
+        crc
+    })
+}
+
+/// CRC-16 with polynomial 0x8005, as whole frames use.
+fn crc16(bytes: &[u8]) -> u16 {
+    bytes.iter().fold(0u16, |mut crc, &b| {
+        crc ^= u16::from(b) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x8005
+            } else {
+                crc << 1
+            };
+        }
+        crc
+    })
+}
+
+/// Big-endian bit packing, most significant bit first.
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    pending: u64,
+    pending_bits: u32,
+}
+
+impl BitWriter {
+    fn from_bytes(bytes: Vec<u8>) -> Self {
+        Self {
+            bytes,
+            ..Self::default()
+        }
+    }
+
+    /// The low `bits` (at most 32) of `value`.
+    fn put(&mut self, value: u64, bits: u32) {
+        if bits == 0 {
+            return;
+        }
+        self.pending = (self.pending << bits) | (value & ((1 << bits) - 1));
+        self.pending_bits += bits;
+        while self.pending_bits >= 8 {
+            self.pending_bits -= 8;
+            self.bytes.push((self.pending >> self.pending_bits) as u8);
+        }
+        self.pending &= (1 << self.pending_bits) - 1;
+    }
+
+    /// `value` in two's complement over `bits`.
+    fn put_signed(&mut self, value: i64, bits: u32) {
+        self.put(value as u64, bits);
+    }
+
+    /// `count` zero bits and a one.
+    fn put_unary(&mut self, mut count: u64) {
+        while count >= 32 {
+            self.put(0, 32);
+            count -= 32;
+        }
+        self.put(1, count as u32 + 1);
+    }
+
+    /// `value` in the UTF-8-like coding of frame numbers.
+    fn put_utf8(&mut self, value: u64) {
+        if value < 0x80 {
+            self.put(value, 8);
+            return;
+        }
+        let extra = (1..6).find(|&n| value < 1 << (5 * n + 6)).unwrap_or(6);
+        let lead_bits = 6 - extra;
+        let marker = (0xFF00u64 >> (extra + 1)) & 0xFF;
+        self.put(marker | (value >> (6 * extra)), 8);
+        debug_assert!(value >> (6 * extra) < 1 << lead_bits);
+        for i in (0..extra).rev() {
+            self.put(0x80 | ((value >> (6 * i)) & 0x3F), 8);
+        }
+    }
+
+    /// The bytes written, the last one zero-padded.
+    fn finish(mut self) -> Vec<u8> {
+        if self.pending_bits > 0 {
+            self.put(0, 8 - self.pending_bits);
+        }
+        self.bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::AudioFormat;
+
+    /// Reads back the subset of FLAC [`encode`] writes.
+    fn decode(bytes: &[u8]) -> (u32, Vec<Vec<i64>>) {
+        let mut bits = BitReader { bytes, at: 32 };
+        assert_eq!(&bytes[..4], b"fLaC");
+        bits.take(32);
+        bits.take(32 + 48);
+        let rate = bits.take(20) as u32;
+        let channels = bits.take(3) as usize + 1;
+        let depth = bits.take(5) as u32 + 1;
+        let total = bits.take(36) as usize;
+        bits.take(128);
+        let mut out = vec![Vec::new(); channels];
+        while out[0].len() < total {
+            let start = bits.at / 8;
+            assert_eq!(bits.take(14), 0b11_1111_1111_1110);
+            bits.take(2 + 8);
+            assert_eq!(bits.take(4) as usize, channels - 1);
+            bits.take(4);
+            let lead = bits.take(8);
+            for _ in 0..(lead as u8).leading_ones().saturating_sub(1) {
+                bits.take(8);
+            }
+            let len = bits.take(16) as usize + 1;
+            assert_eq!(crc8(&bytes[start..bits.at / 8]), bits.take(8) as u8);
+            for channel in &mut out {
+                assert_eq!(bits.take(1), 0);
+                let kind = bits.take(6);
+                bits.take(1);
+                let mut block = Vec::new();
+                match kind {
+                    0 => block.resize(len, bits.signed(depth)),
+                    1 => (0..len).for_each(|_| block.push(bits.signed(depth))),
+                    8..=12 => {
+                        let order = kind as usize - 8;
+                        (0..order).for_each(|_| block.push(bits.signed(depth)));
+                        assert_eq!(bits.take(2 + 4), 0);
+                        let k = bits.take(4) as u32;
+                        for i in order..len {
+                            let mut q = 0;
+                            while bits.take(1) == 0 {
+                                q += 1;
+                            }
+                            let u = (q << k) | bits.take(k);
+                            let r = (u >> 1) as i64 ^ -((u & 1) as i64);
+                            let s = |back: usize| block[i - back];
+                            let predicted = match order {
+                                0 => 0,
+                                1 => s(1),
+                                2 => 2 * s(1) - s(2),
+                                3 => 3 * s(1) - 3 * s(2) + s(3),
+                                _ => 4 * s(1) - 6 * s(2) + 4 * s(3) - s(4),
+                            };
+                            block.push(predicted + r);
+                        }
+                    }
+                    other => panic!("unexpected subframe type {other}"),
+                }
+                channel.extend(block);
+            }
+            bits.at = bits.at.div_ceil(8) * 8;
+            let end = bits.at / 8;
+            assert_eq!(crc16(&bytes[start..end]), bits.take(16) as u16);
+        }
+        (rate, out)
+    }
+
+    struct BitReader<'a> {
+        bytes: &'a [u8],
+        at: usize,
+    }
+
+    impl BitReader<'_> {
+        fn take(&mut self, bits: u32) -> u64 {
+            (0..bits).fold(0, |value, _| {
+                let bit = self.bytes[self.at / 8] >> (7 - self.at % 8) & 1;
+                self.at += 1;
+                value << 1 | u64::from(bit)
+            })
+        }
+
+        fn signed(&mut self, bits: u32) -> i64 {
+            let value = self.take(bits) as i64;
+            value << (64 - bits) >> (64 - bits)
+        }
+    }
+
+    #[test]
+    fn test_round_trips_losslessly() {
+        let format = AudioFormat {
+            channels: 2,
+            ..AudioFormat::default()
+        };
+        // A tone on the left, silence then noise on the right, across
+        // several blocks and a partial one.
+        let mut seed = 7u32;
+        let samples: Vec<f32> = (0..10_000)
+            .flat_map(|i| {
+                seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                let right = if i < 5000 {
+                    0.0
+                } else {
+                    seed as f32 / u32::MAX as f32 - 0.5
+                };
+                [0.5 * (i as f32 * 0.05).sin(), right]
+            })
+            .collect();
+        let buffer = AudioBuffer::new(format, samples);
+        let bytes = encode(&buffer).unwrap();
+        assert!(bytes.len() < buffer.samples.len() * 2);
+        let (rate, channels) = decode(&bytes);
+        assert_eq!(rate, 16000);
+        for (decoded, original) in channels.iter().zip(buffer.channels()) {
+            let expected: Vec<i64> = original
+                .iter()
+                .map(|&s| i64::from(format::f32_to_int(s, 16)))
+                .collect();
+            assert_eq!(decoded, &expected);
+        }
+        assert_eq!(bits_for(SampleType::Float(32)), 24);
+    }
+}
@@ -0,0 +1,272 @@
+//! Audio format descriptors and in-memory buffers converted between them.
+
+use crate::error::{Error, Result};
+use crate::resample::resample;
+use hound::{SampleFormat, WavSpec};
+use std::fmt;
+
+/// How each sample is stored on disk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SampleType {
+    /// Signed integer PCM with the given bit depth.
+    Int(u16),
+    /// IEEE float with the given bit depth.
+    Float(u16),
+}
+
+impl SampleType {
+    pub fn bits(self) -> u16 {
+        match self {
+            Self::Int(bits) | Self::Float(bits) => bits,
+        }
+    }
+
+    /// Largest magnitude of an integer sample, used to normalize to [-1.0, 1.0].
+    fn int_scale(bits: u16) -> f32 {
+        (1u64 << (bits.clamp(1, 32) - 1)) as f32
+    }
+}
+
+impl fmt::Display for SampleType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Int(bits) => write!(f, "{bits}-bit int"),
+            Self::Float(bits) => write!(f, "{bits}-bit float"),
+        }
+    }
+}
+
+/// Sample rate, channel count, and sample storage of a stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AudioFormat {
+    pub sample_rate: u32,
+    pub channels: u16,
+    pub sample_type: SampleType,
+}
+
+impl Default for AudioFormat {
+    /// Mono 16-bit PCM at 16000 Hz, the format the CLI accepts.
+    fn default() -> Self {
+        Self {
+            sample_rate: 16000,
+            channels: 1,
+            sample_type: SampleType::Int(16),
+        }
+    }
+}
+
+impl From<WavSpec> for AudioFormat {
+    fn from(spec: WavSpec) -> Self {
+        let sample_type = match spec.sample_format {
+            SampleFormat::Int => SampleType::Int(spec.bits_per_sample),
+            SampleFormat::Float => SampleType::Float(spec.bits_per_sample),
+        };
+        Self {
+            sample_rate: spec.sample_rate,
+            channels: spec.channels,
+            sample_type,
+        }
+    }
+}
+
+impl From<AudioFormat> for WavSpec {
+    fn from(format: AudioFormat) -> Self {
+        let sample_format = match format.sample_type {
+            SampleType::Int(_) => SampleFormat::Int,
+            SampleType::Float(_) => SampleFormat::Float,
+        };
+        Self {
+            channels: format.channels,
+            sample_rate: format.sample_rate,
+            bits_per_sample: format.sample_type.bits(),
+            sample_format,
+        }
+    }
+}
+
+impl fmt::Display for AudioFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} Hz, {} ch, {}",
+            self.sample_rate, self.channels, self.sample_type
+        )
+    }
+}
+
+/// Interleaved samples normalized to [-1.0, 1.0], tagged with their format.
+///
+/// The sample type records how the buffer was (or will be) stored; in memory
+/// every sample is f32.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AudioBuffer {
+    pub format: AudioFormat,
+    pub samples: Vec<f32>,
+}
+
+impl AudioBuffer {
+    pub fn new(format: AudioFormat, samples: Vec<f32>) -> Self {
+        Self { format, samples }
+    }
+
+    /// Builds an interleaved buffer from one sample vector per channel.
+    ///
+    /// Channels are truncated to the shortest one.
+    pub fn from_channels(format: AudioFormat, channels: Vec<Vec<f32>>) -> Self {
+        let frames = channels.iter().map(Vec::len).min().unwrap_or(0);
+        let mut samples = Vec::with_capacity(frames * channels.len());
+        for i in 0..frames {
+            samples.extend(channels.iter().map(|c| c[i]));
+        }
+        Self::new(format, samples)
+    }
+
+    /// Samples per channel.
+    pub fn frames(&self) -> usize {
+        self.samples.len() / self.format.channels.max(1) as usize
+    }
+
+    pub fn duration_secs(&self) -> f64 {
+        self.frames() as f64 / self.format.sample_rate.max(1) as f64
+    }
+
+    /// Cuts or pads with silence to exactly `frames` frames.
+    pub fn set_frames(&mut self, frames: usize) {
+        self.samples
+            .resize(frames * self.format.channels.max(1) as usize, 0.0);
+    }
+
+    /// The samples averaged across channels.
+    pub fn mono(&self) -> Vec<f32> {
+        let count = self.format.channels.max(1) as usize;
+        self.samples
+            .chunks(count)
+            .map(|frame| frame.iter().sum::<f32>() / frame.len() as f32)
+            .collect()
+    }
+
+    /// Splits the interleaved samples into one vector per channel.
+    pub fn channels(&self) -> Vec<Vec<f32>> {
+        let count = self.format.channels.max(1) as usize;
+        (0..count)
+            .map(|c| {
+                self.samples
+                    .iter()
+                    .skip(c)
+                    .step_by(count)
+                    .copied()
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Converts the buffer to `target`: resampling, downmixing to or upmixing
+    /// from mono, and requantizing to the target's integer precision.
+    ///
+    /// Fails with [`Error::UnsupportedFormat`] when the channel layouts have
+    /// no defined mapping.
+    pub fn convert(mut self, target: AudioFormat) -> Result<Self> {
+        let (from, to) = (self.format.channels, target.channels);
+        if from != to && from != 1 && to != 1 {
+            return Err(Error::UnsupportedFormat {
+                found: self.format,
+                expected: target,
+            });
+        }
+        if self.format.sample_rate != target.sample_rate {
+            let channels = self
+                .channels()
+                .iter()
+                .map(|c| resample(c, self.format.sample_rate, target.sample_rate))
+                .collect();
+            let format = AudioFormat {
+                sample_rate: target.sample_rate,
+                ..self.format
+            };
+            self = Self::from_channels(format, channels);
+        }
+        let mut samples = if from == to {
+            self.samples
+        } else if to == 1 {
+            self.samples
+                .chunks_exact(from as usize)
+                .map(|frame| frame.iter().sum::<f32>() / from as f32)
+                .collect()
+        } else {
+            self.samples
+                .iter()
+                .flat_map(|&s| std::iter::repeat_n(s, to as usize))
+                .collect()
+        };
+        if let SampleType::Int(bits) = target.sample_type
+            && target.sample_type != self.format.sample_type
+        {
+            samples
+                .iter_mut()
+                .for_each(|s| *s = int_to_f32(f32_to_int(*s, bits), bits));
+        }
+        Ok(Self::new(target, samples))
+    }
+}
+
+/// Normalizes an integer sample of the given bit depth.
+pub fn int_to_f32(sample: i32, bits: u16) -> f32 {
+    sample as f32 / SampleType::int_scale(bits)
+}
+
+/// Inverse of [`int_to_f32`], so untouched samples round-trip exactly.
+pub fn f32_to_int(sample: f32, bits: u16) -> i32 {
+    let scale = SampleType::int_scale(bits);
+    (sample * scale).round().clamp(-scale, scale - 1.0) as i32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_round_trips_through_wav_spec() {
+        let format = AudioFormat {
+            sample_rate: 44100,
+            channels: 2,
+            sample_type: SampleType::Float(32),
+        };
+        assert_eq!(AudioFormat::from(WavSpec::from(format)), format);
+        assert_eq!(format.to_string(), "44100 Hz, 2 ch, 32-bit float");
+        assert_eq!(
+            f32_to_int(int_to_f32(-32768, 16), 16),
+            -32768,
+            "full-scale negative sample survives"
+        );
+    }
+
+    #[test]
+    fn test_convert_changes_channel_layout() {
+        let stereo = AudioFormat {
+            channels: 2,
+            ..AudioFormat::default()
+        };
+        let buffer = AudioBuffer::new(stereo, vec![0.5, 0.25, -1.0, 0.0]);
+        assert_eq!(buffer.frames(), 2);
+        assert_eq!(buffer.channels(), vec![vec![0.5, -1.0], vec![0.25, 0.0]]);
+
+        let mono = buffer.clone().convert(AudioFormat::default()).unwrap();
+        assert_eq!(mono.samples, vec![0.375, -0.5]);
+        let back = mono.convert(stereo).unwrap();
+        assert_eq!(back.samples, vec![0.375, 0.375, -0.5, -0.5]);
+
+        let surround = AudioFormat {
+            channels: 6,
+            ..stereo
+        };
+        assert!(matches!(
+            buffer.clone().convert(surround),
+            Err(Error::UnsupportedFormat { .. })
+        ));
+        let resampled = AudioFormat {
+            sample_rate: 32000,
+            ..stereo
+        };
+        assert_eq!(buffer.convert(resampled).unwrap().frames(), 4);
+    }
+}
@@ -0,0 +1,201 @@
+//! Glob patterns that pick which input files a run takes, matched against
+//! paths relative to their input root with `/` separators.
+//!
+//! `*` matches within one path component, `**` across any number of them
+//! (`**/` also matches none), `?` one character, and `[a-z]`, `[!0-9]` one
+//! character in or out of a set.
+
+use crate::error::{Error, Result};
+use crate::paths::{self, PathStyle};
+use std::path::Path;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Char(char),
+    /// `?`
+    One,
+    /// `*`
+    Star,
+    /// `**`
+    Globstar,
+    /// `**/`: nothing, or anything ending in `/`.
+    Dirs,
+    /// `[...]`: ranges, and whether they are negated.
+    Class(Vec<(char, char)>, bool),
+}
+
+/// One compiled pattern.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Glob {
+    text: String,
+    tokens: Vec<Token>,
+}
+
+impl FromStr for Glob {
+    type Err = Error;
+
+    fn from_str(text: &str) -> Result<Self> {
+        let mut tokens = Vec::new();
+        let mut chars = text.chars().peekable();
+        while let Some(c) = chars.next() {
+            let token = match c {
+                '*' if chars.peek() == Some(&'*') => {
+                    chars.next();
+                    if chars.peek() == Some(&'/') {
+                        chars.next();
+                        Token::Dirs
+                    } else {
+                        Token::Globstar
+                    }
+                }
+                '*' => Token::Star,
+                '?' => Token::One,
+                '[' => {
+                    let negated = chars.next_if(|&c| c == '!' || c == '^').is_some();
+                    let mut ranges = Vec::new();
+                    loop {
+                        match chars.next() {
+                            Some(']') if !ranges.is_empty() => break,
+                            Some(low) => {
+                                let high = if chars.next_if_eq(&'-').is_some() {
+                                    chars.next_if(|&c| c != ']').unwrap_or('-')
+                                } else {
+                                    low
+                                };
+                                ranges.push((low, high));
+                            }
+                            None => {
+                                return Err(Error::invalid(format!(
+                                    "Invalid glob {text:?}: unclosed ["
+                                )));
+                            }
+                        }
+                    }
+                    Token::Class(ranges, negated)
+                }
+                c => Token::Char(c),
+            };
+            tokens.push(token);
+        }
+        Ok(Self {
+            text: text.to_string(),
+            tokens,
+        })
+    }
+}
+
+impl Glob {
+    /// The pattern as written.
+    pub fn as_str(&self) -> &str {
+        &self.text
+    }
+
+    /// Whether the whole of `path`, `/`-separated, matches.
+    pub fn matches(&self, path: &str) -> bool {
+        matches(&self.tokens, &path.chars().collect::<Vec<_>>())
+    }
+
+    /// Whether everything under the directory `dir` matches, as for a
+    /// pattern such as `**/raw/**`, so a walk needn't enter it.
+    fn covers_dir(&self, dir: &str) -> bool {
+        match self.tokens.as_slice() {
+            [head @ .., Token::Char('/'), Token::Globstar] => {
+                matches(head, &dir.chars().collect::<Vec<_>>())
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Whether `tokens` match all of `chars`: `matched[i][j]` says whether
+/// `tokens[i..]` match `chars[j..]`, filled from the end.
+fn matches(tokens: &[Token], chars: &[char]) -> bool {
+    let n = chars.len();
+    let mut next = vec![false; n + 1];
+    next[n] = true;
+    for token in tokens.iter().rev() {
+        let mut here = vec![false; n + 1];
+        // For `**/`: whether some `/` at or after `j` ends the match.
+        let mut slash_after = false;
+        for j in (0..=n).rev() {
+            let c = chars.get(j).copied();
+            let step = |ok: bool| ok && next[j + 1];
+            here[j] = match token {
+                Token::Char(want) => c == Some(*want) && step(true),
+                Token::One => c.is_some_and(|c| c != '/') && step(true),
+                Token::Class(ranges, negated) => c.is_some_and(|c| {
+                    let inside = ranges.iter().any(|&(low, high)| (low..=high).contains(&c));
+                    c != '/' && inside != *negated && step(true)
+                }),
+                Token::Star => next[j] || (c.is_some_and(|c| c != '/') && here[j + 1]),
+                Token::Globstar => next[j] || (c.is_some() && here[j + 1]),
+                Token::Dirs => {
+                    slash_after |= c == Some('/') && step(true);
+                    next[j] || slash_after
+                }
+            };
+        }
+        next = here;
+    }
+    next[0]
+}
+
+/// `--include` and `--exclude` patterns together. A file is taken when it
+/// matches an include (or there are none) and no exclude.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Filter {
+    pub include: Vec<Glob>,
+    pub exclude: Vec<Glob>,
+}
+
+impl Filter {
+    /// Whether the file at `relative`, under its input root, is taken.
+    pub fn accepts(&self, relative: &Path) -> bool {
+        let text = paths::to_text(relative, PathStyle::Posix);
+        (self.include.is_empty() || self.include.iter().any(|g| g.matches(&text)))
+            && !self.exclude.iter().any(|g| g.matches(&text))
+    }
+
+    /// Whether no file under the directory `relative` can be taken.
+    pub fn skips_dir(&self, relative: &Path) -> bool {
+        let text = paths::to_text(relative, PathStyle::Posix);
+        self.exclude.iter().any(|g| g.covers_dir(&text))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glob_matching() {
+        let glob = |text: &str| text.parse::<Glob>().unwrap();
+        let session = glob("speaker_*/session1/**.wav");
+        assert!(session.matches("speaker_01/session1/a.wav"));
+        assert!(session.matches("speaker_01/session1/take/2/a.wav"));
+        assert!(!session.matches("speaker_01/session2/a.wav"));
+        assert!(!session.matches("speaker_01/x/session1/a.wav"));
+
+        let raw = glob("**/raw/**");
+        assert!(raw.matches("raw/a.wav"));
+        assert!(raw.matches("spk/raw/deep/a.wav"));
+        assert!(!raw.matches("spk/rawer/a.wav"));
+        assert!(raw.covers_dir("spk/raw") && raw.covers_dir("raw"));
+        assert!(!raw.covers_dir("spk"));
+
+        assert!(glob("take_[0-9]?.wav").matches("take_17.wav"));
+        assert!(!glob("take_[!0-9]?.wav").matches("take_17.wav"));
+        assert!(!glob("*.wav").matches("sub/a.wav"));
+        assert!("take_[0-9".parse::<Glob>().is_err());
+
+        let filter = Filter {
+            include: vec![glob("**/*.wav")],
+            exclude: vec![raw],
+        };
+        assert!(filter.accepts(Path::new("spk/a.wav")));
+        assert!(!filter.accepts(Path::new("spk/raw/a.wav")));
+        assert!(!filter.accepts(Path::new("spk/a.w64")));
+        assert!(filter.skips_dir(Path::new("spk/raw")));
+    }
+}
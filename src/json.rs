@@ -0,0 +1,297 @@
+//! Minimal JSON values for the machine-readable reports.
+
+use crate::error::{Error, Result};
+use std::fmt;
+use std::iter::Peekable;
+use std::str::Chars;
+
+/// A JSON value; objects keep their keys in insertion order.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl fmt::Display for Json {
+    /// Single-line JSON with a space after each `:` and `,`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Null => f.write_str("null"),
+            Self::Bool(b) => write!(f, "{b}"),
+            Self::Number(x) if !x.is_finite() => f.write_str("null"),
+            Self::Number(x) if x.fract() == 0.0 && x.abs() < 1e15 => write!(f, "{}", *x as i64),
+            Self::Number(x) => write!(f, "{x}"),
+            Self::String(s) => f.write_str(&quote(s)),
+            Self::Array(items) => {
+                f.write_str("[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{item}")?;
+                }
+                f.write_str("]")
+            }
+            Self::Object(fields) => {
+                f.write_str("{")?;
+                for (i, (key, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{}: {value}", quote(key))?;
+                }
+                f.write_str("}")
+            }
+        }
+    }
+}
+
+impl Json {
+    /// Parses one JSON value, such as a report written earlier.
+    pub fn parse(text: &str) -> Result<Json> {
+        let mut chars = text.chars().peekable();
+        let value = value(&mut chars)?;
+        skip_space(&mut chars);
+        match chars.next() {
+            None => Ok(value),
+            Some(c) => Err(invalid(&format!("unexpected {c:?} after the value"))),
+        }
+    }
+
+    /// The value of `key`, when this is an object that has it.
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Self::Object(fields) => fields.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Self::Number(x) => Some(*x),
+            _ => None,
+        }
+    }
+}
+
+fn invalid(reason: &str) -> Error {
+    Error::invalid(format!("Invalid JSON: {reason}"))
+}
+
+fn skip_space(chars: &mut Peekable<Chars>) {
+    while chars.next_if(|c| c.is_ascii_whitespace()).is_some() {}
+}
+
+fn value(chars: &mut Peekable<Chars>) -> Result<Json> {
+    skip_space(chars);
+    let value = match chars.peek().copied() {
+        Some('{') => {
+            chars.next();
+            let mut fields = Vec::new();
+            skip_space(chars);
+            if chars.next_if_eq(&'}').is_none() {
+                loop {
+                    skip_space(chars);
+                    if chars.next() != Some('"') {
+                        return Err(invalid("expected a key"));
+                    }
+                    let key = string(chars)?;
+                    skip_space(chars);
+                    if chars.next() != Some(':') {
+                        return Err(invalid("expected ':' after a key"));
+                    }
+                    fields.push((key, value(chars)?));
+                    skip_space(chars);
+                    match chars.next() {
+                        Some(',') => {}
+                        Some('}') => break,
+                        _ => return Err(invalid("expected ',' or '}' in an object")),
+                    }
+                }
+            }
+            Json::Object(fields)
+        }
+        Some('[') => {
+            chars.next();
+            let mut items = Vec::new();
+            skip_space(chars);
+            if chars.next_if_eq(&']').is_none() {
+                loop {
+                    items.push(value(chars)?);
+                    skip_space(chars);
+                    match chars.next() {
+                        Some(',') => {}
+                        Some(']') => break,
+                        _ => return Err(invalid("expected ',' or ']' in an array")),
+                    }
+                }
+            }
+            Json::Array(items)
+        }
+        Some('"') => {
+            chars.next();
+            Json::String(string(chars)?)
+        }
+        Some(c) if c == '-' || c.is_ascii_digit() => {
+            let mut number = String::new();
+            while let Some(c) = chars.next_if(|c| "+-.eE".contains(*c) || c.is_ascii_digit()) {
+                number.push(c);
+            }
+            Json::Number(
+                number
+                    .parse()
+                    .map_err(|_| invalid(&format!("bad number {number:?}")))?,
+            )
+        }
+        Some(_) => {
+            let word: String =
+                std::iter::from_fn(|| chars.next_if(char::is_ascii_alphabetic)).collect();
+            match word.as_str() {
+                "null" => Json::Null,
+                "true" => Json::Bool(true),
+                "false" => Json::Bool(false),
+                _ => return Err(invalid(&format!("unexpected {word:?}"))),
+            }
+        }
+        None => return Err(invalid("unexpected end")),
+    };
+    Ok(value)
+}
+
+/// The rest of a string whose opening quote has been read.
+fn string(chars: &mut Peekable<Chars>) -> Result<String> {
+    let mut out = String::new();
+    loop {
+        match chars.next().ok_or_else(|| invalid("unterminated string"))? {
+            '"' => return Ok(out),
+            '\\' => {
+                let escaped = match chars.next() {
+                    Some('n') => '\n',
+                    Some('r') => '\r',
+                    Some('t') => '\t',
+                    Some('b') => '\u{8}',
+                    Some('f') => '\u{c}',
+                    Some('u') => {
+                        let hex: String = chars.by_ref().take(4).collect();
+                        let mut code = u32::from_str_radix(&hex, 16)
+                            .map_err(|_| invalid(&format!("bad escape \\u{hex}")))?;
+                        // A surrogate pair spells one character outside the BMP.
+                        if (0xd800..0xdc00).contains(&code) {
+                            let low: String = chars.by_ref().take(6).collect();
+                            let low = low
+                                .strip_prefix("\\u")
+                                .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                                .ok_or_else(|| invalid("unpaired surrogate"))?;
+                            code = 0x10000
+                                + ((code - 0xd800) << 10)
+                                + (low.wrapping_sub(0xdc00) & 0x3ff);
+                        }
+                        char::from_u32(code).ok_or_else(|| invalid("bad escape"))?
+                    }
+                    Some(c @ ('"' | '\\' | '/')) => c,
+                    _ => return Err(invalid("bad escape")),
+                };
+                out.push(escaped);
+            }
+            c => out.push(c),
+        }
+    }
+}
+
+impl From<&str> for Json {
+    fn from(s: &str) -> Self {
+        Self::String(s.to_string())
+    }
+}
+
+impl From<String> for Json {
+    fn from(s: String) -> Self {
+        Self::String(s)
+    }
+}
+
+impl From<f64> for Json {
+    fn from(x: f64) -> Self {
+        Self::Number(x)
+    }
+}
+
+impl From<usize> for Json {
+    fn from(n: usize) -> Self {
+        Self::Number(n as f64)
+    }
+}
+
+impl From<bool> for Json {
+    fn from(b: bool) -> Self {
+        Self::Bool(b)
+    }
+}
+
+/// A double-quoted string, escaped the way both TOML and JSON read it.
+pub(crate) fn quote(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => out.push_str(&format!("\\u{:04X}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display_is_valid_json() {
+        let value = Json::Object(vec![
+            ("path".into(), "a \"b\"\\c.wav".into()),
+            ("secs".into(), 1.5.into()),
+            ("count".into(), 3.0.into()),
+            ("ok".into(), true.into()),
+            (
+                "tags".into(),
+                Json::Array(vec![Json::Null, f64::NAN.into()]),
+            ),
+        ]);
+        assert_eq!(
+            value.to_string(),
+            r#"{"path": "a \"b\"\\c.wav", "secs": 1.5, "count": 3, "ok": true, "tags": [null, null]}"#
+        );
+    }
+
+    #[test]
+    fn test_parse_reads_what_display_writes() {
+        let value = Json::Object(vec![
+            ("path".into(), "a \"b\"\\c\u{1}\t.wav".into()),
+            ("secs".into(), (-1.5e-3).into()),
+            ("tags".into(), Json::Array(vec![Json::Null, true.into()])),
+            ("empty".into(), Json::Object(Vec::new())),
+        ]);
+        assert_eq!(Json::parse(&value.to_string()).unwrap(), value);
+        let parsed = Json::parse("{\"s\": \"\\ud83c\\udfb5\\/\"}\n").unwrap();
+        assert_eq!(parsed.get("s").and_then(Json::as_str), Some("🎵/"));
+        assert!(Json::parse("[1, 2").is_err());
+        assert!(Json::parse("{} x").is_err());
+    }
+}
@@ -0,0 +1,220 @@
+//! Peak and integrated loudness (ITU-R BS.1770) measurement for normalization.
+
+use crate::error::{Error, Result};
+use std::f64::consts::PI;
+
+/// Gating block length in seconds.
+const BLOCK_SECS: f64 = 0.4;
+
+/// Gating block step in seconds (75% overlap).
+const STEP_SECS: f64 = 0.1;
+
+/// Blocks quieter than this are never counted.
+const ABSOLUTE_GATE: f64 = -70.0;
+
+/// Blocks this far below the ungated mean are dropped.
+const RELATIVE_GATE: f64 = -10.0;
+
+/// Highest absolute sample value, in dBFS.
+pub fn peak_dbfs(samples: &[f32]) -> f32 {
+    let peak = samples.iter().fold(0.0f32, |m, &s| m.max(s.abs()));
+    20.0 * peak.log10()
+}
+
+/// Number of samples beyond full scale, which clip once quantized.
+pub fn clipped_samples(samples: &[f32]) -> usize {
+    samples.iter().filter(|s| s.abs() > 1.0).count()
+}
+
+/// Magnitude of the largest positive 16-bit sample, the lowest full-scale code.
+const FULL_SCALE: f32 = 32767.0 / 32768.0;
+
+/// Peak level and the number of samples at full scale, the signs of a hot
+/// or clipped file.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Levels {
+    pub peak_dbfs: f32,
+    /// Samples at the 16-bit full-scale code or beyond.
+    pub clipped: usize,
+}
+
+impl Levels {
+    pub fn of(samples: &[f32]) -> Self {
+        Self::of_samples(samples.iter().copied())
+    }
+
+    /// Like [`Levels::of`] in one pass over samples that needn't be in memory.
+    pub fn of_samples(samples: impl IntoIterator<Item = f32>) -> Self {
+        let (peak, clipped) = samples.into_iter().fold((0.0f32, 0), |(peak, clipped), s| {
+            (
+                peak.max(s.abs()),
+                clipped + usize::from(s.abs() >= FULL_SCALE),
+            )
+        });
+        Self {
+            peak_dbfs: 20.0 * peak.log10(),
+            clipped,
+        }
+    }
+}
+
+/// Root-mean-square level in dBFS; negative infinity for silence.
+pub fn rms_dbfs(samples: &[f32]) -> f32 {
+    let power = samples.iter().map(|&s| s as f64 * s as f64).sum::<f64>();
+    let mean = power / samples.len().max(1) as f64;
+    10.0 * mean.log10() as f32
+}
+
+/// Parses a level such as `-50`, `-50dB`, or `-50dBFS` (case-insensitive).
+pub fn parse_db(s: &str) -> Result<f32> {
+    let lower = s.trim().to_ascii_lowercase();
+    let number = lower
+        .strip_suffix("dbfs")
+        .or_else(|| lower.strip_suffix("db"))
+        .unwrap_or(&lower);
+    number
+        .trim()
+        .parse::<f32>()
+        .ok()
+        .filter(|db| db.is_finite())
+        .ok_or_else(|| Error::invalid(format!("Invalid level {s:?}")))
+}
+
+/// Gated integrated loudness of a mono signal in LUFS.
+///
+/// Returns `None` when the signal is shorter than one gating block or
+/// entirely below the absolute gate.
+pub fn integrated_lufs(samples: &[f32], sample_rate: u32) -> Option<f32> {
+    let powers = block_powers(samples, sample_rate);
+    gated_loudness(&powers).map(|l| l as f32)
+}
+
+/// Mean-square power of every K-weighted gating block.
+pub fn block_powers(samples: &[f32], sample_rate: u32) -> Vec<f64> {
+    let weighted = k_weight(samples, sample_rate);
+    let block = (BLOCK_SECS * sample_rate as f64) as usize;
+    let step = (STEP_SECS * sample_rate as f64) as usize;
+    if block == 0 || weighted.len() < block {
+        return Vec::new();
+    }
+    (0..=(weighted.len() - block) / step)
+        .map(|i| {
+            let frame = &weighted[i * step..i * step + block];
+            frame.iter().map(|&s| s * s).sum::<f64>() / block as f64
+        })
+        .collect()
+}
+
+/// Applies the two-stage gate to a set of block powers.
+pub fn gated_loudness(powers: &[f64]) -> Option<f64> {
+    let above_absolute: Vec<f64> = powers
+        .iter()
+        .copied()
+        .filter(|&p| to_lufs(p) > ABSOLUTE_GATE)
+        .collect();
+    if above_absolute.is_empty() {
+        return None;
+    }
+    let threshold = to_lufs(mean(&above_absolute)) + RELATIVE_GATE;
+    let gated: Vec<f64> = above_absolute
+        .into_iter()
+        .filter(|&p| to_lufs(p) > threshold)
+        .collect();
+    Some(to_lufs(mean(&gated)))
+}
+
+fn mean(values: &[f64]) -> f64 {
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+fn to_lufs(power: f64) -> f64 {
+    -0.691 + 10.0 * power.max(1e-20).log10()
+}
+
+/// K-weighting: a high-shelf "head" filter followed by a high-pass.
+fn k_weight(samples: &[f32], sample_rate: u32) -> Vec<f64> {
+    let fs = sample_rate as f64;
+
+    let (f0, gain, q) = (1681.974450955533, 3.999843853973347, 0.7071752369554196);
+    let k = (PI * f0 / fs).tan();
+    let vh = 10f64.powf(gain / 20.0);
+    let vb = vh.powf(0.4996667741545416);
+    let a0 = 1.0 + k / q + k * k;
+    let shelf = Biquad {
+        b: [
+            (vh + vb * k / q + k * k) / a0,
+            2.0 * (k * k - vh) / a0,
+            (vh - vb * k / q + k * k) / a0,
+        ],
+        a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+    };
+
+    let (f0, q) = (38.13547087602444, 0.5003270373238773);
+    let k = (PI * f0 / fs).tan();
+    let a0 = 1.0 + k / q + k * k;
+    let highpass = Biquad {
+        b: [1.0, -2.0, 1.0],
+        a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+    };
+
+    let stage1 = shelf.apply(samples.iter().map(|&s| s as f64));
+    highpass.apply(stage1.into_iter())
+}
+
+/// Direct-form I biquad with `a[0]` normalized to 1.
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2],
+}
+
+impl Biquad {
+    fn apply(&self, input: impl Iterator<Item = f64>) -> Vec<f64> {
+        let (mut x1, mut x2, mut y1, mut y2) = (0.0, 0.0, 0.0, 0.0);
+        input
+            .map(|x| {
+                let y = self.b[0] * x + self.b[1] * x1 + self.b[2] * x2
+                    - self.a[0] * y1
+                    - self.a[1] * y2;
+                (x2, x1, y2, y1) = (x1, x, y1, y);
+                y
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(freq: f32, rate: u32, secs: f32, amplitude: f32) -> Vec<f32> {
+        (0..(rate as f32 * secs) as usize)
+            .map(|i| (i as f32 * freq * 2.0 * std::f32::consts::PI / rate as f32).sin() * amplitude)
+            .collect()
+    }
+
+    #[test]
+    fn test_full_scale_1khz_sine_reads_minus_3_lufs() {
+        let lufs = integrated_lufs(&sine(1000.0, 48000, 2.0, 1.0), 48000).unwrap();
+        assert!((lufs + 3.01).abs() < 0.1, "lufs {lufs}");
+    }
+
+    #[test]
+    fn test_short_or_silent_signal_has_no_loudness() {
+        assert_eq!(integrated_lufs(&sine(1000.0, 16000, 0.2, 1.0), 16000), None);
+        assert_eq!(integrated_lufs(&vec![0.0; 16000], 16000), None);
+    }
+
+    #[test]
+    fn test_peak_dbfs() {
+        assert!((peak_dbfs(&[0.5, -0.25]) + 6.02).abs() < 0.01);
+        assert!((rms_dbfs(&[0.5, -0.5]) + 6.02).abs() < 0.01);
+        assert_eq!(rms_dbfs(&[0.0; 4]), f32::NEG_INFINITY);
+        assert_eq!(parse_db("-50dB").unwrap(), -50.0);
+        assert_eq!(parse_db("-60 dBFS").unwrap(), -60.0);
+        assert!(parse_db("quiet").is_err());
+
+        let levels = Levels::of(&[0.5, 32767.0 / 32768.0, -1.0, 1.2]);
+        assert_eq!(levels.clipped, 3);
+        assert!((levels.peak_dbfs - 1.58).abs() < 0.01);
+    }
+}
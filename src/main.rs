@@ -1,202 +1,3638 @@
 use anyhow::{Context, Result};
-use clap::Parser;
-use hound::{SampleFormat, WavReader, WavWriter};
+use clap::parser::ValueSource;
+use clap::{ArgAction, ArgMatches, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
+use hound::WavReader;
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsString;
 use std::fs;
+use std::io::{self, BufWriter, IsTerminal, Write};
+use std::ops::ControlFlow;
 use std::path::{Path, PathBuf};
-use walkdir::WalkDir;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{Receiver, RecvTimeoutError, channel};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use wav_files_tempo::album::{self, Chapter};
+use wav_files_tempo::analysis;
+use wav_files_tempo::atomic;
+use wav_files_tempo::band;
+use wav_files_tempo::compare::{self, RunStats};
+use wav_files_tempo::config::{Config, Table, Value, closest};
+use wav_files_tempo::decode::{DecodeLimits, RawAudio};
+use wav_files_tempo::dedupe::{Deduper, csv_field};
+use wav_files_tempo::denoise::{self, NoiseSource};
+use wav_files_tempo::durations;
+use wav_files_tempo::fingerprint::Fingerprint;
+use wav_files_tempo::flac;
+use wav_files_tempo::glob::{Filter, Glob};
+use wav_files_tempo::json::Json;
+use wav_files_tempo::loudness::{self, Levels};
+use wav_files_tempo::manifest::{self, Entry};
+use wav_files_tempo::pacing::Pacer;
+use wav_files_tempo::pack;
+use wav_files_tempo::paths::{self, PathStyle};
+use wav_files_tempo::pipeline::{self, Threads};
+use wav_files_tempo::progress::{FileProgress, Progress};
+use wav_files_tempo::quality;
+use wav_files_tempo::report::{self, FileReport, Report};
+use wav_files_tempo::resample::resample;
+use wav_files_tempo::resume::{self, State};
+use wav_files_tempo::review::{self, ReviewPair, Snippet};
+use wav_files_tempo::run::{self, Run};
+use wav_files_tempo::segment;
+use wav_files_tempo::selftest::{self, Outcome};
+use wav_files_tempo::stream::{self, stream_file};
+use wav_files_tempo::tempo::{self, TempoMap, TempoSource};
+use wav_files_tempo::units::{parse_byte_size, parse_duration, parse_seconds};
+use wav_files_tempo::usage::ResourceUsage;
+use wav_files_tempo::validate::Profile;
+use wav_files_tempo::watch::{self, JobQueue, Overflow, Status, Watcher};
+use wav_files_tempo::{
+    Algorithm, AudioBuffer, AudioFormat, Error, MetadataPolicy, Mode, Normalization, OutputFormat,
+    ProcessOptions, Quality, SampleType, copy_output, decode_wav, read_raw_within, read_wav,
+    read_wav_within, transform, walk, write_output, write_wav,
+};
 
-/// CLI arguments for the tempo adjustment tool.
+/// Command-line interface: processing by default, or a utility subcommand.
 #[derive(Debug, Parser)]
 #[command(name = "wav-files-tempo")]
 #[command(
-    about = "Adjusts playback tempo of mono 16kHz 16-bit WAV files without altering pitch using time-stretching."
+    about = "Adjusts playback tempo of WAV files without altering pitch using time-stretching."
 )]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    process: Option<Args>,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Validate every WAV file against a format profile without writing anything.
+    Check(CheckArgs),
+    /// Estimate tempo and key of every WAV file and write them as CSV.
+    Analyze(AnalyzeArgs),
+    /// Process the bundled fixtures and compare them with golden outputs, to
+    /// check this build on this platform.
+    SelfTest(SelfTestArgs),
+    /// Work with the JSON reports that --report writes.
+    Report(ReportArgs),
+}
+
+/// CLI arguments for the `report` subcommand.
+#[derive(Debug, clap::Args)]
+struct ReportArgs {
+    #[command(subcommand)]
+    command: ReportCommand,
+}
+
+#[derive(Debug, Subcommand)]
+enum ReportCommand {
+    /// Compare two runs' reports (counts, durations, failures, timing) and list
+    /// the regressions; exits non-zero if there are any.
+    Diff {
+        /// Report of the earlier run.
+        old: PathBuf,
+        /// Report of the run to check against it.
+        new: PathBuf,
+    },
+}
+
+/// CLI arguments for the `self-test` subcommand.
+#[derive(Debug, clap::Args)]
+struct SelfTestArgs {
+    /// Write this build's outputs here as new goldens instead of comparing.
+    #[arg(long, value_name = "DIR")]
+    bless: Option<PathBuf>,
+}
+
+/// CLI arguments for the `analyze` subcommand.
+#[derive(Debug, clap::Args)]
+struct AnalyzeArgs {
+    /// Input directory containing WAV files (analyzed recursively; repeatable).
+    #[arg(short = 'i', long, required = true)]
+    input_dir: Vec<PathBuf>,
+
+    /// Write the CSV here instead of standard output.
+    #[arg(short = 'o', long, value_name = "FILE")]
+    output: Option<PathBuf>,
+}
+
+/// CLI arguments for the `check` subcommand.
+#[derive(Debug, clap::Args)]
+struct CheckArgs {
+    /// Input directory containing WAV files (checked recursively; repeatable).
+    #[arg(short = 'i', long, required = true)]
+    input_dir: Vec<PathBuf>,
+
+    /// Required sample rate in Hz.
+    #[arg(long, default_value_t = 16000)]
+    rate: u32,
+
+    /// Required channel count.
+    #[arg(long, default_value_t = 1)]
+    channels: u16,
+
+    /// Required bits per sample.
+    #[arg(long, default_value_t = 16)]
+    bits: u16,
+
+    /// Require IEEE float samples instead of integer PCM.
+    #[arg(long)]
+    float: bool,
+
+    /// Maximum allowed duration (e.g. 30, 90s, 1m30s, or 00:01:30.500).
+    #[arg(long, value_name = "DURATION", value_parser = parse_seconds)]
+    max_duration: Option<f64>,
+}
+
+/// CLI arguments for the tempo adjustment tool.
+#[derive(Debug, clap::Args)]
 struct Args {
-    /// Input directory containing WAV files (processed recursively).
-    #[arg(short = 'i', long)]
-    input_dir: PathBuf,
+    /// Input directory containing WAV files (processed recursively; repeat for several
+    /// roots), or a single file, or - to read one from standard input.
+    #[arg(short = 'i', long, visible_alias = "input", required = true)]
+    input_dir: Vec<PathBuf>,
+
+    /// With several input roots, write all of them into one output tree instead of
+    /// one subdirectory per root.
+    #[arg(long)]
+    merge_roots: bool,
+
+    /// Only take input files whose path under their input root matches this glob,
+    /// e.g. `speaker_*/session1/**.wav`. Repeatable; a file matching any is taken.
+    #[arg(long, value_name = "GLOB")]
+    include: Vec<Glob>,
+
+    /// Skip input files whose path under their input root matches this glob, e.g.
+    /// `**/raw/**`. Repeatable; wins over --include.
+    #[arg(long, value_name = "GLOB")]
+    exclude: Vec<Glob>,
+
+    /// Directories to list at once while looking for inputs; raise it for large
+    /// trees on network storage.
+    #[arg(long, value_name = "N", default_value_t = 8)]
+    walk_threads: usize,
+
+    /// Only take files at most N levels below each input root, 1 being the
+    /// root itself.
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..))]
+    max_depth: Option<u64>,
+
+    /// Only take files directly in each input root; same as --max-depth 1.
+    #[arg(long, conflicts_with = "max_depth")]
+    no_recursive: bool,
+
+    /// Follow symbolic links to files and directories while looking for
+    /// inputs, which are otherwise skipped.
+    #[arg(long)]
+    follow_symlinks: bool,
+
+    /// Route inputs by extension as EXT=ACTION (repeatable): stretch, or
+    /// stretch:wav, stretch:w64, or stretch:flac to write that format; copy to
+    /// copy the file over unchanged; or skip. WAV and Wave64 files are
+    /// stretched unless routed, and other extensions are ignored (see
+    /// --copy-others).
+    #[arg(long, value_name = "EXT=ACTION")]
+    route: Vec<Route>,
+
+    /// Copy every input that isn't stretched or routed into the output tree
+    /// unchanged, so transcripts, metadata, and READMEs come along and the
+    /// output mirrors the input.
+    #[arg(long)]
+    copy_others: bool,
+
+    /// Extensions of the inputs to stretch, comma-separated and matched in any
+    /// case; other files are read as WAV unless named `.w64`.
+    #[arg(
+        long,
+        value_name = "LIST",
+        value_delimiter = ',',
+        default_value = "wav,w64",
+        value_parser = parse_extension
+    )]
+    extensions: Vec<String>,
+
+    /// Output directory for processed files (preserves relative paths); with a
+    /// single input file, the output file, or - to write it to standard output.
+    #[arg(short = 'o', long, visible_alias = "output")]
+    output_dir: PathBuf,
+
+    /// Tempo multiplier (e.g., 1.2 for 120% speed; default 1.0 = no change). A
+    /// comma-separated list such as 0.9,1.0,1.1 writes one output per factor,
+    /// named with --tempo-suffix, from a single read of each input.
+    #[arg(short = 't', long, value_delimiter = ',', default_value = "1.0")]
+    tempo: Vec<f32>,
+
+    /// Suffix added to each output's file stem when --tempo lists several
+    /// factors; `{tempo}` is replaced by the factor.
+    #[arg(long, default_value = "_t{tempo}")]
+    tempo_suffix: String,
+
+    /// Write each input as a language-course album: a folder of chapters at
+    /// 0.6x, 0.75x, 0.9x, and 1.0x (`lesson/01_lesson_0.60x.wav`, ...) with an
+    /// index.m3u playlist of them in order.
+    #[arg(
+        long,
+        conflicts_with_all = ["tempo", "tempo_suffix", "max_output_duration", "pack_to"]
+    )]
+    speed_album: bool,
+
+    /// Original tempo in BPM; with --to-bpm, sets the tempo to their ratio.
+    #[arg(
+        long,
+        value_name = "BPM",
+        requires = "to_bpm",
+        conflicts_with_all = ["tempo", "tempo_start", "tempo_end"]
+    )]
+    from_bpm: Option<f32>,
+
+    /// Wanted tempo in BPM, e.g. --from-bpm 120 --to-bpm 128 for a 1.0667x speedup.
+    #[arg(long, value_name = "BPM", requires = "from_bpm")]
+    to_bpm: Option<f32>,
+
+    /// Tempo at the start of each file when ramping (use with --tempo-end).
+    #[arg(long, requires = "tempo_end", conflicts_with = "tempo")]
+    tempo_start: Option<f32>,
+
+    /// Ramp the tempo linearly to this value by the end of each file, starting
+    /// from --tempo-start (or --tempo).
+    #[arg(long)]
+    tempo_end: Option<f32>,
+
+    /// Follow a tempo curve over each file, read from a CSV of
+    /// time_seconds,tempo points and interpolated linearly between them.
+    #[arg(
+        long,
+        value_name = "FILE",
+        conflicts_with_all = ["tempo", "tempo_start", "tempo_end", "from_bpm"]
+    )]
+    tempo_map: Option<PathBuf>,
+
+    /// Shift pitch by this many semitones (negative lowers it), independently of
+    /// the tempo. Needs the ssstretch algorithm.
+    #[arg(long, default_value_t = 0.0, allow_hyphen_values = true)]
+    pitch_semitones: f32,
+
+    /// Fine pitch shift in cents, added to --pitch-semitones.
+    #[arg(long, default_value_t = 0.0, allow_hyphen_values = true)]
+    pitch_cents: f32,
+
+    /// How the tempo is changed: `stretch` keeps the pitch, `vinyl` resamples so
+    /// the pitch follows the tempo, like a record played faster or slower.
+    #[arg(long, value_enum, default_value_t = ModeArg::Stretch)]
+    mode: ModeArg,
+
+    /// Measure each file's speech rate (syllables per second) and pick a tempo per
+    /// file that brings it to the median rate of all inputs.
+    #[arg(long, conflicts_with_all = ["tempo_start", "tempo_end"])]
+    equalize_rate: bool,
+
+    /// Stretch each file to the duration of the file at the same relative path under
+    /// DIR, which must share its sample rate and channel count.
+    #[arg(long, value_name = "DIR")]
+    match_duration_of: Option<PathBuf>,
+
+    /// Stretch every file to this duration (e.g. 10, 10s, or 00:00:10), picking
+    /// each file's tempo from its length and cutting or padding the last frames
+    /// so outputs have exactly the same length.
+    #[arg(
+        long,
+        value_name = "DURATION",
+        value_parser = parse_seconds,
+        conflicts_with_all = ["tempo", "tempo_start", "tempo_end", "tempo_map", "stream"]
+    )]
+    target_duration: Option<f64>,
+
+    /// Split outputs longer than this (e.g. 30, 30s, or 00:00:30) into numbered
+    /// segments (`name_001.wav`, ...), cutting in a pause near the limit when
+    /// there is one.
+    #[arg(
+        long,
+        value_name = "DURATION",
+        value_parser = parse_seconds,
+        conflicts_with_all = ["stream", "also_output", "content_dedupe", "watch"]
+    )]
+    max_output_duration: Option<f64>,
+
+    /// After stretching, join consecutive outputs in the same directory into
+    /// `pack_00001.wav`, ... of up to this duration (e.g. 30s), mapped in pack.csv.
+    #[arg(
+        long,
+        value_name = "DURATION",
+        value_parser = parse_seconds,
+        conflicts_with_all = ["max_output_duration", "also_output", "content_dedupe", "watch"]
+    )]
+    pack_to: Option<f64>,
+
+    /// Silence between the files joined by --pack-to.
+    #[arg(
+        long,
+        value_name = "DURATION",
+        value_parser = parse_seconds,
+        default_value = "0.25",
+        requires = "pack_to"
+    )]
+    pack_gap: f64,
+
+    /// Detect each file's beat and stretch it to this BPM, e.g. 120. Files without
+    /// a steady beat keep their tempo.
+    #[arg(long, value_name = "BPM", conflicts_with_all = ["tempo_start", "tempo_end", "tempo_map"])]
+    target_bpm: Option<f32>,
+
+    /// Give each file a random tempo drawn uniformly from LOW:HIGH, e.g. 0.85:1.15
+    /// for speed-perturbed training data.
+    #[arg(
+        long,
+        value_name = "LOW:HIGH",
+        value_parser = tempo::parse_range,
+        conflicts_with_all = ["tempo_start", "tempo_end", "tempo_map"]
+    )]
+    tempo_range: Option<(f32, f32)>,
+
+    /// Seed for --tempo-range, so a run's tempos can be drawn again. Without it a
+    /// seed is picked and printed.
+    #[arg(long, requires = "tempo_range")]
+    seed: Option<u64>,
+
+    /// CSV of `path,tempo` lines giving individual files their own tempo (paths
+    /// relative to the manifest). With a header such as
+    /// `path,tempo,pitch,gain,format,output`, it also sets their pitch shift,
+    /// gain, output format, and output path.
+    #[arg(long, visible_alias = "manifest", value_name = "FILE")]
+    tempo_manifest: Option<PathBuf>,
+
+    /// Take the tempo tagged at the end of a file name, e.g. `talk@1.25x.wav`.
+    #[arg(long)]
+    tempo_from_name: bool,
+
+    /// Take the tempo from the nearest `tempo.toml` (`tempo = 1.2`) between a file's
+    /// directory and its input root.
+    #[arg(long)]
+    tempo_from_dir: bool,
+
+    /// Use only this tempo source instead of the first that covers a file, in the
+    /// order manifest, reference, name, dir, target, bpm, speech-rate, random, flag.
+    #[arg(long, value_enum, value_name = "SOURCE")]
+    tempo_source: Option<TempoSourceArg>,
+
+    /// Stretch vowels and other steady-state regions more than consonants and
+    /// transients, for more natural slowed-down speech (constant tempo only).
+    #[arg(long, conflicts_with_all = ["tempo_start", "tempo_end", "tempo_map"])]
+    syllable_aware: bool,
+
+    /// Experimental: stretch only the LOW:HIGH band in Hz, e.g. 300:3400, at the
+    /// chosen quality, and the rest of the signal at the cheaper preset.
+    #[arg(long, value_name = "LOW:HIGH", value_parser = band::parse_band, conflicts_with = "syllable_aware")]
+    band_split: Option<(f32, f32)>,
+
+    /// Rebuild clipped peaks before stretching (attenuates the file to fit them).
+    #[arg(long)]
+    declip: bool,
+
+    /// Remove short impulsive clicks before stretching.
+    #[arg(long)]
+    declick: bool,
+
+    /// Apply spectral-gating noise reduction before stretching.
+    #[arg(long)]
+    denoise: bool,
+
+    /// Length of the leading noise-only excerpt profiled in each file (ms).
+    #[arg(long, default_value_t = 500, requires = "denoise")]
+    noise_profile_ms: u32,
+
+    /// WAV file containing only background noise, used as the profile for every file.
+    #[arg(long, requires = "denoise")]
+    noise_sample: Option<PathBuf>,
+
+    /// Normalize output level after stretching, e.g. `-1dBFS` (peak) or `-16LUFS` (loudness).
+    #[arg(long, value_name = "TARGET", allow_hyphen_values = true)]
+    normalize: Option<Normalization>,
+
+    /// Level each input directory (e.g. one speaker per directory) to this
+    /// integrated loudness, e.g. `-23LUFS`, with one gain for all of its files so
+    /// their relative levels survive. Reads every input twice.
+    #[arg(
+        long,
+        value_name = "TARGET",
+        allow_hyphen_values = true,
+        conflicts_with = "normalize"
+    )]
+    normalize_per_dir: Option<Normalization>,
+
+    /// Copy non-audio chunks (LIST/INFO, bext, cue, ...) from each input onto its output.
+    #[arg(long)]
+    preserve_metadata: bool,
+
+    /// Files processed at once: N stretch workers and up to 2 for reading and
+    /// writing (default: one per CPU core); the per-stage flags below override it.
+    #[arg(short = 'j', long, value_name = "N")]
+    jobs: Option<usize>,
+
+    /// Worker threads reading and decoding input files (default: 2).
+    #[arg(long)]
+    decode_threads: Option<usize>,
+
+    /// Worker threads restoring and stretching audio (default: one per CPU core).
+    #[arg(long)]
+    stretch_threads: Option<usize>,
+
+    /// Worker threads encoding and writing output files (default: 2).
+    #[arg(long)]
+    encode_threads: Option<usize>,
+
+    /// Cap throughput at N seconds of audio per wall-clock second (e.g., 4 = 4x real time).
+    #[arg(long, value_name = "N")]
+    max_realtime_factor: Option<f64>,
+
+    /// Skip inputs larger than this many bytes (suffixes K, M, G allowed).
+    #[arg(long, value_name = "BYTES", value_parser = parse_byte_size)]
+    max_input_size: Option<u64>,
+
+    /// Skip inputs holding more than this much audio (e.g. 600, 10m, or 01:00:00).
+    #[arg(long, value_name = "DURATION", value_parser = parse_seconds)]
+    max_input_duration: Option<f64>,
+
+    /// Give up on any one file that takes longer than this to process (e.g. 300s,
+    /// 5m) and move on to the next.
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration)]
+    file_timeout: Option<Duration>,
+
+    /// Keep running as a hot-folder service: poll the inputs every --watch-interval
+    /// and process files that are new or changed since their output was written.
+    #[arg(long)]
+    watch: bool,
+
+    /// How often --watch polls, and how long an input must go unmodified before
+    /// it is picked up (e.g. 5s).
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration, default_value = "2s")]
+    watch_interval: Duration,
+
+    /// Most files --watch holds in memory waiting to be processed.
+    #[arg(long, value_name = "N", default_value_t = 1000)]
+    watch_queue: usize,
+
+    /// What --watch does with new files once its queue is full: wait for room,
+    /// drop the oldest queued file, or spill the rest to a list on disk.
+    #[arg(long, value_enum, default_value_t = OverflowArg::Block)]
+    watch_overflow: OverflowArg,
+
+    /// Serve a JSON health/status endpoint at this address in --watch mode (e.g.
+    /// 127.0.0.1:8080), on GET /health and GET /status.
+    #[arg(long, value_name = "ADDR", requires = "watch")]
+    health_addr: Option<String>,
+
+    /// Rewrite `progress.json` in the output directory at this interval (e.g. 10s)
+    /// with file counts, files in flight, and a timestamp, for external monitors.
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration)]
+    progress_interval: Option<Duration>,
+
+    /// Don't draw the progress line (files done, current file, throughput, and ETA)
+    /// that batches show on a terminal, or print the summary they end with.
+    #[arg(short = 'q', long)]
+    quiet: bool,
+
+    /// Print each finished file as `text` messages on standard error, or also as
+    /// `json`: one NDJSON event per output on standard output, for scripts and CI.
+    #[arg(long, value_enum, value_name = "FORMAT", default_value_t = LogFormatArg::Text)]
+    log_format: LogFormatArg,
+
+    /// Spell paths in reports, events, and pack.csv as the platform does
+    /// (`native`), or with `/` separators (`posix`) for use on other machines.
+    #[arg(long, value_enum, value_name = "STYLE", default_value_t = PathStyleArg::Native)]
+    path_style: PathStyleArg,
+
+    /// Write a JSON report with each file's outcome and the run's resource usage to
+    /// this path, or a CSV of each file's status, tempo, and durations when it
+    /// ends in .csv.
+    #[arg(long, value_name = "FILE")]
+    report: Option<PathBuf>,
+
+    /// Write each output's duration to this path as a Kaldi utt2dur file,
+    /// with IDs from the outputs' paths under the output directory.
+    #[arg(long, value_name = "FILE")]
+    emit_utt2dur: Option<PathBuf>,
+
+    /// Write each output's duration, and their total, to this path as JSON.
+    #[arg(long, value_name = "FILE")]
+    emit_durations_json: Option<PathBuf>,
+
+    /// Instead of walking the inputs, process again only the files this JSON
+    /// report from an earlier run marks failed; pass the same settings.
+    #[arg(
+        long,
+        value_name = "REPORT",
+        conflicts_with_all = ["watch", "normalize_per_dir", "equalize_rate"]
+    )]
+    retry_failed: Option<PathBuf>,
+
+    /// Skip inputs that an earlier run with the same settings already processed
+    /// and that haven't changed since (by size and modification time), as
+    /// recorded in resume-state.tsv in the output directory.
+    #[arg(
+        long,
+        conflicts_with_all = ["watch", "retry_failed", "normalize_per_dir", "equalize_rate", "pack_to"]
+    )]
+    resume: bool,
+
+    /// Replace outputs whose decoded audio exactly repeats another output, from
+    /// this run or an earlier one, with hard links, recorded in dedupe.csv.
+    #[arg(long)]
+    content_dedupe: bool,
+
+    /// Add an acoustic fingerprint of each input and output to the report, for
+    /// deduplicating datasets or matching outputs back to their sources.
+    #[arg(long, requires = "report")]
+    fingerprint: bool,
+
+    /// Warn when an output's median F0 differs from its input's by more than this
+    /// many cents beyond the configured pitch shift, e.g. because pitch was not
+    /// preserved.
+    #[arg(long, value_name = "CENTS")]
+    pitch_tolerance: Option<f32>,
+
+    /// Skip (and report) inputs whose RMS level is below this, e.g. `-50dB`, instead
+    /// of stretching and storing silence.
+    #[arg(long, value_name = "LEVEL", allow_hyphen_values = true, value_parser = loudness::parse_db)]
+    skip_silent: Option<f32>,
+
+    /// Re-read every output after writing it and mark the file failed unless the
+    /// header, frame count, and first/last blocks of samples match what was written.
+    #[arg(long)]
+    verify_output: bool,
+
+    /// Validate each output against the RIFF spec (size fields, byte rate, block
+    /// align, cbSize, fact chunk) and mark the file failed on any deviation.
+    #[arg(long)]
+    header_strict: bool,
+
+    /// Turn any internal invariant violation (a panic) while processing a file
+    /// into a failed file in the report instead of a crashed batch.
+    #[arg(long)]
+    strict_internal: bool,
+
+    /// Stop at the first file that fails: files already being stretched or
+    /// written finish, the rest are left unprocessed.
+    #[arg(long, conflicts_with = "watch")]
+    fail_fast: bool,
+
+    /// Also write `name.click.wav` with a metronome at this BPM mixed in, to
+    /// audibly check the stretch hit the intended tempo.
+    #[arg(long, value_name = "BPM")]
+    overlay_click: Option<f32>,
+
+    /// Write one review track alternating the opening seconds of each original
+    /// with the same passage after processing, for quick A/B listening.
+    #[arg(long, value_name = "FILE")]
+    ab_review: Option<PathBuf>,
+
+    /// Score each output for stretch artifacts (spectral flatness change and
+    /// flicker against its input) and flag files scoring above this for review.
+    #[arg(long, value_name = "SCORE")]
+    artifact_threshold: Option<f32>,
+
+    /// Also write each output in these formats, next to it with their extension,
+    /// from the same processed audio: any of wav, w64, and flac, comma-separated.
+    #[arg(long, value_name = "FORMATS", value_delimiter = ',')]
+    also_output: Vec<AlsoOutput>,
+
+    /// Read, stretch, and write each file a block at a time instead of whole, so
+    /// multi-hour recordings fit in memory (Signalsmith only; no restoration,
+    /// normalization, format conversion, or read-back checks).
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "fingerprint", "skip_silent", "pitch_tolerance", "ab_review",
+            "artifact_threshold", "equalize_rate", "normalize_per_dir", "also_output",
+        ]
+    )]
+    stream: bool,
+
+    /// Stream inputs larger than this (suffixes K, M, G allowed) when nothing asked
+    /// for needs them whole; smaller ones are stretched in one go.
+    #[arg(long, value_name = "BYTES", value_parser = parse_byte_size, default_value = "256M")]
+    stream_above: u64,
+
+    /// Split streamed inputs larger than this into parts stretched on a thread each,
+    /// as many as --stretch-threads, joined with short crossfades.
+    #[arg(long, value_name = "BYTES", value_parser = parse_byte_size, default_value = "1G")]
+    split_above: u64,
+
+    /// Time-stretch algorithm: the Signalsmith engine or the built-in phase vocoder
+    /// (either may be left out of the build; see the Cargo features).
+    #[arg(long, value_enum, default_value_t = AlgorithmArg::default())]
+    algorithm: AlgorithmArg,
+
+    /// Signalsmith engine preset: `default` favours quality (music), `cheaper` speed
+    /// (large speech corpora).
+    #[arg(long, value_enum, default_value_t = PresetArg::Default)]
+    preset: PresetArg,
+
+    /// Signalsmith analysis block length in milliseconds, overriding the preset's
+    /// (120 for default, 100 for cheaper).
+    #[arg(long, value_name = "MS")]
+    block_ms: Option<u32>,
+
+    /// Signalsmith hop between blocks in milliseconds, overriding the preset's (30
+    /// for default, 40 for cheaper).
+    #[arg(long, value_name = "MS")]
+    interval_ms: Option<u32>,
+
+    /// Phase vocoder FFT size in samples (power of two).
+    #[arg(long, default_value_t = 2048)]
+    fft_size: usize,
+
+    /// Phase vocoder frames overlapping each sample (the hop is fft-size / overlap).
+    #[arg(long, default_value_t = 4)]
+    overlap: usize,
+
+    /// Reject inputs at any other sample rate than this, in Hz (default: accept any).
+    #[arg(long, value_name = "HZ")]
+    require_rate: Option<u32>,
+
+    /// Output sample rate in Hz (default: same as input).
+    #[arg(long, value_name = "HZ")]
+    rate: Option<u32>,
+
+    /// Output channel count; mono inputs are duplicated, multichannel ones downmixed
+    /// (default: same as input).
+    #[arg(long)]
+    channels: Option<u16>,
+
+    /// Output bits per sample (default: same as input).
+    #[arg(long)]
+    bits: Option<u16>,
+
+    /// Write IEEE float samples (32-bit unless --bits says otherwise).
+    #[arg(long)]
+    float: bool,
+
+    /// Whether each output mirrors its input's format or every output conforms to
+    /// one corpus-wide format (16 kHz mono 16-bit); --rate, --channels, --bits, and
+    /// --float override either.
+    #[arg(long, value_enum, value_name = "SPEC", default_value_t = OutputSpec::Mirror)]
+    output_spec: OutputSpec,
+
+    /// Config file supplying defaults and named profiles; flags on the command line win.
+    #[arg(long, value_name = "FILE")]
+    config: Option<PathBuf>,
+
+    /// Named `[profile.NAME]` section of the config file to apply.
+    #[arg(long, value_name = "NAME", requires = "config")]
+    profile: Option<String>,
+
+    /// Print every resolved setting and where it came from (default, config file,
+    /// environment, or command line) before running.
+    #[arg(
+        long,
+        value_enum,
+        value_name = "FORMAT",
+        num_args = 0..=1,
+        default_missing_value = "toml"
+    )]
+    print_config: Option<ConfigFormat>,
+}
+
+/// Lists every file violating the profile; fails if any does.
+fn check(args: CheckArgs) -> Result<()> {
+    let profile = Profile {
+        format: AudioFormat {
+            sample_rate: args.rate,
+            channels: args.channels,
+            sample_type: if args.float {
+                SampleType::Float(args.bits)
+            } else {
+                SampleType::Int(args.bits)
+            },
+        },
+        max_duration: args.max_duration,
+    };
+
+    let (mut total, mut failed) = (0usize, 0usize);
+    for root in &args.input_dir {
+        for path in walk::wav_files(root, None) {
+            total += 1;
+            let problems = match WavReader::open(&path) {
+                Ok(reader) => {
+                    let format = AudioFormat::from(reader.spec());
+                    let duration = reader.duration() as f64 / format.sample_rate.max(1) as f64;
+                    profile
+                        .violations(&format, duration)
+                        .iter()
+                        .map(ToString::to_string)
+                        .collect()
+                }
+                Err(e) => vec![format!("unreadable ({e})")],
+            };
+            if !problems.is_empty() {
+                failed += 1;
+                println!("{}: {}", path.display(), problems.join("; "));
+            }
+        }
+    }
+
+    anyhow::ensure!(
+        failed == 0,
+        "{failed} of {total} files violate the format profile"
+    );
+    println!("All {total} files match the format profile");
+    Ok(())
+}
+
+/// Prefix of the environment variables that mirror the flags.
+const ENV_PREFIX: &str = "WAV_TEMPO_";
+
+/// The CLI with every long flag also read from `WAV_TEMPO_<FLAG>`, e.g.
+/// `WAV_TEMPO_OUTPUT_DIR` for `--output-dir`.
+fn command() -> clap::Command {
+    Cli::command().mut_args(|arg| {
+        let Some(long) = arg.get_long().filter(|l| !matches!(*l, "help" | "version")) else {
+            return arg;
+        };
+        let name = format!("{ENV_PREFIX}{}", long.to_uppercase().replace('-', "_"));
+        arg.env(name)
+    })
+}
+
+/// Parses the command line over environment variables over the config file.
+///
+/// Config keys are the long flag names; each one not given on the command
+/// line is injected ahead of the real arguments, once the whole file has
+/// been checked against the flags.
+fn parse_cli() -> Result<Cli> {
+    let argv: Vec<OsString> = std::env::args_os().collect();
+    // Subcommands take neither environment variables nor config files.
+    let plain = Cli::command().ignore_errors(true).get_matches_from(&argv);
+    if plain.subcommand_name().is_some() {
+        return Ok(Cli::parse_from(argv));
+    }
+    let lenient = command().ignore_errors(true).get_matches_from(&argv);
+    let given = |id: &str| {
+        matches!(
+            lenient.value_source(id),
+            Some(ValueSource::CommandLine | ValueSource::EnvVariable)
+        )
+    };
+    if let Some(problem) = tempo_conflict(given) {
+        anyhow::bail!(problem);
+    }
+
+    let command = command();
+    let mut layered = vec![argv[0].clone()];
+    // Ids of the arguments the config file supplied.
+    let mut from_config = Vec::new();
+    if let Some(config_path) = lenient.get_one::<PathBuf>("config") {
+        let config = Config::load(config_path)?;
+        let profile = lenient.get_one::<String>("profile").map(String::as_str);
+        let table = config.resolve(profile)?;
+        let problems = config_problems(&command, &table, &lenient);
+        if !problems.is_empty() {
+            anyhow::bail!(
+                "Invalid config {:?}:\n  - {}",
+                config_path,
+                problems.join("\n  - ")
+            );
+        }
+        for (key, value) in table {
+            let Some(arg) = config_arg(&command, &key) else {
+                continue;
+            };
+            if explicit(&lenient, arg) {
+                continue;
+            }
+            match value {
+                _ if arg.get_action().takes_values() => {
+                    let items = match value {
+                        Value::Array(items) => items,
+                        single => vec![single],
+                    };
+                    layered.extend(items.iter().map(|v| format!("--{key}={v}").into()));
+                }
+                Value::Bool(true) => layered.push(format!("--{key}").into()),
+                Value::Bool(false) => {}
+                _ => unreachable!("config_problems checks flags take booleans"),
+            }
+            from_config.push(arg.get_id().to_string());
+        }
+    }
+    layered.extend(argv.into_iter().skip(1));
+
+    let matches = command.get_matches_from(layered);
+    let cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    if let Some(format) = cli.process.as_ref().and_then(|args| args.print_config) {
+        print!("{}", resolved_config(&matches, &from_config, format));
+    }
+    Ok(cli)
+}
+
+/// The flag a config key sets, if it names one that config files may set.
+fn config_arg<'a>(command: &'a clap::Command, key: &str) -> Option<&'a clap::Arg> {
+    command
+        .get_arguments()
+        .find(|a| a.get_long() == Some(key) && !matches!(key, "config" | "profile"))
+}
+
+/// Whether `arg` was given on the command line or in the environment.
+fn explicit(matches: &ArgMatches, arg: &clap::Arg) -> bool {
+    matches!(
+        matches.value_source(arg.get_id().as_str()),
+        Some(ValueSource::CommandLine | ValueSource::EnvVariable)
+    )
+}
+
+/// Everything wrong with a resolved config `table`: unknown keys (with the
+/// closest flag as a suggestion), values the flag would reject, and keys that
+/// conflict with each other or with flags given in `matches`.
+fn config_problems(command: &clap::Command, table: &Table, matches: &ArgMatches) -> Vec<String> {
+    let mut problems = Vec::new();
+    let mut used = Vec::new();
+    for (key, value) in table {
+        let Some(arg) = config_arg(command, key) else {
+            let known = command
+                .get_arguments()
+                .filter_map(clap::Arg::get_long)
+                .filter(|long| !matches!(*long, "config" | "profile" | "help" | "version"));
+            problems.push(match closest(key, known) {
+                Some(suggestion) => format!("unknown key {key:?} (did you mean {suggestion:?}?)"),
+                None => format!("unknown key {key:?}"),
+            });
+            continue;
+        };
+        if !arg.get_action().takes_values() {
+            if !matches!(value, Value::Bool(_)) {
+                problems.push(format!(
+                    "{key:?} is a switch and takes true or false, not {}",
+                    value.to_toml()
+                ));
+            }
+        } else if let Some(problem) = value_problem(arg, key, value) {
+            problems.push(problem);
+        }
+        if *value != Value::Bool(false) && !explicit(matches, arg) {
+            used.push(arg);
+        }
+    }
+    let given: Vec<&clap::Arg> = command
+        .get_arguments()
+        .filter(|arg| explicit(matches, arg))
+        .collect();
+    let from_config = |id: &str| used.iter().any(|arg| arg.get_id() == id);
+    let set = |id: &str| from_config(id) || given.iter().any(|arg| arg.get_id() == id);
+    if std::iter::once("tempo")
+        .chain(TEMPO_SOURCES)
+        .any(from_config)
+        && let Some(problem) = tempo_conflict(set)
+    {
+        problems.push(problem);
+    }
+    for (i, a) in used.iter().enumerate() {
+        // --tempo against a tempo source was reported above, with its fix.
+        let tempo_pair = |b: &clap::Arg| {
+            let ids = [a.get_id().as_str(), b.get_id().as_str()];
+            ids.contains(&"tempo") && ids.iter().any(|id| TEMPO_SOURCES.contains(id))
+        };
+        let conflicts = |b: &clap::Arg| {
+            !tempo_pair(b)
+                && (command.get_arg_conflicts_with(a).contains(&b)
+                    || command.get_arg_conflicts_with(b).contains(a))
+        };
+        let long = |arg: &clap::Arg| arg.get_long().unwrap_or_default().to_string();
+        for b in &used[i + 1..] {
+            if conflicts(b) {
+                problems.push(format!(
+                    "{:?} and {:?} cannot be used together; remove one",
+                    long(a),
+                    long(b)
+                ));
+            }
+        }
+        for b in given.iter().filter(|b| conflicts(b)) {
+            problems.push(format!(
+                "{:?} conflicts with --{} from the command line or environment; drop one of the two",
+                long(a),
+                long(b)
+            ));
+        }
+    }
+    problems
+}
+
+/// Flags that pick each file's tempo themselves, so an explicit `--tempo`
+/// would go unused.
+const TEMPO_SOURCES: [&str; 5] = [
+    "target_duration",
+    "target_bpm",
+    "tempo_range",
+    "match_duration_of",
+    "equalize_rate",
+];
+
+/// Why `--tempo` can't be set alongside one of [`TEMPO_SOURCES`], if both
+/// are, given which flags `is_set`.
+fn tempo_conflict(is_set: impl Fn(&str) -> bool) -> Option<String> {
+    if !is_set("tempo") {
+        return None;
+    }
+    let source = TEMPO_SOURCES.into_iter().find(|id| is_set(id))?;
+    let flag = format!("--{}", source.replace('_', "-"));
+    Some(format!(
+        "--tempo would be ignored, as {flag} picks each file's tempo; \
+         drop --tempo, or drop {flag} to give every file the same tempo"
+    ))
+}
+
+/// Why the flag `arg` would reject the config `value`, if it would.
+fn value_problem(arg: &clap::Arg, key: &str, value: &Value) -> Option<String> {
+    let items = match value {
+        Value::Array(items) if matches!(arg.get_action(), ArgAction::Append) => items.clone(),
+        Value::Array(_) => return Some(format!("{key:?} takes a single value, not an array")),
+        single => vec![single.clone()],
+    };
+    // A bare copy of the flag, so its value parser runs without the rest of the CLI.
+    let name = match arg.get_value_names() {
+        Some([name, ..]) => name.to_string(),
+        _ => arg.get_id().as_str().to_uppercase(),
+    };
+    let probe = clap::Arg::new("value")
+        .long(key.to_string())
+        .value_name(name)
+        .value_parser(arg.get_value_parser().clone())
+        .action(ArgAction::Append);
+    let probe = clap::Command::new("config")
+        .no_binary_name(true)
+        .disable_help_flag(true)
+        .color(clap::ColorChoice::Never)
+        .arg(probe);
+    let args = items.iter().map(|item| format!("--{key}={item}"));
+    let error = probe.try_get_matches_from(args).err()?;
+    // The first line states the problem; a later one may list the valid values.
+    let message = error.to_string();
+    let mut lines = message.lines().map(str::trim).filter(|l| !l.is_empty());
+    let mut problem = lines
+        .next()
+        .unwrap_or_default()
+        .trim_start_matches("error: ")
+        .to_string();
+    if let Some(valid) = lines.find(|l| l.starts_with("[possible values")) {
+        problem = format!("{problem} {valid}");
+    }
+    Some(format!("{key:?}: {problem}"))
+}
+
+/// Output format of `--print-config`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum ConfigFormat {
+    Toml,
+    Json,
+}
+
+/// Every setting `matches` resolved to, with the source it came from.
+///
+/// The TOML form can be passed back as a `--config` file.
+fn resolved_config(matches: &ArgMatches, from_config: &[String], format: ConfigFormat) -> String {
+    let command = command();
+    let mut entries = Vec::new();
+    for arg in command.get_arguments() {
+        let id = arg.get_id().as_str();
+        let (Some(key), false) = (
+            arg.get_long(),
+            matches!(
+                id,
+                "help" | "version" | "config" | "profile" | "print_config"
+            ),
+        ) else {
+            continue;
+        };
+        let Some(raw) = matches.get_raw(id) else {
+            continue;
+        };
+        let mut values: Vec<Value> = raw.map(|v| Value::infer(&v.to_string_lossy())).collect();
+        let value = match (arg.get_action(), values.len()) {
+            (ArgAction::Append, _) => Value::Array(values),
+            (_, 1) => values.remove(0),
+            _ => continue,
+        };
+        let source = if from_config.iter().any(|c| c == id) {
+            "config file"
+        } else {
+            match matches.value_source(id) {
+                Some(ValueSource::CommandLine) => "command line",
+                Some(ValueSource::EnvVariable) => "environment",
+                _ => "default",
+            }
+        };
+        entries.push((key, value, source));
+    }
+
+    match format {
+        ConfigFormat::Toml => entries
+            .iter()
+            .map(|(key, value, source)| format!("{key} = {}  # {source}\n", value.to_toml()))
+            .collect(),
+        ConfigFormat::Json => {
+            let fields: Vec<String> = entries
+                .iter()
+                .map(|(key, value, source)| {
+                    format!(
+                        "  \"{key}\": {{\"value\": {}, \"source\": \"{source}\"}}",
+                        value.to_json()
+                    )
+                })
+                .collect();
+            format!("{{\n{}\n}}\n", fields.join(",\n"))
+        }
+    }
+}
+
+/// Writes one CSV row per file with its duration, estimated BPM, and key.
+fn analyze(args: AnalyzeArgs) -> Result<()> {
+    let mut out: Box<dyn Write> = match &args.output {
+        Some(path) => Box::new(BufWriter::new(
+            fs::File::create(path).context("Failed to create CSV output")?,
+        )),
+        None => Box::new(io::stdout().lock()),
+    };
+    writeln!(
+        out,
+        "path,duration_s,bpm,key,f0_median_hz,f0_low_hz,f0_high_hz"
+    )?;
+    // Rows go out in path order, however the directories were walked.
+    let mut rows = Vec::new();
+    for root in &args.input_dir {
+        for path in walk::wav_files(root, None) {
+            let buffer = match decode_wav(&path) {
+                Ok(buffer) => {
+                    let mono = AudioFormat {
+                        channels: 1,
+                        ..buffer.format
+                    };
+                    buffer.convert(mono)?
+                }
+                Err(e) => {
+                    eprintln!("Error analyzing {:?}: {}", path, e);
+                    continue;
+                }
+            };
+            let rate = buffer.format.sample_rate;
+            let bpm = analysis::estimate_bpm(&buffer.samples, rate)
+                .map(|b| format!("{b:.1}"))
+                .unwrap_or_default();
+            let key = analysis::estimate_key(&buffer.samples, rate)
+                .map(|k| k.to_string())
+                .unwrap_or_default();
+            let pitch = analysis::estimate_pitch(&buffer.samples, rate)
+                .map(|p| format!("{:.1},{:.1},{:.1}", p.median_hz, p.low_hz, p.high_hz))
+                .unwrap_or_else(|| ",,".to_string());
+            let row = format!(
+                "{},{:.3},{bpm},{key},{pitch}",
+                csv_field(&path.display().to_string()),
+                buffer.duration_secs()
+            );
+            rows.push((path, row));
+        }
+    }
+    rows.sort();
+    for (_, row) in rows {
+        writeln!(out, "{row}")?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+/// Compares two run reports and fails on any regression.
+fn report_command(args: ReportArgs) -> Result<()> {
+    match args.command {
+        ReportCommand::Diff { old, new } => {
+            let comparison = compare::compare(&RunStats::load(&old)?, &RunStats::load(&new)?);
+            print!("{}", comparison.to_text());
+            let regressions = comparison.regressions.len();
+            anyhow::ensure!(
+                regressions == 0,
+                "{regressions} regression(s) from {:?} to {:?}",
+                old,
+                new
+            );
+            Ok(())
+        }
+    }
+}
+
+/// Runs every self-test case and prints how it compared; fails if any case
+/// is out of tolerance.
+fn self_test(args: SelfTestArgs) -> Result<()> {
+    if let Some(dir) = &args.bless {
+        fs::create_dir_all(dir).context("Failed to create golden directory")?;
+        for case in &selftest::CASES {
+            if let Some(engine) = case.missing_engine() {
+                println!("skip  {} ({engine} not compiled in)", case.name);
+                continue;
+            }
+            let path = dir.join(format!("{}.wav", case.name));
+            write_wav(&path, &case.render()?)?;
+            println!("wrote {}", path.display());
+        }
+        return Ok(());
+    }
+    println!(
+        "Tolerances: SNR >= {} dB, max error <= {}, spectral distance <= {} dB",
+        selftest::MIN_SNR_DB,
+        selftest::MAX_ERROR,
+        selftest::MAX_SPECTRAL_DB
+    );
+    let mut failed = 0;
+    for case in &selftest::CASES {
+        match case.run()? {
+            Outcome::Skipped(algorithm) => {
+                println!("skip  {} ({algorithm} not compiled in)", case.name)
+            }
+            Outcome::Compared(c) => {
+                let verdict = if c.passes() { "ok" } else { "FAIL" };
+                failed += usize::from(!c.passes());
+                println!(
+                    "{verdict:<5} {:<24} frames {}/{}  SNR {:.1} dB  max error {:.5}  spectral {:.2} dB",
+                    case.name, c.frames, c.golden_frames, c.snr_db, c.max_error, c.spectral_db
+                );
+            }
+        }
+    }
+    if failed > 0 {
+        anyhow::bail!(
+            "{failed} of {} self-test cases are out of tolerance",
+            selftest::CASES.len()
+        );
+    }
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    let cli = parse_cli()?;
+    match cli.command {
+        Some(Command::Check(args)) => check(args),
+        Some(Command::Analyze(args)) => analyze(args),
+        Some(Command::SelfTest(args)) => self_test(args),
+        Some(Command::Report(args)) => report_command(args),
+        None => match cli.process {
+            Some(args) => process(args),
+            None => Ok(Cli::command().print_help()?),
+        },
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum TempoSourceArg {
+    /// `--tempo-manifest` entries.
+    Manifest,
+    /// `--match-duration-of` references.
+    Reference,
+    /// `--tempo-from-name` tags.
+    Name,
+    /// `--tempo-from-dir` files.
+    Dir,
+    /// `--target-duration`.
+    Target,
+    /// `--target-bpm` detections.
+    Bpm,
+    /// `--equalize-rate` measurements.
+    SpeechRate,
+    /// `--tempo-range` draws.
+    Random,
+    /// `--tempo` alone.
+    Flag,
+}
+
+impl From<TempoSourceArg> for TempoSource {
+    fn from(arg: TempoSourceArg) -> Self {
+        match arg {
+            TempoSourceArg::Manifest => Self::Manifest,
+            TempoSourceArg::Reference => Self::Reference,
+            TempoSourceArg::Name => Self::Name,
+            TempoSourceArg::Dir => Self::Directory,
+            TempoSourceArg::Target => Self::Target,
+            TempoSourceArg::Bpm => Self::Bpm,
+            TempoSourceArg::SpeechRate => Self::SpeechRate,
+            TempoSourceArg::Random => Self::Random,
+            TempoSourceArg::Flag => Self::Flag,
+        }
+    }
+}
+
+/// An extra format `--also-output` writes each output in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AlsoOutput {
+    Wav,
+    W64,
+    Flac,
+}
+
+impl AlsoOutput {
+    fn extension(self) -> &'static str {
+        match self {
+            Self::Wav => "wav",
+            Self::W64 => "w64",
+            Self::Flac => "flac",
+        }
+    }
+
+    /// Writes `buffer` in this format beside `output`, unless that is
+    /// `output` itself.
+    fn write(self, output: &Path, buffer: &AudioBuffer) -> wav_files_tempo::Result<()> {
+        let path = output.with_extension(self.extension());
+        match self {
+            _ if path == output => Ok(()),
+            Self::Wav | Self::W64 => atomic::write(&path, |temp| write_wav(temp, buffer)),
+            Self::Flac => flac::write_flac(&path, buffer),
+        }
+    }
+}
+
+impl FromStr for AlsoOutput {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, String> {
+        let (name, bitrate) = match s.trim().split_once('@') {
+            Some((name, bitrate)) => (name, Some(bitrate)),
+            None => (s.trim(), None),
+        };
+        let format = match name.to_ascii_lowercase().as_str() {
+            "wav" => Self::Wav,
+            "w64" => Self::W64,
+            "flac" => Self::Flac,
+            // Not yet: no Opus or MP3 encoder is among the dependencies.
+            "opus" | "mp3" | "aac" | "ogg" | "vorbis" => {
+                return Err(format!(
+                    "{name} is not supported yet, as no lossy encoder is built in \
+                     (available: wav, w64, flac); encode a WAV output with ffmpeg instead"
+                ));
+            }
+            _ => {
+                return Err(format!(
+                    "unknown format {name:?} (expected wav, w64, or flac)"
+                ));
+            }
+        };
+        match bitrate {
+            Some(_) => Err(format!("{name} is lossless and takes no @bitrate")),
+            None => Ok(format),
+        }
+    }
+}
+
+/// What `--route` does with the inputs of one extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RouteAction {
+    /// Stretch, writing the output in this format or else the input's own.
+    Stretch(Option<AlsoOutput>),
+    /// Copy the file to the output tree as it is.
+    Copy,
+    Skip,
+}
+
+/// One `--route EXT=ACTION` rule.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Route {
+    extension: String,
+    action: RouteAction,
+}
+
+impl FromStr for Route {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, String> {
+        let (extension, action) = s
+            .split_once('=')
+            .ok_or_else(|| format!("expected EXT=ACTION, e.g. txt=copy, found {s:?}"))?;
+        let extension = extension.trim().trim_start_matches('.').to_string();
+        if extension.is_empty() {
+            return Err(format!("no extension in {s:?}"));
+        }
+        let action = match action.trim().split_once(':') {
+            None if action.trim() == "stretch" => RouteAction::Stretch(None),
+            None if action.trim() == "copy" => RouteAction::Copy,
+            None if action.trim() == "skip" => RouteAction::Skip,
+            Some(("stretch", format)) => RouteAction::Stretch(Some(format.parse()?)),
+            _ => {
+                return Err(format!(
+                    "unknown action {action:?} (expected stretch[:FORMAT], copy, or skip)"
+                ));
+            }
+        };
+        if let RouteAction::Stretch(_) = action
+            && !walk::AUDIO_EXTENSIONS
+                .iter()
+                .any(|e| e.eq_ignore_ascii_case(&extension))
+        {
+            return Err(format!(
+                "{extension} files can't be stretched: only wav and w64 inputs are decoded"
+            ));
+        }
+        Ok(Self { extension, action })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum OutputSpec {
+    /// Keep each input's rate, channels, and sample type.
+    Mirror,
+    /// Write every output in the default format.
+    Conform,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum AlgorithmArg {
+    /// Signalsmith Stretch.
+    Ssstretch,
+    /// Built-in phase vocoder.
+    Pv,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum PathStyleArg {
+    /// As the platform spells them.
+    Native,
+    /// With `/` separators, even on Windows.
+    Posix,
+}
+
+impl From<PathStyleArg> for PathStyle {
+    fn from(style: PathStyleArg) -> Self {
+        match style {
+            PathStyleArg::Native => Self::Native,
+            PathStyleArg::Posix => Self::Posix,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum LogFormatArg {
+    /// Messages for people, on standard error.
+    Text,
+    /// NDJSON events on standard output, besides the messages.
+    Json,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum ModeArg {
+    /// Time-stretch, keeping the pitch.
+    Stretch,
+    /// Resample, so the pitch follows the tempo.
+    Vinyl,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum OverflowArg {
+    /// Stop polling until the queue has room.
+    Block,
+    /// Drop the oldest queued file.
+    DropOldest,
+    /// Append to watch-queue.tsv in the output directory.
+    Spill,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum PresetArg {
+    /// Signalsmith's default preset, tuned for quality.
+    Default,
+    /// Smaller blocks and intervals: faster, slightly lower quality.
+    Cheaper,
+}
+
+impl Default for AlgorithmArg {
+    fn default() -> Self {
+        match Algorithm::default() {
+            Algorithm::PhaseVocoder { .. } => Self::Pv,
+            _ => Self::Ssstretch,
+        }
+    }
+}
+
+/// The tempo given for the whole batch, as `--tempo` or a BPM pair.
+fn batch_tempo(args: &Args) -> f32 {
+    bpm_ratio(args).unwrap_or(args.tempo[0])
+}
+
+/// The multiplier `--from-bpm` and `--to-bpm` ask for, if given.
+fn bpm_ratio(args: &Args) -> Option<f32> {
+    args.from_bpm.zip(args.to_bpm).map(|(from, to)| to / from)
+}
+
+/// Builds the library options from the command line.
+fn process_options(args: &Args) -> Result<ProcessOptions> {
+    let noise = if !args.denoise {
+        None
+    } else if let Some(path) = &args.noise_sample {
+        let sample = read_wav(path).context("Failed to read noise sample")?;
+        Some(NoiseSource::Spectrum(denoise::noise_spectrum(
+            &sample.samples,
+        )))
+    } else {
+        Some(NoiseSource::Leading(args.noise_profile_ms))
+    };
+    let metadata = if args.preserve_metadata {
+        MetadataPolicy::Preserve
+    } else {
+        MetadataPolicy::Drop
+    };
+
+    let mut limits = DecodeLimits {
+        max_duration: args.max_input_duration,
+        ..DecodeLimits::default()
+    };
+    if let Some(max) = args.max_input_size {
+        limits.max_bytes = max;
+    }
+
+    let sample_type = match (args.bits, args.float) {
+        (bits, true) => Some(SampleType::Float(bits.unwrap_or(32))),
+        (Some(bits), false) => Some(SampleType::Int(bits)),
+        (None, false) => None,
+    };
+    let mut output = OutputFormat {
+        sample_rate: args.rate,
+        channels: args.channels,
+        sample_type,
+    };
+    if args.output_spec == OutputSpec::Conform {
+        output = output.conform_to(AudioFormat::default());
+    }
+
+    let preset = match args.preset {
+        PresetArg::Default => Quality::Default,
+        PresetArg::Cheaper => Quality::Cheaper,
+    };
+    let quality = match (args.block_ms, args.interval_ms) {
+        (None, None) => preset,
+        (block_ms, interval_ms) => {
+            let (block, interval) = preset.millis();
+            Quality::Custom {
+                block_ms: block_ms.unwrap_or(block),
+                interval_ms: interval_ms.unwrap_or(interval),
+            }
+        }
+    };
+
+    let algorithm = match args.algorithm {
+        AlgorithmArg::Ssstretch => Algorithm::Signalsmith,
+        AlgorithmArg::Pv => Algorithm::PhaseVocoder {
+            fft_size: args.fft_size,
+            overlap: args.overlap,
+        },
+    };
+
+    Ok(ProcessOptions::builder()
+        .tempo(args.tempo_start.unwrap_or(batch_tempo(args)))
+        .tempo_end(args.tempo_end)
+        .tempo_map(args.tempo_map.as_deref().map(TempoMap::load).transpose()?)
+        .pitch_semitones(args.pitch_semitones + args.pitch_cents / 100.0)
+        .mode(match args.mode {
+            ModeArg::Stretch => Mode::Stretch,
+            ModeArg::Vinyl => Mode::Vinyl,
+        })
+        .syllable_aware(args.syllable_aware)
+        .algorithm(algorithm)
+        .band_split(args.band_split)
+        .quality(quality)
+        .declip(args.declip)
+        .declick(args.declick)
+        .denoise(noise)
+        .normalization(args.normalize.unwrap_or_default())
+        .metadata(metadata)
+        .limits(limits)
+        .required_rate(args.require_rate)
+        .output(output)
+        .click_overlay(args.overlay_click)
+        .verify_output(args.verify_output)
+        .header_strict(args.header_strict)
+        .build()?)
+}
+
+/// A warning if `output`'s median F0 moved away from `before` by more than
+/// `tolerance` cents beyond the intended shift.
+fn check_pitch(
+    before: analysis::PitchStats,
+    output: &AudioBuffer,
+    shift_semitones: f32,
+    tolerance: f32,
+) -> Option<String> {
+    let Some(after) = analysis::estimate_pitch(&output.mono(), output.format.sample_rate) else {
+        return Some("no voiced frames left to check pitch against".to_string());
+    };
+    let moved = 1200.0 * (after.median_hz / before.median_hz).log2();
+    let expected = shift_semitones * 100.0;
+    ((moved - expected).abs() > tolerance).then(|| {
+        format!(
+            "median F0 moved {moved:+.0} cents ({:.1} Hz -> {:.1} Hz), expected {expected:+.0}",
+            before.median_hz, after.median_hz
+        )
+    })
+}
 
-    /// Output directory for processed files (preserves relative paths).
-    #[arg(short = 'o', long)]
-    output_dir: PathBuf,
+/// Gain in dB that brings each input directory's combined integrated loudness
+/// to `target` LUFS. Directories that are silent or unreadable get no entry.
+fn directory_gains(
+    jobs: &[walk::Job],
+    target: f32,
+    limits: &DecodeLimits,
+) -> HashMap<PathBuf, f32> {
+    let mut powers: HashMap<PathBuf, Vec<f64>> = HashMap::new();
+    for job in jobs {
+        let Some(dir) = job.input.parent() else {
+            continue;
+        };
+        // Unreadable files are reported when the pipeline reaches them.
+        let Ok(buffer) = read_wav_within(&job.input, limits) else {
+            continue;
+        };
+        let rate = buffer.format.sample_rate * buffer.format.channels.max(1) as u32;
+        powers
+            .entry(dir.to_path_buf())
+            .or_default()
+            .extend(loudness::block_powers(&buffer.samples, rate));
+    }
+    powers
+        .into_iter()
+        .filter_map(|(dir, powers)| {
+            let lufs = loudness::gated_loudness(&powers)?;
+            Some((dir, target - lufs as f32))
+        })
+        .collect()
+}
 
-    /// Tempo multiplier (e.g., 1.2 for 120% speed; default 1.0 = no change).
-    #[arg(short = 't', long, default_value_t = 1.0)]
-    tempo: f32,
+/// Tempo per input that brings its speech rate to the median over all
+/// inputs, scaled by `tempo`. Files without measurable speech get no entry
+/// and keep the plain `--tempo`.
+fn speech_tempos(jobs: &[walk::Job], tempo: f32, limits: &DecodeLimits) -> HashMap<PathBuf, f32> {
+    let rates: Vec<(PathBuf, f32)> = jobs
+        .iter()
+        .filter_map(|job| {
+            // Unreadable files are reported when the pipeline reaches them.
+            let buffer = read_wav_within(&job.input, limits).ok()?;
+            let rate = analysis::speech_rate(&buffer.mono(), buffer.format.sample_rate)?;
+            Some((job.input.clone(), rate))
+        })
+        .collect();
+    let mut sorted: Vec<f32> = rates.iter().map(|(_, rate)| *rate).collect();
+    sorted.sort_by(f32::total_cmp);
+    let Some(&median) = sorted.get(sorted.len() / 2) else {
+        return HashMap::new();
+    };
+    rates
+        .into_iter()
+        .map(|(input, rate)| (input, tempo * median / rate))
+        .collect()
+}
+
+/// The tempo matching each input to its counterpart under `dir`, for inputs
+/// that have one. Every pair whose formats can't be compared is listed
+/// before anything is processed.
+fn reference_tempos(
+    args: &Args,
+    jobs: &[walk::Job],
+    dir: &Path,
+    limits: &DecodeLimits,
+) -> Result<HashMap<PathBuf, f32>> {
+    let mut tempos = HashMap::new();
+    let mut problems = Vec::new();
+    for job in jobs {
+        let relative = args
+            .input_dir
+            .iter()
+            .find_map(|root| job.input.strip_prefix(root).ok());
+        let Some(reference) = relative.map(|relative| dir.join(relative)) else {
+            continue;
+        };
+        if !reference.is_file() {
+            continue;
+        }
+        match tempo::match_duration(&job.input, &reference, limits) {
+            Ok(tempo) => {
+                tempos.insert(job.input.clone(), tempo);
+            }
+            Err(e) => problems.push(format!("{}: {e}", job.input.display())),
+        }
+    }
+    if !problems.is_empty() {
+        anyhow::bail!(
+            "--match-duration-of cannot pair these files:\n  - {}",
+            problems.join("\n  - ")
+        );
+    }
+    Ok(tempos)
+}
+
+/// Each file's own tempo and its source, from the first source in
+/// [`TempoSource::PRECEDENCE`] that covers it (or only `--tempo-source`).
+/// Files without an entry keep the batch's `--tempo`.
+fn file_tempos(
+    args: &Args,
+    jobs: &[walk::Job],
+    entries: &HashMap<PathBuf, Entry>,
+    limits: &DecodeLimits,
+) -> Result<HashMap<PathBuf, (f32, TempoSource)>> {
+    let forced = args.tempo_source.map(TempoSource::from);
+    let enabled = match forced {
+        Some(TempoSource::Manifest) => args.tempo_manifest.is_some(),
+        Some(TempoSource::Reference) => args.match_duration_of.is_some(),
+        Some(TempoSource::Name) => args.tempo_from_name,
+        Some(TempoSource::Directory) => args.tempo_from_dir,
+        Some(TempoSource::Target) => args.target_duration.is_some(),
+        Some(TempoSource::Bpm) => args.target_bpm.is_some(),
+        Some(TempoSource::SpeechRate) => args.equalize_rate,
+        Some(TempoSource::Random) => args.tempo_range.is_some(),
+        Some(TempoSource::Flag) | None => true,
+    };
+    if let Some(source) = forced {
+        anyhow::ensure!(
+            enabled,
+            "--tempo-source {} names a source that is not enabled",
+            source.name()
+        );
+    }
+    let wanted = |source| forced.is_none_or(|f| f == source);
+    let references = match &args.match_duration_of {
+        Some(dir) if wanted(TempoSource::Reference) => reference_tempos(args, jobs, dir, limits)?,
+        _ => HashMap::new(),
+    };
+    let speech = if args.equalize_rate && wanted(TempoSource::SpeechRate) {
+        speech_tempos(jobs, batch_tempo(args), limits)
+    } else {
+        HashMap::new()
+    };
+    let mut tempos = HashMap::new();
+    for job in jobs {
+        let root = args
+            .input_dir
+            .iter()
+            .find(|root| job.input.starts_with(root));
+        let picked = tempo::pick(forced, |source| match source {
+            TempoSource::Manifest => Ok(entries.get(&job.input).and_then(|entry| entry.tempo)),
+            TempoSource::Reference => Ok(references.get(&job.input).copied()),
+            TempoSource::Name if args.tempo_from_name => Ok(tempo::from_name(&job.input)),
+            TempoSource::Directory if args.tempo_from_dir => match root {
+                Some(root) => tempo::from_directory(&job.input, root),
+                None => Ok(None),
+            },
+            // Unreadable files are reported when the pipeline reaches them.
+            TempoSource::Target => Ok(args
+                .target_duration
+                .and_then(|secs| tempo::for_duration(&job.input, secs, limits).ok().flatten())),
+            TempoSource::Bpm => Ok(args
+                .target_bpm
+                .and_then(|bpm| tempo::for_bpm(&job.input, bpm, limits).ok().flatten())),
+            TempoSource::SpeechRate => Ok(speech.get(&job.input).copied()),
+            TempoSource::Random => Ok(args.tempo_range.zip(args.seed).map(|(range, seed)| {
+                let key = root.and_then(|root| job.input.strip_prefix(root).ok());
+                tempo::jitter(range, seed, key.unwrap_or(&job.input))
+            })),
+            _ => Ok(None),
+        })?;
+        if let Some(picked) = picked {
+            tempos.insert(job.input.clone(), picked);
+        }
+    }
+    Ok(tempos)
 }
 
-/// Stretches audio samples by the inverse tempo factor without pitch shift.
-fn stretch_samples(input: &[f32], sample_rate: u32, tempo: f32) -> Vec<f32> {
-    if tempo == 1.0 {
-        return input.to_vec();
+/// Runs one stage of a file before its `--file-timeout` deadline, if any.
+fn timed<T: Send + 'static>(
+    deadline: Option<(Instant, Duration)>,
+    work: impl FnOnce() -> wav_files_tempo::Result<T> + Send + 'static,
+) -> wav_files_tempo::Result<T> {
+    match deadline {
+        Some((at, limit)) => {
+            pipeline::with_deadline(Some(at), work).unwrap_or(Err(Error::Timeout(limit)))
+        }
+        None => work(),
     }
+}
 
-    let stretch_ratio = 1.0 / tempo;
-    let input_len = input.len();
-    let output_len = (input_len as f32 * stretch_ratio) as usize;
+/// Name of the `--progress-interval` snapshot inside the output directory.
+const PROGRESS_FILE: &str = "progress.json";
 
-    let mut output = vec![0.0f32; output_len];
+/// How often the terminal progress line is redrawn.
+const BAR_INTERVAL: Duration = Duration::from_millis(250);
 
-    let mut stretch = ssstretch::Stretch::new();
-    stretch.preset_default(1, sample_rate as f32);
+/// How often `--log-format json` prints a `progress` event, unless
+/// --progress-interval says otherwise.
+const EVENT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Processes every WAV file under the input roots into the output directory.
+fn process(mut args: Args) -> Result<()> {
+    let single = match args.input_dir.as_slice() {
+        [input] => is_stdio(input) || input.is_file(),
+        _ => false,
+    };
+    if !single && is_stdio(&args.output_dir) {
+        anyhow::bail!("-o - writes to standard output, which takes a single input file");
+    }
+    if single {
+        anyhow::ensure!(!args.watch, "--watch needs an input directory");
+        anyhow::ensure!(
+            args.retry_failed.is_none(),
+            "--retry-failed takes its inputs from the report, not a single file"
+        );
+        anyhow::ensure!(
+            !(is_stdio(&args.output_dir) && args.tempo.len() > 1),
+            "Several --tempo factors give several outputs, which standard output can't hold"
+        );
+        anyhow::ensure!(
+            !(is_stdio(&args.output_dir) && args.log_format == LogFormatArg::Json),
+            "--log-format json writes events to standard output, which -o - takes"
+        );
+    } else {
+        // Ensure output dir exists.
+        fs::create_dir_all(&args.output_dir).context("Failed to create output directory")?;
+    }
 
-    // For mono: single-channel buffers.
-    let input_ptr: *const f32 = input.as_ptr();
-    let output_ptr: *mut f32 = output.as_mut_ptr();
+    for bpm in [args.from_bpm, args.to_bpm].into_iter().flatten() {
+        anyhow::ensure!(
+            bpm.is_finite() && bpm > 0.0,
+            "--from-bpm and --to-bpm must be positive, found {bpm}"
+        );
+    }
+    let run = Run::start();
+    let mut options = process_options(&args)?;
+    options.run_id = Some(run.id.clone());
+    let options = Arc::new(options);
 
-    // Process the entire signal in one block (efficient for typical file sizes).
-    // Assumes ssstretch API mirrors C++: process with buffers and lengths.
-    unsafe {
-        stretch.process(
-            &[input_ptr],
-            input_len as i32,
-            &mut [output_ptr],
-            output_len as i32,
+    if let Some(factor) = args.max_realtime_factor {
+        anyhow::ensure!(factor > 0.0, "--max-realtime-factor must be positive");
+    }
+    if args.stream
+        && let Some(what) = stream::unsupported(&options)
+    {
+        anyhow::bail!("--stream does not support {what}");
+    }
+    if args.stream
+        && let Some(rule) = args.route.iter().find(
+            |rule| matches!(rule.action, RouteAction::Stretch(Some(f)) if f != AlsoOutput::Wav),
         )
+    {
+        anyhow::bail!(
+            "--stream writes WAV only, but --route routes {} elsewhere",
+            rule.extension
+        );
+    }
+    if let Some(secs) = args.target_duration {
+        anyhow::ensure!(secs > 0.0, "--target-duration must be positive");
+    }
+    if let Some(bpm) = args.target_bpm {
+        anyhow::ensure!(
+            bpm.is_finite() && bpm > 0.0,
+            "--target-bpm must be positive, found {bpm}"
+        );
+    }
+    if let Some(threshold) = args.artifact_threshold {
+        anyhow::ensure!(
+            threshold.is_finite() && threshold >= 0.0,
+            "--artifact-threshold must be a non-negative number"
+        );
+    }
+    if args.speed_album {
+        args.tempo = album::TEMPOS.to_vec();
+    }
+    if args.tempo.len() > 1 {
+        check_variants(&args)?;
+    }
+    if args.tempo_range.is_some() && args.seed.is_none() {
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_nanos() as u64);
+        eprintln!("Drawing --tempo-range tempos with --seed {seed}");
+        args.seed = Some(seed);
+    }
+    if single {
+        return process_single(args, &options, &run, &mut io::stdin(), &mut io::stdout());
+    }
+    if args.watch {
+        return watch(&args, &options, &run);
+    }
+
+    // Recursively collect WAV files, preserving structure.
+    let (roots, output_dir) = (&args.input_dir, &args.output_dir);
+    let extensions = routed_extensions(&args);
+    let filter = input_filter(&args);
+    let inputs = if let Some(path) = &args.retry_failed {
+        let mut jobs = Vec::new();
+        for (input, output) in report::load_failed(path)? {
+            if let Some(parent) = output.parent() {
+                fs::create_dir_all(parent).context("Failed to create output subdir")?;
+            }
+            jobs.push(walk::Job { input, output });
+        }
+        let plural = if jobs.len() == 1 { "" } else { "s" };
+        eprintln!(
+            "Retrying {} failed file{plural} from {:?}",
+            jobs.len(),
+            path
+        );
+        Inputs::Listed(jobs)
+    } else if plans_batch(&args) {
+        Inputs::Listed(walk::collect(
+            roots,
+            output_dir,
+            args.merge_roots,
+            args.walk_threads,
+            &extensions,
+            &filter,
+            traversal(&args),
+        )?)
+    } else {
+        Inputs::Found(walk::discover(
+            roots,
+            output_dir,
+            args.merge_roots,
+            args.walk_threads,
+            &extensions,
+            &filter,
+            traversal(&args),
+        )?)
     };
+    let report = batch(&args, &options, &run, inputs, None)?;
+    if let Some(target) = args.pack_to {
+        pack_outputs(&args, &options, &report, target)?;
+    }
+    let failed = report.summary().failed;
+    anyhow::ensure!(
+        failed == 0,
+        "{failed} file{} failed",
+        if failed == 1 { "" } else { "s" }
+    );
+    Ok(())
+}
+
+/// Name of the `--pack-to` mapping inside the output directory.
+const PACK_FILE: &str = "pack.csv";
+
+/// Joins the batch's WAV outputs into packs of up to `target` seconds,
+/// replacing the files packed, and maps each input to its place in a pack.
+fn pack_outputs(args: &Args, options: &ProcessOptions, report: &Report, target: f64) -> Result<()> {
+    let written = report
+        .files
+        .iter()
+        .filter(|f| f.error.is_none() && f.skipped.is_none() && !writes_flac(&f.output));
+    let mut inputs = Vec::new();
+    let mut pieces = Vec::new();
+    for file in written {
+        let buffer = read_wav(&file.output)
+            .with_context(|| format!("Failed to read {:?} for packing", file.output))?;
+        inputs.push(&file.input);
+        pieces.push(pack::Piece {
+            path: file.output.clone(),
+            format: buffer.format,
+            secs: buffer.duration_secs(),
+        });
+    }
+    // Packs carry the run's ID but no one input's metadata.
+    let mut options = options.clone();
+    options.metadata = MetadataPolicy::Drop;
+    let mut numbers: HashMap<PathBuf, usize> = HashMap::new();
+    let mut csv = String::from("pack,input,start_s,end_s\n");
+    for indices in pack::plan(&pieces, target, args.pack_gap) {
+        let buffers = indices
+            .iter()
+            .map(|&i| read_wav(&pieces[i].path))
+            .collect::<wav_files_tempo::Result<Vec<_>>>()?;
+        let (joined, starts) = pack::join(&buffers, args.pack_gap);
+        let dir = pieces[indices[0]].path.parent().unwrap_or(Path::new(""));
+        let number = numbers.entry(dir.to_path_buf()).or_default();
+        *number += 1;
+        let path = pack::pack_path(dir, *number);
+        write_output(inputs[indices[0]], &path, &joined, &options)?;
+        let rate = f64::from(joined.format.sample_rate);
+        let name = path.strip_prefix(&args.output_dir).unwrap_or(&path);
+        for ((&i, start), buffer) in indices.iter().zip(starts).zip(&buffers) {
+            csv.push_str(&format!(
+                "{},{},{:.3},{:.3}\n",
+                csv_field(&paths::to_text(name, args.path_style.into())),
+                csv_field(&paths::to_text(inputs[i], args.path_style.into())),
+                start as f64 / rate,
+                (start + buffer.frames()) as f64 / rate
+            ));
+            if pieces[i].path != path {
+                fs::remove_file(&pieces[i].path)
+                    .with_context(|| format!("Failed to remove packed {:?}", pieces[i].path))?;
+            }
+        }
+    }
+    fs::write(args.output_dir.join(PACK_FILE), csv).context("Failed to write pack mapping")?;
+    Ok(())
+}
+
+/// Whether a path names standard input or output.
+fn is_stdio(path: &Path) -> bool {
+    path.as_os_str() == "-"
+}
+
+/// Processes one input file into one output file, either of which may be
+/// `-`. Pipes are staged through a temporary directory, since decoding
+/// needs the whole input and writing seeks back to patch the header; `-`
+/// reads `stdin` and writes `stdout`.
+fn process_single(
+    mut args: Args,
+    options: &Arc<ProcessOptions>,
+    run: &Run,
+    stdin: &mut dyn io::Read,
+    stdout: &mut dyn Write,
+) -> Result<()> {
+    let staging = std::env::temp_dir().join(format!("wav-files-tempo-{}", run.id));
+    let staged = (|| {
+        let mut input = args.input_dir[0].clone();
+        if is_stdio(&input) {
+            let mut bytes = Vec::new();
+            stdin
+                .read_to_end(&mut bytes)
+                .context("Failed to read standard input")?;
+            // Wave64 files open with a lowercase `riff` GUID.
+            let name = if bytes.starts_with(b"riff") {
+                "stdin.w64"
+            } else {
+                "stdin.wav"
+            };
+            fs::create_dir_all(&staging).context("Failed to create staging directory")?;
+            input = staging.join(name);
+            fs::write(&input, bytes).context("Failed to stage standard input")?;
+        }
+        let to_stdout = is_stdio(&args.output_dir);
+        let output = if to_stdout {
+            fs::create_dir_all(&staging).context("Failed to create staging directory")?;
+            staging.join("stdout.wav")
+        } else {
+            args.output_dir.clone()
+        };
+        // Anything the batch writes beside its outputs goes beside this one.
+        args.output_dir = output.parent().map_or_else(PathBuf::new, Path::to_path_buf);
+        if !args.output_dir.as_os_str().is_empty() {
+            fs::create_dir_all(&args.output_dir).context("Failed to create output directory")?;
+        }
+        let job = walk::Job { input, output };
+        let report = batch(&args, options, run, Inputs::Listed(vec![job.clone()]), None)?;
+        if let Some(failed) = report.files.iter().find_map(|f| f.error.as_ref()) {
+            anyhow::bail!("Failed to process {:?}: {failed}", args.input_dir[0]);
+        }
+        if to_stdout {
+            let mut file = fs::File::open(&job.output).context("Failed to reopen output")?;
+            io::copy(&mut file, stdout).context("Failed to write standard output")?;
+            stdout.flush()?;
+        }
+        Ok(())
+    })();
+    if staging.exists() {
+        fs::remove_dir_all(&staging).context("Failed to remove staging directory")?;
+    }
+    staged
+}
 
-    output
+/// Whether anything must look at every input before the first is processed,
+/// so the walk has to finish first.
+fn plans_batch(args: &Args) -> bool {
+    args.normalize_per_dir.is_some()
+        || args.equalize_rate
+        || args.match_duration_of.is_some()
+        || args.tempo_manifest.is_some()
+        || args.tempo_from_name
+        || args.tempo_from_dir
+        || args.tempo_range.is_some()
+        || args.target_duration.is_some()
+        || args.target_bpm.is_some()
+        || args.content_dedupe
 }
 
-/// Processes a single WAV file: reads, stretches, and writes to output path.
-fn process_file(input_path: &Path, output_path: &Path, tempo: f32) -> Result<()> {
-    let mut reader = WavReader::open(input_path).context("Failed to open input WAV")?;
-    let spec = reader.spec();
+/// The inputs of a batch: all listed before it starts, or still arriving
+/// from the walk.
+enum Inputs {
+    Listed(Vec<walk::Job>),
+    Found(Receiver<wav_files_tempo::Result<walk::Job>>),
+}
 
-    // Validate format as per user spec.
-    if spec.channels != 1
-        || spec.sample_rate != 16000
-        || spec.bits_per_sample != 16
-        || spec.sample_format != SampleFormat::Int
+/// The `--route` action for files with `extension`: the last rule naming it,
+/// or else stretching for `--extensions`, and copying the rest with
+/// `--copy-others` or skipping them without.
+fn route(args: &Args, extension: &str) -> RouteAction {
+    match args
+        .route
+        .iter()
+        .rev()
+        .find(|rule| rule.extension.eq_ignore_ascii_case(extension))
     {
-        anyhow::bail!("Unsupported format: expected mono 16-bit PCM at 16000 Hz");
+        Some(rule) => rule.action,
+        None if args
+            .extensions
+            .iter()
+            .any(|e| e.eq_ignore_ascii_case(extension)) =>
+        {
+            RouteAction::Stretch(None)
+        }
+        None if args.copy_others => RouteAction::Copy,
+        None => RouteAction::Skip,
+    }
+}
+
+/// The action for `path`, by [`route`]: the last `--route` rule naming its
+/// extension wins, matched in any case.
+fn route_of(args: &Args, path: &Path) -> RouteAction {
+    route(
+        args,
+        path.extension().and_then(|e| e.to_str()).unwrap_or(""),
+    )
+}
+
+/// Copies a file routed with `copy` (or taken by --copy-others) to its output
+/// path, atomically and under the batch's [`atomic::Claim`].
+fn copy_routed(input: &Path, output: &Path) -> wav_files_tempo::Result<()> {
+    atomic::write(output, |temp| {
+        fs::copy(input, temp).map(drop).map_err(|source| Error::Io {
+            context: "Failed to copy routed file".to_string(),
+            source,
+        })
+    })
+}
+
+/// How deep the walk goes, from --max-depth, --no-recursive, and
+/// --follow-symlinks.
+fn traversal(args: &Args) -> walk::Traversal {
+    walk::Traversal {
+        max_depth: match args.max_depth {
+            _ if args.no_recursive => Some(1),
+            depth => depth.map(|depth| depth as usize),
+        },
+        follow_symlinks: args.follow_symlinks,
     }
+}
 
-    // Read and normalize to f32 [-1.0, 1.0].
-    let samples: Result<Vec<i16>> = reader
-        .samples::<i16>()
-        .map(|res| res.context("Invalid sample"))
-        .collect::<Result<Vec<i16>>>();
-    let input_samples: Vec<f32> = samples?.iter().map(|&s| s as f32 / 32768.0).collect();
+/// The --include and --exclude globs the walk applies.
+fn input_filter(args: &Args) -> Filter {
+    Filter {
+        include: args.include.clone(),
+        exclude: args.exclude.clone(),
+    }
+}
 
-    // Stretch samples.
-    let output_samples = stretch_samples(&input_samples, spec.sample_rate, tempo);
+/// An `--extensions` entry, without any leading dot.
+fn parse_extension(text: &str) -> std::result::Result<String, String> {
+    let extension = text.trim().trim_start_matches('.');
+    if extension.is_empty() {
+        return Err(format!("no extension in {text:?}"));
+    }
+    Ok(extension.to_string())
+}
 
-    // Denormalize to i16.
-    let output_i16: Vec<i16> = output_samples
+/// The extensions the walk picks up: every one not routed to skip.
+fn routed_extensions(args: &Args) -> Vec<&str> {
+    let rules = args.route.iter().map(|rule| rule.extension.as_str());
+    args.extensions
         .iter()
-        .map(|&s| (s * 32767.0).clamp(-32768.0, 32767.0) as i16)
-        .collect();
+        .map(String::as_str)
+        .chain(rules)
+        .chain(args.copy_others.then_some(walk::ANY_EXTENSION))
+        .filter(|&extension| route(args, extension) != RouteAction::Skip)
+        .collect()
+}
+
+/// Whether `job` goes through the batch: with --copy-others the walk takes
+/// every file, including those routed to skip.
+fn kept(args: &Args, job: &walk::Job) -> bool {
+    route_of(args, &job.input) != RouteAction::Skip
+}
 
-    // Write output WAV (same spec, adjusted length).
-    let mut writer = WavWriter::create(output_path, spec).context("Failed to create output WAV")?;
-    for &sample in &output_i16 {
-        writer
-            .write_sample(sample)
-            .context("Failed to write sample")?;
+/// `job` with its output renamed for the format its route writes.
+fn routed(args: &Args, mut job: walk::Job) -> walk::Job {
+    if let RouteAction::Stretch(Some(format)) = route_of(args, &job.input) {
+        job.output.set_extension(format.extension());
     }
-    writer.finalize().context("Failed to finalize WAV")?;
+    job
+}
 
+/// Rejects what can't be combined with several `--tempo` factors.
+fn check_variants(args: &Args) -> Result<()> {
+    let conflicts = [
+        (args.tempo_end.is_some(), "--tempo-end"),
+        (args.tempo_map.is_some(), "--tempo-map"),
+        (args.tempo_manifest.is_some(), "--tempo-manifest"),
+        (args.match_duration_of.is_some(), "--match-duration-of"),
+        (args.tempo_from_name, "--tempo-from-name"),
+        (args.tempo_from_dir, "--tempo-from-dir"),
+        (args.equalize_rate, "--equalize-rate"),
+        (args.tempo_range.is_some(), "--tempo-range"),
+        (args.target_duration.is_some(), "--target-duration"),
+        (args.target_bpm.is_some(), "--target-bpm"),
+        (args.stream, "--stream"),
+        (args.watch, "--watch"),
+    ];
+    if let Some((_, flag)) = conflicts.iter().find(|(set, _)| *set) {
+        let what = if args.speed_album {
+            "--speed-album"
+        } else {
+            "Several --tempo factors"
+        };
+        anyhow::bail!("{what} cannot be combined with {flag}");
+    }
+    anyhow::ensure!(
+        args.speed_album || args.tempo_suffix.contains("{tempo}"),
+        "--tempo-suffix must contain {{tempo}} so each factor gets its own output"
+    );
+    let mut seen = Vec::new();
+    for &tempo in &args.tempo {
+        anyhow::ensure!(
+            !seen.contains(&tempo),
+            "--tempo lists {tempo} more than once"
+        );
+        seen.push(tempo);
+    }
     Ok(())
 }
 
-fn main() -> Result<()> {
-    let args = Args::parse();
+/// Each output written for `job`, with the tempo it's written at: one per
+/// `--tempo` factor or album chapter, or just the job's own output at its own
+/// tempo.
+fn variants(args: &Args, job: &walk::Job) -> Vec<(PathBuf, Option<f32>)> {
+    if args.tempo.len() == 1 {
+        return vec![(job.output.clone(), None)];
+    }
+    if args.speed_album {
+        return (args.tempo.iter().enumerate())
+            .map(|(k, &tempo)| (album::chapter_path(&job.output, k + 1, tempo), Some(tempo)))
+            .collect();
+    }
+    args.tempo
+        .iter()
+        .map(|&tempo| {
+            let suffix = args.tempo_suffix.replace("{tempo}", &format!("{tempo:?}"));
+            (walk::with_suffix(&job.output, &suffix), Some(tempo))
+        })
+        .collect()
+}
 
-    // Ensure output dir exists.
-    fs::create_dir_all(&args.output_dir).context("Failed to create output directory")?;
+/// Name of the `--watch-overflow spill` list inside the output directory.
+const SPILL_FILE: &str = "watch-queue.tsv";
 
-    // Recursively process WAV files, preserving structure.
-    for entry in WalkDir::new(&args.input_dir)
-        .into_iter()
-        .filter_map(Result::ok)
-        .filter(|e| e.file_type().is_file() && e.path().extension() == Some("wav".as_ref()))
-    {
-        let rel_path = entry
-            .path()
-            .strip_prefix(&args.input_dir)
-            .map_err(|_| anyhow::anyhow!("Invalid relative path"))?;
-        let out_path = args.output_dir.join(rel_path);
-        fs::create_dir_all(out_path.parent().unwrap_or_else(|| Path::new(".")))
-            .context("Failed to create output subdir")?;
+/// Processes new and changed inputs as they appear, until interrupted. One
+/// thread polls into the queue while this one processes what it holds.
+fn watch(args: &Args, options: &Arc<ProcessOptions>, run: &Run) -> Result<()> {
+    let status = Arc::new(Status::new(run));
+    if let Some(addr) = &args.health_addr {
+        let bound = watch::serve(addr, Arc::clone(&status))?;
+        eprintln!("Serving status on http://{bound}/health");
+    }
+    let overflow = match args.watch_overflow {
+        OverflowArg::Block => Overflow::Block,
+        OverflowArg::DropOldest => Overflow::DropOldest,
+        OverflowArg::Spill => Overflow::Spill(args.output_dir.join(SPILL_FILE)),
+    };
+    let queue = JobQueue::new(args.watch_queue, overflow)?;
+    thread::scope(|s| {
+        s.spawn(|| {
+            let mut watcher = Watcher::new(args.watch_interval);
+            loop {
+                let (roots, output_dir) = (&args.input_dir, &args.output_dir);
+                let (extensions, filter) = (routed_extensions(args), input_filter(args));
+                let (merge, threads) = (args.merge_roots, args.walk_threads);
+                let depth = traversal(args);
+                match walk::collect(
+                    roots,
+                    output_dir,
+                    merge,
+                    threads,
+                    &extensions,
+                    &filter,
+                    depth,
+                ) {
+                    Ok(jobs) => {
+                        let (ready, settling) = watcher.poll(jobs);
+                        for job in ready {
+                            match queue.push(job) {
+                                Ok(Some(dropped)) => {
+                                    eprintln!(
+                                        "Warning: {:?}: dropped from the full watch queue",
+                                        dropped.input
+                                    );
+                                    status.drop_job(&dropped.input);
+                                }
+                                Ok(None) => {}
+                                Err(e) => eprintln!("Error: {e}"),
+                            }
+                        }
+                        status.set_pending(queue.len() + settling);
+                    }
+                    Err(e) => eprintln!("Error: {e}"),
+                }
+                thread::sleep(args.watch_interval);
+            }
+        });
+        loop {
+            match queue.take() {
+                Ok(jobs) => {
+                    let inputs = Inputs::Listed(jobs);
+                    if let Err(e) = batch(args, options, run, inputs, Some(&status)) {
+                        eprintln!("Error: {e:#}");
+                    }
+                }
+                Err(e) => {
+                    eprintln!("Error: {e}");
+                    thread::sleep(args.watch_interval);
+                }
+            }
+        }
+    })
+}
+
+/// Runs `jobs` through the pipeline and writes everything the batch produces
+/// besides the outputs, reporting each file to `status` in watch mode.
+fn batch(
+    args: &Args,
+    options: &Arc<ProcessOptions>,
+    run: &Run,
+    inputs: Inputs,
+    status: Option<&Status>,
+) -> Result<Report> {
+    let clock = Instant::now();
+    let (jobs, found) = match inputs {
+        Inputs::Listed(jobs) => (
+            jobs.into_iter()
+                .filter(|job| kept(args, job))
+                .map(|job| routed(args, job))
+                .collect(),
+            None,
+        ),
+        Inputs::Found(found) => (Vec::new(), Some(found)),
+    };
+    let gains = match args.normalize_per_dir {
+        Some(Normalization::Loudness(target)) => directory_gains(&jobs, target, &options.limits),
+        Some(_) => anyhow::bail!("--normalize-per-dir takes a LUFS target, e.g. -23LUFS"),
+        None => HashMap::new(),
+    };
+    let (mut jobs, entries) = manifest_entries(args, options, jobs)?;
+    let resume = if args.resume {
+        let path = args.output_dir.join(resume::STATE_FILE);
+        Some(Arc::new(State::load(&path, &resume_key(args, options)?)?))
+    } else {
+        None
+    };
+    let resumed = AtomicUsize::new(0);
+    let earlier = resume.clone();
+    let unfinished = |job: &walk::Job| {
+        let done = resumable(args, earlier.as_deref(), job);
+        if done {
+            resumed.fetch_add(1, Ordering::Relaxed);
+        }
+        !done
+    };
+    jobs.retain(&unfinished);
+    let tempos = file_tempos(args, &jobs, &entries, &options.limits)?;
+    let planned = Planned {
+        gains,
+        tempos,
+        entries,
+        resume,
+    };
+    let mut deduper = if args.content_dedupe {
+        let mut deduper = Deduper::load(&args.output_dir)?;
+        let outputs: Vec<PathBuf> = jobs
+            .iter()
+            .flat_map(|job| variants(args, job))
+            .map(|(output, _)| output)
+            .collect();
+        deduper.prepare(&outputs)?;
+        Some(deduper)
+    } else {
+        None
+    };
 
-        if let Err(e) = process_file(entry.path(), &out_path, args.tempo) {
-            eprintln!("Error processing {:?}: {}", entry.path(), e);
+    let progress = Arc::new(Progress::new(jobs.len(), &run.id));
+    // A failed walk stops handing over jobs; it fails the batch once the
+    // ones already found are done.
+    let walk_failure = Mutex::new(None);
+    let jobs: Box<dyn Iterator<Item = walk::Job> + Send + '_> = match found {
+        None => Box::new(jobs.into_iter()),
+        Some(found) => Box::new(
+            found
+                .into_iter()
+                .map_while(|job| match job {
+                    Ok(job) => Some(routed(args, job)),
+                    Err(e) => {
+                        *walk_failure.lock().unwrap_or_else(|e| e.into_inner()) = Some(e);
+                        None
+                    }
+                })
+                .filter(|job| kept(args, job))
+                .filter(unfinished)
+                .inspect(|_| progress.add(1)),
+        ),
+    };
+    let reviews = Mutex::new(Vec::new());
+    let progress_path = args.output_dir.join(PROGRESS_FILE);
+    let (stop_heartbeat, stopped) = channel::<()>();
+    let (stop_bar, bar_stopped) = channel::<()>();
+    let (stop_events, events_stopped) = channel::<()>();
+    let draw_bar = status.is_none() && !args.quiet && io::stderr().is_terminal();
+
+    let mut report = thread::scope(|s| {
+        if draw_bar {
+            let progress = &progress;
+            let width = std::env::var("COLUMNS")
+                .ok()
+                .and_then(|columns| columns.parse().ok())
+                .unwrap_or(80);
+            s.spawn(move || {
+                loop {
+                    let stop = bar_stopped.recv_timeout(BAR_INTERVAL);
+                    eprint!("\r\x1b[2K{}", progress.status_line(width));
+                    if stop != Err(RecvTimeoutError::Timeout) {
+                        eprintln!();
+                        break;
+                    }
+                }
+            });
+        }
+        if let Some(interval) = args.progress_interval {
+            let (progress, path) = (&progress, &progress_path);
+            s.spawn(move || {
+                loop {
+                    if let Err(e) = progress.write(path) {
+                        eprintln!("Warning: {e}");
+                    }
+                    if stopped.recv_timeout(interval) != Err(RecvTimeoutError::Timeout) {
+                        break;
+                    }
+                }
+            });
+        }
+        if args.log_format == LogFormatArg::Json {
+            let (progress, style) = (&progress, args.path_style.into());
+            let interval = args.progress_interval.unwrap_or(EVENT_INTERVAL);
+            s.spawn(move || {
+                while events_stopped.recv_timeout(interval) == Err(RecvTimeoutError::Timeout) {
+                    let mut stdout = io::stdout().lock();
+                    let event = progress.to_event(style);
+                    let _ = writeln!(stdout, "{event}").and_then(|()| stdout.flush());
+                }
+            });
+        }
+        let report = run_pipeline(args, jobs, options, &planned, &progress, &reviews, status);
+        drop(stop_heartbeat);
+        drop(stop_bar);
+        drop(stop_events);
+        report
+    });
+    if let Some(deduper) = &mut deduper {
+        let written = report
+            .files
+            .iter_mut()
+            .filter(|record| record.error.is_none() && record.skipped.is_none());
+        for record in written {
+            match deduper.add(&record.output) {
+                Ok(original) => record.duplicate_of = original,
+                Err(e) => {
+                    eprintln!("Warning: {:?}: not deduplicated: {e}", record.input);
+                    record.warnings.push(format!("not deduplicated: {e}"));
+                }
+            }
+        }
+        deduper.save()?;
+    }
+    if let Some(path) = &args.ab_review {
+        let mut reviews = reviews.into_inner().unwrap_or_else(|e| e.into_inner());
+        reviews.sort_by(|a, b| a.0.cmp(&b.0));
+        let pairs: Vec<ReviewPair> = reviews.into_iter().map(|(_, pair)| pair).collect();
+        review::write(path, &pairs)?;
+    }
+    let report = Report {
+        resources: Some(ResourceUsage::current()),
+        run: Some(run.clone()),
+        finished: Some(run::unix_now()),
+        path_style: args.path_style.into(),
+        ..report
+    };
+    if let Some(path) = &args.report {
+        report.write(path, options.tempo)?;
+    }
+    if args.emit_utt2dur.is_some() || args.emit_durations_json.is_some() {
+        let outputs = durations::outputs(&report);
+        if let Some(path) = &args.emit_utt2dur {
+            durations::write(path, &durations::utt2dur(&outputs, &args.output_dir))?;
+        }
+        if let Some(path) = &args.emit_durations_json {
+            durations::write(path, &durations::to_json(&outputs, report.path_style))?;
+        }
+    }
+    if status.is_none() && !args.quiet {
+        let resumed = resumed.into_inner();
+        if resumed > 0 {
+            let plural = if resumed == 1 { "" } else { "s" };
+            eprintln!("Skipped {resumed} input{plural} already done by an earlier run (--resume)");
         }
+        eprintln!("{}", report.summary().line(clock.elapsed()));
     }
 
-    Ok(())
+    if args.progress_interval.is_some() {
+        progress.close();
+        progress.write(&progress_path)?;
+    }
+    match walk_failure.into_inner().unwrap_or_else(|e| e.into_inner()) {
+        Some(e) => Err(e.into()),
+        None => Ok(report),
+    }
+}
+
+/// Writes the playlist of the album made from `output`, whose chapters
+/// `records` describe, into its folder.
+fn write_album_index(output: &Path, records: &[FileReport]) -> wav_files_tempo::Result<()> {
+    let chapters: Vec<Chapter> = records
+        .iter()
+        .map(|record| Chapter {
+            path: record.output.clone(),
+            tempo: record.tempo.unwrap_or(1.0),
+            secs: record.output_secs,
+        })
+        .collect();
+    let title = output.file_stem().unwrap_or_default().to_string_lossy();
+    let path = output.with_extension("").join(album::INDEX_FILE);
+    atomic::write(&path, |temp| {
+        fs::write(temp, album::playlist(&title, &chapters)).map_err(|e| Error::Io {
+            context: "Failed to write album index".to_string(),
+            source: e,
+        })
+    })
+}
+
+/// Prints one NDJSON `file` event per record on `out` (standard output):
+/// the record's report entry plus how long the file took.
+fn log_events(
+    out: &mut dyn Write,
+    records: &[FileReport],
+    elapsed: Duration,
+    style: PathStyle,
+    tempo: f32,
+) {
+    for record in records {
+        let Json::Object(mut fields) = record.to_json(style, tempo) else {
+            continue;
+        };
+        fields.insert(0, ("event".to_string(), "file".into()));
+        fields.push((
+            "elapsed_ms".to_string(),
+            Json::from(elapsed.as_millis() as usize),
+        ));
+        // A closed pipe shouldn't stop the batch.
+        let _ = writeln!(out, "{}", Json::Object(fields)).and_then(|()| out.flush());
+    }
+}
+
+/// Runs one stage of one file, with panics caught as failed files under
+/// `--strict-internal`.
+fn guarded<T>(
+    strict: bool,
+    work: impl FnOnce() -> wav_files_tempo::Result<T>,
+) -> wav_files_tempo::Result<T> {
+    if strict {
+        pipeline::catch_internal(work)
+    } else {
+        work()
+    }
+}
+
+/// `options` with a file's own directory gain and tempo, if it has them.
+fn file_options(
+    options: &Arc<ProcessOptions>,
+    gain: Option<f32>,
+    tempo: Option<f32>,
+    pitch: Option<f32>,
+) -> Arc<ProcessOptions> {
+    if gain.is_none() && tempo.is_none() && pitch.is_none() {
+        return Arc::clone(options);
+    }
+    let mut adjusted = (**options).clone();
+    if let Some(gain) = gain {
+        adjusted.normalization = Normalization::Gain(gain);
+    }
+    if let Some(pitch) = pitch {
+        adjusted.pitch_semitones = pitch;
+    }
+    if let Some(tempo) = tempo {
+        // A file's own tempo replaces any ramp or map.
+        adjusted.tempo = tempo;
+        adjusted.tempo_end = None;
+        adjusted.tempo_map = None;
+    }
+    Arc::new(adjusted)
+}
+
+/// Per-file settings worked out from the whole batch before it starts.
+struct Planned {
+    /// `--normalize-per-dir` gain of each input directory.
+    gains: HashMap<PathBuf, f32>,
+    /// Each input's own tempo and the source it came from.
+    tempos: HashMap<PathBuf, (f32, TempoSource)>,
+    /// Each input's `--tempo-manifest` entry.
+    entries: HashMap<PathBuf, Entry>,
+    /// What earlier runs did, with `--resume`.
+    resume: Option<Arc<State>>,
+}
+
+/// What `--resume` compares to tell whether a run's settings differ from an
+/// earlier one's: everything that shapes the outputs.
+fn resume_key(args: &Args, options: &ProcessOptions) -> Result<String> {
+    let mut options = options.clone();
+    options.run_id = None;
+    let manifest = match &args.tempo_manifest {
+        Some(path) => fs::read_to_string(path).context("Failed to read tempo manifest")?,
+        None => String::new(),
+    };
+    let sources = (
+        args.tempo_source,
+        args.tempo_from_name,
+        args.tempo_from_dir,
+        &args.match_duration_of,
+        args.target_duration,
+        args.target_bpm,
+        args.tempo_range,
+        args.seed,
+    );
+    let outputs = (
+        &args.tempo,
+        &args.tempo_suffix,
+        args.speed_album,
+        &args.route,
+        &args.also_output,
+        args.max_output_duration,
+    );
+    Ok(resume::settings_key(&format!(
+        "{options:?}|{sources:?}|{outputs:?}|{manifest}"
+    )))
+}
+
+/// Whether `--resume` can skip `job`: an earlier run did its input, as it is
+/// now, with these settings, and its outputs are still there.
+fn resumable(args: &Args, state: Option<&State>, job: &walk::Job) -> bool {
+    state.is_some_and(|state| state.is_done(&job.input))
+        && (args.max_output_duration.is_some()
+            || variants(args, job)
+                .iter()
+                .all(|(output, _)| output.exists()))
+}
+
+/// The `--tempo-manifest` entry of each of `jobs`, which are moved to the
+/// outputs their entries ask for.
+fn manifest_entries(
+    args: &Args,
+    options: &ProcessOptions,
+    jobs: Vec<walk::Job>,
+) -> Result<(Vec<walk::Job>, HashMap<PathBuf, Entry>)> {
+    let Some(path) = &args.tempo_manifest else {
+        return Ok((jobs, HashMap::new()));
+    };
+    let manifest = manifest::load(path)?;
+    let mut entries = HashMap::new();
+    let mut outputs = HashSet::new();
+    let mut moved = Vec::with_capacity(jobs.len());
+    for mut job in jobs {
+        let entry = fs::canonicalize(&job.input)
+            .ok()
+            .and_then(|key| manifest.get(&key));
+        if let Some(entry) = entry {
+            anyhow::ensure!(
+                entry.pitch_semitones.is_none() || options.mode != Mode::Vinyl,
+                "{:?} has a manifest pitch, but vinyl mode's pitch follows the tempo",
+                job.input
+            );
+            job.output = entry.output_path(&job.output, &args.output_dir);
+            if let Some(parent) = job.output.parent() {
+                fs::create_dir_all(parent).context("Failed to create output subdir")?;
+            }
+            entries.insert(job.input.clone(), entry.clone());
+        }
+        anyhow::ensure!(
+            outputs.insert(job.output.clone()),
+            "Several inputs map to {:?}; manifest outputs must not collide",
+            job.output
+        );
+        moved.push(job);
+    }
+    Ok((moved, entries))
+}
+
+/// What the stretch stage hands to the encoder.
+enum Rendered {
+    /// Input samples to copy through untouched.
+    Copied(RawAudio),
+    Processed(AudioBuffer),
+}
+
+/// How a file is processed, picked from its size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Tier {
+    /// Decoded and stretched in one go.
+    Whole,
+    /// Streamed a block at a time.
+    Stream,
+    /// Cut into parts streamed on several threads.
+    Split,
+}
+
+/// The tier for `job`: every file streams under `--stream`, and otherwise
+/// `--stream-above` and `--split-above` pick it from the input's size, as
+/// far as streaming can do everything the run asks for.
+fn tier(args: &Args, options: &ProcessOptions, job: &walk::Job) -> Tier {
+    let size = fs::metadata(&job.input).map_or(0, |meta| meta.len());
+    let needs_whole = args.fingerprint
+        || args.skip_silent.is_some()
+        || args.pitch_tolerance.is_some()
+        || args.ab_review.is_some()
+        || args.artifact_threshold.is_some()
+        || args.equalize_rate
+        || args.normalize_per_dir.is_some()
+        || !args.also_output.is_empty()
+        || args.tempo.len() > 1
+        || args.target_duration.is_some()
+        || args.max_output_duration.is_some()
+        || writes_flac(&job.output);
+    let whole = size <= args.stream_above
+        || needs_whole
+        || !stream::streamable(&job.input, &job.output, options);
+    if whole && !args.stream {
+        Tier::Whole
+    } else if size > args.split_above {
+        Tier::Split
+    } else {
+        Tier::Stream
+    }
+}
+
+/// The duration of the WAV file at `path` from its header.
+fn wav_secs(path: &Path) -> Option<f64> {
+    let reader = hound::WavReader::open(path).ok()?;
+    Some(f64::from(reader.duration()) / f64::from(reader.spec().sample_rate.max(1)))
+}
+
+/// Whether `output` is routed to FLAC rather than a WAV container.
+fn writes_flac(output: &Path) -> bool {
+    output.extension() == Some("flac".as_ref())
+}
+
+/// Runs every job through decode, stretch, and encode, recording each in
+/// `progress` (and `status`, when watching) and, with `--ab-review`, its
+/// snippets in `reviews`.
+fn run_pipeline(
+    args: &Args,
+    jobs: impl IntoIterator<Item = walk::Job, IntoIter: Send>,
+    options: &Arc<ProcessOptions>,
+    planned: &Planned,
+    progress: &Arc<Progress>,
+    reviews: &Mutex<Vec<(PathBuf, ReviewPair)>>,
+    status: Option<&Status>,
+) -> Report {
+    // One pacer per batch, shared by every worker, stretching block by block.
+    let paced;
+    let options = match args.max_realtime_factor {
+        Some(factor) => {
+            let mut with_pacer = (**options).clone();
+            with_pacer.pacer = Some(Arc::new(Pacer::new(factor)));
+            paced = Arc::new(with_pacer);
+            &paced
+        }
+        None => options,
+    };
+    let threads = Threads::resolve(
+        args.jobs,
+        args.decode_threads,
+        args.stretch_threads,
+        args.encode_threads,
+    );
+    let parts = threads.stretch;
+    let mut records = Vec::new();
+    pipeline::run(
+        jobs,
+        threads,
+        |job| {
+            guarded(args.strict_internal, || {
+                progress.start(&job.input);
+                let deadline = args
+                    .file_timeout
+                    .map(|limit| (Instant::now() + limit, limit));
+                if route_of(args, &job.input) == RouteAction::Copy
+                    || tier(args, options, job) != Tier::Whole
+                {
+                    return Ok((deadline, None));
+                }
+                let (input, options) = (job.input.clone(), Arc::clone(options));
+                let raw = timed(deadline, move || {
+                    let raw = read_raw_within(&input, &options.limits)?;
+                    options.check_input(&raw.format)?;
+                    Ok(raw)
+                })?;
+                Ok((deadline, Some(raw)))
+            })
+        },
+        |job, (deadline, raw)| {
+            guarded(args.strict_internal, || {
+                let mut record = FileReport::new(&job.input, &job.output);
+                let entry = planned.entries.get(&job.input);
+                let gain = entry.and_then(|entry| entry.gain_db).or_else(|| {
+                    let dir = job.input.parent()?;
+                    planned.gains.get(dir).copied()
+                });
+                let pitch = entry.and_then(|entry| entry.pitch_semitones);
+                let picked = planned.tempos.get(&job.input).copied();
+                let tempo = picked.map(|(tempo, _)| tempo);
+                record.tempo = tempo.or(bpm_ratio(args));
+                record.tempo_source = picked.map(|(_, source)| source);
+                if let Some(forced) = args.tempo_source.map(TempoSource::from)
+                    && forced != TempoSource::Flag
+                    && picked.is_none()
+                {
+                    record.warnings.push(format!(
+                        "--tempo-source {} gives no tempo for this file; kept --tempo",
+                        forced.name()
+                    ));
+                }
+                match (args.target_bpm, picked) {
+                    (Some(target), Some((tempo, TempoSource::Bpm))) => {
+                        record.input_bpm = Some(target / tempo);
+                    }
+                    (Some(_), None) if args.tempo_source.is_none() => record
+                        .warnings
+                        .push("no steady beat for --target-bpm; kept the tempo".to_string()),
+                    _ => {}
+                }
+                let Some(mut raw) = raw else {
+                    let (input, output) = (job.input.clone(), job.output.clone());
+                    if route_of(args, &input) == RouteAction::Copy {
+                        timed(deadline, move || copy_routed(&input, &output))?;
+                        return Ok((deadline, vec![(None, record)]));
+                    }
+                    let split = tier(args, options, job) == Tier::Split;
+                    record.input_secs = wav_secs(&input);
+                    if let Some(secs) = record.input_secs {
+                        progress.duration(&input, secs);
+                    }
+                    let options = file_options(options, gain, tempo, pitch);
+                    let progress = Arc::clone(progress);
+                    timed(deadline, move || {
+                        let on_block = |done, total| progress.advance(&input, done, total);
+                        if split {
+                            stream::split_file(&input, &output, &options, parts, on_block)
+                        } else {
+                            stream_file(&input, &output, &options, on_block)
+                        }
+                    })?;
+                    record.output_secs = wav_secs(&job.output);
+                    return Ok((deadline, vec![(None, record)]));
+                };
+                record.warnings.append(&mut raw.repairs);
+                if options.metadata == MetadataPolicy::Drop && !raw.metadata.is_empty() {
+                    let ids: Vec<_> = raw
+                        .metadata
+                        .iter()
+                        .map(|id| String::from_utf8_lossy(id))
+                        .collect();
+                    record.warnings.push(format!(
+                        "dropped metadata chunks {} (keep them with --preserve-metadata)",
+                        ids.join(", ")
+                    ));
+                }
+                // Nothing needs the samples, so they never leave the integer domain.
+                let untouched = gain.is_none()
+                    && pitch.is_none()
+                    && args.tempo.len() == 1
+                    && tempo.is_none_or(|t| t == 1.0)
+                    && args.skip_silent.is_none()
+                    && !args.fingerprint
+                    && args.pitch_tolerance.is_none()
+                    && args.ab_review.is_none()
+                    && args.artifact_threshold.is_none()
+                    && !writes_flac(&job.output)
+                    && args.max_output_duration.is_none()
+                    && options.is_passthrough(raw.format);
+                let measure = args.report.is_some();
+                if untouched {
+                    if measure {
+                        let levels = Levels::of_samples(raw.samples());
+                        record.input_levels = Some(levels);
+                        record.output_levels = Some(levels);
+                    }
+                    if let Some(pacer) = &options.pacer {
+                        pacer.pace(raw.duration_secs());
+                    }
+                    record.input_secs = Some(raw.duration_secs());
+                    record.output_secs = record.input_secs;
+                    return Ok((deadline, vec![(Some(Rendered::Copied(raw)), record)]));
+                }
+                let buffer = raw.into_buffer();
+                progress.duration(&job.input, buffer.duration_secs());
+                record.input_secs = Some(buffer.duration_secs());
+                let variants = variants(args, job);
+                // Whole files count their frames as the stretch works through
+                // them, one output after another.
+                let frames = buffer.frames();
+                let total = frames * variants.len();
+                progress.advance(&job.input, 0, total);
+                if measure {
+                    record.input_levels = Some(Levels::of(&buffer.samples));
+                }
+                if let Some(threshold) = args.skip_silent {
+                    let level = loudness::rms_dbfs(&buffer.samples);
+                    if level < threshold {
+                        record.skipped = Some(format!(
+                            "RMS level {level:.1} dBFS is below {threshold} dBFS"
+                        ));
+                        return Ok((deadline, vec![(None, record)]));
+                    }
+                }
+                if args.fingerprint {
+                    record.input_fingerprint = Fingerprint::of_buffer(&buffer);
+                }
+                let input_pitch = args.pitch_tolerance.and_then(|_| {
+                    analysis::estimate_pitch(&buffer.mono(), buffer.format.sample_rate)
+                });
+                let snippet = args.ab_review.as_ref().map(|_| Snippet::opening(&buffer));
+                let reference = args
+                    .artifact_threshold
+                    .map(|_| (buffer.mono(), buffer.format.sample_rate));
+                let shift = options.pitch_semitones;
+                let count = variants.len();
+                let mut rendered = Vec::with_capacity(count);
+                // The last output takes the decoded samples; earlier ones copy them.
+                let copies = std::iter::repeat_n(buffer, count);
+                for ((path, own_tempo), buffer) in variants.into_iter().zip(copies) {
+                    let mut record = record.clone();
+                    if own_tempo.is_some() {
+                        record.output = path;
+                        record.tempo = own_tempo;
+                    }
+                    let mut counted =
+                        (*file_options(options, gain, own_tempo.or(tempo), pitch)).clone();
+                    let done = frames * rendered.len();
+                    let counter = FileProgress::new(Arc::clone(progress), &job.input, done, total);
+                    counted.progress = Some(Arc::new(counter));
+                    let mut output = timed(deadline, move || transform(buffer, &counted))?;
+                    if let (Some(secs), Some(TempoSource::Target)) =
+                        (args.target_duration, record.tempo_source)
+                    {
+                        // The stretch lands within a frame; this makes it exact.
+                        let rate = f64::from(output.format.sample_rate);
+                        output.set_frames((secs * rate).round() as usize);
+                    }
+                    let done = frames * (rendered.len() + 1);
+                    progress.advance(&job.input, done, total);
+                    if args.fingerprint {
+                        record.output_fingerprint = Fingerprint::of_buffer(&output);
+                    }
+                    if record.input_bpm.is_some() {
+                        let rate = output.format.sample_rate;
+                        record.output_bpm = analysis::estimate_bpm(&output.mono(), rate);
+                    }
+                    if measure {
+                        record.output_levels = Some(Levels::of(&output.samples));
+                    }
+                    if let (Some(threshold), Some((before, rate))) =
+                        (args.artifact_threshold, &reference)
+                    {
+                        let after = resample(&output.mono(), output.format.sample_rate, *rate);
+                        record.artifact_score = quality::artifact_score(before, &after, *rate);
+                        record.needs_review = record.artifact_score.is_some_and(|s| s > threshold);
+                    }
+                    if let Some(snippet) = &snippet
+                        && let Ok(mut reviews) = reviews.lock()
+                    {
+                        reviews.push((job.input.clone(), snippet.clone().pair(&output)));
+                    }
+                    if let SampleType::Int(_) = output.format.sample_type {
+                        let clipped = loudness::clipped_samples(&output.samples);
+                        if clipped > 0 {
+                            record
+                                .warnings
+                                .push(format!("{clipped} samples clipped at full scale"));
+                        }
+                    }
+                    if let (Some(tolerance), Some(before)) = (args.pitch_tolerance, input_pitch) {
+                        record
+                            .warnings
+                            .extend(check_pitch(before, &output, shift, tolerance));
+                    }
+                    record.output_secs = Some(output.duration_secs());
+                    rendered.push((Some(Rendered::Processed(output)), record));
+                }
+                Ok((deadline, rendered))
+            })
+        },
+        |job, (deadline, rendered)| {
+            guarded(args.strict_internal, || {
+                let mut records = Vec::with_capacity(rendered.len());
+                let album = args.speed_album && rendered.iter().any(|(r, _)| r.is_some());
+                if album {
+                    let dir = job.output.with_extension("");
+                    fs::create_dir_all(&dir).map_err(|e| Error::Io {
+                        context: "Failed to create album directory".to_string(),
+                        source: e,
+                    })?;
+                }
+                for (rendered, record) in rendered {
+                    let Some(rendered) = rendered else {
+                        records.push(record);
+                        continue;
+                    };
+                    let (input, output) = (job.input.clone(), record.output.clone());
+                    let options = Arc::clone(options);
+                    let also = args.also_output.clone();
+                    let max_secs = args.max_output_duration;
+                    let segments = timed(deadline, move || {
+                        let buffer = match rendered {
+                            Rendered::Copied(raw) => {
+                                copy_output(&input, &output, &raw, &options)?;
+                                if also.is_empty() {
+                                    return Ok(Vec::new());
+                                }
+                                raw.into_buffer()
+                            }
+                            Rendered::Processed(buffer)
+                                if max_secs.is_some_and(|max| buffer.duration_secs() > max) =>
+                            {
+                                let pieces = segment::split(&buffer, max_secs.unwrap_or_default());
+                                let mut segments = Vec::new();
+                                for (k, piece) in pieces.iter().enumerate() {
+                                    let path =
+                                        walk::with_suffix(&output, &format!("_{:03}", k + 1));
+                                    if writes_flac(&output) {
+                                        flac::write_flac(&path, piece)?;
+                                    } else {
+                                        write_output(&input, &path, piece, &options)?;
+                                    }
+                                    segments.push((path, piece.duration_secs()));
+                                }
+                                return Ok(segments);
+                            }
+                            Rendered::Processed(buffer) if writes_flac(&output) => {
+                                flac::write_flac(&output, &buffer)?;
+                                buffer
+                            }
+                            Rendered::Processed(buffer) => {
+                                write_output(&input, &output, &buffer, &options)?;
+                                buffer
+                            }
+                        };
+                        also.iter()
+                            .try_for_each(|format| format.write(&output, &buffer))?;
+                        Ok(Vec::new())
+                    })?;
+                    let (segments, segment_secs) = segments.into_iter().unzip();
+                    records.push(FileReport {
+                        segments,
+                        segment_secs,
+                        ..record
+                    });
+                }
+                if album {
+                    write_album_index(&job.output, &records)?;
+                }
+                Ok(records)
+            })
+        },
+        |job, result: wav_files_tempo::Result<Vec<FileReport>>| {
+            let elapsed = progress.finish(&job.input, result.is_ok());
+            if let (Some(state), Ok(_)) = (&planned.resume, &result)
+                && let Err(e) = state.record(&job.input)
+            {
+                eprintln!("Warning: {e}");
+            }
+            if args.log_format == LogFormatArg::Json {
+                let failed;
+                let records = match &result {
+                    Ok(ours) => ours.as_slice(),
+                    Err(e) => {
+                        failed = [FileReport::failed(&job.input, &job.output, e)];
+                        &failed[..]
+                    }
+                };
+                let mut stdout = io::stdout().lock();
+                let style = args.path_style.into();
+                log_events(&mut stdout, records, elapsed, style, options.tempo);
+            }
+            if let Some(status) = status {
+                status.finish(&job.input, result.as_ref().err().map(ToString::to_string));
+            }
+            match result {
+                Ok(ours) => {
+                    // Warnings about the input repeat in every output's record.
+                    let mut shown = Vec::new();
+                    for record in &ours {
+                        for warning in &record.warnings {
+                            if !shown.contains(&warning) {
+                                eprintln!("Warning: {:?}: {}", job.input, warning);
+                                shown.push(warning);
+                            }
+                        }
+                        if let (true, Some(score)) = (record.needs_review, record.artifact_score) {
+                            eprintln!(
+                                "Review {:?}: artifact score {score:.3} is above the threshold",
+                                record.output
+                            );
+                        }
+                        if let Some(reason) = &record.skipped {
+                            eprintln!("Skipped {:?}: {}", job.input, reason);
+                        }
+                    }
+                    records.extend(ours);
+                }
+                Err(e) => {
+                    eprintln!("Error processing {:?}: {}", job.input, e);
+                    records.push(FileReport::failed(&job.input, &job.output, e));
+                    if args.fail_fast {
+                        eprintln!("Stopping after the first failure (--fail-fast)");
+                        return ControlFlow::Break(());
+                    }
+                }
+            }
+            ControlFlow::Continue(())
+        },
+    );
+    Report::new(records)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    /// The problems `config_problems` finds in `toml` next to `argv`.
+    fn config_check(toml: &str, argv: &[&str]) -> Vec<String> {
+        let table = Config::parse(toml).unwrap().resolve(None).unwrap();
+        let base = ["wav-files-tempo", "-i", "in", "-o", "out"];
+        let matches = command()
+            .ignore_errors(true)
+            .get_matches_from(base.iter().chain(argv));
+        config_problems(&command(), &table, &matches)
+    }
+
+    #[test]
+    fn test_config_problems() {
+        assert!(config_check("tempo = 1.2\nresume = true\n", &[]).is_empty());
+        assert_eq!(
+            config_check("tempp = 1.2\n", &[]),
+            ["unknown key \"tempp\" (did you mean \"tempo\"?)"]
+        );
+        assert_eq!(
+            config_check("resume = 3\n", &[]),
+            ["\"resume\" is a switch and takes true or false, not 3"]
+        );
+        let conflict = "--tempo would be ignored, as --target-duration picks each file's tempo; \
+                        drop --tempo, or drop --target-duration to give every file the same tempo";
+        assert_eq!(
+            config_check("tempo = 1.2\ntarget-duration = \"3s\"\n", &[]),
+            [conflict]
+        );
+        assert_eq!(
+            config_check("target-duration = \"3s\"\n", &["-t", "1.2"]),
+            [conflict]
+        );
+        // Conflicts only on the command line are left to the command line.
+        assert!(config_check("resume = true\n", &["-t", "1.2", "--target-bpm", "120"]).is_empty());
+        for source in [
+            "target-bpm = 120",
+            "tempo-range = \"0.9:1.1\"",
+            "equalize-rate = true",
+        ] {
+            let problems = config_check(&format!("tempo = 0.9\n{source}\n"), &[]);
+            assert_eq!(problems.len(), 1, "{source}: {problems:?}");
+            assert!(
+                problems[0].starts_with("--tempo would be ignored"),
+                "{problems:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_value_problem() {
+        let command = command();
+        let problem = |key: &str, value: Value| {
+            value_problem(config_arg(&command, key).unwrap(), key, &value)
+        };
+        assert_eq!(problem("walk-threads", Value::Integer(4)), None);
+        let threads = problem("walk-threads", Value::String("many".to_string())).unwrap();
+        assert!(
+            threads.starts_with("\"walk-threads\": invalid value 'many'"),
+            "{threads}"
+        );
+        let mode = problem("mode", Value::String("tape".to_string())).unwrap();
+        assert!(mode.contains("[possible values: stretch, vinyl]"), "{mode}");
+        assert_eq!(
+            problem(
+                "output-dir",
+                Value::Array(vec![Value::String("a".to_string())])
+            )
+            .as_deref(),
+            Some("\"output-dir\" takes a single value, not an array")
+        );
+        assert_eq!(
+            problem(
+                "tempo",
+                Value::Array(vec![Value::Float(0.9), Value::Float(1.1)])
+            ),
+            None
+        );
+        assert_eq!(tempo_conflict(|id| id == "tempo"), None);
+        assert!(tempo_conflict(|id| matches!(id, "tempo" | "match_duration_of")).is_some());
+    }
+
+    /// The batch arguments for `argv`, after the required flags.
+    fn args(argv: &[&str]) -> Args {
+        let base = ["wav-files-tempo", "-i", "in", "-o", "out"];
+        Cli::try_parse_from(base.iter().chain(argv))
+            .unwrap()
+            .process
+            .unwrap()
+    }
+
+    #[test]
+    fn test_single_file_through_stdio() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let input = dir.path().join("in.wav");
+        let samples = (0..16000).map(|i| 0.5 * (i as f32 * 0.05).sin()).collect();
+        write_wav(&input, &AudioBuffer::new(AudioFormat::default(), samples))?;
+        let argv = ["wav-files-tempo", "-i", "-", "-o", "-", "-t", "1.25"];
+        let args = Cli::try_parse_from(argv)?.process.unwrap();
+        let options = Arc::new(process_options(&args)?);
+        let run = Run::start();
+
+        let mut stdout = Vec::new();
+        let stdin = fs::read(&input)?;
+        process_single(args, &options, &run, &mut stdin.as_slice(), &mut stdout)?;
+        let output = wav_files_tempo::decode::decode_bytes(&stdout, &DecodeLimits::default())?;
+        assert!(output.frames().abs_diff(12800) <= 1, "{}", output.frames());
+        let staging = std::env::temp_dir().join(format!("wav-files-tempo-{}", run.id));
+        assert!(!staging.exists());
+
+        // Input that isn't audio fails, and still leaves nothing staged.
+        let args = Cli::try_parse_from(argv)?.process.unwrap();
+        let failed = process_single(args, &options, &run, &mut &b"text"[..], &mut Vec::new());
+        assert!(failed.is_err());
+        assert!(!staging.exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_directory_gains_meet_the_target() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let tone = |amplitude: f32| -> Vec<f32> {
+            (0..32000)
+                .map(|i| {
+                    amplitude * (i as f32 * 2.0 * std::f32::consts::PI * 440.0 / 16000.0).sin()
+                })
+                .collect()
+        };
+        // A loud pair, a quiet one, and a silent file; plus one unreadable.
+        let files = [
+            ("loud/a.wav", 0.5),
+            ("loud/b.wav", 0.25),
+            ("quiet/a.wav", 0.02),
+            ("silent/a.wav", 0.0),
+        ];
+        let mut jobs = Vec::new();
+        for (name, amplitude) in files {
+            let input = dir.path().join(name);
+            fs::create_dir_all(input.parent().unwrap())?;
+            write_wav(
+                &input,
+                &AudioBuffer::new(AudioFormat::default(), tone(amplitude)),
+            )?;
+            jobs.push(walk::Job {
+                output: input.clone(),
+                input,
+            });
+        }
+        let broken = dir.path().join("quiet/broken.wav");
+        fs::write(&broken, b"RIFF")?;
+        jobs.push(walk::Job {
+            output: broken.clone(),
+            input: broken,
+        });
+
+        let target = -20.0;
+        let gains = directory_gains(&jobs, target, &DecodeLimits::default());
+        let mut dirs: Vec<&PathBuf> = gains.keys().collect();
+        dirs.sort();
+        assert_eq!(dirs, [&dir.path().join("loud"), &dir.path().join("quiet")]);
+        let (loud, quiet) = (
+            gains[&dir.path().join("loud")],
+            gains[&dir.path().join("quiet")],
+        );
+        assert!(quiet - loud > 20.0, "{loud} {quiet}");
+        // Each directory, with its gain applied, lands on the target.
+        for (sub, gain) in [("loud", loud), ("quiet", quiet)] {
+            let scale = 10f32.powf(gain / 20.0);
+            let mut powers = Vec::new();
+            for (_, amplitude) in files.iter().filter(|(name, _)| name.starts_with(sub)) {
+                let scaled: Vec<f32> = tone(*amplitude).iter().map(|s| s * scale).collect();
+                powers.extend(loudness::block_powers(&scaled, 16000));
+            }
+            let lufs = loudness::gated_loudness(&powers).unwrap() as f32;
+            assert!((lufs - target).abs() < 0.1, "{sub}: {lufs}");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_route_specs() {
+        let parse = |s: &str| s.parse::<Route>();
+        assert_eq!(
+            parse(" .TXT = copy "),
+            Ok(Route {
+                extension: "TXT".to_string(),
+                action: RouteAction::Copy
+            })
+        );
+        assert_eq!(parse("wav=skip").unwrap().action, RouteAction::Skip);
+        assert_eq!(
+            parse("wav=stretch").unwrap().action,
+            RouteAction::Stretch(None)
+        );
+        assert_eq!(
+            parse("W64=stretch:flac").unwrap().action,
+            RouteAction::Stretch(Some(AlsoOutput::Flac))
+        );
+        assert_eq!(
+            parse("wav=stretch:w64").unwrap().action,
+            RouteAction::Stretch(Some(AlsoOutput::W64))
+        );
+        for malformed in [
+            "txt",
+            "=copy",
+            ".=copy",
+            "txt=move",
+            "txt=",
+            "wav=copy:flac",
+            "wav=stretch:",
+            "wav=stretch:opus@32k",
+            "wav=stretch:flac@320k",
+            "txt=stretch",
+        ] {
+            assert!(parse(malformed).is_err(), "{malformed}");
+        }
+    }
+
+    #[test]
+    fn test_route_of_last_rule_wins() {
+        let routed = args(&[
+            "--route",
+            "wav=skip",
+            "--route",
+            "txt=copy",
+            "--route",
+            "WAV=stretch:flac",
+        ]);
+        let flac = RouteAction::Stretch(Some(AlsoOutput::Flac));
+        for path in ["a.wav", "b.WAV", "c.Wav"] {
+            assert_eq!(route_of(&routed, Path::new(path)), flac, "{path}");
+        }
+        assert_eq!(route_of(&routed, Path::new("notes.TXT")), RouteAction::Copy);
+        assert_eq!(
+            route_of(&routed, Path::new("x.W64")),
+            RouteAction::Stretch(None)
+        );
+        assert_eq!(route_of(&routed, Path::new("README")), RouteAction::Skip);
+        assert_eq!(route_of(&routed, Path::new("a.flac")), RouteAction::Skip);
+
+        let others = args(&["--copy-others", "--route", "log=skip"]);
+        assert_eq!(route_of(&others, Path::new("a.flac")), RouteAction::Copy);
+        assert_eq!(route_of(&others, Path::new("run.LOG")), RouteAction::Skip);
+    }
+
+    /// A 440 Hz tone of `secs` at 16 kHz with this peak `amplitude`.
+    fn tone(secs: f32, amplitude: f32) -> AudioBuffer {
+        let frames = (secs * 16000.0) as usize;
+        let samples = (0..frames)
+            .map(|i| amplitude * (i as f32 * 2.0 * std::f32::consts::PI * 440.0 / 16000.0).sin())
+            .collect();
+        AudioBuffer::new(AudioFormat::default(), samples)
+    }
+
+    /// Runs a batch from `dir/in` into `dir/out` with `argv`, returning its
+    /// `--report` entries by input file name.
+    fn batch_report(dir: &Path, argv: &[&str]) -> anyhow::Result<HashMap<String, Json>> {
+        let (input, output) = (dir.join("in"), dir.join("out"));
+        let report = dir.join("report.json");
+        let base = [
+            "wav-files-tempo",
+            "-i",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "-q",
+            "--report",
+            report.to_str().unwrap(),
+        ];
+        process(
+            Cli::try_parse_from(base.iter().chain(argv))?
+                .process
+                .unwrap(),
+        )?;
+        let Some(Json::Array(files)) = Json::parse(&fs::read_to_string(&report)?)?
+            .get("files")
+            .cloned()
+        else {
+            anyhow::bail!("no files in the report");
+        };
+        let name = |file: &Json| {
+            let input = file.get("input").and_then(Json::as_str).unwrap_or_default();
+            Path::new(input)
+                .file_name()
+                .unwrap()
+                .to_string_lossy()
+                .into_owned()
+        };
+        Ok(files.into_iter().map(|file| (name(&file), file)).collect())
+    }
+
+    #[test]
+    fn test_skip_silent_leaves_quiet_inputs_out() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        fs::create_dir(dir.path().join("in"))?;
+        write_wav(&dir.path().join("in/loud.wav"), &tone(1.0, 0.5))?;
+        write_wav(&dir.path().join("in/hiss.wav"), &tone(1.0, 0.001))?;
+        let files = batch_report(dir.path(), &["-t", "1.25", "--skip-silent", "-50dB"])?;
+
+        assert_eq!(files["loud.wav"].get("status"), Some(&Json::from("ok")));
+        assert!(dir.path().join("out/loud.wav").exists());
+        let hiss = &files["hiss.wav"];
+        assert_eq!(hiss.get("status"), Some(&Json::from("skipped")));
+        let reason = hiss
+            .get("reason")
+            .and_then(Json::as_str)
+            .unwrap_or_default();
+        assert!(reason.ends_with("dBFS is below -50 dBFS"), "{reason}");
+        assert!(!dir.path().join("out/hiss.wav").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_target_bpm_stretches_each_beat_to_the_target() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        fs::create_dir(dir.path().join("in"))?;
+        let clicks = wav_files_tempo::click::click_track(16000 * 12, 16000, 100.0);
+        let beat = AudioBuffer::new(AudioFormat::default(), clicks);
+        write_wav(&dir.path().join("in/beat.wav"), &beat)?;
+        write_wav(&dir.path().join("in/hum.wav"), &tone(8.0, 0.5))?;
+        let files = batch_report(dir.path(), &["--target-bpm", "120"])?;
+
+        let number = |file: &str, key: &str| files[file].get(key).and_then(Json::as_f64);
+        let detected = number("beat.wav", "input_bpm").unwrap();
+        assert!((detected - 100.0).abs() < 2.0, "{detected}");
+        let tempo = number("beat.wav", "tempo").unwrap();
+        assert!((tempo - 120.0 / detected).abs() < 1e-3, "{tempo}");
+        let reached = number("beat.wav", "output_bpm").unwrap();
+        assert!((reached - 120.0).abs() < 3.0, "{reached}");
+        let out = wav_files_tempo::read_wav(&dir.path().join("out/beat.wav"))?;
+        let expected = beat.frames() as f64 / tempo;
+        assert!(
+            (out.frames() as f64 - expected).abs() <= 2.0,
+            "{}",
+            out.frames()
+        );
+
+        // Without a steady beat the file keeps its tempo, with a warning.
+        assert_eq!(
+            files["hum.wav"].get("warnings"),
+            Some(&Json::Array(vec![Json::from(
+                "no steady beat for --target-bpm; kept the tempo"
+            )]))
+        );
+        let hum = wav_files_tempo::read_wav(&dir.path().join("out/hum.wav"))?;
+        assert_eq!(hum.frames(), 16000 * 8);
+        Ok(())
+    }
+
+    #[test]
+    fn test_log_events_are_one_json_object_per_line() -> anyhow::Result<()> {
+        let mut done = FileReport::new(Path::new("in/a.wav"), Path::new("out/a.wav"));
+        (done.input_secs, done.output_secs) = (Some(2.0), Some(1.6));
+        let error = Error::Timeout(Duration::from_secs(30));
+        let failed = FileReport::failed(Path::new("in/b.wav"), Path::new("out/b.wav"), &error);
+        let mut out = Vec::new();
+        let elapsed = Duration::from_millis(65);
+        log_events(&mut out, &[done, failed], elapsed, PathStyle::Posix, 1.25);
+
+        let text = String::from_utf8(out)?;
+        let events: Vec<Json> = text.lines().map(Json::parse).collect::<Result<_, _>>()?;
+        assert_eq!(events.len(), 2, "{text}");
+        assert!(
+            text.lines()
+                .all(|line| line.starts_with(r#"{"event": "file", "#))
+        );
+        let field = |event: &Json, key: &str| event.get(key).cloned();
+        for (event, (input, status)) in events
+            .iter()
+            .zip([("in/a.wav", "ok"), ("in/b.wav", "failed")])
+        {
+            assert_eq!(field(event, "input"), Some(Json::from(input)));
+            assert_eq!(field(event, "status"), Some(Json::from(status)));
+            assert_eq!(field(event, "elapsed_ms"), Some(Json::from(65)));
+        }
+        assert_eq!(field(&events[0], "input_duration_s"), Some(Json::from(2.0)));
+        assert_eq!(
+            field(&events[0], "output_duration_s"),
+            Some(Json::from(1.6))
+        );
+        assert_eq!(field(&events[0], "tempo"), Some(Json::from(1.25)));
+        assert_eq!(field(&events[0], "error"), None);
+        assert_eq!(
+            field(&events[1], "error"),
+            Some(Json::from(error.to_string().as_str()))
+        );
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "ssstretch")]
+    fn test_presets_and_tuning_reach_the_engine() -> anyhow::Result<()> {
+        let input = tone(2.0, 0.5);
+        let render = |argv: &[&str]| -> anyhow::Result<(Quality, Vec<f32>)> {
+            let options = process_options(&args(argv))?;
+            let output = transform(input.clone(), &options)?;
+            assert!(output.frames().abs_diff(25600) <= 1, "{argv:?}");
+            Ok((options.quality, output.samples))
+        };
+        let (quality, default) = render(&["-t", "1.25"])?;
+        assert_eq!(quality, Quality::Default);
+        let (quality, cheaper) = render(&["-t", "1.25", "--preset", "cheaper"])?;
+        assert_eq!(quality, Quality::Cheaper);
+        assert_ne!(cheaper, default);
+        // The default preset's own block and interval render the same samples.
+        let (quality, spelled_out) =
+            render(&["-t", "1.25", "--block-ms", "120", "--interval-ms", "30"])?;
+        assert_eq!(
+            quality,
+            Quality::Custom {
+                block_ms: 120,
+                interval_ms: 30
+            }
+        );
+        assert_eq!(spelled_out, default);
+        // One knob keeps the preset's other.
+        let (quality, tuned) = render(&["-t", "1.25", "--preset", "cheaper", "--block-ms", "50"])?;
+        assert_eq!(
+            quality,
+            Quality::Custom {
+                block_ms: 50,
+                interval_ms: 40
+            }
+        );
+        assert_ne!(tuned, cheaper);
+        Ok(())
+    }
+
     #[test]
-    fn test_stretch_samples_no_change() {
-        let input = vec![0.1, 0.2, 0.3, 0.4, 0.5];
-        let sample_rate = 16000;
-        let tempo = 1.0;
-        let output = stretch_samples(&input, sample_rate, tempo);
-        assert_eq!(output, input);
+    fn test_bpm_pair_sets_every_file_tempo() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        fs::create_dir(dir.path().join("in"))?;
+        for name in ["a.wav", "b.wav"] {
+            write_wav(&dir.path().join("in").join(name), &tone(1.0, 0.5))?;
+        }
+        let files = batch_report(dir.path(), &["--from-bpm", "100", "--to-bpm", "125"])?;
+        for name in ["a.wav", "b.wav"] {
+            assert_eq!(files[name].get("tempo"), Some(&Json::from(1.25)), "{name}");
+            let output = wav_files_tempo::read_wav(&dir.path().join("out").join(name))?;
+            assert!(output.frames().abs_diff(12800) <= 1, "{name}");
+        }
+
+        let argv = ["--from-bpm", "100", "--to-bpm", "0"];
+        let err = batch_report(dir.path(), &argv).unwrap_err();
+        assert!(err.to_string().contains("must be positive"), "{err}");
+        let base = ["wav-files-tempo", "-i", "in", "-o", "out", "-t", "1.1"];
+        let clash =
+            Cli::try_parse_from(base.iter().chain(&["--from-bpm", "100", "--to-bpm", "125"]));
+        assert!(clash.is_err());
+        Ok(())
     }
 
     #[test]
-    fn test_stretch_samples_faster() {
-        let input = vec![0.1, 0.2, 0.3, 0.4, 0.5, 0.6, 0.7, 0.8, 0.9, 1.0];
-        let sample_rate = 16000;
-        let tempo = 2.0; // Twice as fast, output should be roughly half length
-        let output = stretch_samples(&input, sample_rate, tempo);
-        assert!((output.len() as f32 - input.len() as f32 / tempo).abs() < 2.0); // Allow for small rounding differences
-        assert!(output.len() < input.len());
+    fn test_copy_others_mirrors_the_input_tree() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let input = dir.path().join("in");
+        // The output sits inside the input, so a rerun walks past it.
+        let output = input.join("out");
+        fs::create_dir_all(input.join("talks"))?;
+        let samples = (0..16000).map(|i| 0.5 * (i as f32 * 0.05).sin()).collect();
+        let tone = AudioBuffer::new(AudioFormat::default(), samples);
+        write_wav(&input.join("talks/a.wav"), &tone)?;
+        let others = [("talks/a.txt", "transcript"), ("README", "about")];
+        for (name, text) in others {
+            fs::write(input.join(name), text)?;
+        }
+        fs::write(input.join("talks/run.log"), "noise")?;
+        fs::write(input.join("talks/draft.txt"), "draft")?;
+
+        let (from, to) = (input.to_str().unwrap(), output.to_str().unwrap());
+        let argv = [
+            "wav-files-tempo",
+            "-i",
+            from,
+            "-o",
+            to,
+            "-t",
+            "1.25",
+            "-q",
+            "--copy-others",
+            "--route",
+            "log=skip",
+            "--exclude",
+            "talks/draft.txt",
+        ];
+        for _ in 0..2 {
+            process(Cli::try_parse_from(argv)?.process.unwrap())?;
+        }
+        for (name, text) in others {
+            assert_eq!(fs::read_to_string(output.join(name))?, text, "{name}");
+        }
+        let stretched = wav_files_tempo::read_wav(&output.join("talks/a.wav"))?;
+        assert!(stretched.frames().abs_diff(12800) <= 1);
+        let mut written: Vec<PathBuf> = walkdir::WalkDir::new(&output)
+            .into_iter()
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_type().is_file())
+            .map(|entry| entry.path().strip_prefix(&output).unwrap().to_path_buf())
+            .collect();
+        written.sort();
+        // Neither the skipped nor the excluded file, nor the first run's
+        // outputs, nor any temporary file.
+        let expected = ["README", "talks/a.txt", "talks/a.wav"].map(PathBuf::from);
+        assert_eq!(written, expected);
+        Ok(())
     }
 
     #[test]
-    fn test_stretch_samples_slower() {
-        let input = vec![0.1, 0.2, 0.3, 0.4, 0.5];
-        let sample_rate = 16000;
-        let tempo = 0.5; // Half as fast, output should be roughly double length
-        let output = stretch_samples(&input, sample_rate, tempo);
-        assert!((output.len() as f32 - input.len() as f32 / tempo).abs() < 2.0); // Allow for small rounding differences
-        assert!(output.len() > input.len());
+    fn test_album_index_is_replaced_whole() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let output = dir.path().join("talk.wav");
+        let folder = dir.path().join("talk");
+        fs::create_dir(&folder)?;
+        let index = folder.join(album::INDEX_FILE);
+        fs::write(&index, "stale")?;
+        let mut record = FileReport::new(Path::new("in/talk.wav"), &folder.join("talk_1.25x.wav"));
+        (record.tempo, record.output_secs) = (Some(1.25), Some(8.0));
+        write_album_index(&output, &[record])?;
+
+        let text = fs::read_to_string(&index)?;
+        assert!(text.starts_with("#EXTM3U"), "{text}");
+        assert!(text.contains("talk_1.25x.wav"), "{text}");
+        assert!(!atomic::temp_path(&index).exists());
+
+        // Under an abandoned claim the old index stays as it was.
+        let claim = atomic::Claim::new();
+        claim.withdraw();
+        assert!(claim.run(|| write_album_index(&output, &[])).is_err());
+        assert_eq!(fs::read_to_string(&index)?, text);
+        assert!(!atomic::temp_path(&index).exists());
+        Ok(())
     }
 
     #[test]
-    fn test_process_file_integration() -> Result<()> {
-        let input_dir = PathBuf::from("test_input");
-        let output_dir = PathBuf::from("test_output");
-        fs::create_dir_all(&input_dir)?;
-        fs::create_dir_all(&output_dir)?;
-
-        // Create a dummy WAV file
-        let input_path = input_dir.join("test_mono.wav");
-        let spec = WavSpec {
-            channels: 1,
-            sample_rate: 16000,
-            bits_per_sample: 16,
-            sample_format: SampleFormat::Int,
-        };
-        let mut writer = WavWriter::create(&input_path, spec)?;
-        for i in 0..16000 {
-            // 1 second of a simple sine wave
-            let sample = (i as f32 * 440.0 * 2.0 * std::f32::consts::PI / 16000.0).sin() * 10000.0;
-            writer.write_sample(sample as i16)?;
-        }
-        writer.finalize()?;
-
-        let output_path = output_dir.join("test_mono_stretched.wav");
-        let tempo = 0.5; // Slow down by half
-
-        process_file(&input_path, &output_path, tempo)?;
-
-        // Verify output file exists and has roughly expected length
-        assert!(output_path.exists());
-        let reader = WavReader::open(&output_path)?;
-        let expected_len = (16000 as f32 / tempo) as usize;
-        assert!(((reader.len() as isize - expected_len as isize) as isize).abs() < 100); // Allow for small differences
-
-        fs::remove_dir_all(&input_dir)?;
-        fs::remove_dir_all(&output_dir)?;
+    fn test_routed_copies_are_atomic() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let (input, output) = (dir.path().join("notes.txt"), dir.path().join("out.txt"));
+        fs::write(&input, "new notes")?;
+
+        // A copy still running at its deadline never shows up, even once the
+        // abandoned work finishes.
+        let limit = Duration::from_millis(50);
+        let (from, to) = (input.clone(), output.clone());
+        let late = timed(Some((Instant::now() + limit, limit)), move || {
+            thread::sleep(Duration::from_millis(200));
+            copy_routed(&from, &to)
+        });
+        assert!(matches!(late, Err(Error::Timeout(_))), "{late:?}");
+        thread::sleep(Duration::from_millis(400));
+        assert!(!output.exists());
+        assert!(!atomic::temp_path(&output).exists());
+
+        // A copy that fails leaves an earlier output whole and no temp file,
+        // whether reading fails or the finished copy can't be put in place.
+        fs::write(&output, "old notes")?;
+        assert!(copy_routed(&dir.path().join("missing.txt"), &output).is_err());
+        assert_eq!(fs::read_to_string(&output)?, "old notes");
+        let blocked = dir.path().join("blocked.txt");
+        fs::create_dir(&blocked)?;
+        assert!(copy_routed(&input, &blocked).is_err());
+        assert!(blocked.is_dir());
+        for path in [&output, &blocked] {
+            assert!(!atomic::temp_path(path).exists(), "{path:?}");
+        }
+
+        copy_routed(&input, &output)?;
+        assert_eq!(fs::read_to_string(&output)?, "new notes");
         Ok(())
     }
+
+    #[test]
+    fn test_also_output_formats() {
+        assert_eq!("FLAC".parse(), Ok(AlsoOutput::Flac));
+        assert_eq!(" w64".parse(), Ok(AlsoOutput::W64));
+        for lossy in ["opus@32k", "mp3@128k", "mp3"] {
+            let err = lossy.parse::<AlsoOutput>().unwrap_err();
+            assert!(err.contains("not supported yet"), "{err}");
+        }
+        assert!("flac@32k".parse::<AlsoOutput>().is_err());
+        assert!("aiff".parse::<AlsoOutput>().is_err());
+    }
 }
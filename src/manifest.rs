@@ -0,0 +1,201 @@
+//! Batch manifests: a CSV naming input files and the settings each one is
+//! processed with, so one invocation can run a whole job description.
+//!
+//! A header row names the columns, `path` first, then any of `tempo`,
+//! `pitch` (semitones), `gain` (dB), `format` (`wav`, `w64`, or `flac`), and
+//! `output`; an empty cell keeps the batch's setting. Without a header, lines
+//! are `path,tempo`.
+
+use crate::dedupe::split_csv;
+use crate::error::{Error, Result};
+use crate::paths;
+use crate::tempo::parse_tempo;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Columns a manifest may have besides `path`.
+const COLUMNS: [&str; 5] = ["tempo", "pitch", "gain", "format", "output"];
+
+/// Output formats an entry may ask for, by extension.
+const FORMATS: [&str; 3] = ["wav", "w64", "flac"];
+
+/// The settings a manifest gives one file; `None` keeps the batch's.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Entry {
+    pub tempo: Option<f32>,
+    pub pitch_semitones: Option<f32>,
+    pub gain_db: Option<f32>,
+    /// Extension of the output format: `wav`, `w64`, or `flac`.
+    pub format: Option<String>,
+    /// Where to write the output, relative to the output directory unless
+    /// absolute.
+    pub output: Option<PathBuf>,
+}
+
+impl Entry {
+    /// `output` as the entry moves and reformats it, with relative paths
+    /// resolved against `output_dir`.
+    pub fn output_path(&self, output: &Path, output_dir: &Path) -> PathBuf {
+        let mut path = match &self.output {
+            Some(path) => output_dir.join(path),
+            None => output.to_path_buf(),
+        };
+        if let Some(format) = &self.format {
+            path.set_extension(format);
+        }
+        path
+    }
+}
+
+/// Reads the manifest at `path`; relative paths are resolved against the
+/// manifest's directory. Blank lines and `#` comments are skipped.
+///
+/// Keys are canonicalized, so entries match however the input was reached.
+pub fn load(path: &Path) -> Result<HashMap<PathBuf, Entry>> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| Error::io(format!("Failed to read tempo manifest {:?}", path), e))?;
+    let base = path.parent().unwrap_or(Path::new(""));
+    // Spreadsheets on Windows often save CSV with a byte order mark.
+    let text = text.strip_prefix('\u{feff}').unwrap_or(&text);
+    let mut columns = vec!["tempo".to_string()];
+    let mut entries = HashMap::new();
+    let mut first = true;
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let at = |message: String| {
+            Error::invalid(format!(
+                "Invalid tempo manifest {:?}: line {}: {message}",
+                path,
+                number + 1
+            ))
+        };
+        let fields: Vec<String> = split_csv(line)
+            .iter()
+            .map(|f| f.trim().to_string())
+            .collect();
+        if std::mem::take(&mut first) && fields[0].eq_ignore_ascii_case("path") {
+            columns = fields[1..].iter().map(|c| c.to_ascii_lowercase()).collect();
+            if let Some(unknown) = columns.iter().find(|c| !COLUMNS.contains(&c.as_str())) {
+                return Err(at(format!(
+                    "unknown column {unknown:?}; expected path then any of {}",
+                    COLUMNS.join(", ")
+                )));
+            }
+            continue;
+        }
+        if fields.len() != columns.len() + 1 {
+            return Err(at(format!(
+                "expected path,{}, found {line}",
+                columns.join(",")
+            )));
+        }
+        let mut entry = Entry::default();
+        for (column, value) in columns.iter().zip(&fields[1..]) {
+            if value.is_empty() {
+                continue;
+            }
+            let number = || -> Result<f32> {
+                value
+                    .parse()
+                    .ok()
+                    .filter(|x: &f32| x.is_finite())
+                    .ok_or_else(|| at(format!("invalid {column} {value}")))
+            };
+            match column.as_str() {
+                "tempo" => {
+                    let tempo = parse_tempo(value);
+                    entry.tempo = Some(tempo.ok_or_else(|| at(format!("invalid tempo {value}")))?);
+                }
+                "pitch" => entry.pitch_semitones = Some(number()?),
+                "gain" => entry.gain_db = Some(number()?),
+                "format" => {
+                    let format = value.to_ascii_lowercase();
+                    if !FORMATS.contains(&format.as_str()) {
+                        return Err(at(format!(
+                            "invalid format {value}; expected one of {}",
+                            FORMATS.join(", ")
+                        )));
+                    }
+                    entry.format = Some(format);
+                }
+                _ => entry.output = Some(paths::from_text(value)),
+            }
+        }
+        let file = base.join(paths::from_text(&fields[0]));
+        let key = fs::canonicalize(&file)
+            .map_err(|e| Error::io(format!("Tempo manifest {:?} lists {:?}", path, file), e))?;
+        entries.insert(key, entry);
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tempo_only_manifests() {
+        let dir = tempfile::tempdir().unwrap();
+        let sub = dir.path().join("sub");
+        fs::create_dir(&sub).unwrap();
+        for name in ["a.wav", "sub/b.wav", "sub/c.wav"] {
+            fs::write(dir.path().join(name), b"").unwrap();
+        }
+        let manifest = dir.path().join("tempos.csv");
+        // As saved on Windows, with a byte order mark and backslashes.
+        let text = "\u{feff}path,tempo\r\n# slow this one\r\nsub\\b.wav, 0.9\r\n";
+        fs::write(&manifest, text).unwrap();
+        let tempos = load(&manifest).unwrap();
+        let b = fs::canonicalize(sub.join("b.wav")).unwrap();
+        let entry = Entry {
+            tempo: Some(0.9),
+            ..Entry::default()
+        };
+        assert_eq!(tempos, HashMap::from([(b, entry)]));
+        fs::write(&manifest, "sub/c.wav,fast\n").unwrap();
+        let err = load(&manifest).unwrap_err().to_string();
+        assert!(err.contains("line 1: invalid tempo fast"), "{err}");
+    }
+
+    #[test]
+    fn test_overrides_by_column() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["a.wav", "b, take 2.wav"] {
+            fs::write(dir.path().join(name), b"").unwrap();
+        }
+        let manifest = dir.path().join("job.csv");
+        fs::write(
+            &manifest,
+            "path,pitch,gain,format,output\na.wav,-2,,flac,\n\"b, take 2.wav\",,3.5,,final/b.wav\n",
+        )
+        .unwrap();
+        let entries = load(&manifest).unwrap();
+        let a = &entries[&fs::canonicalize(dir.path().join("a.wav")).unwrap()];
+        assert_eq!(
+            (a.tempo, a.pitch_semitones, a.gain_db),
+            (None, Some(-2.0), None)
+        );
+        let out = Path::new("out");
+        assert_eq!(
+            a.output_path(Path::new("out/x/a.wav"), out),
+            Path::new("out/x/a.flac")
+        );
+        let b = &entries[&fs::canonicalize(dir.path().join("b, take 2.wav")).unwrap()];
+        assert_eq!(b.gain_db, Some(3.5));
+        assert_eq!(
+            b.output_path(Path::new("out/b, take 2.wav"), out),
+            Path::new("out/final/b.wav")
+        );
+
+        fs::write(&manifest, "path,speed\na.wav,2\n").unwrap();
+        let err = load(&manifest).unwrap_err().to_string();
+        assert!(err.contains("line 1: unknown column \"speed\""), "{err}");
+        fs::write(&manifest, "path,format\na.wav,mp3\n").unwrap();
+        let err = load(&manifest).unwrap_err().to_string();
+        assert!(err.contains("line 2: invalid format mp3"), "{err}");
+    }
+}
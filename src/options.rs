@@ -0,0 +1,693 @@
+//! Processing options shared by the CLI and library callers.
+
+use crate::backend::{self, BACKENDS};
+use crate::decode::DecodeLimits;
+use crate::denoise::NoiseSource;
+use crate::error::{Error, Result};
+use crate::format::{AudioFormat, SampleType};
+use crate::pacing::Pacer;
+use crate::progress::FileProgress;
+use crate::tempo::TempoMap;
+use crate::validate::Profile;
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// Stretch engine configuration preset.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Quality {
+    /// Signalsmith's default preset, tuned for quality.
+    #[default]
+    Default,
+    /// Smaller blocks and intervals; noticeably faster, slightly lower quality.
+    Cheaper,
+    /// Explicit analysis block length and hop between blocks, in
+    /// milliseconds. Longer blocks resolve low tones better; shorter
+    /// intervals cost more time but smear transients less.
+    Custom { block_ms: u32, interval_ms: u32 },
+}
+
+impl Quality {
+    /// Block length and interval in milliseconds, as the presets set them.
+    pub fn millis(self) -> (u32, u32) {
+        match self {
+            Self::Default => (120, 30),
+            Self::Cheaper => (100, 40),
+            Self::Custom {
+                block_ms,
+                interval_ms,
+            } => (block_ms, interval_ms),
+        }
+    }
+}
+
+/// Time-stretch algorithm.
+///
+/// Each backend sits behind a Cargo feature (`ssstretch`, `vocoder`) and is
+/// registered in [`crate::backend::BACKENDS`]; [`Algorithm::available`]
+/// lists the ones in this build.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Algorithm {
+    /// The Signalsmith Stretch engine (via the ssstretch bindings).
+    Signalsmith,
+    /// The built-in pure-Rust phase vocoder. Does not pitch-shift.
+    PhaseVocoder { fft_size: usize, overlap: usize },
+}
+
+impl Default for Algorithm {
+    /// Signalsmith when compiled in, otherwise the phase vocoder.
+    fn default() -> Self {
+        if cfg!(feature = "ssstretch") {
+            Self::Signalsmith
+        } else {
+            Self::PhaseVocoder {
+                fft_size: 2048,
+                overlap: 4,
+            }
+        }
+    }
+}
+
+impl Algorithm {
+    /// Names of the algorithms compiled into this build.
+    pub fn available() -> Vec<&'static str> {
+        BACKENDS.iter().map(|backend| backend.name).collect()
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Signalsmith => "ssstretch",
+            Self::PhaseVocoder { .. } => "pv",
+        }
+    }
+
+    pub fn is_available(&self) -> bool {
+        backend::find(*self).is_some()
+    }
+}
+
+/// Output level normalization applied after stretching.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Normalization {
+    #[default]
+    None,
+    /// Scale so the highest sample reaches this level in dBFS.
+    Peak(f32),
+    /// Scale so integrated loudness (BS.1770) reaches this level in LUFS.
+    Loudness(f32),
+    /// Apply this fixed gain in dB.
+    Gain(f32),
+}
+
+impl FromStr for Normalization {
+    type Err = Error;
+
+    /// Parses targets such as `-1dBFS` or `-16LUFS` (case-insensitive).
+    fn from_str(s: &str) -> Result<Self> {
+        let lower = s.trim().to_ascii_lowercase();
+        let (value, make): (&str, fn(f32) -> Self) = if let Some(v) = lower.strip_suffix("lufs") {
+            (v, Self::Loudness)
+        } else if let Some(v) = lower.strip_suffix("dbfs") {
+            (v, Self::Peak)
+        } else {
+            return Err(Error::invalid(format!(
+                "Normalization target {s:?} must end in dBFS or LUFS"
+            )));
+        };
+        let level: f32 = value
+            .trim()
+            .parse()
+            .map_err(|_| Error::invalid(format!("Invalid normalization level in {s:?}")))?;
+        if level > 0.0 {
+            return Err(Error::invalid(format!(
+                "Normalization target {s:?} must not exceed 0"
+            )));
+        }
+        Ok(make(level))
+    }
+}
+
+/// How the tempo is changed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Mode {
+    /// Time-stretch, keeping the pitch.
+    #[default]
+    Stretch,
+    /// Resample, so the pitch follows the tempo as when a record is played
+    /// faster or slower.
+    Vinyl,
+}
+
+/// What happens to non-audio chunks (LIST/INFO, bext, cue, ...) of the input.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MetadataPolicy {
+    /// Write audio only.
+    #[default]
+    Drop,
+    /// Copy the input's metadata chunks onto the output unchanged.
+    Preserve,
+}
+
+/// Output format overrides; unset fields keep the input's value.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OutputFormat {
+    pub sample_rate: Option<u32>,
+    pub channels: Option<u16>,
+    pub sample_type: Option<SampleType>,
+}
+
+impl OutputFormat {
+    /// The format written for an input in `input`.
+    pub fn resolve(&self, input: AudioFormat) -> AudioFormat {
+        AudioFormat {
+            sample_rate: self.sample_rate.unwrap_or(input.sample_rate),
+            channels: self.channels.unwrap_or(input.channels),
+            sample_type: self.sample_type.unwrap_or(input.sample_type),
+        }
+    }
+
+    /// These overrides with every unset field taken from `format`, so all
+    /// outputs come out in one format whatever their inputs.
+    pub fn conform_to(self, format: AudioFormat) -> Self {
+        Self {
+            sample_rate: self.sample_rate.or(Some(format.sample_rate)),
+            channels: self.channels.or(Some(format.channels)),
+            sample_type: self.sample_type.or(Some(format.sample_type)),
+        }
+    }
+}
+
+/// Everything that controls how a single file is processed.
+///
+/// Built with [`ProcessOptions::builder`], so new options can be added
+/// without breaking callers.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct ProcessOptions {
+    /// Tempo multiplier (>1.0 speeds up).
+    pub tempo: f32,
+    /// When set, the tempo ramps linearly from `tempo` at the start of the
+    /// file to this value at the end.
+    pub tempo_end: Option<f32>,
+    /// When set, the tempo follows this map over the file instead of
+    /// `tempo`.
+    pub tempo_map: Option<TempoMap>,
+    /// Spend the stretch unevenly: steady-state regions such as vowels are
+    /// stretched more than transients such as consonant onsets, keeping the
+    /// overall duration.
+    pub syllable_aware: bool,
+    /// Pitch shift in semitones, independent of tempo.
+    pub pitch_semitones: f32,
+    pub mode: Mode,
+    pub quality: Quality,
+    pub algorithm: Algorithm,
+    /// Experimental: stretch only this band, `(low, high)` in Hz, as
+    /// configured, and the rest of the signal at the cheaper preset.
+    pub band_split: Option<(f32, f32)>,
+    pub normalization: Normalization,
+    pub metadata: MetadataPolicy,
+    /// Rebuild clipped peaks before stretching.
+    pub declip: bool,
+    /// Remove short impulsive clicks before stretching.
+    pub declick: bool,
+    /// Spectral-gating noise reduction before stretching.
+    pub denoise: Option<NoiseSource>,
+    /// Bounds on the inputs accepted for decoding.
+    pub limits: DecodeLimits,
+    /// When set, inputs at any other sample rate are rejected.
+    pub required_rate: Option<u32>,
+    /// Sample rate, channel, and sample type conversion of the output.
+    pub output: OutputFormat,
+    /// Also write a copy with a metronome at this BPM mixed in.
+    pub click_overlay: Option<f32>,
+    /// Read each output back after writing and fail if it doesn't match.
+    pub verify_output: bool,
+    /// Fail outputs whose header departs from the RIFF spec in any way.
+    pub header_strict: bool,
+    /// Tag each output with this run ID in a `LIST/INFO` chunk.
+    pub run_id: Option<String>,
+    /// Throttle stretching to this pace, block by block; share one pacer
+    /// between workers to cap the whole batch.
+    pub pacer: Option<Arc<Pacer>>,
+    /// Count the frames stretched into this, block by block.
+    pub progress: Option<Arc<FileProgress>>,
+}
+
+impl Default for ProcessOptions {
+    fn default() -> Self {
+        Self {
+            tempo: 1.0,
+            tempo_end: None,
+            tempo_map: None,
+            mode: Mode::Stretch,
+            syllable_aware: false,
+            pitch_semitones: 0.0,
+            quality: Quality::Default,
+            algorithm: Algorithm::default(),
+            band_split: None,
+            normalization: Normalization::None,
+            metadata: MetadataPolicy::Drop,
+            declip: false,
+            declick: false,
+            denoise: None,
+            limits: DecodeLimits::default(),
+            required_rate: None,
+            output: OutputFormat::default(),
+            click_overlay: None,
+            verify_output: false,
+            header_strict: false,
+            run_id: None,
+            pacer: None,
+            progress: None,
+        }
+    }
+}
+
+impl ProcessOptions {
+    pub fn builder() -> ProcessOptionsBuilder {
+        ProcessOptionsBuilder::default()
+    }
+
+    /// Rejects inputs in `format` unless they are at [`Self::required_rate`],
+    /// when set. Every channel count and sample type the decoder reads is
+    /// accepted, and outputs keep them unless [`Self::output`] overrides.
+    pub fn check_input(&self, format: &AudioFormat) -> Result<()> {
+        Profile::from(AudioFormat {
+            sample_rate: self.required_rate.unwrap_or(format.sample_rate),
+            ..*format
+        })
+        .check_format(format)
+    }
+
+    /// Whether an input in `format` comes out with the very same samples, so
+    /// it can be copied without ever leaving the integer domain.
+    pub fn is_passthrough(&self, format: AudioFormat) -> bool {
+        !self.needs_stretch()
+            && self.normalization == Normalization::None
+            && !self.declip
+            && !self.declick
+            && self.denoise.is_none()
+            && self.click_overlay.is_none()
+            && self.output.resolve(format) == format
+    }
+
+    /// Whether the stretch engine has to run at all.
+    pub fn needs_stretch(&self) -> bool {
+        self.tempo != 1.0
+            || self.tempo_end.is_some_and(|end| end != self.tempo)
+            || self.tempo_map.is_some()
+            || self.pitch_semitones != 0.0
+    }
+}
+
+/// Builder for [`ProcessOptions`]; `build` validates the combination.
+#[derive(Debug, Clone, Default)]
+pub struct ProcessOptionsBuilder {
+    options: ProcessOptions,
+}
+
+impl ProcessOptionsBuilder {
+    pub fn tempo(mut self, tempo: f32) -> Self {
+        self.options.tempo = tempo;
+        self
+    }
+
+    pub fn tempo_end(mut self, tempo_end: Option<f32>) -> Self {
+        self.options.tempo_end = tempo_end;
+        self
+    }
+
+    pub fn tempo_map(mut self, tempo_map: Option<TempoMap>) -> Self {
+        self.options.tempo_map = tempo_map;
+        self
+    }
+
+    pub fn syllable_aware(mut self, enabled: bool) -> Self {
+        self.options.syllable_aware = enabled;
+        self
+    }
+
+    pub fn pitch_semitones(mut self, semitones: f32) -> Self {
+        self.options.pitch_semitones = semitones;
+        self
+    }
+
+    pub fn mode(mut self, mode: Mode) -> Self {
+        self.options.mode = mode;
+        self
+    }
+
+    pub fn quality(mut self, quality: Quality) -> Self {
+        self.options.quality = quality;
+        self
+    }
+
+    pub fn algorithm(mut self, algorithm: Algorithm) -> Self {
+        self.options.algorithm = algorithm;
+        self
+    }
+
+    pub fn band_split(mut self, band: Option<(f32, f32)>) -> Self {
+        self.options.band_split = band;
+        self
+    }
+
+    pub fn normalization(mut self, normalization: Normalization) -> Self {
+        self.options.normalization = normalization;
+        self
+    }
+
+    pub fn metadata(mut self, metadata: MetadataPolicy) -> Self {
+        self.options.metadata = metadata;
+        self
+    }
+
+    pub fn declip(mut self, enabled: bool) -> Self {
+        self.options.declip = enabled;
+        self
+    }
+
+    pub fn declick(mut self, enabled: bool) -> Self {
+        self.options.declick = enabled;
+        self
+    }
+
+    pub fn denoise(mut self, source: Option<NoiseSource>) -> Self {
+        self.options.denoise = source;
+        self
+    }
+
+    pub fn required_rate(mut self, rate: Option<u32>) -> Self {
+        self.options.required_rate = rate;
+        self
+    }
+
+    pub fn limits(mut self, limits: DecodeLimits) -> Self {
+        self.options.limits = limits;
+        self
+    }
+
+    pub fn output(mut self, output: OutputFormat) -> Self {
+        self.options.output = output;
+        self
+    }
+
+    pub fn click_overlay(mut self, bpm: Option<f32>) -> Self {
+        self.options.click_overlay = bpm;
+        self
+    }
+
+    pub fn verify_output(mut self, enabled: bool) -> Self {
+        self.options.verify_output = enabled;
+        self
+    }
+
+    pub fn header_strict(mut self, enabled: bool) -> Self {
+        self.options.header_strict = enabled;
+        self
+    }
+
+    pub fn run_id(mut self, run_id: Option<String>) -> Self {
+        self.options.run_id = run_id;
+        self
+    }
+
+    pub fn pacer(mut self, pacer: Option<Arc<Pacer>>) -> Self {
+        self.options.pacer = pacer;
+        self
+    }
+
+    pub fn progress(mut self, progress: Option<Arc<FileProgress>>) -> Self {
+        self.options.progress = progress;
+        self
+    }
+
+    pub fn build(self) -> Result<ProcessOptions> {
+        let options = self.options;
+        if !(options.tempo.is_finite() && options.tempo > 0.0) {
+            return Err(Error::invalid(format!(
+                "Tempo must be a positive number, got {}",
+                options.tempo
+            )));
+        }
+        if let Some(end) = options.tempo_end
+            && !(end.is_finite() && end > 0.0)
+        {
+            return Err(Error::invalid(format!(
+                "Tempo ramp end must be a positive number, got {end}"
+            )));
+        }
+        if options.syllable_aware && (options.tempo_end.is_some() || options.tempo_map.is_some()) {
+            return Err(Error::invalid(
+                "Syllable-aware stretching needs a constant tempo, not a ramp",
+            ));
+        }
+        if options.tempo_end.is_some() && options.tempo_map.is_some() {
+            return Err(Error::invalid(
+                "A tempo map and a tempo ramp can't be combined",
+            ));
+        }
+        if let Quality::Custom {
+            block_ms,
+            interval_ms,
+        } = options.quality
+            && !(interval_ms > 0 && interval_ms <= block_ms)
+        {
+            return Err(Error::invalid(format!(
+                "Stretch interval must be positive and at most the block length, got {interval_ms} ms for {block_ms} ms blocks"
+            )));
+        }
+        if !options.pitch_semitones.is_finite() {
+            return Err(Error::invalid(
+                "Pitch shift must be a finite number of semitones",
+            ));
+        }
+        if options.mode == Mode::Vinyl
+            && (options.tempo_end.is_some()
+                || options.tempo_map.is_some()
+                || options.syllable_aware
+                || options.pitch_semitones != 0.0)
+        {
+            return Err(Error::invalid(
+                "Vinyl mode needs a constant tempo, and its pitch follows the tempo",
+            ));
+        }
+        if options.mode == Mode::Stretch
+            && options.needs_stretch()
+            && !options.algorithm.is_available()
+        {
+            return Err(Error::invalid(format!(
+                "The {} algorithm is not compiled into this build (available: {})",
+                options.algorithm.name(),
+                Algorithm::available().join(", ")
+            )));
+        }
+        if let Algorithm::PhaseVocoder { fft_size, overlap } = options.algorithm {
+            if !(fft_size.is_power_of_two() && fft_size >= 64) {
+                return Err(Error::invalid(format!(
+                    "FFT size must be a power of two of at least 64, got {fft_size}"
+                )));
+            }
+            if overlap < 2 || fft_size % overlap != 0 {
+                return Err(Error::invalid(format!(
+                    "Overlap must be at least 2 and divide the FFT size, got {overlap}"
+                )));
+            }
+            if options.pitch_semitones != 0.0 {
+                return Err(Error::invalid(
+                    "The phase vocoder does not support pitch shifting",
+                ));
+            }
+        }
+        if let Some((low, high)) = options.band_split {
+            if !(low >= 0.0 && low < high && high.is_finite()) {
+                return Err(Error::invalid(format!(
+                    "Band split needs 0 <= low < high Hz, got {low}:{high}"
+                )));
+            }
+            if options.mode == Mode::Vinyl || options.syllable_aware {
+                return Err(Error::invalid(
+                    "Band-split stretching works in stretch mode, without syllable-aware tempos",
+                ));
+            }
+        }
+        if let Some(bpm) = options.click_overlay
+            && !(bpm.is_finite() && bpm > 0.0)
+        {
+            return Err(Error::invalid(format!(
+                "Click overlay BPM must be positive, got {bpm}"
+            )));
+        }
+        if options.required_rate == Some(0) {
+            return Err(Error::invalid("Required sample rate must be positive"));
+        }
+        let output = options.output;
+        if output.sample_rate == Some(0) || output.channels == Some(0) {
+            return Err(Error::invalid(
+                "Output sample rate and channel count must be positive",
+            ));
+        }
+        match output.sample_type {
+            None | Some(SampleType::Int(8 | 16 | 24 | 32)) | Some(SampleType::Float(32)) => {}
+            Some(other) => {
+                return Err(Error::invalid(format!(
+                    "Unsupported output sample type {other}"
+                )));
+            }
+        }
+        Ok(options)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builder_validates_tempo() {
+        assert!(ProcessOptions::builder().tempo(0.0).build().is_err());
+        assert!(ProcessOptions::builder().tempo(f32::NAN).build().is_err());
+        let float64 = OutputFormat {
+            sample_type: Some(SampleType::Float(64)),
+            ..OutputFormat::default()
+        };
+        assert!(ProcessOptions::builder().output(float64).build().is_err());
+        let vocoder = |fft_size, overlap| Algorithm::PhaseVocoder { fft_size, overlap };
+        assert!(
+            ProcessOptions::builder()
+                .algorithm(vocoder(2048, 4))
+                .build()
+                .is_ok()
+        );
+        assert!(
+            ProcessOptions::builder()
+                .algorithm(vocoder(1000, 4))
+                .build()
+                .is_err()
+        );
+        assert!(
+            ProcessOptions::builder()
+                .algorithm(vocoder(2048, 3))
+                .build()
+                .is_err()
+        );
+        let options = ProcessOptions::builder()
+            .tempo(1.2)
+            .quality(Quality::Cheaper)
+            .build()
+            .unwrap();
+        assert_eq!(options.tempo, 1.2);
+        assert_eq!(options.quality, Quality::Cheaper);
+        assert!(options.needs_stretch());
+        let uneven = Quality::Custom {
+            block_ms: 40,
+            interval_ms: 60,
+        };
+        assert!(ProcessOptions::builder().quality(uneven).build().is_err());
+    }
+
+    #[test]
+    #[cfg(not(feature = "ssstretch"))]
+    fn test_left_out_backend_is_rejected() {
+        let signalsmith = ProcessOptions {
+            tempo: 1.5,
+            algorithm: Algorithm::Signalsmith,
+            ..ProcessOptions::default()
+        };
+        let err = ProcessOptions::builder()
+            .tempo(1.5)
+            .algorithm(Algorithm::Signalsmith)
+            .build()
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "The ssstretch algorithm is not compiled into this build (available: {})",
+                Algorithm::available().join(", ")
+            )
+        );
+        // Options made without the builder fail when used, not by panicking.
+        let samples = vec![0.0; 1600];
+        let buffer = crate::AudioBuffer::new(AudioFormat::default(), samples.clone());
+        let failures = [
+            crate::transform(buffer, &signalsmith).map(drop),
+            crate::stretch(&samples, 16000, &signalsmith).map(drop),
+            crate::stretch_channels(&[&samples, &samples], 16000, &signalsmith).map(drop),
+        ];
+        for err in failures.into_iter().map(Result::unwrap_err) {
+            assert!(
+                err.to_string().contains("not compiled into this build"),
+                "{err}"
+            );
+        }
+        // Keeping the tempo needs no backend at all.
+        assert!(
+            ProcessOptions::builder()
+                .algorithm(Algorithm::Signalsmith)
+                .build()
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_normalization_from_str() {
+        assert_eq!(
+            "-1dBFS".parse::<Normalization>().unwrap(),
+            Normalization::Peak(-1.0)
+        );
+        assert_eq!(
+            "-16 LUFS".parse::<Normalization>().unwrap(),
+            Normalization::Loudness(-16.0)
+        );
+        assert!("-16".parse::<Normalization>().is_err());
+        assert!("3dBFS".parse::<Normalization>().is_err());
+    }
+
+    #[test]
+    fn test_conformed_output_ignores_input() {
+        let stereo = AudioFormat {
+            sample_rate: 44100,
+            channels: 2,
+            sample_type: SampleType::Float(32),
+        };
+        let mirror = OutputFormat {
+            channels: Some(1),
+            ..OutputFormat::default()
+        };
+        assert_eq!(mirror.resolve(stereo).sample_rate, 44100);
+        let conform = mirror.conform_to(AudioFormat::default());
+        let expected = AudioFormat {
+            channels: 1,
+            ..AudioFormat::default()
+        };
+        assert_eq!(conform.resolve(stereo), expected);
+    }
+
+    #[test]
+    fn test_any_rate_unless_required() {
+        let music = AudioFormat {
+            sample_rate: 44100,
+            channels: 2,
+            ..AudioFormat::default()
+        };
+        let options = ProcessOptions::default();
+        assert!(options.check_input(&music).is_ok());
+        let strict = ProcessOptions::builder()
+            .required_rate(Some(16000))
+            .build()
+            .unwrap();
+        assert!(strict.check_input(&music).is_err());
+        assert!(strict.check_input(&AudioFormat::default()).is_ok());
+        for sample_type in [
+            SampleType::Int(8),
+            SampleType::Int(24),
+            SampleType::Float(32),
+        ] {
+            let format = AudioFormat {
+                sample_type,
+                ..music
+            };
+            assert!(options.check_input(&format).is_ok());
+            assert_eq!(options.output.resolve(format), format);
+        }
+    }
+}
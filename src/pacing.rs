@@ -0,0 +1,59 @@
+//! Throughput throttling so long batches don't peg every core for hours.
+
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Caps processing speed at a multiple of real time, shared by all workers.
+#[derive(Debug)]
+pub struct Pacer {
+    factor: f64,
+    start: Instant,
+    audio_secs: Mutex<f64>,
+}
+
+impl Pacer {
+    /// `factor` is the maximum audio seconds processed per wall-clock second.
+    pub fn new(factor: f64) -> Self {
+        Self {
+            factor,
+            start: Instant::now(),
+            audio_secs: Mutex::new(0.0),
+        }
+    }
+
+    /// Records `secs` of processed audio and sleeps while the batch is ahead of schedule.
+    pub fn pace(&self, secs: f64) {
+        let target = {
+            let Ok(mut total) = self.audio_secs.lock() else {
+                return;
+            };
+            *total += secs;
+            Duration::from_secs_f64(*total / self.factor)
+        };
+        if let Some(ahead) = target.checked_sub(self.start.elapsed()) {
+            thread::sleep(ahead);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pace_sleeps_when_ahead() {
+        let pacer = Pacer::new(2.0);
+        let start = Instant::now();
+        pacer.pace(0.1);
+        assert!(start.elapsed() >= Duration::from_millis(50));
+    }
+
+    #[test]
+    fn test_pace_does_not_sleep_when_behind() {
+        let pacer = Pacer::new(1000.0);
+        let start = Instant::now();
+        pacer.pace(0.01);
+        assert!(start.elapsed() < Duration::from_millis(5));
+    }
+}
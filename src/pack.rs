@@ -0,0 +1,99 @@
+//! Packing short outputs into longer ones: consecutive files are joined,
+//! with a gap of silence between them, up to a target duration, so
+//! sequence models train on examples of an efficient length.
+
+use crate::format::{AudioBuffer, AudioFormat};
+use std::path::{Path, PathBuf};
+
+/// One file to be packed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Piece {
+    pub path: PathBuf,
+    pub format: AudioFormat,
+    pub secs: f64,
+}
+
+/// Groups `pieces`, in order, into packs of at most `target` seconds
+/// including a `gap` between neighbours, returning each pack's indices.
+///
+/// Only consecutive pieces in the same directory and format share a pack;
+/// a piece longer than `target` makes a pack on its own.
+pub fn plan(pieces: &[Piece], target: f64, gap: f64) -> Vec<Vec<usize>> {
+    let mut packs: Vec<Vec<usize>> = Vec::new();
+    let mut length = 0.0;
+    for (i, piece) in pieces.iter().enumerate() {
+        let joins = packs
+            .last()
+            .and_then(|pack| pack.last())
+            .is_some_and(|&last| {
+                let previous = &pieces[last];
+                previous.format == piece.format
+                    && previous.path.parent() == piece.path.parent()
+                    && length + gap + piece.secs <= target
+            });
+        match packs.last_mut() {
+            Some(pack) if joins => {
+                pack.push(i);
+                length += gap + piece.secs;
+            }
+            _ => {
+                packs.push(vec![i]);
+                length = piece.secs;
+            }
+        }
+    }
+    packs
+}
+
+/// `buffers`, all in the same format, one after another with `gap` seconds
+/// of silence between them, and the frame at which each starts.
+pub fn join(buffers: &[AudioBuffer], gap: f64) -> (AudioBuffer, Vec<usize>) {
+    let format = buffers.first().map(|b| b.format).unwrap_or_default();
+    let channels = format.channels.max(1) as usize;
+    let gap = (gap * f64::from(format.sample_rate)).round() as usize * channels;
+    let mut samples = Vec::new();
+    let mut starts = Vec::new();
+    for (k, buffer) in buffers.iter().enumerate() {
+        if k > 0 {
+            samples.resize(samples.len() + gap, 0.0);
+        }
+        starts.push(samples.len() / channels);
+        samples.extend_from_slice(&buffer.samples);
+    }
+    (AudioBuffer::new(format, samples), starts)
+}
+
+/// Path of the `number`th pack (counting from 1) written into `dir`.
+pub fn pack_path(dir: &Path, number: usize) -> PathBuf {
+    dir.join(format!("pack_{number:05}.wav"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plan_and_join() {
+        let format = AudioFormat::default();
+        let piece = |path: &str, secs| Piece {
+            path: PathBuf::from(path),
+            format,
+            secs,
+        };
+        let pieces = [
+            piece("a/1.wav", 10.0),
+            piece("a/2.wav", 12.0),
+            piece("a/3.wav", 9.0),
+            piece("a/4.wav", 40.0),
+            piece("b/5.wav", 5.0),
+            piece("b/6.wav", 5.0),
+        ];
+        let packs = plan(&pieces, 30.0, 0.5);
+        assert_eq!(packs, [vec![0, 1], vec![2], vec![3], vec![4, 5]]);
+
+        let one = AudioBuffer::new(format, vec![0.5; 3]);
+        let (joined, starts) = join(&[one.clone(), one], 2.0 / f64::from(format.sample_rate));
+        assert_eq!(joined.samples, [0.5, 0.5, 0.5, 0.0, 0.0, 0.5, 0.5, 0.5]);
+        assert_eq!(starts, [0, 5]);
+    }
+}
@@ -0,0 +1,88 @@
+//! Paths as text in the files the tool reads and writes, so manifests and
+//! reports made on Windows can be used on Linux, and the other way round.
+
+use std::path::{Component, Path, PathBuf, Prefix};
+
+/// How paths are spelled in reports and other written files.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PathStyle {
+    /// As the platform spells them.
+    #[default]
+    Native,
+    /// With `/` separators, and drive letters without Windows' `\\?\`
+    /// prefix, e.g. `C:/data/a.wav`.
+    Posix,
+}
+
+/// `path` as text in `style`, as UTF-8 with anything else replaced by U+FFFD.
+pub fn to_text(path: &Path, style: PathStyle) -> String {
+    if style == PathStyle::Native {
+        return path.to_string_lossy().into_owned();
+    }
+    let mut text = String::new();
+    for component in path.components() {
+        match component {
+            Component::Prefix(prefix) => match prefix.kind() {
+                Prefix::Disk(drive) | Prefix::VerbatimDisk(drive) => {
+                    text.push(char::from(drive));
+                    text.push(':');
+                }
+                Prefix::UNC(server, share) | Prefix::VerbatimUNC(server, share) => {
+                    text.push_str(&format!(
+                        "//{}/{}",
+                        server.to_string_lossy(),
+                        share.to_string_lossy()
+                    ));
+                }
+                _ => text.push_str(&prefix.as_os_str().to_string_lossy()),
+            },
+            Component::RootDir => text.push('/'),
+            other => {
+                if !(text.is_empty() || text.ends_with('/')) {
+                    text.push('/');
+                }
+                text.push_str(&other.as_os_str().to_string_lossy());
+            }
+        }
+    }
+    text
+}
+
+/// A path read from a manifest or report, which may have been written on
+/// another platform: outside Windows, `\` separates components too.
+pub fn from_text(text: &str) -> PathBuf {
+    if cfg!(windows) {
+        PathBuf::from(text)
+    } else {
+        PathBuf::from(text.replace('\\', "/"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_posix_text_round_trips() {
+        let native = Path::new("/data/corpus/../a b/ç.wav");
+        assert_eq!(
+            to_text(native, PathStyle::Native),
+            "/data/corpus/../a b/ç.wav"
+        );
+        assert_eq!(
+            to_text(native, PathStyle::Posix),
+            "/data/corpus/../a b/ç.wav"
+        );
+        assert_eq!(
+            to_text(Path::new("out/x.wav"), PathStyle::Posix),
+            "out/x.wav"
+        );
+        assert_eq!(
+            from_text(&to_text(Path::new("out/x.wav"), PathStyle::Posix)),
+            Path::new("out/x.wav")
+        );
+        if !cfg!(windows) {
+            assert_eq!(from_text(r"sub\dir\a.wav"), Path::new("sub/dir/a.wav"));
+        }
+    }
+}
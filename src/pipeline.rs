@@ -0,0 +1,322 @@
+//! Decode → stretch → encode pipeline with a separate worker pool per stage.
+//!
+//! I/O-bound stages can be oversubscribed (e.g. on NAS-backed storage)
+//! without also oversubscribing the CPU-bound stretch stage.
+
+use crate::atomic;
+use crate::error::{Error, Result};
+use std::any::Any;
+use std::ops::ControlFlow;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{Receiver, RecvTimeoutError, channel, sync_channel};
+use std::thread;
+use std::time::Instant;
+
+/// I/O-bound stages get this many workers when left on auto.
+const AUTO_IO_THREADS: usize = 2;
+
+/// Worker counts for each pipeline stage.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Threads {
+    pub decode: usize,
+    pub stretch: usize,
+    pub encode: usize,
+}
+
+impl Threads {
+    /// Fills unset stages: stretching gets `jobs` workers, or one per core,
+    /// and I/O stages two each, or `jobs` if fewer.
+    pub fn resolve(
+        jobs: Option<usize>,
+        decode: Option<usize>,
+        stretch: Option<usize>,
+        encode: Option<usize>,
+    ) -> Self {
+        let cores = thread::available_parallelism().map_or(1, |n| n.get());
+        let io = jobs.map_or(AUTO_IO_THREADS, |jobs| jobs.min(AUTO_IO_THREADS));
+        Self {
+            decode: decode.unwrap_or(io).max(1),
+            stretch: stretch.or(jobs).unwrap_or(cores).max(1),
+            encode: encode.unwrap_or(io).max(1),
+        }
+    }
+}
+
+/// Runs every job through the three stages and reports each outcome via `on_done`,
+/// with the encode stage's result on success. Jobs are pulled from `jobs` as
+/// the decoders free up, so they may still be arriving when the first ones
+/// finish.
+///
+/// A job that fails in any stage skips the remaining ones; other jobs are
+/// unaffected. Once `on_done` breaks, no further jobs are pulled or decoded,
+/// and those already being stretched or encoded finish. Queues between
+/// stages are bounded by the size of the consuming pool, so decoded audio
+/// never piles up in memory.
+pub fn run<J, D, E, R, Err>(
+    jobs: impl IntoIterator<Item = J, IntoIter: Send>,
+    threads: Threads,
+    decode: impl Fn(&J) -> Result<D, Err> + Sync,
+    stretch: impl Fn(&J, D) -> Result<E, Err> + Sync,
+    encode: impl Fn(&J, E) -> Result<R, Err> + Sync,
+    mut on_done: impl FnMut(J, Result<R, Err>) -> ControlFlow<()>,
+) where
+    J: Send,
+    D: Send,
+    E: Send,
+    R: Send,
+    Err: Send,
+{
+    let (job_tx, job_rx) = sync_channel::<J>(threads.decode);
+    let (decoded_tx, decoded_rx) = sync_channel::<(J, D)>(threads.stretch);
+    let (stretched_tx, stretched_rx) = sync_channel::<(J, E)>(threads.encode);
+    let (done_tx, done_rx) = channel::<(J, Result<R, Err>)>();
+    let (job_rx, decoded_rx, stretched_rx) = (
+        Mutex::new(job_rx),
+        Mutex::new(decoded_rx),
+        Mutex::new(stretched_rx),
+    );
+    let (decode, stretch, encode) = (&decode, &stretch, &encode);
+    let stopping = &AtomicBool::new(false);
+
+    let jobs = jobs.into_iter();
+    thread::scope(|s| {
+        s.spawn(move || {
+            for job in jobs {
+                if stopping.load(Ordering::Relaxed) || job_tx.send(job).is_err() {
+                    break;
+                }
+            }
+        });
+
+        for _ in 0..threads.decode {
+            let (tx, done, rx) = (decoded_tx.clone(), done_tx.clone(), &job_rx);
+            s.spawn(move || {
+                while let Some(job) = next(rx) {
+                    if stopping.load(Ordering::Relaxed) {
+                        continue;
+                    }
+                    match decode(&job) {
+                        Ok(data) => {
+                            let _ = tx.send((job, data));
+                        }
+                        Err(e) => {
+                            let _ = done.send((job, Err(e)));
+                        }
+                    }
+                }
+            });
+        }
+        drop(decoded_tx);
+
+        for _ in 0..threads.stretch {
+            let (tx, done, rx) = (stretched_tx.clone(), done_tx.clone(), &decoded_rx);
+            s.spawn(move || {
+                while let Some((job, data)) = next(rx) {
+                    match stretch(&job, data) {
+                        Ok(data) => {
+                            let _ = tx.send((job, data));
+                        }
+                        Err(e) => {
+                            let _ = done.send((job, Err(e)));
+                        }
+                    }
+                }
+            });
+        }
+        drop(stretched_tx);
+
+        for _ in 0..threads.encode {
+            let (done, rx) = (done_tx.clone(), &stretched_rx);
+            s.spawn(move || {
+                while let Some((job, data)) = next(rx) {
+                    let result = encode(&job, data);
+                    let _ = done.send((job, result));
+                }
+            });
+        }
+        drop(done_tx);
+
+        for (job, result) in done_rx {
+            if on_done(job, result).is_break() {
+                stopping.store(true, Ordering::Relaxed);
+            }
+        }
+    });
+}
+
+/// Runs `work` on its own thread, giving up on it once `deadline` passes.
+///
+/// Threads cannot be killed, so work that times out keeps running detached
+/// until it returns, and its result is dropped; its outputs are withdrawn
+/// (see [`atomic::Claim`]), so none it wrote stays in place. Without a
+/// deadline `work` runs inline. If `work` panics, the panic is resumed on
+/// the calling thread.
+pub fn with_deadline<T: Send + 'static>(
+    deadline: Option<Instant>,
+    work: impl FnOnce() -> T + Send + 'static,
+) -> Option<T> {
+    let Some(deadline) = deadline else {
+        return Some(work());
+    };
+    let (tx, rx) = channel();
+    let claim = atomic::Claim::new();
+    let working = claim.clone();
+    thread::spawn(move || {
+        let result = working.run(|| panic::catch_unwind(AssertUnwindSafe(work)));
+        let _ = tx.send(result);
+    });
+    match rx.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+        Ok(Ok(value)) => Some(value),
+        Ok(Err(payload)) => panic::resume_unwind(payload),
+        Err(RecvTimeoutError::Timeout) => {
+            claim.withdraw();
+            None
+        }
+        Err(RecvTimeoutError::Disconnected) => unreachable!("timed work always reports back"),
+    }
+}
+
+/// Runs `work`, turning a panic into [`Error::Internal`] so a bug hit by one
+/// file fails that file instead of the whole batch.
+pub fn catch_internal<T>(work: impl FnOnce() -> Result<T>) -> Result<T> {
+    panic::catch_unwind(AssertUnwindSafe(work))
+        .unwrap_or_else(|payload| Err(Error::Internal(panic_message(&*payload))))
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    match (
+        payload.downcast_ref::<&str>(),
+        payload.downcast_ref::<String>(),
+    ) {
+        (Some(message), _) => message.to_string(),
+        (None, Some(message)) => message.clone(),
+        (None, None) => "panicked".to_string(),
+    }
+}
+
+/// Takes the next item from a receiver shared by a worker pool.
+fn next<T>(rx: &Mutex<Receiver<T>>) -> Option<T> {
+    rx.lock().ok()?.recv().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_run_reports_every_job_once() {
+        let threads = Threads::resolve(None, Some(3), Some(2), Some(1));
+        let mut done = Vec::new();
+        run(
+            0..20,
+            threads,
+            |&j: &i32| -> anyhow::Result<i32> {
+                anyhow::ensure!(j != 3, "decode failed");
+                Ok(j * 2)
+            },
+            |_, d| Ok(d + 1),
+            |&j, e| {
+                anyhow::ensure!(e == j * 2 + 1, "wrong data");
+                anyhow::ensure!(j != 7, "encode failed");
+                Ok(())
+            },
+            |j, result| {
+                done.push((j, result.is_ok()));
+                ControlFlow::Continue(())
+            },
+        );
+        done.sort();
+        assert_eq!(done.len(), 20);
+        let failed: Vec<i32> = done.iter().filter(|(_, ok)| !ok).map(|(j, _)| *j).collect();
+        assert_eq!(failed, vec![3, 7]);
+    }
+
+    #[test]
+    fn test_break_stops_pulling_jobs() {
+        let threads = Threads::resolve(Some(1), None, None, None);
+        let mut done = Vec::new();
+        let seen = AtomicBool::new(false);
+        run(
+            0..1000,
+            threads,
+            |&j: &i32| -> anyhow::Result<i32> {
+                anyhow::ensure!(j != 3, "decode failed");
+                // Later decodes outlast handing the failure over, as real ones
+                // do, instead of racing the whole queue through first.
+                while j > 3 && !seen.load(Ordering::SeqCst) {
+                    thread::yield_now();
+                }
+                Ok(j)
+            },
+            |_, d| Ok(d),
+            |_, e| Ok(e),
+            |j, result| {
+                done.push(j);
+                if result.is_err() {
+                    seen.store(true, Ordering::SeqCst);
+                    ControlFlow::Break(())
+                } else {
+                    ControlFlow::Continue(())
+                }
+            },
+        );
+        // Only the few jobs already past decoding finish after the failure.
+        assert!(done.contains(&3) && done.len() < 10, "{done:?}");
+    }
+
+    #[test]
+    fn test_deadline_abandons_slow_work() {
+        let soon = Some(Instant::now() + Duration::from_millis(50));
+        let slow = with_deadline(soon, || thread::sleep(Duration::from_secs(5)));
+        assert_eq!(slow, None);
+        let later = Some(Instant::now() + Duration::from_secs(5));
+        assert_eq!(with_deadline(later, || 42), Some(42));
+        assert_eq!(with_deadline(None, || 7), Some(7));
+
+        // Output the abandoned work finishes later never appears.
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("late.wav");
+        let (tx, rx) = channel();
+        let target = path.clone();
+        let soon = Some(Instant::now() + Duration::from_millis(20));
+        let late = with_deadline(soon, move || {
+            thread::sleep(Duration::from_millis(100));
+            let _ = tx.send(atomic::write(&target, |temp| {
+                std::fs::write(temp, b"x").map_err(|e| Error::io("write", e))
+            }));
+        });
+        assert_eq!(late, None);
+        assert!(rx.recv().unwrap().is_err());
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_panics_become_internal_errors() {
+        let result: Result<()> = catch_internal(|| panic!("index {} out of range", 9));
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Internal error: index 9 out of range"
+        );
+        let later = Some(Instant::now() + Duration::from_secs(5));
+        let result = catch_internal(|| Ok(with_deadline(later, || panic!("deep"))));
+        assert!(matches!(result, Err(Error::Internal(m)) if m == "deep"));
+        assert_eq!(catch_internal(|| Ok(1)).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_resolve_clamps_to_one_worker() {
+        let threads = Threads::resolve(None, Some(0), Some(0), None);
+        assert_eq!(threads.decode, 1);
+        assert_eq!(threads.stretch, 1);
+        assert_eq!(threads.encode, AUTO_IO_THREADS);
+
+        // --jobs sets every stage left unset; explicit stages still win.
+        let serial = Threads::resolve(Some(1), None, None, None);
+        assert_eq!((serial.decode, serial.stretch, serial.encode), (1, 1, 1));
+        let wide = Threads::resolve(Some(8), None, None, Some(4));
+        assert_eq!((wide.decode, wide.stretch, wide.encode), (2, 8, 4));
+    }
+}
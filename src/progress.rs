@@ -0,0 +1,345 @@
+//! Batch progress snapshots for external monitors, and a status line for
+//! the terminal.
+//!
+//! Besides file counts, each file in flight reports how many of its frames
+//! have been processed, so a long file that is still moving can be told
+//! apart from a hung one.
+//!
+//! Snapshots are written to `progress.json` and printed as NDJSON `progress`
+//! events; [`FileProgress`] moves a whole file's count along as it is
+//! stretched.
+
+use crate::error::{Error, Result};
+use crate::json::Json;
+use crate::paths::{self, PathStyle};
+use crate::run::{self, unix_now};
+use crate::units::format_duration;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Counts and in-flight files of a batch, shared by all workers.
+#[derive(Debug)]
+pub struct Progress {
+    total: AtomicUsize,
+    run_id: String,
+    started: u64,
+    clock: Instant,
+    state: Mutex<State>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    succeeded: usize,
+    failed: usize,
+    current: Vec<PathBuf>,
+    /// When each file in `current` was started.
+    since: Vec<(PathBuf, Instant)>,
+    /// Frames done and in total of files in `current` that have reported.
+    frames: Vec<(PathBuf, usize, usize)>,
+    /// Audio duration of files in `current` whose length is known.
+    durations: Vec<(PathBuf, f64)>,
+    /// Seconds of audio in the files that succeeded.
+    audio_secs: f64,
+    finished: bool,
+}
+
+impl Progress {
+    /// Progress of `total` files in the run `run_id`.
+    pub fn new(total: usize, run_id: &str) -> Self {
+        Self {
+            total: AtomicUsize::new(total),
+            run_id: run_id.to_string(),
+            started: unix_now(),
+            clock: Instant::now(),
+            state: Mutex::default(),
+        }
+    }
+
+    /// Counts `files` more in the batch, for batches still being listed.
+    pub fn add(&self, files: usize) {
+        self.total.fetch_add(files, Ordering::Relaxed);
+    }
+
+    /// Marks `file` as being processed.
+    pub fn start(&self, file: &Path) {
+        if let Ok(mut state) = self.state.lock() {
+            state.current.push(file.to_path_buf());
+            state.since.push((file.to_path_buf(), Instant::now()));
+        }
+    }
+
+    /// Marks `file` as done, successfully or not, returning how long it took
+    /// since [`start`](Self::start).
+    pub fn finish(&self, file: &Path, ok: bool) -> Duration {
+        let mut elapsed = Duration::ZERO;
+        if let Ok(mut state) = self.state.lock() {
+            state.current.retain(|f| f != file);
+            if let Some((_, since)) = state.since.iter().find(|(f, _)| f == file) {
+                elapsed = since.elapsed();
+            }
+            state.since.retain(|(f, _)| f != file);
+            state.frames.retain(|(f, _, _)| f != file);
+            let secs = state.durations.iter().find(|(f, _)| f == file).map(|d| d.1);
+            state.durations.retain(|(f, _)| f != file);
+            if ok {
+                state.audio_secs += secs.unwrap_or(0.0);
+                state.succeeded += 1;
+            } else {
+                state.failed += 1;
+            }
+        }
+        elapsed
+    }
+
+    /// Records that `done` of the `total` frames of `file` have been processed.
+    pub fn advance(&self, file: &Path, done: usize, total: usize) {
+        if let Ok(mut state) = self.state.lock() {
+            match state.frames.iter_mut().find(|(f, _, _)| f == file) {
+                Some(entry) => (entry.1, entry.2) = (done, total),
+                None => state.frames.push((file.to_path_buf(), done, total)),
+            }
+        }
+    }
+
+    /// Records that `file` holds `secs` of audio, counted towards the
+    /// throughput once it succeeds.
+    pub fn duration(&self, file: &Path, secs: f64) {
+        if let Ok(mut state) = self.state.lock() {
+            state.durations.push((file.to_path_buf(), secs));
+        }
+    }
+
+    /// A status line at most about `width` columns wide: a bar and count of
+    /// the files done, audio seconds processed per second, the time left at
+    /// the pace so far, and the file being worked on with how far it has got.
+    pub fn status_line(&self, width: usize) -> String {
+        self.line(self.clock.elapsed(), width)
+    }
+
+    fn line(&self, elapsed: Duration, width: usize) -> String {
+        let state = match self.state.lock() {
+            Ok(state) => state,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        let total = self.total.load(Ordering::Relaxed);
+        let done = state.succeeded + state.failed;
+        let secs = elapsed.as_secs_f64();
+        let filled = (BAR * done).checked_div(total).unwrap_or(0).min(BAR);
+        let mut line = format!(
+            "[{}{}] {done}/{total} files",
+            "#".repeat(filled),
+            "-".repeat(BAR - filled)
+        );
+        if state.failed > 0 {
+            line.push_str(&format!(" ({} failed)", state.failed));
+        }
+        if secs > 0.0 && state.audio_secs > 0.0 {
+            line.push_str(&format!(" | {:.1} audio s/s", state.audio_secs / secs));
+        }
+        if done > 0 && done < total {
+            let left = secs / done as f64 * (total - done) as f64;
+            line.push_str(&format!(" | ETA {}", format_duration(left.round())));
+        }
+        if let Some(file) = state.current.first() {
+            let name = file
+                .file_name()
+                .unwrap_or(file.as_os_str())
+                .to_string_lossy();
+            line.push_str(&format!(" | {name}"));
+            if let Some((_, done, total)) = state.frames.iter().find(|(f, _, _)| f == file)
+                && *total > 0
+            {
+                line.push_str(&format!(" {}%", done * 100 / total));
+            }
+        }
+        if line.chars().count() > width {
+            line = line.chars().take(width.saturating_sub(1)).collect();
+            line.push('…');
+        }
+        line
+    }
+
+    /// Marks the whole batch as done.
+    pub fn close(&self) {
+        if let Ok(mut state) = self.state.lock() {
+            state.finished = true;
+        }
+    }
+
+    /// The current snapshot as a JSON object, stamped with the current time.
+    pub fn to_json(&self) -> String {
+        format!("{}\n", Json::Object(self.fields(PathStyle::Native)))
+    }
+
+    /// The current snapshot as an NDJSON `progress` event, with paths in
+    /// `style`.
+    pub fn to_event(&self, style: PathStyle) -> Json {
+        let mut fields = self.fields(style);
+        fields.insert(0, ("event".to_string(), "progress".into()));
+        Json::Object(fields)
+    }
+
+    fn fields(&self, style: PathStyle) -> Vec<(String, Json)> {
+        let state = match self.state.lock() {
+            Ok(state) => state,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        let path = |p: &Path| Json::from(paths::to_text(p, style));
+        let current: Vec<Json> = state.current.iter().map(|f| path(f)).collect();
+        let frames: Vec<Json> = state
+            .frames
+            .iter()
+            .map(|(file, done, total)| {
+                Json::Object(vec![
+                    ("file".to_string(), path(file)),
+                    ("frames_done".to_string(), Json::from(*done)),
+                    ("frames_total".to_string(), Json::from(*total)),
+                ])
+            })
+            .collect();
+        vec![
+            (
+                "total".to_string(),
+                Json::from(self.total.load(Ordering::Relaxed)),
+            ),
+            ("succeeded".to_string(), Json::from(state.succeeded)),
+            ("failed".to_string(), Json::from(state.failed)),
+            ("current".to_string(), Json::Array(current)),
+            ("frames".to_string(), Json::Array(frames)),
+            ("run_id".to_string(), Json::from(self.run_id.as_str())),
+            (
+                "started".to_string(),
+                Json::from(run::timestamp(self.started)),
+            ),
+            ("updated".to_string(), Json::from(run::now())),
+            ("finished".to_string(), Json::from(state.finished)),
+        ]
+    }
+
+    /// Replaces `path` with the current snapshot, atomically so monitors
+    /// never read a half-written file.
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let temp = path.with_extension("json.tmp");
+        fs::write(&temp, self.to_json())
+            .and_then(|()| fs::rename(&temp, path))
+            .map_err(|e| Error::io(format!("Failed to write progress file {:?}", path), e))
+    }
+}
+
+/// Columns of the status line's bar.
+const BAR: usize = 20;
+
+/// Moves one file's frame count in a [`Progress`] along block by block as
+/// the stretch works through it, for [`crate::ProcessOptions::progress`].
+#[derive(Debug)]
+pub struct FileProgress {
+    progress: Arc<Progress>,
+    file: PathBuf,
+    done: AtomicUsize,
+    total: usize,
+}
+
+impl FileProgress {
+    /// Counts on from `done` of the `total` frames of `file`.
+    pub fn new(progress: Arc<Progress>, file: &Path, done: usize, total: usize) -> Self {
+        progress.advance(file, done, total);
+        Self {
+            progress,
+            file: file.to_path_buf(),
+            done: AtomicUsize::new(done),
+            total,
+        }
+    }
+
+    /// Counts `frames` more as done.
+    pub fn add(&self, frames: usize) {
+        let done = self.done.fetch_add(frames, Ordering::Relaxed) + frames;
+        self.progress
+            .advance(&self.file, done.min(self.total), self.total);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snapshot_tracks_files() {
+        let progress = Progress::new(3, "run");
+        progress.start(Path::new("a.wav"));
+        progress.start(Path::new("b.wav"));
+        progress.finish(Path::new("a.wav"), true);
+        progress.advance(Path::new("b.wav"), 4096, 16000);
+        progress.advance(Path::new("b.wav"), 8192, 16000);
+        let json = progress.to_json();
+        assert!(
+            json.starts_with(r#"{"total": 3, "succeeded": 1, "failed": 0, "current": ["b.wav"], "#)
+        );
+        assert!(json.contains(
+            r#""frames": [{"file": "b.wav", "frames_done": 8192, "frames_total": 16000}], "run_id": "run", "started": ""#
+        ));
+        assert!(json.ends_with("\"finished\": false}\n"));
+        progress.finish(Path::new("b.wav"), false);
+        progress.close();
+        let json = progress.to_json();
+        assert!(json.contains(r#""failed": 1, "current": [], "frames": [], "#));
+        assert!(json.ends_with("\"finished\": true}\n"));
+    }
+
+    #[test]
+    fn test_status_line_estimates_time_left() {
+        let progress = Progress::new(4, "run");
+        for file in ["in/a.wav", "in/b.wav"] {
+            progress.start(Path::new(file));
+            progress.duration(Path::new(file), 30.0);
+        }
+        progress.finish(Path::new("in/a.wav"), true);
+        assert_eq!(
+            progress.line(Duration::from_secs(10), 80),
+            "[#####---------------] 1/4 files | 3.0 audio s/s | ETA 30s | b.wav"
+        );
+        progress.advance(Path::new("in/b.wav"), 4000, 16000);
+        assert!(
+            progress
+                .line(Duration::from_secs(10), 80)
+                .ends_with("| b.wav 25%")
+        );
+        assert_eq!(
+            progress.line(Duration::from_secs(10), 30).chars().count(),
+            30
+        );
+    }
+
+    #[test]
+    fn test_file_progress_counts_blocks() {
+        let progress = Arc::new(Progress::new(1, "run"));
+        let file = Path::new("in/a.wav");
+        progress.start(file);
+        // The second of two outputs of a 16000-frame file.
+        let counter = FileProgress::new(Arc::clone(&progress), file, 16000, 32000);
+        counter.add(8000);
+        let event = progress.to_event(PathStyle::Posix).to_string();
+        assert!(event.starts_with(r#"{"event": "progress", "total": 1, "succeeded": 0, "#));
+        assert!(event.contains(
+            r#""frames": [{"file": "in/a.wav", "frames_done": 24000, "frames_total": 32000}]"#
+        ));
+        counter.add(9000);
+        assert!(progress.to_json().contains(r#""frames_done": 32000,"#));
+    }
+
+    #[test]
+    fn test_write_replaces_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("progress.json");
+        let progress = Progress::new(1, "run");
+        progress.write(&path).unwrap();
+        progress.finish(Path::new("a.wav"), true);
+        progress.write(&path).unwrap();
+        let text = fs::read_to_string(&path).unwrap();
+        assert!(text.contains(r#""succeeded": 1"#));
+        assert!(!dir.path().join("progress.json.tmp").exists());
+    }
+}
@@ -0,0 +1,117 @@
+//! Heuristic artifact scores comparing a processed output with its input.
+//!
+//! Two symptoms of a poor stretch are measured frame by frame and compared
+//! with the input, so the material itself doesn't count against it:
+//! spectral flatness, which rises as tones smear into noise, and flicker,
+//! the spectral change between neighbouring frames that phasiness and
+//! warbling add to otherwise steady partials. Zero means the output looks
+//! as clean as its input; the scale has no absolute meaning, but ranks the
+//! outputs of a batch against each other.
+
+use crate::fft::{Complex, fft, hann};
+
+/// Analysis frame length in seconds, rounded up to a power of two samples;
+/// frames overlap by half.
+const FRAME_SECS: f32 = 0.05;
+
+/// Frames quieter than this (mean bin power) are skipped as silence.
+const SILENT_POWER: f32 = 1e-8;
+
+/// Artifact score of `after` against `before`, both mono at `sample_rate`,
+/// or `None` when either has no audible frame to compare.
+pub fn artifact_score(before: &[f32], after: &[f32], sample_rate: u32) -> Option<f32> {
+    let frame = ((sample_rate as f32 * FRAME_SECS) as usize)
+        .next_power_of_two()
+        .max(64);
+    let (flat_before, flicker_before) = measures(before, frame)?;
+    let (flat_after, flicker_after) = measures(after, frame)?;
+    Some((flat_after - flat_before).abs() + (flicker_after - flicker_before).max(0.0))
+}
+
+/// Mean spectral flatness and mean flicker (one minus the cosine similarity
+/// of consecutive magnitude spectra) over the audible frames of `samples`.
+fn measures(samples: &[f32], frame: usize) -> Option<(f32, f32)> {
+    let window = hann(frame);
+    let mut buf = vec![Complex::default(); frame];
+    let mut previous: Option<Vec<f32>> = None;
+    let (mut flatness, mut flicker) = (0.0f64, 0.0f64);
+    let (mut frames, mut pairs) = (0usize, 0usize);
+    let mut start = 0;
+    while start + frame <= samples.len() {
+        for ((b, &s), &w) in buf.iter_mut().zip(&samples[start..]).zip(&window) {
+            *b = Complex::new(s * w, 0.0);
+        }
+        fft(&mut buf, false);
+        start += frame / 2;
+        let magnitude: Vec<f32> = buf[1..frame / 2].iter().map(|c| c.norm()).collect();
+        let power: Vec<f32> = magnitude.iter().map(|m| m * m).collect();
+        let mean = power.iter().sum::<f32>() / power.len() as f32;
+        if mean < SILENT_POWER {
+            previous = None;
+            continue;
+        }
+        let log_mean = power.iter().map(|p| p.max(1e-20).ln()).sum::<f32>() / power.len() as f32;
+        flatness += f64::from(log_mean.exp() / mean);
+        frames += 1;
+        if let Some(previous) = &previous {
+            flicker += f64::from(1.0 - cosine(previous, &magnitude));
+            pairs += 1;
+        }
+        previous = Some(magnitude);
+    }
+    if frames == 0 {
+        return None;
+    }
+    let flicker = if pairs == 0 {
+        0.0
+    } else {
+        flicker / pairs as f64
+    };
+    Some(((flatness / frames as f64) as f32, flicker as f32))
+}
+
+fn cosine(a: &[f32], b: &[f32]) -> f32 {
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norms =
+        a.iter().map(|x| x * x).sum::<f32>().sqrt() * b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norms > 0.0 { dot / norms } else { 1.0 }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stretch_f32;
+    use std::f32::consts::PI;
+
+    #[test]
+    fn test_damaged_outputs_score_higher() {
+        let tone: Vec<f32> = (0..32000)
+            .map(|i| 0.4 * (2.0 * PI * 330.0 * i as f32 / 16000.0).sin())
+            .collect();
+        assert_eq!(artifact_score(&tone, &tone, 16000), Some(0.0));
+
+        // Warbling: the tone jumps between two pitches every few frames.
+        let warbled: Vec<f32> = (0..32000)
+            .map(|i| {
+                let f = if (i / 700) % 2 == 0 { 330.0 } else { 370.0 };
+                0.4 * (2.0 * PI * f * i as f32 / 16000.0).sin()
+            })
+            .collect();
+        // Noise: the same tone under a hiss.
+        let mut seed = 1u32;
+        let hissy: Vec<f32> = tone
+            .iter()
+            .map(|s| {
+                seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                s + 0.2 * (seed as f32 / u32::MAX as f32 - 0.5)
+            })
+            .collect();
+        let stretched = stretch_f32(&tone, 16000, 0.8).unwrap();
+        let clean = artifact_score(&tone, &stretched, 16000).unwrap();
+        for damaged in [&warbled, &hissy] {
+            let score = artifact_score(&tone, damaged, 16000).unwrap();
+            assert!(score > 2.0 * clean, "{score} vs clean {clean}");
+        }
+        assert_eq!(artifact_score(&tone, &[0.0; 4096], 16000), None);
+    }
+}
@@ -0,0 +1,520 @@
+//! Machine-readable report of what a batch did to each file.
+
+use crate::dedupe::csv_field;
+use crate::error::{Error, Result};
+use crate::fingerprint::Fingerprint;
+use crate::json::Json;
+use crate::loudness::Levels;
+use crate::paths::{self, PathStyle};
+use crate::run::{self, Run};
+use crate::tempo::TempoSource;
+use crate::units::format_duration;
+use crate::usage::ResourceUsage;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Outcome of one input file.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FileReport {
+    pub input: PathBuf,
+    pub output: PathBuf,
+    /// Why the file failed, if it did.
+    pub error: Option<String>,
+    /// Why the file was deliberately not processed, if it wasn't.
+    pub skipped: Option<String>,
+    pub input_fingerprint: Option<Fingerprint>,
+    pub output_fingerprint: Option<Fingerprint>,
+    /// Tempo chosen for this file alone, e.g. by `--equalize-rate`.
+    pub tempo: Option<f32>,
+    /// Which source [`tempo`](Self::tempo) came from.
+    pub tempo_source: Option<TempoSource>,
+    /// Beat detected in the input and in the output, from `--target-bpm`.
+    pub input_bpm: Option<f32>,
+    pub output_bpm: Option<f32>,
+    /// Heuristic artifact score of the output, from `--artifact-threshold`.
+    pub artifact_score: Option<f32>,
+    /// Whether the score says someone should listen to this output.
+    pub needs_review: bool,
+    /// Peak and clipping of the decoded input and of the output written.
+    pub input_levels: Option<Levels>,
+    pub output_levels: Option<Levels>,
+    /// The earlier output that this one's audio repeats, which
+    /// `--content-dedupe` replaced it with a link to.
+    pub duplicate_of: Option<PathBuf>,
+    /// The numbered files a `--max-output-duration` split wrote instead of
+    /// `output`, in order.
+    pub segments: Vec<PathBuf>,
+    /// Durations of the [`segments`](Self::segments) in seconds.
+    pub segment_secs: Vec<f64>,
+    /// Everything worth a second look that didn't fail the file.
+    pub warnings: Vec<String>,
+    /// Durations of the input and output in seconds.
+    pub input_secs: Option<f64>,
+    pub output_secs: Option<f64>,
+}
+
+impl FileReport {
+    pub fn new(input: &Path, output: &Path) -> Self {
+        Self {
+            input: input.to_path_buf(),
+            output: output.to_path_buf(),
+            ..Self::default()
+        }
+    }
+
+    pub fn failed(input: &Path, output: &Path, error: impl ToString) -> Self {
+        Self {
+            error: Some(error.to_string()),
+            ..Self::new(input, output)
+        }
+    }
+
+    /// `ok`, `skipped`, or `failed`.
+    pub fn status(&self) -> &'static str {
+        match (&self.error, &self.skipped) {
+            (Some(_), _) => "failed",
+            (None, Some(_)) => "skipped",
+            (None, None) => "ok",
+        }
+    }
+
+    /// The tempo the file was processed at: its own, or else the batch's
+    /// `tempo`. `None` unless it was processed.
+    pub fn applied_tempo(&self, tempo: f32) -> Option<f32> {
+        (self.status() == "ok").then(|| self.tempo.unwrap_or(tempo))
+    }
+
+    /// This entry as a JSON object, with paths in `style` and the tempo as
+    /// in [`applied_tempo`](Self::applied_tempo); unset fields are left out.
+    pub fn to_json(&self, style: PathStyle, tempo: f32) -> Json {
+        let path = |p: &Path| Json::from(paths::to_text(p, style));
+        let mut fields = vec![
+            ("input".to_string(), path(&self.input)),
+            ("output".to_string(), path(&self.output)),
+        ];
+        fields.push(("status".to_string(), self.status().into()));
+        if let Some(error) = &self.error {
+            fields.push(("error".to_string(), error.as_str().into()));
+        }
+        if let Some(reason) = &self.skipped {
+            fields.push(("reason".to_string(), reason.as_str().into()));
+        }
+        let fingerprints = [
+            ("input_fingerprint", self.input_fingerprint),
+            ("output_fingerprint", self.output_fingerprint),
+        ];
+        for (key, fingerprint) in fingerprints {
+            if let Some(fingerprint) = fingerprint {
+                fields.push((key.to_string(), fingerprint.to_string().into()));
+            }
+        }
+        // Via the shortest decimal form, so 1.2 doesn't print as 1.2000000476837158.
+        let number = |x: f32| Json::Number(x.to_string().parse().unwrap_or(f64::from(x)));
+        if let Some(tempo) = self.applied_tempo(tempo) {
+            fields.push(("tempo".to_string(), number(tempo)));
+        }
+        if let Some(source) = self.tempo_source {
+            fields.push(("tempo_source".to_string(), source.name().into()));
+        }
+        let durations = [
+            ("input_duration_s", self.input_secs),
+            ("output_duration_s", self.output_secs),
+        ];
+        for (key, secs) in durations {
+            if let Some(secs) = secs {
+                fields.push((
+                    key.to_string(),
+                    Json::Number((secs * 1000.0).round() / 1000.0),
+                ));
+            }
+        }
+        let bpms = [
+            ("input_bpm", self.input_bpm),
+            ("output_bpm", self.output_bpm),
+        ];
+        for (key, bpm) in bpms {
+            if let Some(bpm) = bpm {
+                fields.push((
+                    key.to_string(),
+                    Json::Number((f64::from(bpm) * 10.0).round() / 10.0),
+                ));
+            }
+        }
+        if let Some(score) = self.artifact_score {
+            fields.push(("artifact_score".to_string(), number(score)));
+        }
+        let levels = [("input", self.input_levels), ("output", self.output_levels)];
+        for (side, levels) in levels {
+            if let Some(levels) = levels {
+                let peak = (f64::from(levels.peak_dbfs) * 100.0).round() / 100.0;
+                fields.push((format!("{side}_peak_dbfs"), Json::Number(peak)));
+                fields.push((format!("{side}_clipped_samples"), levels.clipped.into()));
+            }
+        }
+        if self.needs_review {
+            fields.push(("needs_review".to_string(), Json::Bool(true)));
+        }
+        if let Some(original) = &self.duplicate_of {
+            fields.push(("duplicate_of".to_string(), path(original)));
+        }
+        if !self.segments.is_empty() {
+            let segments = self.segments.iter().map(|s| path(s)).collect();
+            fields.push(("segments".to_string(), Json::Array(segments)));
+        }
+        if !self.warnings.is_empty() {
+            let warnings = self.warnings.iter().map(|w| w.as_str().into()).collect();
+            fields.push(("warnings".to_string(), Json::Array(warnings)));
+        }
+        Json::Object(fields)
+    }
+}
+
+/// Header of the CSV report.
+const CSV_HEADER: &str =
+    "input,output,status,tempo,tempo_source,input_duration_s,output_duration_s,error";
+
+/// Totals over a batch's files.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Summary {
+    pub processed: usize,
+    pub skipped: usize,
+    pub failed: usize,
+    /// Audio read and written, in seconds, over the files processed.
+    pub input_secs: f64,
+    pub output_secs: f64,
+}
+
+impl Summary {
+    /// The totals as one line, with `wall` the time the batch took, e.g.
+    /// `Processed 40 files (2 skipped, 1 failed): 10m00s of audio in, 8m00s out, in 1m05s`.
+    pub fn line(&self, wall: Duration) -> String {
+        format!(
+            "Processed {} file{} ({} skipped, {} failed): {} of audio in, {} out, in {}",
+            self.processed,
+            if self.processed == 1 { "" } else { "s" },
+            self.skipped,
+            self.failed,
+            format_duration(self.input_secs),
+            format_duration(self.output_secs),
+            format_duration((wall.as_secs_f64() * 10.0).round() / 10.0)
+        )
+    }
+}
+
+/// Files listed as the hottest in the clipping census.
+const HOTTEST: usize = 10;
+
+/// Every file of a batch, in input path order.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Report {
+    pub files: Vec<FileReport>,
+    /// What the whole run cost, taken once it finished.
+    pub resources: Option<ResourceUsage>,
+    /// The run this batch belongs to, and when the batch finished.
+    pub run: Option<Run>,
+    pub finished: Option<u64>,
+    /// How paths are spelled when the report is written.
+    pub path_style: PathStyle,
+}
+
+impl Report {
+    pub fn new(mut files: Vec<FileReport>) -> Self {
+        files.sort_by(|a, b| a.input.cmp(&b.input));
+        Self {
+            files,
+            resources: None,
+            run: None,
+            finished: None,
+            path_style: PathStyle::Native,
+        }
+    }
+
+    /// The report as JSON, one file entry per line, with `tempo` for the
+    /// files that didn't get a tempo of their own.
+    pub fn to_json(&self, tempo: f32) -> String {
+        let files: Vec<String> = self
+            .files
+            .iter()
+            .map(|f| format!("    {}", f.to_json(self.path_style, tempo)))
+            .collect();
+        let mut stamps = String::new();
+        if let Some(run) = &self.run {
+            stamps.push_str(&format!(
+                "  \"run_id\": {},\n  \"started\": {},\n",
+                Json::from(run.id.as_str()),
+                Json::from(run::timestamp(run.started))
+            ));
+        }
+        if let Some(finished) = self.finished {
+            let finished = Json::from(run::timestamp(finished));
+            stamps.push_str(&format!("  \"finished\": {finished},\n"));
+        }
+        let resources = self
+            .resources
+            .map(|usage| format!("  \"resources\": {},\n", usage.to_json()))
+            .unwrap_or_default();
+        let clipping = self
+            .clipping_census()
+            .map(|census| format!("  \"clipping\": {census},\n"))
+            .unwrap_or_default();
+        format!(
+            "{{\n{stamps}{resources}{clipping}  \"files\": [\n{}\n  ]\n}}\n",
+            files.join(",\n")
+        )
+    }
+
+    /// Counts of the files by status, and the audio the processed ones held.
+    pub fn summary(&self) -> Summary {
+        let mut summary = Summary::default();
+        for file in &self.files {
+            match file.status() {
+                "failed" => summary.failed += 1,
+                "skipped" => summary.skipped += 1,
+                _ => {
+                    summary.processed += 1;
+                    summary.input_secs += file.input_secs.unwrap_or(0.0);
+                    summary.output_secs += file.output_secs.unwrap_or(0.0);
+                }
+            }
+        }
+        summary
+    }
+
+    /// The report as CSV, one row per file, with `tempo` for the files that
+    /// didn't get a tempo of their own.
+    pub fn to_csv(&self, tempo: f32) -> String {
+        let mut text = format!("{CSV_HEADER}\n");
+        for file in &self.files {
+            let secs = |secs: Option<f64>| secs.map(|s| format!("{s:.3}")).unwrap_or_default();
+            let tempo = file
+                .applied_tempo(tempo)
+                .map(|t| t.to_string())
+                .unwrap_or_default();
+            let row = [
+                csv_field(&paths::to_text(&file.input, self.path_style)),
+                csv_field(&paths::to_text(&file.output, self.path_style)),
+                file.status().to_string(),
+                tempo,
+                file.tempo_source
+                    .map(|s| s.name())
+                    .unwrap_or_default()
+                    .to_string(),
+                secs(file.input_secs),
+                secs(file.output_secs),
+                csv_field(file.error.as_deref().unwrap_or_default()),
+            ];
+            text.push_str(&row.join(","));
+            text.push('\n');
+        }
+        text
+    }
+
+    /// How many measured inputs and outputs reach full scale, and the files
+    /// with the highest peaks, or `None` when no file was measured.
+    pub fn clipping_census(&self) -> Option<Json> {
+        let measured: Vec<&FileReport> = self
+            .files
+            .iter()
+            .filter(|f| f.input_levels.is_some() || f.output_levels.is_some())
+            .collect();
+        if measured.is_empty() {
+            return None;
+        }
+        let clipped = |levels: fn(&FileReport) -> Option<Levels>| {
+            measured
+                .iter()
+                .filter(|f| levels(f).is_some_and(|l| l.clipped > 0))
+                .count()
+        };
+        let peak = |f: &FileReport| {
+            [f.input_levels, f.output_levels]
+                .into_iter()
+                .flatten()
+                .map(|l| l.peak_dbfs)
+                .fold(f32::NEG_INFINITY, f32::max)
+        };
+        let mut hottest = measured.clone();
+        hottest.sort_by(|a, b| peak(b).total_cmp(&peak(a)));
+        let hottest = hottest
+            .into_iter()
+            .take(HOTTEST)
+            .map(|f| {
+                let peak = (f64::from(peak(f)) * 100.0).round() / 100.0;
+                Json::Object(vec![
+                    (
+                        "input".to_string(),
+                        paths::to_text(&f.input, self.path_style).into(),
+                    ),
+                    ("peak_dbfs".to_string(), Json::Number(peak)),
+                ])
+            })
+            .collect();
+        Some(Json::Object(vec![
+            ("measured".to_string(), measured.len().into()),
+            (
+                "clipped_inputs".to_string(),
+                clipped(|f| f.input_levels).into(),
+            ),
+            (
+                "clipped_outputs".to_string(),
+                clipped(|f| f.output_levels).into(),
+            ),
+            ("hottest".to_string(), Json::Array(hottest)),
+        ]))
+    }
+
+    /// Writes the report to `path`: as CSV when it ends in `.csv`, and as
+    /// JSON otherwise, with `tempo` for the files that didn't get their own.
+    pub fn write(&self, path: &Path, tempo: f32) -> Result<()> {
+        let csv = path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("csv"));
+        let text = if csv {
+            self.to_csv(tempo)
+        } else {
+            self.to_json(tempo)
+        };
+        fs::write(path, text)
+            .map_err(|e| Error::io(format!("Failed to write report {:?}", path), e))
+    }
+}
+
+/// The input and output of each file that the JSON report at `path`, from
+/// an earlier run, marks failed, in the report's order.
+pub fn load_failed(path: &Path) -> Result<Vec<(PathBuf, PathBuf)>> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| Error::io(format!("Failed to read report {:?}", path), e))?;
+    let report =
+        Json::parse(&text).map_err(|e| Error::invalid(format!("Report {:?}: {e}", path)))?;
+    let Some(Json::Array(files)) = report.get("files") else {
+        return Err(Error::invalid(format!(
+            "Report {:?} has no \"files\" list; --report writes one when its path doesn't end in .csv",
+            path
+        )));
+    };
+    let mut failed = Vec::new();
+    for file in files {
+        if file.get("status").and_then(Json::as_str) != Some("failed") {
+            continue;
+        }
+        match (
+            file.get("input").and_then(Json::as_str),
+            file.get("output").and_then(Json::as_str),
+        ) {
+            (Some(input), Some(output)) => {
+                let pair = (paths::from_text(input), paths::from_text(output));
+                if !failed.contains(&pair) {
+                    failed.push(pair);
+                }
+            }
+            _ => {
+                return Err(Error::invalid(format!(
+                    "Report {:?} lists a failed file without its input and output",
+                    path
+                )));
+            }
+        }
+    }
+    Ok(failed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report_lists_files_in_order() {
+        let mut ok = FileReport::new(Path::new("in/b.wav"), Path::new("out/b.wav"));
+        ok.input_fingerprint = Some(Fingerprint(1));
+        ok.artifact_score = Some(0.25);
+        ok.input_bpm = Some(98.44);
+        ok.output_bpm = Some(119.96);
+        ok.needs_review = true;
+        ok.warnings.push("3 samples clipped".to_string());
+        ok.output_levels = Some(Levels {
+            peak_dbfs: 0.5,
+            clipped: 7,
+        });
+        let failed =
+            FileReport::failed(Path::new("in/a.wav"), Path::new("out/a.wav"), "bad header");
+        let mut report = Report::new(vec![ok, failed]);
+        report.run = Some(Run {
+            id: "2c5ea4c0-4067-41f0-8b83-25a5bd3d8e2f".to_string(),
+            started: 1_700_000_000,
+        });
+        report.finished = Some(1_700_000_042);
+        assert_eq!(
+            report.to_json(1.25),
+            concat!(
+                "{\n",
+                "  \"run_id\": \"2c5ea4c0-4067-41f0-8b83-25a5bd3d8e2f\",\n",
+                "  \"started\": \"2023-11-14T22:13:20Z\",\n",
+                "  \"finished\": \"2023-11-14T22:14:02Z\",\n",
+                r#"  "clipping": {"measured": 1, "clipped_inputs": 0, "clipped_outputs": 1, "hottest": [{"input": "in/b.wav", "peak_dbfs": 0.5}]},"#,
+                "\n  \"files\": [\n",
+                r#"    {"input": "in/a.wav", "output": "out/a.wav", "status": "failed", "error": "bad header"},"#,
+                "\n",
+                r#"    {"input": "in/b.wav", "output": "out/b.wav", "status": "ok", "input_fingerprint": "0000000000000001", "tempo": 1.25, "input_bpm": 98.4, "output_bpm": 120, "artifact_score": 0.25, "output_peak_dbfs": 0.5, "output_clipped_samples": 7, "needs_review": true, "warnings": ["3 samples clipped"]}"#,
+                "\n  ]\n}\n"
+            )
+        );
+    }
+
+    #[test]
+    fn test_csv_and_summary() {
+        let mut ok = FileReport::new(Path::new("in/b.wav"), Path::new("out/b.wav"));
+        ok.input_secs = Some(10.0);
+        ok.output_secs = Some(8.0);
+        let mut own = FileReport::new(Path::new("in/c.wav"), Path::new("out/c.wav"));
+        own.tempo = Some(0.5);
+        own.tempo_source = Some(TempoSource::Name);
+        own.input_secs = Some(2.0);
+        own.output_secs = Some(4.0);
+        let failed = FileReport::failed(
+            Path::new("in/a, take 2.wav"),
+            Path::new("out/a.wav"),
+            "bad \"fmt \" chunk",
+        );
+        let report = Report::new(vec![ok, own, failed]);
+        assert_eq!(
+            report.to_csv(1.25),
+            concat!(
+                "input,output,status,tempo,tempo_source,input_duration_s,output_duration_s,error\n",
+                "\"in/a, take 2.wav\",out/a.wav,failed,,,,,\"bad \"\"fmt \"\" chunk\"\n",
+                "in/b.wav,out/b.wav,ok,1.25,,10.000,8.000,\n",
+                "in/c.wav,out/c.wav,ok,0.5,name,2.000,4.000,\n",
+            )
+        );
+        // The JSON report gives the same tempos as the CSV.
+        let json = report.to_json(1.25);
+        assert!(
+            json.contains(r#""out/b.wav", "status": "ok", "tempo": 1.25,"#),
+            "{json}"
+        );
+        assert!(
+            json.contains(r#""status": "ok", "tempo": 0.5, "tempo_source""#),
+            "{json}"
+        );
+        assert!(!json.contains(r#""failed", "tempo""#), "{json}");
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("report.json");
+        report.write(&path, 1.0).unwrap();
+        assert_eq!(
+            load_failed(&path).unwrap(),
+            [(
+                PathBuf::from("in/a, take 2.wav"),
+                PathBuf::from("out/a.wav")
+            )]
+        );
+        let summary = report.summary();
+        assert_eq!(
+            (summary.processed, summary.skipped, summary.failed),
+            (2, 0, 1)
+        );
+        assert_eq!(
+            summary.line(Duration::from_millis(65_430)),
+            "Processed 2 files (0 skipped, 1 failed): 12s of audio in, 12s out, in 1m05.4s"
+        );
+    }
+}
@@ -0,0 +1,93 @@
+//! Band-limited sample rate conversion.
+
+use std::f64::consts::PI;
+
+/// Zero crossings of the sinc kernel on each side of a sample.
+const HALF_TAPS: usize = 16;
+
+/// Converts `samples` from `from` Hz to `to` Hz with a Hann-windowed sinc.
+///
+/// The cutoff sits at the lower of the two Nyquist frequencies, so
+/// downsampling does not alias.
+pub fn resample(samples: &[f32], from: u32, to: u32) -> Vec<f32> {
+    if from == 0 || to == 0 {
+        return samples.to_vec();
+    }
+    resample_by(samples, to as f64 / from as f64)
+}
+
+/// Resamples `samples` to `ratio` times as many, as [`resample`] does.
+pub fn resample_by(samples: &[f32], ratio: f64) -> Vec<f32> {
+    if ratio == 1.0 || samples.is_empty() || !(ratio.is_finite() && ratio > 0.0) {
+        return samples.to_vec();
+    }
+    let cutoff = ratio.min(1.0);
+    // Kernel half-width in input samples.
+    let width = HALF_TAPS as f64 / cutoff;
+    let out_len = (samples.len() as f64 * ratio).round() as usize;
+
+    (0..out_len)
+        .map(|n| {
+            let center = n as f64 / ratio;
+            let first = (center - width).ceil().max(0.0) as usize;
+            let last = ((center + width).floor() as usize).min(samples.len() - 1);
+            let mut acc = 0.0;
+            for (i, &s) in samples.iter().enumerate().take(last + 1).skip(first) {
+                let x = i as f64 - center;
+                acc += s as f64 * kernel(x * cutoff) * cutoff * window(x / width);
+            }
+            acc as f32
+        })
+        .collect()
+}
+
+fn kernel(x: f64) -> f64 {
+    if x.abs() < 1e-9 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+/// Hann window over [-1, 1].
+fn window(t: f64) -> f64 {
+    if t.abs() >= 1.0 {
+        0.0
+    } else {
+        0.5 + 0.5 * (PI * t).cos()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(freq: f64, rate: u32, len: usize) -> Vec<f32> {
+        (0..len)
+            .map(|i| (2.0 * PI * freq * i as f64 / rate as f64).sin() as f32)
+            .collect()
+    }
+
+    #[test]
+    fn test_upsampled_sine_matches_analytic_signal() {
+        let output = resample(&sine(440.0, 16000, 16000), 16000, 44100);
+        assert_eq!(output.len(), 44100);
+        let expected = sine(440.0, 44100, 44100);
+        // Skip the edges, where the kernel runs out of input.
+        let error = output[1000..43000]
+            .iter()
+            .zip(&expected[1000..43000])
+            .fold(0.0f32, |m, (a, b)| m.max((a - b).abs()));
+        assert!(error < 0.01, "max error {error}");
+    }
+
+    #[test]
+    fn test_downsampling_removes_content_above_nyquist() {
+        // 7 kHz cannot be represented at 8 kHz and must not alias down to 1 kHz.
+        let output = resample(&sine(7000.0, 16000, 16000), 16000, 8000);
+        assert_eq!(output.len(), 8000);
+        let rms = (output[500..7500].iter().map(|s| s * s).sum::<f32>() / 7000.0).sqrt();
+        assert!(rms < 0.05, "rms {rms}");
+        assert_eq!(resample(&[0.5, 0.25], 16000, 16000), vec![0.5, 0.25]);
+    }
+}
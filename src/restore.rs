@@ -0,0 +1,175 @@
+//! Restoration effects for damaged recordings, applied before time-stretching.
+
+/// Samples at or above this absolute level are treated as clipped.
+const CLIP_LEVEL: f32 = 0.999;
+
+/// Clicks longer than this (in seconds) are assumed to be real transients.
+const MAX_CLICK_SECS: f32 = 0.002;
+
+/// Window (in samples) used to estimate the local noise floor of the signal derivative.
+const CLICK_WINDOW: usize = 512;
+
+/// Derivative magnitude, in multiples of the local mean, that marks a click.
+const CLICK_SENSITIVITY: f32 = 12.0;
+
+/// Peak level the declipper scales reconstructed audio down to.
+const DECLIP_HEADROOM: f32 = 0.99;
+
+/// Rebuilds flat-topped clipped runs with cubic interpolation.
+///
+/// Reconstructed peaks usually exceed full scale, so when any run was
+/// rebuilt the whole signal is attenuated afterwards just enough to keep
+/// them representable. Audio with nothing to rebuild is left untouched.
+pub fn declip(samples: &mut [f32]) -> usize {
+    let mut repaired = 0;
+    let mut i = 0;
+    while i < samples.len() {
+        if samples[i].abs() < CLIP_LEVEL {
+            i += 1;
+            continue;
+        }
+        let start = i;
+        while i < samples.len() && samples[i].abs() >= CLIP_LEVEL {
+            i += 1;
+        }
+        // Runs touching either edge have no anchor on one side.
+        if start >= 2 && i + 1 < samples.len() {
+            let slope_in = samples[start - 1] - samples[start - 2];
+            let slope_out = samples[i + 1] - samples[i];
+            cubic_fill(samples, start - 1, i, slope_in, slope_out);
+            repaired += i - start;
+        }
+    }
+
+    if repaired == 0 {
+        return 0;
+    }
+    let peak = samples.iter().fold(0.0f32, |m, &s| m.max(s.abs()));
+    if peak > DECLIP_HEADROOM {
+        let gain = DECLIP_HEADROOM / peak;
+        samples.iter_mut().for_each(|s| *s *= gain);
+    }
+    repaired
+}
+
+/// Removes short impulsive clicks by interpolating across them.
+///
+/// A click is a run of samples whose first difference jumps far above the
+/// local average; runs longer than ~2 ms are left alone as genuine onsets.
+pub fn declick(samples: &mut [f32], sample_rate: u32) -> usize {
+    if samples.len() < 3 {
+        return 0;
+    }
+    let diffs: Vec<f32> = samples.windows(2).map(|w| (w[1] - w[0]).abs()).collect();
+    let max_click = ((sample_rate as f32 * MAX_CLICK_SECS) as usize).max(1);
+
+    let mut flagged = vec![false; samples.len()];
+    for (start, chunk) in diffs.chunks(CLICK_WINDOW).enumerate() {
+        let mean = chunk.iter().sum::<f32>() / chunk.len() as f32;
+        let threshold = (mean * CLICK_SENSITIVITY).max(f32::EPSILON);
+        for (j, &d) in chunk.iter().enumerate() {
+            if d > threshold {
+                flagged[start * CLICK_WINDOW + j + 1] = true;
+            }
+        }
+    }
+
+    let mut repaired = 0;
+    let mut i = 1;
+    while i < samples.len() - 1 {
+        if !flagged[i] {
+            i += 1;
+            continue;
+        }
+        let start = i;
+        while i < samples.len() - 1 && flagged[i] {
+            i += 1;
+        }
+        // A click is a jump away and back: the last flagged sample is the
+        // first good one again, while a lone flag is just a step in level.
+        let end = i - 1;
+        if end > start && end - start <= max_click {
+            linear_fill(samples, start - 1, end);
+            repaired += end - start;
+        }
+    }
+    repaired
+}
+
+/// Replaces samples strictly between `a` and `b` with a straight line.
+fn linear_fill(samples: &mut [f32], a: usize, b: usize) {
+    let (ya, yb) = (samples[a], samples[b]);
+    let span = (b - a) as f32;
+    for (k, s) in samples[a + 1..b].iter_mut().enumerate() {
+        let t = (k + 1) as f32 / span;
+        *s = ya + (yb - ya) * t;
+    }
+}
+
+/// Replaces samples strictly between `a` and `b` with a cubic Hermite curve.
+fn cubic_fill(samples: &mut [f32], a: usize, b: usize, slope_a: f32, slope_b: f32) {
+    let (ya, yb) = (samples[a], samples[b]);
+    let span = (b - a) as f32;
+    let (ma, mb) = (slope_a * span, slope_b * span);
+    for (k, s) in samples[a + 1..b].iter_mut().enumerate() {
+        let t = (k + 1) as f32 / span;
+        let (t2, t3) = (t * t, t * t * t);
+        *s = (2.0 * t3 - 3.0 * t2 + 1.0) * ya
+            + (t3 - 2.0 * t2 + t) * ma
+            + (-2.0 * t3 + 3.0 * t2) * yb
+            + (t3 - t2) * mb;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(len: usize, amplitude: f32) -> Vec<f32> {
+        (0..len)
+            .map(|i| (i as f32 * 440.0 * 2.0 * std::f32::consts::PI / 16000.0).sin() * amplitude)
+            .collect()
+    }
+
+    #[test]
+    fn test_declick_removes_impulse() {
+        let mut samples = sine(4000, 0.3);
+        let original = samples[2000];
+        samples[2000] = 0.95;
+        let repaired = declick(&mut samples, 16000);
+        assert!(repaired >= 1);
+        assert!((samples[2000] - original).abs() < 0.05);
+    }
+
+    #[test]
+    fn test_declick_leaves_clean_signal() {
+        let mut samples = sine(4000, 0.3);
+        let original = samples.clone();
+        assert_eq!(declick(&mut samples, 16000), 0);
+        assert_eq!(samples, original);
+    }
+
+    #[test]
+    fn test_declip_rebuilds_peaks_within_range() {
+        let mut samples: Vec<f32> = sine(4000, 1.5).iter().map(|s| s.clamp(-1.0, 1.0)).collect();
+        let repaired = declip(&mut samples);
+        assert!(repaired > 0);
+        assert!(samples.iter().all(|s| s.abs() <= DECLIP_HEADROOM + 1e-6));
+        // Peaks are now rounded rather than flat.
+        let flat = samples
+            .windows(2)
+            .filter(|w| w[0] == w[1] && w[0].abs() > 0.5)
+            .count();
+        assert_eq!(flat, 0);
+    }
+
+    #[test]
+    fn test_declip_leaves_unclipped_signal() {
+        // Hot, above the headroom, but never at full scale.
+        let mut samples = sine(4000, 0.995);
+        let original = samples.clone();
+        assert!(original.iter().any(|s| s.abs() > DECLIP_HEADROOM));
+        assert_eq!(declip(&mut samples), 0);
+        assert_eq!(samples, original);
+    }
+}
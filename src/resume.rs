@@ -0,0 +1,146 @@
+//! Resuming batches: a state file in the output directory records each
+//! input done, with its size and modification time and the settings it was
+//! processed with, so a later run can skip inputs that haven't changed.
+//!
+//! Entries are appended as files finish, so an interrupted run loses only
+//! the files in flight; the last entry for an input wins.
+
+use crate::error::{Error, Result};
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::UNIX_EPOCH;
+
+/// Name of the state file inside the output directory.
+pub const STATE_FILE: &str = "resume-state.tsv";
+
+/// What identifies one version of an input without reading it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Stamp {
+    pub size: u64,
+    /// Modification time in nanoseconds since the Unix epoch.
+    pub modified: u128,
+}
+
+impl Stamp {
+    /// The stamp of the file at `path` now, if it can be read.
+    pub fn of(path: &Path) -> Option<Self> {
+        let meta = fs::metadata(path).ok()?;
+        let modified = meta.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+        Some(Self {
+            size: meta.len(),
+            modified: modified.as_nanos(),
+        })
+    }
+}
+
+/// The inputs an output directory already holds results for.
+#[derive(Debug)]
+pub struct State {
+    path: PathBuf,
+    settings: String,
+    done: HashMap<PathBuf, (Stamp, String)>,
+    file: Mutex<Option<File>>,
+}
+
+impl State {
+    /// Reads the state file at `path`, if there is one, for a run whose
+    /// settings hash to `settings` (see [`settings_key`]).
+    pub fn load(path: &Path, settings: &str) -> Result<Self> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(Error::io(format!("Failed to read {:?}", path), e)),
+        };
+        let mut done = HashMap::new();
+        for line in text.lines() {
+            // A line cut short by an interruption is just ignored.
+            let fields: Vec<&str> = line.split('\t').collect();
+            let [input, size, modified, key] = fields[..] else {
+                continue;
+            };
+            if let (Ok(size), Ok(modified)) = (size.parse(), modified.parse()) {
+                let stamp = Stamp { size, modified };
+                done.insert(PathBuf::from(input), (stamp, key.to_string()));
+            }
+        }
+        Ok(Self {
+            path: path.to_path_buf(),
+            settings: settings.to_string(),
+            done,
+            file: Mutex::new(None),
+        })
+    }
+
+    /// Whether `input` was done with these settings and hasn't changed since.
+    pub fn is_done(&self, input: &Path) -> bool {
+        self.done.get(input).is_some_and(|(stamp, key)| {
+            *key == self.settings && Stamp::of(input).as_ref() == Some(stamp)
+        })
+    }
+
+    /// Records `input` as done, as it is now.
+    pub fn record(&self, input: &Path) -> Result<()> {
+        let Some(stamp) = Stamp::of(input) else {
+            return Ok(());
+        };
+        let mut file = self.file.lock().unwrap_or_else(|e| e.into_inner());
+        if file.is_none() {
+            let opened = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.path)
+                .map_err(|e| Error::io(format!("Failed to open {:?}", self.path), e))?;
+            *file = Some(opened);
+        }
+        let line = format!(
+            "{}\t{}\t{}\t{}\n",
+            input.display(),
+            stamp.size,
+            stamp.modified,
+            self.settings
+        );
+        file.as_mut()
+            .map_or(Ok(()), |file| file.write_all(line.as_bytes()))
+            .map_err(|e| Error::io(format!("Failed to write {:?}", self.path), e))
+    }
+}
+
+/// A short key for `settings`, a description of everything that shapes the
+/// outputs, to tell whether a run changed them: its 64-bit FNV-1a hash.
+pub fn settings_key(settings: &str) -> String {
+    let hash = settings
+        .bytes()
+        .fold(0xcbf2_9ce4_8422_2325u64, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+        });
+    format!("{hash:016x}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_state_skips_unchanged_inputs() {
+        let dir = tempfile::tempdir().unwrap();
+        let (a, b) = (dir.path().join("a.wav"), dir.path().join("b.wav"));
+        fs::write(&a, b"one").unwrap();
+        fs::write(&b, b"two").unwrap();
+        let path = dir.path().join(STATE_FILE);
+        let key = settings_key("tempo 1.25");
+        let state = State::load(&path, &key).unwrap();
+        assert!(!state.is_done(&a));
+        state.record(&a).unwrap();
+        state.record(&b).unwrap();
+
+        fs::write(&b, b"longer").unwrap();
+        let state = State::load(&path, &key).unwrap();
+        assert!(state.is_done(&a));
+        assert!(!state.is_done(&b));
+        let other = State::load(&path, &settings_key("tempo 1.5")).unwrap();
+        assert!(!other.is_done(&a));
+    }
+}
@@ -0,0 +1,116 @@
+//! A/B review tracks: the opening of each original followed by the same
+//! passage after processing, for judging settings by ear.
+
+use crate::error::Result;
+use crate::format::{AudioBuffer, AudioFormat, SampleType};
+use crate::resample::resample;
+use std::path::Path;
+
+/// Length of each original snippet in seconds.
+const SNIPPET_SECS: f64 = 3.0;
+
+/// Silence between the original and processed snippet of a file, in seconds;
+/// files are separated by twice as much.
+const GAP_SECS: f64 = 0.5;
+
+/// The opening of an original, downmixed to mono, waiting for its
+/// processed counterpart.
+#[derive(Debug, Clone)]
+pub struct Snippet {
+    sample_rate: u32,
+    samples: Vec<f32>,
+    /// Length of the whole original, to find the same passage once processed.
+    frames: usize,
+}
+
+impl Snippet {
+    /// The first [`SNIPPET_SECS`] of `original`.
+    pub fn opening(original: &AudioBuffer) -> Self {
+        let len = (SNIPPET_SECS * original.format.sample_rate as f64) as usize;
+        let mut samples = original.mono();
+        samples.truncate(len);
+        Self {
+            sample_rate: original.format.sample_rate,
+            samples,
+            frames: original.frames(),
+        }
+    }
+
+    /// Pairs the snippet with the same passage of `processed`, however much
+    /// it was stretched.
+    pub fn pair(self, processed: &AudioBuffer) -> ReviewPair {
+        let share = self.samples.len() as f64 / self.frames.max(1) as f64;
+        let mut after = processed.mono();
+        after.truncate((share * processed.frames() as f64).round() as usize);
+        ReviewPair {
+            original: (self.sample_rate, self.samples),
+            processed: (processed.format.sample_rate, after),
+        }
+    }
+}
+
+/// Mono original and processed snippets of one file, each at its own rate.
+#[derive(Debug, Clone)]
+pub struct ReviewPair {
+    original: (u32, Vec<f32>),
+    processed: (u32, Vec<f32>),
+}
+
+/// All `pairs` back to back as one mono track, at the highest rate among them.
+pub fn track(pairs: &[ReviewPair]) -> AudioBuffer {
+    let sample_rate = pairs
+        .iter()
+        .flat_map(|pair| [pair.original.0, pair.processed.0])
+        .max()
+        .unwrap_or(AudioFormat::default().sample_rate);
+    let gap = (GAP_SECS * sample_rate as f64) as usize;
+    let mut samples = Vec::new();
+    for (i, pair) in pairs.iter().enumerate() {
+        if i > 0 {
+            samples.resize(samples.len() + 2 * gap, 0.0);
+        }
+        samples.extend(resample(&pair.original.1, pair.original.0, sample_rate));
+        samples.resize(samples.len() + gap, 0.0);
+        samples.extend(resample(&pair.processed.1, pair.processed.0, sample_rate));
+    }
+    let format = AudioFormat {
+        sample_rate,
+        channels: 1,
+        sample_type: SampleType::Int(16),
+    };
+    AudioBuffer::new(format, samples)
+}
+
+/// Writes the review track of `pairs` to `path` as 16-bit mono.
+pub fn write(path: &Path, pairs: &[ReviewPair]) -> Result<()> {
+    crate::write_wav(path, &track(pairs))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pairs_cover_the_same_passage() {
+        let format = AudioFormat::default();
+        let original = AudioBuffer::new(format, vec![0.25; 16000 * 10]);
+        let slowed = AudioBuffer::new(format, vec![0.5; 16000 * 20]);
+        let pair = Snippet::opening(&original).pair(&slowed);
+        assert_eq!(pair.original.1.len(), 48000);
+        assert_eq!(pair.processed.1.len(), 96000);
+
+        // A second file at a higher rate lifts the whole track to it.
+        let fast = AudioFormat {
+            sample_rate: 32000,
+            ..format
+        };
+        let short = AudioBuffer::new(fast, vec![0.1; 32000]);
+        let other = Snippet::opening(&short).pair(&short);
+        let track = track(&[pair, other]);
+        assert_eq!(track.format.sample_rate, 32000);
+        // 3 s + gap + 6 s, two gaps, then 1 s + gap + 1 s.
+        assert_eq!(track.samples.len(), 32000 * 9 + 16000 * 4 + 32000 * 2);
+        assert_eq!(track.samples[0], 0.25);
+        assert_eq!(track.samples.last(), Some(&0.1));
+    }
+}
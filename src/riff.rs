@@ -0,0 +1,592 @@
+//! Raw RIFF chunk access for the metadata hound does not carry over.
+
+use crate::error::{Error, Result};
+use crate::format::{AudioFormat, SampleType};
+use crate::w64;
+use std::fs::{self, OpenOptions};
+use std::io::{Seek, SeekFrom, Write};
+use std::ops::Range;
+use std::path::Path;
+
+/// Chunks describing the audio itself, which the writer regenerates.
+const AUDIO_CHUNKS: [&[u8; 4]; 3] = [b"fmt ", b"data", b"fact"];
+
+const FORMAT_PCM: u16 = 1;
+const FORMAT_FLOAT: u16 = 3;
+const FORMAT_EXTENSIBLE: u16 = 0xfffe;
+
+/// One top-level chunk of a RIFF/WAVE file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Chunk {
+    pub id: [u8; 4],
+    pub data: Vec<u8>,
+}
+
+/// Parses the top-level chunks of a RIFF/WAVE byte buffer.
+pub fn parse_chunks(bytes: &[u8]) -> Result<Vec<Chunk>> {
+    Ok(chunk_spans(bytes)?
+        .into_iter()
+        .map(|(id, range)| Chunk {
+            id,
+            data: bytes[range].to_vec(),
+        })
+        .collect())
+}
+
+/// Id and data range of each top-level chunk of a RIFF/WAVE byte buffer.
+/// Truncated trailing chunks keep whatever data is present.
+fn chunk_spans(bytes: &[u8]) -> Result<Vec<([u8; 4], Range<usize>)>> {
+    if !(bytes.len() >= 12 && &bytes[0..4] == b"RIFF" && &bytes[8..12] == b"WAVE") {
+        return Err(Error::decode(
+            "Failed to parse RIFF chunks",
+            hound::Error::FormatError("no RIFF/WAVE header"),
+        ));
+    }
+    Ok(spans(bytes)
+        .into_iter()
+        .map(|(id, start, size)| (id, start..start.saturating_add(size).min(bytes.len())))
+        .collect())
+}
+
+/// Like [`parse_container`], but gives each chunk's data as its range in
+/// `bytes` instead of copying it.
+pub(crate) fn container_spans(bytes: &[u8]) -> Result<Vec<([u8; 4], Range<usize>)>> {
+    if w64::is_w64(bytes) {
+        w64::chunk_spans(bytes)
+    } else {
+        chunk_spans(bytes)
+    }
+}
+
+/// Parses the top-level chunks of either a RIFF/WAVE or a Wave64 file.
+pub fn parse_container(bytes: &[u8]) -> Result<Vec<Chunk>> {
+    if w64::is_w64(bytes) {
+        w64::parse_chunks(bytes)
+    } else {
+        parse_chunks(bytes)
+    }
+}
+
+/// Writes `chunks` as Wave64 for `.w64` paths and as RIFF/WAVE otherwise.
+pub fn write_container(path: &Path, chunks: &[Chunk]) -> Result<()> {
+    if w64::is_w64_path(path) {
+        w64::write_chunks(path, chunks)
+    } else {
+        write_chunks(path, chunks)
+    }
+}
+
+/// The `fmt ` chunk body hound writes for `format`: plain `WAVEFORMAT` up to
+/// two channels of 16-bit PCM, `WAVEFORMATEXTENSIBLE` beyond that.
+pub fn fmt_body(format: AudioFormat) -> Vec<u8> {
+    let bits = format.sample_type.bits();
+    let block_align = format.channels * bits.div_ceil(8);
+    let extensible = format.channels > 2 || bits > 16;
+    let tag: u16 = match (extensible, format.sample_type) {
+        (true, _) => FORMAT_EXTENSIBLE,
+        (false, SampleType::Int(_)) => FORMAT_PCM,
+        (false, SampleType::Float(_)) => FORMAT_FLOAT,
+    };
+    let mut body = Vec::with_capacity(40);
+    body.extend_from_slice(&tag.to_le_bytes());
+    body.extend_from_slice(&format.channels.to_le_bytes());
+    body.extend_from_slice(&format.sample_rate.to_le_bytes());
+    body.extend_from_slice(&(format.sample_rate * block_align as u32).to_le_bytes());
+    body.extend_from_slice(&block_align.to_le_bytes());
+    body.extend_from_slice(&(bits.div_ceil(8) * 8).to_le_bytes());
+    if extensible {
+        let mask = (1u32 << format.channels.min(18)) - 1;
+        let sub_format: u16 = match format.sample_type {
+            SampleType::Int(_) => FORMAT_PCM,
+            SampleType::Float(_) => FORMAT_FLOAT,
+        };
+        body.extend_from_slice(&22u16.to_le_bytes());
+        body.extend_from_slice(&bits.to_le_bytes());
+        body.extend_from_slice(&mask.to_le_bytes());
+        // KSDATAFORMAT_SUBTYPE_PCM / _IEEE_FLOAT.
+        body.extend_from_slice(&sub_format.to_le_bytes());
+        body.extend_from_slice(b"\x00\x00\x00\x00\x10\x00\x80\x00\x00\xaa\x00\x38\x9b\x71");
+    }
+    body
+}
+
+/// The first chunk whose declared size runs past the end of the RIFF/WAVE
+/// file in `bytes`, with that size.
+pub fn overrun(bytes: &[u8]) -> Option<([u8; 4], usize)> {
+    if !bytes.starts_with(b"RIFF") {
+        return None;
+    }
+    spans(bytes)
+        .into_iter()
+        .find(|&(_, start, size)| start.saturating_add(size) > bytes.len())
+        .map(|(id, _, size)| (id, size))
+}
+
+/// Id, data offset, and declared size of each top-level chunk after the
+/// 12-byte RIFF header.
+fn spans(bytes: &[u8]) -> Vec<([u8; 4], usize, usize)> {
+    let mut spans = Vec::new();
+    let mut pos = 12;
+    while pos + 8 <= bytes.len() {
+        let id = [bytes[pos], bytes[pos + 1], bytes[pos + 2], bytes[pos + 3]];
+        let size = u32::from_le_bytes([
+            bytes[pos + 4],
+            bytes[pos + 5],
+            bytes[pos + 6],
+            bytes[pos + 7],
+        ]) as usize;
+        let start = pos + 8;
+        spans.push((id, start, size));
+        pos = start.saturating_add(size).min(bytes.len()) + (size & 1);
+    }
+    spans
+}
+
+/// Whether a chunk is metadata (LIST/INFO, bext, cue, ...) rather than
+/// describing the audio itself.
+pub fn is_metadata(id: &[u8; 4]) -> bool {
+    !AUDIO_CHUNKS.contains(&id)
+}
+
+/// Non-audio chunks of the WAV file at `path`.
+pub fn metadata_chunks(path: &Path) -> Result<Vec<Chunk>> {
+    let bytes = fs::read(path).map_err(|e| Error::io("Failed to read input for metadata", e))?;
+    metadata_of(&bytes)
+}
+
+/// Non-audio chunks of the WAV file in `bytes`.
+pub fn metadata_of(bytes: &[u8]) -> Result<Vec<Chunk>> {
+    let chunks = parse_container(bytes)?;
+    Ok(chunks.into_iter().filter(|c| is_metadata(&c.id)).collect())
+}
+
+/// Appends chunks to a finalized WAV file and patches the RIFF size.
+pub fn append_chunks(path: &Path, chunks: &[Chunk]) -> Result<()> {
+    if chunks.is_empty() {
+        return Ok(());
+    }
+    if w64::is_w64_path(path) {
+        let bytes = fs::read(path).map_err(|e| Error::io("Failed to reopen output WAV", e))?;
+        let mut all = w64::parse_chunks(&bytes)?;
+        all.extend_from_slice(chunks);
+        return w64::write_chunks(path, &all);
+    }
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(path)
+        .map_err(|e| Error::io("Failed to reopen output WAV", e))?;
+    let write = |file: &mut fs::File| -> std::io::Result<u64> {
+        file.seek(SeekFrom::End(0))?;
+        for chunk in chunks {
+            file.write_all(&chunk.id)?;
+            file.write_all(&(chunk.data.len() as u32).to_le_bytes())?;
+            file.write_all(&chunk.data)?;
+            if chunk.data.len() % 2 == 1 {
+                file.write_all(&[0])?;
+            }
+        }
+        file.stream_position()
+    };
+    let len = write(&mut file).map_err(|e| Error::io("Failed to append metadata chunks", e))?;
+    patch_riff_size(&mut file, len)
+}
+
+/// Adds the pad byte hound leaves off an odd-sized `data` chunk at the end
+/// of a finalized WAV file, without reading the file back.
+pub fn pad_final_chunk(path: &Path) -> Result<()> {
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(path)
+        .map_err(|e| Error::io("Failed to reopen output WAV", e))?;
+    let len = file
+        .seek(SeekFrom::End(0))
+        .and_then(|_| file.write_all(&[0]))
+        .and_then(|_| file.stream_position())
+        .map_err(|e| Error::io("Failed to pad output WAV", e))?;
+    patch_riff_size(&mut file, len)
+}
+
+/// Sets the RIFF size of a file `len` bytes long.
+fn patch_riff_size(file: &mut fs::File, len: u64) -> Result<()> {
+    let riff_size =
+        u32::try_from(len - 8).map_err(|_| Error::invalid("Output exceeds the RIFF size limit"))?;
+    file.seek(SeekFrom::Start(4))
+        .and_then(|_| file.write_all(&riff_size.to_le_bytes()))
+        .map_err(|e| Error::io("Failed to patch RIFF size", e))
+}
+
+/// Copies the metadata chunks of `input` onto the already-written `output`.
+pub fn copy_metadata(input: &Path, output: &Path) -> Result<()> {
+    append_chunks(output, &metadata_chunks(input)?)
+}
+
+/// Moves the sample positions in `chunk` to where they land once the audio
+/// is resampled to `ratio` times as many frames: the points of a `cue `
+/// chunk and the loops of a `smpl` chunk, whose root note also moves up by
+/// `semitones`. Other chunks are left as they are.
+pub fn rescale_positions(chunk: &mut Chunk, ratio: f64, semitones: f64) {
+    let data = &mut chunk.data;
+    let get = |data: &[u8], at: usize| {
+        data.get(at..at + 4)
+            .map(|b| u32::from_le_bytes(b.try_into().unwrap()))
+    };
+    let scale = |data: &mut Vec<u8>, at: usize| {
+        if let Some(value) = get(data, at) {
+            let scaled = (f64::from(value) * ratio).round() as u32;
+            data[at..at + 4].copy_from_slice(&scaled.to_le_bytes());
+        }
+    };
+    match &chunk.id {
+        // A count, then 24-byte points holding a position and a sample offset.
+        b"cue " => {
+            for point in 0..get(data, 0).unwrap_or(0) as usize {
+                let at = 4 + 24 * point;
+                scale(data, at + 4);
+                scale(data, at + 20);
+            }
+        }
+        // A 36-byte header with the root note and loop count, then 24-byte
+        // loops holding a start and an end.
+        b"smpl" => {
+            if let (Some(note), Some(fraction)) = (get(data, 12), get(data, 16)) {
+                let pitch = f64::from(note) + f64::from(fraction) / 4_294_967_296.0 + semitones;
+                let pitch = pitch.clamp(0.0, 127.0);
+                let fraction = (pitch.fract() * 4_294_967_296.0) as u32;
+                data[12..16].copy_from_slice(&(pitch.trunc() as u32).to_le_bytes());
+                data[16..20].copy_from_slice(&fraction.to_le_bytes());
+            }
+            for point in 0..get(data, 28).unwrap_or(0) as usize {
+                let at = 36 + 24 * point;
+                scale(data, at + 8);
+                scale(data, at + 12);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Rewrites a hound-written WAV file into what the RIFF spec asks for:
+/// every odd-sized chunk gets its pad byte (hound leaves the `data` chunk
+/// unpadded), and with `fact_frames` a `fact` chunk holding the frame count
+/// goes right after `fmt `, as every non-PCM encoding requires.
+pub fn fix_header(path: &Path, fact_frames: Option<usize>) -> Result<()> {
+    let bytes = fs::read(path).map_err(|e| Error::io("Failed to reopen output WAV", e))?;
+    let mut chunks = parse_chunks(&bytes)?;
+    if let Some(frames) = fact_frames {
+        let frames = u32::try_from(frames)
+            .map_err(|_| Error::invalid("Output exceeds the fact chunk frame limit"))?;
+        chunks.retain(|c| &c.id != b"fact");
+        let at = chunks
+            .iter()
+            .position(|c| &c.id == b"fmt ")
+            .map_or(0, |i| i + 1);
+        chunks.insert(
+            at,
+            Chunk {
+                id: *b"fact",
+                data: frames.to_le_bytes().to_vec(),
+            },
+        );
+    }
+    write_chunks(path, &chunks)
+}
+
+/// Writes `chunks` as a complete RIFF/WAVE file, padding odd-sized ones.
+pub fn write_chunks(path: &Path, chunks: &[Chunk]) -> Result<()> {
+    let out = encode_chunks(chunks)?;
+    fs::write(path, out).map_err(|e| Error::io("Failed to write output WAV", e))
+}
+
+/// The bytes [`write_chunks`] writes for `chunks`.
+pub fn encode_chunks(chunks: &[Chunk]) -> Result<Vec<u8>> {
+    let len = chunks
+        .iter()
+        .map(|c| 8 + c.data.len() + c.data.len() % 2)
+        .sum::<usize>();
+    let mut out = Vec::with_capacity(12 + len);
+    out.extend_from_slice(b"RIFF\0\0\0\0WAVE");
+    for chunk in chunks {
+        out.extend_from_slice(&chunk.id);
+        out.extend_from_slice(&(chunk.data.len() as u32).to_le_bytes());
+        out.extend_from_slice(&chunk.data);
+        if chunk.data.len() % 2 == 1 {
+            out.push(0);
+        }
+    }
+    let riff_size = u32::try_from(out.len() - 8)
+        .map_err(|_| Error::invalid("Output exceeds the RIFF size limit"))?;
+    out[4..8].copy_from_slice(&riff_size.to_le_bytes());
+    Ok(out)
+}
+
+/// Fails unless the WAV file at `path` passes [`header_problems`].
+pub fn check_header(path: &Path) -> Result<()> {
+    let bytes = fs::read(path).map_err(|e| Error::io("Failed to reopen output WAV", e))?;
+    check_header_bytes(&bytes)
+}
+
+/// [`check_header`] for a file already in memory.
+pub fn check_header_bytes(bytes: &[u8]) -> Result<()> {
+    let problems = if w64::is_w64(bytes) {
+        w64::header_problems(bytes)
+    } else {
+        header_problems(bytes)
+    };
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::Verification(format!(
+            "header breaks the RIFF spec: {}",
+            problems.join("; ")
+        )))
+    }
+}
+
+/// Every way the RIFF/WAVE header of `bytes` departs from the spec: size
+/// fields that disagree with the file, `fmt ` fields that disagree with each
+/// other, and a missing or wrong `fact` chunk for non-PCM data.
+pub fn header_problems(bytes: &[u8]) -> Vec<String> {
+    if !(bytes.len() >= 12 && &bytes[0..4] == b"RIFF" && &bytes[8..12] == b"WAVE") {
+        return vec!["no RIFF/WAVE header".to_string()];
+    }
+    let u32_at = |b: &[u8], i: usize| u32::from_le_bytes([b[i], b[i + 1], b[i + 2], b[i + 3]]);
+    let mut problems = Vec::new();
+    let riff_size = u32_at(bytes, 4) as usize;
+    if riff_size != bytes.len() - 8 {
+        problems.push(format!(
+            "RIFF size {riff_size}, expected {}",
+            bytes.len() - 8
+        ));
+    }
+
+    // Unlike `parse_chunks`, nothing truncated or unpadded is tolerated.
+    let mut chunks: Vec<(&[u8], &[u8])> = Vec::new();
+    let mut pos = 12;
+    while pos + 8 <= bytes.len() {
+        let id = &bytes[pos..pos + 4];
+        let size = u32_at(bytes, pos + 4) as usize;
+        let name = String::from_utf8_lossy(id).into_owned();
+        let end = pos + 8 + size;
+        if end > bytes.len() {
+            problems.push(format!("{name:?} chunk overruns the file"));
+            break;
+        }
+        chunks.push((id, &bytes[pos + 8..end]));
+        pos = end + (size & 1);
+        if pos > bytes.len() {
+            problems.push(format!("{name:?} chunk is missing its pad byte"));
+        }
+    }
+    if pos < bytes.len() && problems.is_empty() {
+        problems.push(format!(
+            "{} stray bytes after the last chunk",
+            bytes.len() - pos
+        ));
+    }
+
+    problems.extend(format_problems(&chunks));
+    problems
+}
+
+/// The container-independent part of [`header_problems`]: `fmt `, `data`,
+/// and `fact` checks over already split `(id, body)` chunks.
+pub(crate) fn format_problems(chunks: &[(&[u8], &[u8])]) -> Vec<String> {
+    let u16_at = |b: &[u8], i: usize| u16::from_le_bytes([b[i], b[i + 1]]);
+    let u32_at = |b: &[u8], i: usize| u32::from_le_bytes([b[i], b[i + 1], b[i + 2], b[i + 3]]);
+    let mut problems = Vec::new();
+    let find = |id: &[u8]| chunks.iter().position(|(c, _)| *c == id);
+    let (Some(fmt_at), Some(data_at)) = (find(b"fmt "), find(b"data")) else {
+        problems.push("missing fmt or data chunk".to_string());
+        return problems;
+    };
+    let (fmt, data) = (chunks[fmt_at].1, chunks[data_at].1);
+    if fmt_at > data_at {
+        problems.push("fmt chunk comes after data".to_string());
+    }
+    if fmt.len() < 16 {
+        problems.push(format!(
+            "fmt chunk of {} bytes, expected at least 16",
+            fmt.len()
+        ));
+        return problems;
+    }
+    let mut tag = u16_at(fmt, 0);
+    let channels = u16_at(fmt, 2) as u32;
+    let sample_rate = u32_at(fmt, 4);
+    let byte_rate = u32_at(fmt, 8);
+    let block_align = u16_at(fmt, 12) as u32;
+    let bits = u16_at(fmt, 14) as u32;
+
+    let expected_align = channels * bits.div_ceil(8);
+    if block_align != expected_align {
+        problems.push(format!(
+            "block align {block_align}, expected {expected_align}"
+        ));
+    }
+    if byte_rate != sample_rate * block_align {
+        problems.push(format!(
+            "byte rate {byte_rate}, expected {}",
+            sample_rate * block_align
+        ));
+    }
+    if tag != FORMAT_PCM {
+        // WAVEFORMATEX: every non-PCM fmt carries cbSize.
+        let extra = (fmt.len() >= 18).then(|| u16_at(fmt, 16) as usize);
+        if extra.is_none_or(|extra| fmt.len() != 18 + extra) {
+            problems.push("non-PCM fmt chunk with a missing or wrong cbSize".to_string());
+        }
+    }
+    if tag == FORMAT_EXTENSIBLE {
+        if fmt.len() < 40 {
+            problems.push(format!(
+                "extensible fmt chunk of {} bytes, expected 40",
+                fmt.len()
+            ));
+            return problems;
+        }
+        tag = u16_at(fmt, 24);
+    }
+    if block_align > 0 && data.len() % block_align as usize != 0 {
+        problems.push(format!(
+            "data size {} is not a multiple of the block align",
+            data.len()
+        ));
+    }
+    if tag != FORMAT_PCM {
+        let frames = data.len() / block_align.max(1) as usize;
+        match find(b"fact").map(|i| chunks[i].1) {
+            None => problems.push("non-PCM data without a fact chunk".to_string()),
+            Some(fact) if fact.len() < 4 || u32_at(fact, 0) as usize != frames => {
+                problems.push(format!(
+                    "fact chunk disagrees with the {frames} data frames"
+                ));
+            }
+            Some(_) => {}
+        }
+    }
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hound::{SampleFormat, WavSpec, WavWriter};
+
+    fn write_silence(path: &Path) -> anyhow::Result<()> {
+        let spec = WavSpec {
+            channels: 1,
+            sample_rate: 16000,
+            bits_per_sample: 16,
+            sample_format: SampleFormat::Int,
+        };
+        let mut writer = WavWriter::create(path, spec)?;
+        for _ in 0..101 {
+            writer.write_sample(0i16)?;
+        }
+        writer.finalize()?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_fact_chunk_satisfies_strict_header() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("float.wav");
+        let spec = WavSpec {
+            channels: 2,
+            sample_rate: 16000,
+            bits_per_sample: 32,
+            sample_format: SampleFormat::Float,
+        };
+        let mut writer = WavWriter::create(&path, spec)?;
+        for _ in 0..200 {
+            writer.write_sample(0.25f32)?;
+        }
+        writer.finalize()?;
+        assert_eq!(
+            header_problems(&fs::read(&path)?),
+            vec!["non-PCM data without a fact chunk"]
+        );
+
+        fix_header(&path, Some(100))?;
+        check_header(&path)?;
+        let mut reader = hound::WavReader::open(&path)?;
+        assert_eq!(reader.samples::<f32>().count(), 200);
+
+        let mut bytes = fs::read(&path)?;
+        bytes[12 + 8 + 12] = 4;
+        assert_eq!(header_problems(&bytes)[0], "block align 4, expected 8");
+        Ok(())
+    }
+
+    #[test]
+    fn test_fmt_body_matches_hound() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("fmt.wav");
+        for (channels, bits, sample_format) in [
+            (1, 8, SampleFormat::Int),
+            (2, 16, SampleFormat::Int),
+            (3, 16, SampleFormat::Int),
+            (1, 24, SampleFormat::Int),
+            (2, 32, SampleFormat::Float),
+        ] {
+            let spec = WavSpec {
+                channels,
+                sample_rate: 44100,
+                bits_per_sample: bits,
+                sample_format,
+            };
+            WavWriter::create(&path, spec)?.finalize()?;
+            let chunks = parse_chunks(&fs::read(&path)?)?;
+            assert_eq!(fmt_body(spec.into()), chunks[0].data, "{spec:?}");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_rescaled_cues_and_loops() {
+        let le =
+            |values: &[u32]| -> Vec<u8> { values.iter().flat_map(|v| v.to_le_bytes()).collect() };
+        let mut cue = Chunk {
+            id: *b"cue ",
+            data: le(&[1, 7, 1000, u32::from_le_bytes(*b"data"), 0, 0, 1000]),
+        };
+        rescale_positions(&mut cue, 0.5, 12.0);
+        assert_eq!(
+            cue.data,
+            le(&[1, 7, 500, u32::from_le_bytes(*b"data"), 0, 0, 500])
+        );
+        let mut smpl = Chunk {
+            id: *b"smpl",
+            data: le(&[0, 0, 62500, 60, 0, 0, 0, 1, 0, 0, 0, 2000, 8000, 0, 0, 0]),
+        };
+        rescale_positions(&mut smpl, 0.5, 12.0);
+        assert_eq!(
+            smpl.data,
+            le(&[0, 0, 62500, 72, 0, 0, 0, 1, 0, 0, 0, 1000, 4000, 0, 0, 0])
+        );
+    }
+
+    #[test]
+    fn test_copy_metadata_round_trip() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let (input, output) = (dir.path().join("in.wav"), dir.path().join("out.wav"));
+        write_silence(&input)?;
+        write_silence(&output)?;
+        let info = Chunk {
+            id: *b"LIST",
+            data: b"INFOINAM\x05\x00\x00\x00Take\x00\x00".to_vec(),
+        };
+        append_chunks(&input, std::slice::from_ref(&info))?;
+
+        copy_metadata(&input, &output)?;
+        assert_eq!(metadata_chunks(&output)?, vec![info]);
+        // The patched file still decodes.
+        assert_eq!(hound::WavReader::open(&output)?.len(), 101);
+        let bytes = fs::read(&output)?;
+        let riff_size = u32::from_le_bytes(bytes[4..8].try_into()?) as usize;
+        assert_eq!(riff_size, bytes.len() - 8);
+        Ok(())
+    }
+}
@@ -0,0 +1,166 @@
+//! Run IDs and timestamps that join one run's reports, sidecars, and outputs.
+//!
+//! Timestamps are RFC 3339 in UTC, so they read, sort, and parse the same
+//! whatever the locale or time zone of the machine that wrote them. Each
+//! output carries its run's ID in a `LIST/INFO` comment.
+
+use crate::riff::Chunk;
+use std::fs::File;
+use std::io::Read;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// One invocation of the tool.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Run {
+    /// A random (version 4) UUID.
+    pub id: String,
+    /// Seconds since the Unix epoch when the run began.
+    pub started: u64,
+}
+
+impl Run {
+    /// A run starting now, with a fresh ID.
+    pub fn start() -> Self {
+        Self {
+            id: new_id(),
+            started: unix_now(),
+        }
+    }
+}
+
+/// A random version 4 UUID, from the OS's random source where there is one.
+pub fn new_id() -> String {
+    let mut bytes = [0u8; 16];
+    let random = File::open("/dev/urandom").and_then(|mut f| f.read_exact(&mut bytes));
+    if random.is_err() {
+        // Without it, mix the clock and process ID; still unique per run.
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_nanos() as u64);
+        let halves = [nanos, u64::from(std::process::id()) ^ nanos.rotate_left(32)];
+        for (half, seed) in bytes.chunks_mut(8).zip(halves) {
+            half.copy_from_slice(&splitmix(seed).to_le_bytes());
+        }
+    }
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let hex: String = bytes.iter().map(|b| format!("{b:02x}")).collect();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
+
+fn splitmix(seed: u64) -> u64 {
+    let mut z = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// Seconds since the Unix epoch.
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+/// `secs` since the Unix epoch as an RFC 3339 UTC timestamp, e.g.
+/// `2024-03-01T12:00:00Z`.
+pub fn timestamp(secs: u64) -> String {
+    let (days, rest) = (secs / 86_400, secs % 86_400);
+    // Civil date from days since 1970-01-01, after Howard Hinnant.
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        rest / 3600,
+        rest / 60 % 60,
+        rest % 60
+    )
+}
+
+/// Seconds since the Unix epoch of a [`timestamp`], if `text` is one.
+pub fn parse_timestamp(text: &str) -> Option<u64> {
+    let digits = |range: std::ops::Range<usize>| text.get(range)?.parse::<i64>().ok();
+    let shape =
+        text.len() == 20 && [4, 7, 10, 13, 16, 19].map(|i| text.as_bytes()[i]) == *b"--T::Z";
+    if !shape {
+        return None;
+    }
+    let (year, month, day) = (digits(0..4)?, digits(5..7)?, digits(8..10)?);
+    let (hour, minute, second) = (digits(11..13)?, digits(14..16)?, digits(17..19)?);
+    // Days since 1970-01-01 of a civil date, after Howard Hinnant.
+    let year = year - i64::from(month <= 2);
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146_097 + day_of_era - 719_468;
+    u64::try_from(days * 86_400 + hour * 3600 + minute * 60 + second).ok()
+}
+
+/// The current time as an RFC 3339 UTC timestamp.
+pub fn now() -> String {
+    timestamp(unix_now())
+}
+
+/// A `LIST/INFO` chunk naming the tool and, as its comment, the run.
+pub fn info_chunk(run_id: &str) -> Chunk {
+    let mut data = b"INFO".to_vec();
+    let software = concat!("wav-files-tempo ", env!("CARGO_PKG_VERSION"));
+    for (id, text) in [
+        (b"ISFT", software.to_string()),
+        (b"ICMT", format!("run {run_id}")),
+    ] {
+        // Each string is NUL-terminated and padded to an even length.
+        let mut value = text.into_bytes();
+        value.push(0);
+        data.extend_from_slice(id);
+        data.extend_from_slice(&(value.len() as u32).to_le_bytes());
+        if value.len() % 2 == 1 {
+            value.push(0);
+        }
+        data.extend_from_slice(&value);
+    }
+    Chunk { id: *b"LIST", data }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timestamps_are_utc_rfc3339() {
+        assert_eq!(timestamp(0), "1970-01-01T00:00:00Z");
+        assert_eq!(timestamp(951_782_400), "2000-02-29T00:00:00Z");
+        assert_eq!(timestamp(1_700_000_000), "2023-11-14T22:13:20Z");
+        assert_eq!(timestamp(4_107_542_399), "2100-02-28T23:59:59Z");
+        for secs in [0, 951_782_400, 1_700_000_000, 4_107_542_399] {
+            assert_eq!(parse_timestamp(&timestamp(secs)), Some(secs));
+        }
+        assert_eq!(parse_timestamp("2023-11-14 22:13:20"), None);
+    }
+
+    #[test]
+    fn test_ids_are_version_4_uuids() {
+        let (a, b) = (new_id(), new_id());
+        assert_ne!(a, b);
+        let groups: Vec<usize> = a.split('-').map(str::len).collect();
+        assert_eq!(groups, [8, 4, 4, 4, 12]);
+        assert_eq!(&a[14..15], "4");
+        assert!("89ab".contains(&a[19..20]), "{a}");
+    }
+}
@@ -0,0 +1,102 @@
+//! Cutting long outputs into segments no longer than a limit.
+//!
+//! Each cut falls in the quietest pause near the end of its segment, so
+//! words aren't split where the material allows it; with no pause to be
+//! found, the segment is cut at the limit.
+
+use crate::format::AudioBuffer;
+
+/// Length of the windows whose level is compared when looking for a pause.
+const WINDOW_SECS: f64 = 0.01;
+
+/// How far back from the limit a cut may move to land in a pause, as a
+/// fraction of the limit.
+const SEARCH_FRACTION: f64 = 0.25;
+
+/// Windows quieter than this (RMS) count as a pause.
+const SILENCE_DBFS: f32 = -40.0;
+
+/// Frame offsets at which to cut `mono`, at `sample_rate`, into pieces of at
+/// most `max_frames` frames, not counting the start and end.
+pub fn cut_points(mono: &[f32], sample_rate: u32, max_frames: usize) -> Vec<usize> {
+    let window = ((f64::from(sample_rate) * WINDOW_SECS) as usize).max(1);
+    let max_frames = max_frames.max(window);
+    let search = (max_frames as f64 * SEARCH_FRACTION) as usize;
+    let silence = 10f32.powf(SILENCE_DBFS / 20.0);
+    let mut cuts = Vec::new();
+    let mut start = 0;
+    while mono.len() - start > max_frames {
+        let limit = start + max_frames;
+        // The quietest window ending by the limit, searching back from it.
+        let mut best: Option<(f32, usize)> = None;
+        let lower = (limit - search).max(start + window);
+        let mut end = limit;
+        while end >= lower {
+            let level = rms(&mono[end - window..end]);
+            if level < silence && best.is_none_or(|(quietest, _)| level < quietest) {
+                best = Some((level, end - window / 2));
+            }
+            end -= window;
+        }
+        let cut = best.map_or(limit, |(_, at)| at);
+        cuts.push(cut);
+        start = cut;
+    }
+    cuts
+}
+
+/// `buffer` cut into segments of at most `max_secs`, at pauses when possible.
+pub fn split(buffer: &AudioBuffer, max_secs: f64) -> Vec<AudioBuffer> {
+    let rate = buffer.format.sample_rate;
+    let max_frames = (max_secs * f64::from(rate)).floor() as usize;
+    let channels = buffer.format.channels.max(1) as usize;
+    let mut bounds = vec![0];
+    bounds.extend(cut_points(&buffer.mono(), rate, max_frames));
+    bounds.push(buffer.frames());
+    bounds
+        .windows(2)
+        .map(|pair| {
+            let samples = buffer.samples[pair[0] * channels..pair[1] * channels].to_vec();
+            AudioBuffer::new(buffer.format, samples)
+        })
+        .collect()
+}
+
+fn rms(samples: &[f32]) -> f32 {
+    (samples.iter().map(|s| s * s).sum::<f32>() / samples.len().max(1) as f32).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::AudioFormat;
+
+    #[test]
+    fn test_cuts_land_in_pauses() {
+        // One second of tone with a pause from 0.8 s to 0.85 s, then more tone.
+        let rate = 1000;
+        let samples: Vec<f32> = (0..2500)
+            .map(|i| if (800..850).contains(&i) { 0.0 } else { 0.5 })
+            .collect();
+        let cuts = cut_points(&samples, rate, 1000);
+        assert!((800..850).contains(&cuts[0]), "{cuts:?}");
+        // Without a pause near the limit, the cut falls right at it.
+        assert_eq!(cuts[1..], [cuts[0] + 1000]);
+
+        let buffer = AudioBuffer::from_channels(
+            AudioFormat {
+                sample_rate: rate,
+                channels: 2,
+                ..AudioFormat::default()
+            },
+            vec![samples.clone(), samples],
+        );
+        let segments = split(&buffer, 1.0);
+        assert_eq!(segments.len(), 3);
+        assert!(segments.iter().all(|s| s.frames() <= 1000));
+        assert_eq!(
+            segments.iter().map(AudioBuffer::frames).sum::<usize>(),
+            2500
+        );
+    }
+}
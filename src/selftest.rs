@@ -0,0 +1,303 @@
+//! The `self-test` suite: bundled fixtures processed with fixed settings and
+//! compared with golden outputs, so a build can be checked on its platform
+//! before it is trusted with real data.
+//!
+//! Fixtures are synthesized, so only the goldens ship with the crate. The
+//! engines differ in their last bits across compilers and CPUs, so outputs
+//! are compared within tolerances rather than bit for bit: sample by sample,
+//! as the largest difference and the signal-to-error ratio, and spectrally,
+//! as the RMS distance between the long-term spectra in dB.
+
+use crate::decode::{self, DecodeLimits};
+use crate::error::Result;
+use crate::fft::{Complex, fft, hann};
+use crate::format::{AudioBuffer, AudioFormat, SampleType};
+use crate::options::{Algorithm, Normalization, ProcessOptions, ProcessOptionsBuilder};
+use std::f32::consts::PI;
+
+/// Sample rate of every fixture.
+const RATE: u32 = 16000;
+
+/// Frames in every fixture (half a second).
+const FRAMES: usize = 8000;
+
+/// Smallest signal-to-error ratio, in dB, an output may have.
+pub const MIN_SNR_DB: f32 = 40.0;
+
+/// Largest difference from the golden any one sample may have.
+pub const MAX_ERROR: f32 = 0.05;
+
+/// Largest RMS log-spectral distance, in dB, an output may have.
+pub const MAX_SPECTRAL_DB: f32 = 1.0;
+
+/// Spectral analysis frame; frames overlap by half.
+const FRAME: usize = 1024;
+
+/// Spectral bins this far (in dB) below the golden's loudest are ignored.
+const SPECTRAL_FLOOR_DB: f32 = 60.0;
+
+/// One fixture, the settings it is processed with, and its golden output.
+pub struct Case {
+    pub name: &'static str,
+    fixture: fn() -> AudioBuffer,
+    /// The engine the case stretches with; `None` when it doesn't stretch.
+    algorithm: Option<Algorithm>,
+    options: fn(ProcessOptionsBuilder) -> ProcessOptionsBuilder,
+    golden: &'static [u8],
+}
+
+const VOCODER: Algorithm = Algorithm::PhaseVocoder {
+    fft_size: 2048,
+    overlap: 4,
+};
+
+/// Every case of the suite.
+pub const CASES: [Case; 6] = [
+    Case {
+        name: "tone-normalize",
+        fixture: tone,
+        algorithm: None,
+        options: |o| o.normalization(Normalization::Peak(-3.0)),
+        golden: include_bytes!("../fixtures/golden/tone-normalize.wav"),
+    },
+    Case {
+        name: "tone-ssstretch-1.25x",
+        fixture: tone,
+        algorithm: Some(Algorithm::Signalsmith),
+        options: |o| o.tempo(1.25),
+        golden: include_bytes!("../fixtures/golden/tone-ssstretch-1.25x.wav"),
+    },
+    Case {
+        name: "chirp-ssstretch-0.8x",
+        fixture: chirp,
+        algorithm: Some(Algorithm::Signalsmith),
+        options: |o| o.tempo(0.8),
+        golden: include_bytes!("../fixtures/golden/chirp-ssstretch-0.8x.wav"),
+    },
+    Case {
+        name: "stereo-ssstretch-pitch",
+        fixture: stereo,
+        algorithm: Some(Algorithm::Signalsmith),
+        options: |o| o.tempo(1.1).pitch_semitones(3.0),
+        golden: include_bytes!("../fixtures/golden/stereo-ssstretch-pitch.wav"),
+    },
+    Case {
+        name: "chirp-pv-1.5x",
+        fixture: chirp,
+        algorithm: Some(VOCODER),
+        options: |o| o.tempo(1.5),
+        golden: include_bytes!("../fixtures/golden/chirp-pv-1.5x.wav"),
+    },
+    Case {
+        name: "clicks-pv-ramp",
+        fixture: clicks,
+        algorithm: Some(VOCODER),
+        options: |o| o.tempo(0.9).tempo_end(Some(1.2)),
+        golden: include_bytes!("../fixtures/golden/clicks-pv-ramp.wav"),
+    },
+];
+
+/// What running one case found.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Outcome {
+    /// The case's algorithm is not compiled into this build.
+    Skipped(&'static str),
+    Compared(Comparison),
+}
+
+/// How an output differs from its golden.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Comparison {
+    pub frames: usize,
+    pub golden_frames: usize,
+    /// Golden energy over error energy in dB; infinite for identical samples.
+    pub snr_db: f32,
+    pub max_error: f32,
+    pub spectral_db: f32,
+}
+
+impl Comparison {
+    /// Whether the output is as long as the golden and within every tolerance.
+    pub fn passes(&self) -> bool {
+        self.frames == self.golden_frames
+            && self.snr_db >= MIN_SNR_DB
+            && self.max_error <= MAX_ERROR
+            && self.spectral_db <= MAX_SPECTRAL_DB
+    }
+}
+
+impl Case {
+    pub fn options(&self) -> Result<ProcessOptions> {
+        let builder = ProcessOptions::builder().algorithm(self.algorithm.unwrap_or_default());
+        (self.options)(builder).build()
+    }
+
+    /// The name of the case's engine when it is not compiled into this build.
+    pub fn missing_engine(&self) -> Option<&'static str> {
+        self.algorithm
+            .filter(|algorithm| !algorithm.is_available())
+            .map(|algorithm| algorithm.name())
+    }
+
+    /// Processes the fixture the way this build does.
+    pub fn render(&self) -> Result<AudioBuffer> {
+        crate::transform((self.fixture)(), &self.options()?)
+    }
+
+    /// Renders the case and compares it with its golden.
+    pub fn run(&self) -> Result<Outcome> {
+        if let Some(engine) = self.missing_engine() {
+            return Ok(Outcome::Skipped(engine));
+        }
+        let golden = decode::decode_bytes(self.golden, &DecodeLimits::default())?;
+        Ok(Outcome::Compared(compare(&self.render()?, &golden)))
+    }
+}
+
+/// Compares `output` with `golden` over the frames they share.
+pub fn compare(output: &AudioBuffer, golden: &AudioBuffer) -> Comparison {
+    let len = output.samples.len().min(golden.samples.len());
+    let (mut signal, mut error, mut max_error) = (0.0f64, 0.0f64, 0.0f32);
+    for (&a, &b) in output.samples[..len].iter().zip(&golden.samples[..len]) {
+        signal += f64::from(b) * f64::from(b);
+        error += f64::from(a - b) * f64::from(a - b);
+        max_error = max_error.max((a - b).abs());
+    }
+    let snr_db = if error == 0.0 {
+        f32::INFINITY
+    } else {
+        (10.0 * (signal / error).log10()) as f32
+    };
+    let (ours, theirs) = (spectrum(&output.mono()), spectrum(&golden.mono()));
+    let loudest = theirs.iter().copied().fold(0.0, f32::max);
+    let floor = loudest * 10f32.powf(-SPECTRAL_FLOOR_DB / 10.0);
+    let distances: Vec<f32> = ours
+        .iter()
+        .zip(&theirs)
+        .filter(|&(_, &g)| g > floor)
+        .map(|(&o, &g)| 10.0 * (o.max(1e-20) / g).log10())
+        .collect();
+    let spectral_db = if distances.is_empty() {
+        0.0
+    } else {
+        (distances.iter().map(|d| d * d).sum::<f32>() / distances.len() as f32).sqrt()
+    };
+    Comparison {
+        frames: output.frames(),
+        golden_frames: golden.frames(),
+        snr_db,
+        max_error,
+        spectral_db,
+    }
+}
+
+/// Mean power per bin of `samples` over half-overlapping Hann frames.
+fn spectrum(samples: &[f32]) -> Vec<f32> {
+    let window = hann(FRAME);
+    let mut buf = vec![Complex::default(); FRAME];
+    let mut power = vec![0.0; FRAME / 2];
+    let mut start = 0;
+    while start + FRAME <= samples.len() {
+        for ((b, &s), &w) in buf.iter_mut().zip(&samples[start..]).zip(&window) {
+            *b = Complex::new(s * w, 0.0);
+        }
+        fft(&mut buf, false);
+        for (p, c) in power.iter_mut().zip(&buf) {
+            *p += c.norm() * c.norm();
+        }
+        start += FRAME / 2;
+    }
+    power
+}
+
+fn format(channels: u16) -> AudioFormat {
+    AudioFormat {
+        sample_rate: RATE,
+        channels,
+        sample_type: SampleType::Float(32),
+    }
+}
+
+/// A 440 Hz note with its octave.
+fn tone() -> AudioBuffer {
+    let samples = (0..FRAMES)
+        .map(|i| {
+            let t = i as f32 / RATE as f32;
+            0.4 * (2.0 * PI * 440.0 * t).sin() + 0.2 * (2.0 * PI * 880.0 * t).sin()
+        })
+        .collect();
+    AudioBuffer::new(format(1), samples)
+}
+
+/// An exponential sweep from 200 Hz to 2 kHz.
+fn chirp() -> AudioBuffer {
+    AudioBuffer::new(format(1), sweep())
+}
+
+fn sweep() -> Vec<f32> {
+    let (low, high) = (200.0f32, 2000.0f32);
+    let secs = FRAMES as f32 / RATE as f32;
+    let rate = (high / low).ln() / secs;
+    (0..FRAMES)
+        .map(|i| {
+            let t = i as f32 / RATE as f32;
+            0.5 * (2.0 * PI * low * ((rate * t).exp() - 1.0) / rate).sin()
+        })
+        .collect()
+}
+
+/// Quiet noise with a click every 100 ms.
+fn clicks() -> AudioBuffer {
+    let mut state = 0x2545_f491_4f6c_dd1du64;
+    let samples = (0..FRAMES)
+        .map(|i| {
+            // A fixed xorshift, so the noise is the same on every platform.
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            let noise = 0.1 * ((state >> 40) as f32 / (1u64 << 24) as f32 - 0.5);
+            if i % (RATE as usize / 10) < 16 {
+                0.8
+            } else {
+                noise
+            }
+        })
+        .collect();
+    AudioBuffer::new(format(1), samples)
+}
+
+/// The tone on the left and the sweep on the right.
+fn stereo() -> AudioBuffer {
+    AudioBuffer::from_channels(format(2), vec![tone().samples, sweep()])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cases_match_their_goldens() {
+        for case in &CASES {
+            match case.run().unwrap() {
+                Outcome::Compared(comparison) => {
+                    assert!(comparison.passes(), "{}: {comparison:?}", case.name)
+                }
+                Outcome::Skipped(_) => {}
+            }
+        }
+    }
+
+    #[test]
+    fn test_compare_flags_drift() {
+        let golden = tone();
+        let same = compare(&golden, &golden);
+        assert!(same.passes() && same.snr_db.is_infinite(), "{same:?}");
+        let mut louder = golden.clone();
+        louder.samples.iter_mut().for_each(|s| *s *= 1.5);
+        let loud = compare(&louder, &golden);
+        assert!(!loud.passes() && loud.spectral_db > 3.0, "{loud:?}");
+        let mut short = golden.clone();
+        short.set_frames(FRAMES - 1);
+        assert!(!compare(&short, &golden).passes());
+    }
+}
@@ -0,0 +1,167 @@
+//! Where [`crate::process_to`] puts an output: a file, or anything else an
+//! embedder routes stretched audio to, such as an in-memory buffer, a
+//! network stream, or an archive entry, without a temporary file between.
+
+use crate::decode::{self, RawAudio};
+use crate::error::{Error, Result};
+use crate::options::ProcessOptions;
+use crate::{atomic, riff, w64};
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+
+/// Takes each finished output, whole and already encoded.
+///
+/// Every [`Write`] is a sink, so a `Vec<u8>`, a `TcpStream`, or an archive
+/// writer positioned at a new entry can be passed as they are.
+pub trait Sink {
+    /// Takes the complete bytes of one output file.
+    fn write(&mut self, bytes: &[u8]) -> Result<()>;
+
+    /// Whether outputs should be encoded as Wave64 rather than RIFF/WAVE.
+    fn wave64(&self) -> bool {
+        false
+    }
+}
+
+impl<W: Write> Sink for W {
+    fn write(&mut self, bytes: &[u8]) -> Result<()> {
+        self.write_all(bytes)
+            .and_then(|_| self.flush())
+            .map_err(|e| Error::io("Failed to write output to sink", e))
+    }
+}
+
+/// A file at `path`, which appears only once complete (see [`atomic`]) and
+/// is Wave64 when named `.w64`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileSink {
+    pub path: PathBuf,
+}
+
+impl FileSink {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+impl Sink for FileSink {
+    fn write(&mut self, bytes: &[u8]) -> Result<()> {
+        atomic::write(&self.path, |temp| {
+            fs::write(temp, bytes).map_err(|e| Error::io("Failed to write output WAV", e))
+        })
+    }
+
+    fn wave64(&self) -> bool {
+        w64::is_w64_path(&self.path)
+    }
+}
+
+/// The output file for `raw` followed by the `trailing` chunks, with the
+/// header and read-back checks `options` asks for, all done in memory.
+pub(crate) fn encode(
+    raw: &RawAudio,
+    trailing: Vec<riff::Chunk>,
+    options: &ProcessOptions,
+    wave64: bool,
+) -> Result<Vec<u8>> {
+    let mut chunks = crate::raw_chunks(raw)?;
+    chunks.extend(trailing);
+    let bytes = if wave64 {
+        w64::encode_chunks(&chunks)
+    } else {
+        riff::encode_chunks(&chunks)?
+    };
+    if options.header_strict {
+        riff::check_header_bytes(&bytes)?;
+    }
+    if options.verify_output {
+        let written = decode::decode_raw(&bytes, &crate::read_back_limits())?;
+        if written.format != raw.format || written.data != raw.data {
+            return Err(Error::Verification(
+                "samples read back differ from what was written".to_string(),
+            ));
+        }
+    }
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::FileSource;
+    use crate::{AudioBuffer, AudioFormat, MetadataPolicy};
+
+    #[test]
+    fn test_sinks_get_the_bytes_process_file_writes() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let input = dir.path().join("in.wav");
+        let samples = (0..16001).map(|i| 0.5 * (i as f32 * 0.05).sin()).collect();
+        crate::write_wav(&input, &AudioBuffer::new(AudioFormat::default(), samples))?;
+        riff::append_chunks(
+            &input,
+            &[riff::Chunk {
+                id: *b"LIST",
+                data: b"INFOICMT\x03\0\0\0hi\0".to_vec(),
+            }],
+        )?;
+        let options = ProcessOptions::builder()
+            .tempo(1.25)
+            .metadata(MetadataPolicy::Preserve)
+            .run_id(Some("r1".to_string()))
+            .verify_output(true)
+            .header_strict(true)
+            .build()?;
+        for name in ["out.wav", "out.w64"] {
+            let output = dir.path().join(name);
+            crate::process_file(&input, &output, &options)?;
+            let mut file = FileSink::new(dir.path().join(format!("sink_{name}")));
+            crate::process_to(&mut FileSource::new(&input), &mut file, &options)?;
+            assert_eq!(fs::read(&file.path)?, fs::read(&output)?, "{name}");
+        }
+
+        let mut buffer = Vec::new();
+        crate::process_to(&mut FileSource::new(&input), &mut buffer, &options)?;
+        assert_eq!(buffer, fs::read(dir.path().join("out.wav"))?);
+        let clicks = ProcessOptions::builder()
+            .click_overlay(Some(120.0))
+            .build()?;
+        assert!(crate::process_to(&mut FileSource::new(&input), &mut buffer, &clicks).is_err());
+        Ok(())
+    }
+
+    /// Keeps every output it takes in memory, as Wave64.
+    #[derive(Default)]
+    struct Outputs(Vec<Vec<u8>>);
+
+    impl Sink for Outputs {
+        fn write(&mut self, bytes: &[u8]) -> Result<()> {
+            self.0.push(bytes.to_vec());
+            Ok(())
+        }
+
+        fn wave64(&self) -> bool {
+            true
+        }
+    }
+
+    #[test]
+    fn test_memory_source_and_sink() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let (input, output) = (dir.path().join("in.wav"), dir.path().join("out.w64"));
+        let samples = (0..16000).map(|i| 0.5 * (i as f32 * 0.05).sin()).collect();
+        crate::write_wav(&input, &AudioBuffer::new(AudioFormat::default(), samples))?;
+        let options = ProcessOptions::builder().tempo(1.25).build()?;
+
+        // As a pipe hands them over: a reader, and bytes never on disk.
+        let mut piped = std::io::Cursor::new(fs::read(&input)?);
+        let mut outputs = Outputs::default();
+        crate::process_to(&mut piped, &mut outputs, &options)?;
+        crate::process_file(&input, &output, &options)?;
+        assert_eq!(outputs.0, [fs::read(&output)?]);
+        assert!(outputs.0[0].starts_with(b"riff"));
+        let frames = decode::decode_bytes(&outputs.0[0], &Default::default())?.frames();
+        assert!(frames.abs_diff(12800) <= 1, "{frames}");
+        Ok(())
+    }
+}
@@ -0,0 +1,92 @@
+//! Where [`crate::process_to`] reads an input from, mirroring
+//! [`crate::sink`]: a file, or any stream or handle an embedder supplies,
+//! such as bytes already in memory, a network body, an archive entry, or an
+//! object-store object.
+
+use crate::error::{Error, Result};
+use crate::units::format_bytes;
+use std::fs;
+use std::io::Read;
+use std::path::PathBuf;
+
+/// Hands over one input file, whole and still encoded.
+///
+/// Every [`Read`] is a source, so a `&[u8]`, a `File`, a `TcpStream`, or an
+/// archive reader positioned at an entry can be passed as they are.
+pub trait Source {
+    /// Reads the whole input. One longer than `max_bytes` may be cut short
+    /// past that point, since decoding refuses it anyway.
+    fn read(&mut self, max_bytes: u64) -> Result<Vec<u8>>;
+}
+
+impl<R: Read> Source for R {
+    fn read(&mut self, max_bytes: u64) -> Result<Vec<u8>> {
+        let mut bytes = Vec::new();
+        self.take(max_bytes.saturating_add(1))
+            .read_to_end(&mut bytes)
+            .map_err(|e| Error::io("Failed to read input from source", e))?;
+        Ok(bytes)
+    }
+}
+
+/// The file at `path`, whose size is checked before it is read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileSource {
+    pub path: PathBuf,
+}
+
+impl FileSource {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+impl Source for FileSource {
+    fn read(&mut self, max_bytes: u64) -> Result<Vec<u8>> {
+        let len = fs::metadata(&self.path)
+            .map_err(|e| Error::io("Failed to open input WAV", e))?
+            .len();
+        if len > max_bytes {
+            return Err(Error::LimitExceeded(format!(
+                "input is {} (max {})",
+                format_bytes(len),
+                format_bytes(max_bytes)
+            )));
+        }
+        fs::read(&self.path).map_err(|e| Error::io("Failed to open input WAV", e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AudioBuffer, AudioFormat, ProcessOptions};
+
+    #[test]
+    fn test_streams_process_like_files() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let input = dir.path().join("in.wav");
+        let samples = (0..16000).map(|i| 0.5 * (i as f32 * 0.05).sin()).collect();
+        crate::write_wav(&input, &AudioBuffer::new(AudioFormat::default(), samples))?;
+        let options = ProcessOptions::builder().tempo(0.8).build()?;
+
+        let (mut from_file, mut from_bytes) = (Vec::new(), Vec::new());
+        crate::process_to(&mut FileSource::new(&input), &mut from_file, &options)?;
+        let bytes = fs::read(&input)?;
+        crate::process_to(&mut bytes.as_slice(), &mut from_bytes, &options)?;
+        assert_eq!(from_file, from_bytes);
+        let frames = crate::decode::decode_bytes(&from_bytes, &Default::default())?.frames();
+        assert!(frames.abs_diff(20000) <= 1, "{frames}");
+
+        let mut limits = options.limits;
+        limits.max_bytes = 1000;
+        let small = ProcessOptions { limits, ..options };
+        for result in [
+            crate::process_to(&mut FileSource::new(&input), &mut Vec::new(), &small),
+            crate::process_to(&mut bytes.as_slice(), &mut Vec::new(), &small),
+        ] {
+            assert!(matches!(result, Err(Error::LimitExceeded(_))), "{result:?}");
+        }
+        Ok(())
+    }
+}
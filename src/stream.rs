@@ -0,0 +1,681 @@
+//! Block-by-block processing for recordings too long to hold in memory.
+//!
+//! [`stream_file`] reads a block of samples, stretches it with one
+//! Signalsmith instance kept for the whole file, and writes the result before
+//! reading on, so memory stays flat however long the input. Whatever needs
+//! the whole signal or file at once is unavailable; [`unsupported`] names it.
+//!
+//! [`split_file`] goes further for huge files: it cuts the input into parts,
+//! streams each through its own engine on its own thread, and joins them
+//! with short crossfades.
+
+use crate::error::{Error, Result};
+use crate::format::{self, AudioFormat, SampleType};
+use crate::options::{Algorithm, MetadataPolicy, Mode, Normalization, ProcessOptions};
+use crate::pacing::Pacer;
+use crate::riff::Chunk;
+use crate::writer::WavStream;
+use crate::{atomic, check_engine, run, w64};
+use hound::WavReader;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+/// Frames read, stretched, and written per step; a tempo ramp moves on once
+/// per block.
+const BLOCK_FRAMES: usize = 4096;
+
+/// What `options` asks for that can't be done block by block, if anything.
+pub fn unsupported(options: &ProcessOptions) -> Option<&'static str> {
+    if options.declip || options.declick || options.denoise.is_some() {
+        Some("restoration (declip, declick, denoise)")
+    } else if matches!(
+        options.normalization,
+        Normalization::Peak(_) | Normalization::Loudness(_)
+    ) {
+        Some("peak or loudness normalization")
+    } else if options.syllable_aware {
+        Some("syllable-aware stretching")
+    } else if options.band_split.is_some() {
+        Some("band-split stretching")
+    } else if options.mode == Mode::Vinyl {
+        Some("vinyl mode")
+    } else if options.needs_stretch() && options.algorithm != Algorithm::Signalsmith {
+        Some("the phase vocoder")
+    } else if options.metadata == MetadataPolicy::Preserve {
+        Some("metadata preservation")
+    } else if options.header_strict || options.verify_output {
+        Some("reading the output back to check it")
+    } else if options.click_overlay.is_some() {
+        Some("click-track overlays")
+    } else {
+        None
+    }
+}
+
+/// Input frames each part of a split file reads past its own range on
+/// either side, in seconds, so its engine has settled by the first frame
+/// kept and runs on past the crossfade.
+const SPLIT_PAD_SECS: f64 = 1.0;
+
+/// Output seconds over which neighbouring parts crossfade.
+const CROSSFADE_SECS: f64 = 0.02;
+
+/// Whether [`stream_file`] can write `output_path` from `input_path` with
+/// `options`.
+pub fn streamable(input_path: &Path, output_path: &Path, options: &ProcessOptions) -> bool {
+    open(input_path, output_path, options).is_ok()
+}
+
+/// An input opened for streaming and checked against the options.
+struct Source {
+    reader: WavReader<BufReader<File>>,
+    format: AudioFormat,
+    frames: usize,
+    target: AudioFormat,
+    gain: f32,
+}
+
+fn open(input_path: &Path, output_path: &Path, options: &ProcessOptions) -> Result<Source> {
+    if let Some(what) = unsupported(options) {
+        return Err(Error::invalid(format!("Streaming does not support {what}")));
+    }
+    if w64::is_w64_path(input_path) || w64::is_w64_path(output_path) {
+        return Err(Error::invalid("Streaming reads and writes RIFF/WAVE only"));
+    }
+    let limits = &options.limits;
+    let len = fs::metadata(input_path)
+        .map_err(|e| Error::io("Failed to open input WAV", e))?
+        .len();
+    if len > limits.max_bytes {
+        return Err(Error::LimitExceeded(format!(
+            "input is {} (max {})",
+            crate::units::format_bytes(len),
+            crate::units::format_bytes(limits.max_bytes)
+        )));
+    }
+    let reader =
+        WavReader::open(input_path).map_err(|e| Error::decode("Failed to open input WAV", e))?;
+    let format = AudioFormat::from(reader.spec());
+    options.check_input(&format)?;
+    let frames = reader.duration() as usize;
+    if let Some(max) = limits.max_duration {
+        let duration = frames as f64 / format.sample_rate as f64;
+        if duration > max {
+            return Err(Error::LimitExceeded(format!(
+                "duration {} (max {})",
+                crate::units::format_duration(duration),
+                crate::units::format_duration(max)
+            )));
+        }
+    }
+    // The engine only ever sees one block.
+    check_engine(BLOCK_FRAMES, options)?;
+    let target = options.output.resolve(format);
+    if (target.sample_rate, target.channels) != (format.sample_rate, format.channels) {
+        return Err(Error::invalid(
+            "Streaming does not support sample rate or channel conversion",
+        ));
+    }
+    let gain = match options.normalization {
+        Normalization::Gain(gain_db) => 10f32.powf(gain_db / 20.0),
+        _ => 1.0,
+    };
+    Ok(Source {
+        reader,
+        format,
+        frames,
+        target,
+        gain,
+    })
+}
+
+/// Like [`crate::process_file`] for RIFF/WAVE files, holding only a block of
+/// samples at a time. After each block, `on_block` gets the input frames
+/// processed so far and the file's total. Like every output, the file only
+/// appears at `output_path` once complete (see [`atomic`]).
+pub fn stream_file(
+    input_path: &Path,
+    output_path: &Path,
+    options: &ProcessOptions,
+    mut on_block: impl FnMut(usize, usize),
+) -> Result<()> {
+    let Source {
+        mut reader,
+        format,
+        frames,
+        target,
+        gain,
+    } = open(input_path, output_path, options)?;
+    atomic::write(output_path, |temp| {
+        let mut writer = WavStream::create(temp, target, None)?;
+        let mut samples = samples(&mut reader, format.sample_type);
+        let channels = format.channels.max(1) as usize;
+        let mut stretcher = Stretcher::new(format, frames, options);
+        let mut block = Vec::with_capacity(BLOCK_FRAMES * channels);
+        loop {
+            block.clear();
+            for sample in samples.by_ref().take(BLOCK_FRAMES * channels) {
+                block.push(sample.map_err(|e| Error::decode("Failed to read sample", e))?);
+            }
+            // A trailing partial frame is dropped, as when decoding whole files.
+            block.truncate(block.len() / channels * channels);
+            if block.is_empty() {
+                break;
+            }
+            writer.write(&scaled(stretcher.process(&block), gain))?;
+            on_block(stretcher.read, frames);
+        }
+        writer.write(&scaled(stretcher.finish(), gain))?;
+        writer.finish_with(&tags(options))
+    })
+}
+
+/// Like [`stream_file`], with the file cut into up to `parts` parts, each
+/// streamed through its own engine on its own thread into a temporary file
+/// beside the output, then joined with crossfades. `on_block` is called from
+/// every thread. Tempo ramps, and files too short to split, are streamed
+/// whole.
+///
+/// The parts are [`atomic::scratch`] files under the caller's claim, so
+/// work abandoned at its deadline leaves none of them behind.
+pub fn split_file(
+    input_path: &Path,
+    output_path: &Path,
+    options: &ProcessOptions,
+    parts: usize,
+    on_block: impl Fn(usize, usize) + Sync,
+) -> Result<()> {
+    let Source {
+        format,
+        frames,
+        target,
+        gain,
+        ..
+    } = open(input_path, output_path, options)?;
+    let rate = f64::from(format.sample_rate);
+    let pad = (SPLIT_PAD_SECS * rate) as usize;
+    let parts = parts.min(frames / (2 * pad).max(1));
+    if parts <= 1 || options.tempo_end.is_some() || options.tempo_map.is_some() {
+        return stream_file(input_path, output_path, options, on_block);
+    }
+    let len = frames.div_ceil(parts);
+    let bounds: Vec<usize> = (0..=parts).map(|k| (k * len).min(frames)).collect();
+    let temps: Vec<PathBuf> = (0..parts)
+        .map(|k| output_path.with_extension(format!("part{k}.tmp")))
+        .collect();
+    let read = AtomicUsize::new(0);
+    let claim = atomic::Claim::current().unwrap_or_default();
+    let rendered = thread::scope(|s| {
+        let workers: Vec<_> = (0..parts)
+            .map(|k| {
+                let range = bounds[k].saturating_sub(pad)..(bounds[k + 1] + pad).min(frames);
+                let (temp, read, on_block, claim) = (&temps[k], &read, &on_block, &claim);
+                s.spawn(move || {
+                    claim.run(|| {
+                        render_part(input_path, temp, format, range, options, |n| {
+                            let done = read.fetch_add(n, Ordering::Relaxed) + n;
+                            on_block(done.min(frames), frames);
+                        })
+                    })
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .map(|worker| {
+                worker
+                    .join()
+                    .unwrap_or_else(|_| Err(Error::Internal("a split part panicked".to_string())))
+            })
+            .collect::<Result<Vec<()>>>()
+    });
+    let joined = rendered.and_then(|_| {
+        let plan = Joins {
+            bounds,
+            pad,
+            tempo: f64::from(options.tempo),
+            fade: (CROSSFADE_SECS * rate) as usize,
+            channels: target.channels.max(1) as usize,
+        };
+        atomic::write(output_path, |temp| {
+            let mut writer = WavStream::create(temp, target, None)?;
+            plan.join(&temps, &mut writer, gain)?;
+            writer.finish_with(&tags(options))
+        })
+    });
+    for temp in &temps {
+        let _ = fs::remove_file(temp);
+    }
+    joined?;
+    on_block(frames, frames);
+    Ok(())
+}
+
+/// Streams the input frames in `range` through a fresh engine into `temp`
+/// as raw interleaved `f32`s, calling `on_read` with each block's length.
+fn render_part(
+    input_path: &Path,
+    temp: &Path,
+    format: AudioFormat,
+    range: std::ops::Range<usize>,
+    options: &ProcessOptions,
+    on_read: impl Fn(usize),
+) -> Result<()> {
+    let mut reader =
+        WavReader::open(input_path).map_err(|e| Error::decode("Failed to open input WAV", e))?;
+    reader
+        .seek(range.start as u32)
+        .map_err(|e| Error::io("Failed to seek input WAV", e))?;
+    let channels = format.channels.max(1) as usize;
+    let mut samples = samples(&mut reader, format.sample_type).take(range.len() * channels);
+    let mut out = BufWriter::new(atomic::scratch(temp)?);
+    let mut put = |samples: Vec<f32>| {
+        let bytes: Vec<u8> = samples.iter().flat_map(|s| s.to_le_bytes()).collect();
+        out.write_all(&bytes)
+            .map_err(|e| Error::io("Failed to write split part", e))
+    };
+    let mut stretcher = Stretcher::new(format, range.len(), options);
+    let mut block = Vec::with_capacity(BLOCK_FRAMES * channels);
+    loop {
+        block.clear();
+        for sample in samples.by_ref().take(BLOCK_FRAMES * channels) {
+            block.push(sample.map_err(|e| Error::decode("Failed to read sample", e))?);
+        }
+        block.truncate(block.len() / channels * channels);
+        if block.is_empty() {
+            break;
+        }
+        put(stretcher.process(&block))?;
+        on_read(block.len() / channels);
+    }
+    put(stretcher.finish())?;
+    out.flush()
+        .map_err(|e| Error::io("Failed to write split part", e))
+}
+
+/// Where the parts of a split file are cut and joined, in output frames.
+struct Joins {
+    /// Input frames each part starts at, and the total at the end.
+    bounds: Vec<usize>,
+    /// Input frames each part reads before its start.
+    pad: usize,
+    tempo: f64,
+    /// Output frames each join crossfades over.
+    fade: usize,
+    channels: usize,
+}
+
+impl Joins {
+    /// The output frame that input frame `frame` lands on.
+    fn at(&self, frame: usize) -> usize {
+        (frame as f64 / self.tempo).round() as usize
+    }
+
+    /// Writes each part's own range from `temps` to `writer`, crossfading
+    /// into each part over the first frames of its range.
+    fn join(&self, temps: &[PathBuf], writer: &mut WavStream, gain: f32) -> Result<()> {
+        let last = temps.len() - 1;
+        let mut tail = Vec::new();
+        for (k, temp) in temps.iter().enumerate() {
+            let file = File::open(temp).map_err(|e| Error::io("Failed to read split part", e))?;
+            let mut part = Part {
+                file: BufReader::new(file),
+                channels: self.channels,
+            };
+            let (start, end) = (self.at(self.bounds[k]), self.at(self.bounds[k + 1]));
+            part.skip(start - self.at(self.bounds[k].saturating_sub(self.pad)))?;
+            let mut from = start;
+            if k > 0 {
+                let head = part.exact(self.fade)?;
+                writer.write(&scaled(crossfade(&tail, &head, self.channels), gain))?;
+                from += self.fade;
+            }
+            if k == last {
+                loop {
+                    let block = part.read(BLOCK_FRAMES)?;
+                    if block.is_empty() {
+                        break;
+                    }
+                    writer.write(&scaled(block, gain))?;
+                }
+                break;
+            }
+            for at in (from..end).step_by(BLOCK_FRAMES) {
+                writer.write(&scaled(part.exact(BLOCK_FRAMES.min(end - at))?, gain))?;
+            }
+            tail = part.exact(self.fade)?;
+        }
+        Ok(())
+    }
+}
+
+/// One part's rendered samples, read back in frames.
+struct Part {
+    file: BufReader<File>,
+    channels: usize,
+}
+
+impl Part {
+    fn skip(&mut self, frames: usize) -> Result<()> {
+        let bytes = (frames * self.channels * 4) as i64;
+        self.file
+            .seek(SeekFrom::Current(bytes))
+            .map(drop)
+            .map_err(|e| Error::io("Failed to read split part", e))
+    }
+
+    /// Up to `frames` frames, fewer at the end of the part.
+    fn read(&mut self, frames: usize) -> Result<Vec<f32>> {
+        let mut bytes = Vec::with_capacity(frames * self.channels * 4);
+        (&mut self.file)
+            .take((frames * self.channels * 4) as u64)
+            .read_to_end(&mut bytes)
+            .map_err(|e| Error::io("Failed to read split part", e))?;
+        Ok(bytes
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect())
+    }
+
+    /// Exactly `frames` frames, padded with silence past the end of the part.
+    fn exact(&mut self, frames: usize) -> Result<Vec<f32>> {
+        let mut samples = self.read(frames)?;
+        samples.resize(frames * self.channels, 0.0);
+        Ok(samples)
+    }
+}
+
+/// Fades interleaved `from` out and `to` in over their common length.
+fn crossfade(from: &[f32], to: &[f32], channels: usize) -> Vec<f32> {
+    let frames = (from.len().min(to.len()) / channels).max(1);
+    from.iter()
+        .zip(to)
+        .enumerate()
+        .map(|(i, (a, b))| {
+            let w = (i / channels) as f32 / frames as f32;
+            a * (1.0 - w) + b * w
+        })
+        .collect()
+}
+
+/// The chunks written after the samples: the run's tag, if it has one.
+fn tags(options: &ProcessOptions) -> Vec<Chunk> {
+    options
+        .run_id
+        .iter()
+        .map(|id| run::info_chunk(id))
+        .collect()
+}
+
+/// The samples of `reader`, normalized to [-1.0, 1.0].
+fn samples(
+    reader: &mut WavReader<BufReader<File>>,
+    sample_type: SampleType,
+) -> Box<dyn Iterator<Item = hound::Result<f32>> + '_> {
+    match sample_type {
+        SampleType::Int(bits) => Box::new(
+            reader
+                .samples::<i32>()
+                .map(move |s| s.map(|s| format::int_to_f32(s, bits))),
+        ),
+        SampleType::Float(_) => Box::new(
+            reader
+                .samples::<f32>()
+                .map(|s| s.map(|s| if s.is_finite() { s } else { 0.0 })),
+        ),
+    }
+}
+
+fn scaled(mut samples: Vec<f32>, gain: f32) -> Vec<f32> {
+    if gain != 1.0 {
+        samples.iter_mut().for_each(|s| *s *= gain);
+    }
+    samples
+}
+
+/// One file's stretch state, kept across blocks so they join seamlessly.
+struct Stretcher {
+    #[cfg(feature = "ssstretch")]
+    engine: Option<crate::Aligned>,
+    channels: usize,
+    read: usize,
+    rate: u32,
+    pacer: Option<Arc<Pacer>>,
+}
+
+impl Stretcher {
+    #[cfg_attr(not(feature = "ssstretch"), allow(unused_variables))]
+    fn new(format: AudioFormat, frames: usize, options: &ProcessOptions) -> Self {
+        let channels = format.channels.max(1) as usize;
+        #[cfg(feature = "ssstretch")]
+        let engine = options.needs_stretch().then(|| {
+            let (start, end) = (options.tempo, options.tempo_end.unwrap_or(options.tempo));
+            let frames = frames.max(1) as f32;
+            let rate = f64::from(format.sample_rate);
+            let tempo_at: Box<dyn Fn(usize) -> f32> = match options.tempo_map.clone() {
+                Some(map) => Box::new(move |frame| map.at(frame as f64 / rate)),
+                None => {
+                    Box::new(move |frame| start + (end - start) * (frame as f32 / frames).min(1.0))
+                }
+            };
+            let stretch = crate::engine(channels, format.sample_rate, options);
+            crate::Aligned::new(stretch, tempo_at)
+        });
+        Self {
+            #[cfg(feature = "ssstretch")]
+            engine,
+            channels,
+            read: 0,
+            rate: format.sample_rate,
+            pacer: options.pacer.clone(),
+        }
+    }
+
+    /// Stretches the next interleaved block of the file.
+    fn process(&mut self, block: &[f32]) -> Vec<f32> {
+        let ch = self.channels;
+        self.read += block.len() / ch;
+        if let Some(pacer) = &self.pacer {
+            pacer.pace((block.len() / ch) as f64 / f64::from(self.rate.max(1)));
+        }
+        #[cfg(feature = "ssstretch")]
+        if let Some(engine) = &mut self.engine {
+            let inputs: Vec<Vec<f32>> = (0..ch)
+                .map(|c| block.iter().skip(c).step_by(ch).copied().collect())
+                .collect();
+            let inputs: Vec<&[f32]> = inputs.iter().map(Vec::as_slice).collect();
+            return interleave(engine.process(&inputs));
+        }
+        block.to_vec()
+    }
+
+    /// The interleaved tail left in the engine once the input has run out.
+    fn finish(&mut self) -> Vec<f32> {
+        #[cfg(feature = "ssstretch")]
+        if let Some(engine) = &mut self.engine {
+            return interleave(engine.finish());
+        }
+        Vec::new()
+    }
+}
+
+#[cfg_attr(not(feature = "ssstretch"), allow(dead_code))]
+fn interleave(channels: Vec<Vec<f32>>) -> Vec<f32> {
+    let frames = channels.iter().map(Vec::len).min().unwrap_or(0);
+    (0..frames)
+        .flat_map(|i| channels.iter().map(move |c| c[i]))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::PI;
+
+    #[test]
+    fn test_stream_matches_in_memory_length() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let input = dir.path().join("in.wav");
+        let samples: Vec<f32> = (0..40_000)
+            .map(|i| 0.3 * (2.0 * PI * 440.0 * i as f32 / 16000.0).sin())
+            .collect();
+        crate::write_wav(
+            &input,
+            &crate::AudioBuffer::new(AudioFormat::default(), samples),
+        )?;
+        let options = ProcessOptions::builder().tempo(1.25).build()?;
+        let (streamed, whole) = (dir.path().join("s.wav"), dir.path().join("w.wav"));
+        let mut reported = Vec::new();
+        stream_file(&input, &streamed, &options, |done, total| {
+            reported.push((done, total))
+        })?;
+        assert_eq!(reported.len(), 40_000_usize.div_ceil(BLOCK_FRAMES));
+        assert_eq!(reported.last(), Some(&(40_000, 40_000)));
+        crate::process_file(&input, &whole, &options)?;
+        let (a, b) = (crate::read_wav(&streamed)?, crate::read_wav(&whole)?);
+        assert_eq!(a.format, b.format);
+        assert!(a.frames().abs_diff(b.frames()) <= 1);
+        assert!(crate::loudness::peak_dbfs(&a.samples) > -20.0);
+
+        let peak = ProcessOptions::builder()
+            .normalization(Normalization::Peak(-1.0))
+            .build()?;
+        let err = stream_file(&input, &streamed, &peak, |_, _| {}).unwrap_err();
+        assert!(err.to_string().contains("peak or loudness"), "{err}");
+        Ok(())
+    }
+
+    #[test]
+    fn test_split_parts_join_seamlessly() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let input = dir.path().join("in.wav");
+        let format = AudioFormat {
+            channels: 2,
+            ..AudioFormat::default()
+        };
+        let samples: Vec<f32> = (0..16000 * 6 * 2)
+            .map(|i| 0.3 * (2.0 * PI * 220.0 * (i / 2) as f32 / 16000.0).sin())
+            .collect();
+        crate::write_wav(&input, &crate::AudioBuffer::new(format, samples))?;
+        let options = ProcessOptions::builder().tempo(0.8).build()?;
+        let (split, streamed) = (dir.path().join("p.wav"), dir.path().join("s.wav"));
+        let last = std::sync::Mutex::new((0, 0));
+        split_file(&input, &split, &options, 3, |done, total| {
+            *last.lock().unwrap() = (done, total)
+        })?;
+        assert_eq!(*last.lock().unwrap(), (96_000, 96_000));
+        stream_file(&input, &streamed, &options, |_, _| {})?;
+        let (a, b) = (crate::read_wav(&split)?, crate::read_wav(&streamed)?);
+        assert_eq!(a.format, b.format);
+        assert!(
+            a.frames().abs_diff(b.frames()) <= 2,
+            "{} vs {}",
+            a.frames(),
+            b.frames()
+        );
+        // A join out of step would jump far more than a 220 Hz tone can.
+        let left: Vec<f32> = a.samples.iter().step_by(2).copied().collect();
+        let jump = left
+            .windows(2)
+            .map(|w| (w[1] - w[0]).abs())
+            .fold(0.0, f32::max);
+        assert!(jump < 0.05, "{jump}");
+        assert!(
+            fs::read_dir(dir.path())?.all(|e| !e
+                .unwrap()
+                .path()
+                .to_string_lossy()
+                .ends_with(".tmp"))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_split_parts_never_outlive_their_work() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let input = dir.path().join("in.wav");
+        let samples: Vec<f32> = (0..16000 * 6)
+            .map(|i| 0.3 * (2.0 * PI * 220.0 * i as f32 / 16000.0).sin())
+            .collect();
+        crate::write_wav(
+            &input,
+            &crate::AudioBuffer::new(AudioFormat::default(), samples),
+        )?;
+        let output = dir.path().join("p.wav");
+        let leftovers = || -> Vec<PathBuf> {
+            let names = fs::read_dir(dir.path()).unwrap().map(|e| e.unwrap().path());
+            names
+                .filter(|path| path.is_file() && path != &input)
+                .collect()
+        };
+
+        // A part that can't be created fails the split, and the parts that
+        // were go with it.
+        fs::create_dir(output.with_extension("part1.tmp"))?;
+        let options = ProcessOptions::builder().tempo(0.8).build()?;
+        assert!(split_file(&input, &output, &options, 3, |_, _| {}).is_err());
+        assert_eq!(leftovers(), Vec::<PathBuf>::new());
+        fs::remove_dir(output.with_extension("part1.tmp"))?;
+
+        // Paced to about half a second, but given a tenth.
+        let paced = ProcessOptions {
+            pacer: Some(Arc::new(Pacer::new(12.0))),
+            ..options
+        };
+        let (done, finished) = std::sync::mpsc::channel();
+        let (from, to) = (input.clone(), output.clone());
+        let deadline = std::time::Instant::now() + std::time::Duration::from_millis(100);
+        let late = crate::pipeline::with_deadline(Some(deadline), move || {
+            let result = split_file(&from, &to, &paced, 3, |_, _| {});
+            let _ = done.send(result.is_ok());
+        });
+        assert!(late.is_none());
+        assert_eq!(leftovers(), Vec::<PathBuf>::new());
+        // The abandoned split runs on, and neither its parts nor its output
+        // ever show up.
+        assert!(!finished.recv()?);
+        assert_eq!(leftovers(), Vec::<PathBuf>::new());
+        Ok(())
+    }
+
+    #[test]
+    fn test_pacer_holds_back_every_block() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let input = dir.path().join("in.wav");
+        let samples: Vec<f32> = (0..16000)
+            .map(|i| 0.3 * (2.0 * PI * 440.0 * i as f32 / 16000.0).sin())
+            .collect();
+        let buffer = crate::AudioBuffer::new(AudioFormat::default(), samples);
+        crate::write_wav(&input, &buffer)?;
+        // Ten seconds of audio per second: a tenth of a second for the file.
+        let options = ProcessOptions::builder()
+            .tempo(1.25)
+            .pacer(Some(Arc::new(Pacer::new(10.0))))
+            .build()?;
+        let start = std::time::Instant::now();
+        let mut blocks = Vec::new();
+        stream_file(&input, &dir.path().join("s.wav"), &options, |done, _| {
+            blocks.push((done, start.elapsed().as_secs_f64()))
+        })?;
+        assert!(blocks.len() > 1);
+        for (done, elapsed) in blocks {
+            assert!(
+                elapsed >= done as f64 / 16000.0 / 10.0 - 0.005,
+                "{done} {elapsed}"
+            );
+        }
+
+        let fresh = ProcessOptions {
+            pacer: Some(Arc::new(Pacer::new(10.0))),
+            ..options
+        };
+        let start = std::time::Instant::now();
+        crate::transform(buffer, &fresh)?;
+        assert!(start.elapsed().as_secs_f64() >= 0.095);
+        Ok(())
+    }
+}
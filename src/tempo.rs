@@ -0,0 +1,403 @@
+//! Per-file tempos from the sources a batch can combine.
+//!
+//! When several sources give a file a tempo, the most specific one wins:
+//! a manifest entry naming the file, then the duration of its reference,
+//! then a tag in its name, then the nearest directory file, then
+//! `--target-duration`, then `--target-bpm` from the detected beat, then a
+//! measured speech rate, then a random draw from
+//! `--tempo-range`, then `--tempo`.
+
+use crate::analysis;
+use crate::config::{Config, Value};
+use crate::decode::{self, DecodeLimits};
+use crate::error::{Error, Result};
+use std::fs;
+use std::path::Path;
+
+/// Name of the per-directory tempo file, holding `tempo = <TEMPO>`.
+pub const DIRECTORY_FILE: &str = "tempo.toml";
+
+/// Where a file's tempo came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TempoSource {
+    /// A `--tempo-manifest` entry for the file.
+    Manifest,
+    /// The duration of the file's `--match-duration-of` counterpart.
+    Reference,
+    /// A `name@1.25x.wav` tag in the file name.
+    Name,
+    /// The nearest [`DIRECTORY_FILE`] above the file.
+    Directory,
+    /// `--target-duration` from the file's own length.
+    Target,
+    /// `--target-bpm` from the file's detected beat.
+    Bpm,
+    /// `--equalize-rate` from the measured speech rate.
+    SpeechRate,
+    /// A draw from `--tempo-range`.
+    Random,
+    /// The `--tempo` flag (or its ramp) for the whole batch.
+    Flag,
+}
+
+impl TempoSource {
+    /// Every source, highest precedence first.
+    pub const PRECEDENCE: [Self; 9] = [
+        Self::Manifest,
+        Self::Reference,
+        Self::Name,
+        Self::Directory,
+        Self::Target,
+        Self::Bpm,
+        Self::SpeechRate,
+        Self::Random,
+        Self::Flag,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Manifest => "manifest",
+            Self::Reference => "reference",
+            Self::Name => "name",
+            Self::Directory => "dir",
+            Self::Target => "target",
+            Self::Bpm => "bpm",
+            Self::SpeechRate => "speech-rate",
+            Self::Random => "random",
+            Self::Flag => "flag",
+        }
+    }
+}
+
+/// The tempo of the highest-precedence source that `tempo_from` gives one
+/// for, considering only `forced` if set. `None` leaves the file at the
+/// batch's `--tempo`.
+pub fn pick(
+    forced: Option<TempoSource>,
+    mut tempo_from: impl FnMut(TempoSource) -> Result<Option<f32>>,
+) -> Result<Option<(f32, TempoSource)>> {
+    for source in TempoSource::PRECEDENCE {
+        if forced.is_some_and(|f| f != source) {
+            continue;
+        }
+        if let Some(tempo) = tempo_from(source)? {
+            return Ok(Some((tempo, source)));
+        }
+    }
+    Ok(None)
+}
+
+/// A tempo that changes over a file: `(seconds, tempo)` points, interpolated
+/// linearly between them and held before the first and after the last.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TempoMap {
+    points: Vec<(f64, f32)>,
+}
+
+impl TempoMap {
+    /// A map through `points`, which must be in order of time.
+    pub fn new(points: Vec<(f64, f32)>) -> Result<Self> {
+        if points.is_empty() {
+            return Err(Error::invalid("Tempo map has no points"));
+        }
+        for pair in points.windows(2) {
+            if pair[1].0 <= pair[0].0 {
+                return Err(Error::invalid(format!(
+                    "Tempo map times must increase, found {} after {}",
+                    pair[1].0, pair[0].0
+                )));
+            }
+        }
+        Ok(Self { points })
+    }
+
+    /// Reads a map of `time_seconds,tempo` lines. Blank lines, `#` comments,
+    /// and a `time_seconds,tempo` header are skipped.
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| Error::io(format!("Failed to read tempo map {:?}", path), e))?;
+        let mut points = Vec::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty()
+                || line.starts_with('#')
+                || line.eq_ignore_ascii_case("time_seconds,tempo")
+            {
+                continue;
+            }
+            let at = |message: String| {
+                Error::invalid(format!(
+                    "Invalid tempo map {:?}: line {}: {message}",
+                    path,
+                    number + 1
+                ))
+            };
+            let (time, tempo) = line
+                .split_once(',')
+                .ok_or_else(|| at(format!("expected time_seconds,tempo, found {line}")))?;
+            let time: f64 = time
+                .trim()
+                .parse()
+                .ok()
+                .filter(|t: &f64| t.is_finite() && *t >= 0.0)
+                .ok_or_else(|| at(format!("invalid time {time}")))?;
+            let tempo =
+                parse_tempo(tempo.trim()).ok_or_else(|| at(format!("invalid tempo {tempo}")))?;
+            points.push((time, tempo));
+        }
+        Self::new(points).map_err(|e| Error::invalid(format!("{:?}: {e}", path)))
+    }
+
+    /// The tempo `secs` into the file.
+    pub fn at(&self, secs: f64) -> f32 {
+        let after = self.points.partition_point(|&(time, _)| time <= secs);
+        match (after.checked_sub(1), self.points.get(after)) {
+            (Some(before), Some(&(t1, v1))) => {
+                let (t0, v0) = self.points[before];
+                let t = ((secs - t0) / (t1 - t0)) as f32;
+                v0 + (v1 - v0) * t
+            }
+            (Some(before), None) => self.points[before].1,
+            (None, _) => self.points[0].1,
+        }
+    }
+
+    /// The slowest tempo anywhere on the map.
+    pub fn slowest(&self) -> f32 {
+        self.points.iter().map(|&(_, t)| t).fold(f32::MAX, f32::min)
+    }
+}
+
+/// The tempo that stretches `input` to the duration of `reference`.
+///
+/// The two must share a sample rate and channel count; a mismatch would
+/// usually mean the pairs are misaligned, so it fails naming both formats
+/// rather than producing a tempo that only looks plausible.
+pub fn match_duration(input: &Path, reference: &Path, limits: &DecodeLimits) -> Result<f32> {
+    let input = decode::decode_raw_file(input, limits)?;
+    let reference = decode::decode_raw_file(reference, limits)
+        .map_err(|e| Error::invalid(format!("reference: {e}")))?;
+    let (ours, theirs) = (input.format, reference.format);
+    if (ours.sample_rate, ours.channels) != (theirs.sample_rate, theirs.channels) {
+        return Err(Error::invalid(format!(
+            "input is {ours} but its reference is {theirs}"
+        )));
+    }
+    if input.frames() == 0 || reference.frames() == 0 {
+        return Err(Error::invalid(
+            "cannot match durations with an empty input or reference",
+        ));
+    }
+    Ok(input.frames() as f32 / reference.frames() as f32)
+}
+
+/// The tempo that brings `input` to `target` seconds, judged from its
+/// header where it can be; `None` for an empty file.
+pub fn for_duration(input: &Path, target: f64, limits: &DecodeLimits) -> Result<Option<f32>> {
+    let (frames, rate) = match hound::WavReader::open(input) {
+        Ok(reader) => (reader.duration() as usize, reader.spec().sample_rate),
+        // Wave64 and damaged files need the hardened decoder.
+        Err(_) => {
+            let raw = decode::decode_raw_file(input, limits)?;
+            (raw.frames(), raw.format.sample_rate)
+        }
+    };
+    let duration = frames as f64 / f64::from(rate.max(1));
+    Ok((frames > 0).then(|| (duration / target) as f32))
+}
+
+/// The tempo that brings the beat detected in `input` to `target` BPM;
+/// `None` when no steady beat is found.
+pub fn for_bpm(input: &Path, target: f32, limits: &DecodeLimits) -> Result<Option<f32>> {
+    let buffer = decode::decode_file(input, limits)?;
+    let detected = analysis::estimate_bpm(&buffer.mono(), buffer.format.sample_rate);
+    Ok(detected.map(|bpm| target / bpm))
+}
+
+/// The tempo tagged at the end of a file name, e.g. 1.25 for `talk@1.25x.wav`.
+pub fn from_name(path: &Path) -> Option<f32> {
+    let stem = path.file_stem()?.to_str()?;
+    let (_, tag) = stem.rsplit_once('@')?;
+    parse_tempo(tag.strip_suffix(['x', 'X'])?)
+}
+
+/// The tempo set by the nearest [`DIRECTORY_FILE`] from the directory of
+/// `input` up to and including `root`.
+pub fn from_directory(input: &Path, root: &Path) -> Result<Option<f32>> {
+    for dir in input.ancestors().skip(1) {
+        let file = dir.join(DIRECTORY_FILE);
+        if file.is_file() {
+            let config = Config::load(&file)?;
+            let tempo = config.defaults.iter().find(|(key, _)| key == "tempo");
+            return match tempo.map(|(_, value)| value) {
+                Some(Value::Float(x)) => Some(*x as f32),
+                Some(Value::Integer(n)) => Some(*n as f32),
+                _ => None,
+            }
+            .filter(|&t| is_valid(t))
+            .map(Some)
+            .ok_or_else(|| {
+                Error::invalid(format!("{:?} must set tempo = <positive number>", file))
+            });
+        }
+        if dir == root {
+            break;
+        }
+    }
+    Ok(None)
+}
+
+/// Parses a `LOW:HIGH` tempo range such as `0.85:1.15`.
+pub fn parse_range(text: &str) -> Result<(f32, f32)> {
+    text.split_once(':')
+        .and_then(|(low, high)| Some((parse_tempo(low.trim())?, parse_tempo(high.trim())?)))
+        .filter(|(low, high)| low <= high)
+        .ok_or_else(|| {
+            Error::invalid(format!(
+                "Invalid tempo range {text:?} (expected LOW:HIGH, e.g. 0.85:1.15)"
+            ))
+        })
+}
+
+/// A tempo drawn uniformly from `range` for the file at `key`. The draw
+/// depends only on `seed` and `key`, so a batch gets the same tempos
+/// whatever order or however many threads its files run in.
+pub fn jitter((low, high): (f32, f32), seed: u64, key: &Path) -> f32 {
+    // FNV-1a over the key, then the SplitMix64 finalizer to spread it.
+    let mut z = seed ^ 0xcbf2_9ce4_8422_2325;
+    for byte in key.to_string_lossy().bytes() {
+        z = (z ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3);
+    }
+    z = z.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^= z >> 31;
+    let unit = (z >> 11) as f64 / (1u64 << 53) as f64;
+    (f64::from(low) + f64::from(high - low) * unit) as f32
+}
+
+pub(crate) fn parse_tempo(text: &str) -> Option<f32> {
+    text.parse().ok().filter(|&t| is_valid(t))
+}
+
+fn is_valid(tempo: f32) -> bool {
+    tempo.is_finite() && tempo > 0.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pick_follows_precedence() {
+        let given = |source| {
+            Ok(match source {
+                TempoSource::Name => Some(1.5),
+                TempoSource::SpeechRate => Some(0.9),
+                _ => None,
+            })
+        };
+        assert_eq!(pick(None, given).unwrap(), Some((1.5, TempoSource::Name)));
+        let forced = Some(TempoSource::SpeechRate);
+        assert_eq!(
+            pick(forced, given).unwrap(),
+            Some((0.9, TempoSource::SpeechRate))
+        );
+        assert_eq!(pick(Some(TempoSource::Manifest), given).unwrap(), None);
+    }
+
+    #[test]
+    fn test_tempo_map_interpolates() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("curve.csv");
+        fs::write(&path, "time_seconds,tempo\n# ease in\n1,1.0\n3, 1.5\n").unwrap();
+        let map = TempoMap::load(&path).unwrap();
+        assert_eq!(map.at(0.0), 1.0);
+        assert_eq!(map.at(2.0), 1.25);
+        assert_eq!(map.at(10.0), 1.5);
+        assert_eq!(map.slowest(), 1.0);
+        fs::write(&path, "2,1.0\n1,1.5\n").unwrap();
+        let err = TempoMap::load(&path).unwrap_err().to_string();
+        assert!(err.contains("must increase"), "{err}");
+        fs::write(&path, "1,0\n").unwrap();
+        let err = TempoMap::load(&path).unwrap_err().to_string();
+        assert!(err.contains("line 1: invalid tempo 0"), "{err}");
+    }
+
+    #[test]
+    fn test_name_tags() {
+        assert_eq!(from_name(Path::new("in/talk@1.25x.wav")), Some(1.25));
+        assert_eq!(from_name(Path::new("a@b@0.8X.w64")), Some(0.8));
+        assert_eq!(from_name(Path::new("talk.wav")), None);
+        assert_eq!(from_name(Path::new("me@home.wav")), None);
+        assert_eq!(from_name(Path::new("talk@-1x.wav")), None);
+    }
+
+    #[test]
+    fn test_directory_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let sub = dir.path().join("sub");
+        fs::create_dir(&sub).unwrap();
+        fs::write(dir.path().join(DIRECTORY_FILE), "tempo = 1.1\n").unwrap();
+        fs::write(sub.join(DIRECTORY_FILE), "tempo = 2\n").unwrap();
+        let root = dir.path();
+        assert_eq!(from_directory(&sub.join("c.wav"), root).unwrap(), Some(2.0));
+        assert_eq!(
+            from_directory(&root.join("a.wav"), root).unwrap(),
+            Some(1.1)
+        );
+        assert_eq!(from_directory(&sub.join("c.wav"), &sub).unwrap(), Some(2.0));
+        fs::write(sub.join(DIRECTORY_FILE), "tempo = \"fast\"\n").unwrap();
+        assert!(from_directory(&sub.join("c.wav"), root).is_err());
+    }
+
+    #[test]
+    fn test_jitter_is_reproducible() {
+        let range = parse_range("0.85:1.15").unwrap();
+        assert_eq!(range, (0.85, 1.15));
+        assert!(parse_range("1.2:0.8").is_err());
+        assert!(parse_range("0.9").is_err());
+        let draws: Vec<f32> = (0..200)
+            .map(|i| jitter(range, 42, Path::new(&format!("sub/{i}.wav"))))
+            .collect();
+        assert!(draws.iter().all(|t| (0.85..=1.15).contains(t)));
+        let mean = draws.iter().sum::<f32>() / draws.len() as f32;
+        assert!((mean - 1.0).abs() < 0.03, "{mean}");
+        let key = Path::new("sub/7.wav");
+        assert_eq!(jitter(range, 42, key), draws[7]);
+        assert_ne!(jitter(range, 43, key), draws[7]);
+    }
+
+    #[test]
+    fn test_match_duration_checks_formats() {
+        use crate::format::{AudioBuffer, AudioFormat};
+        let dir = tempfile::tempdir().unwrap();
+        let write = |name: &str, sample_rate, frames| {
+            let format = AudioFormat {
+                sample_rate,
+                ..AudioFormat::default()
+            };
+            let path = dir.path().join(name);
+            crate::write_wav(&path, &AudioBuffer::new(format, vec![0.1; frames])).unwrap();
+            path
+        };
+        let input = write("in.wav", 16000, 32000);
+        let limits = DecodeLimits::default();
+        let short = write("short.wav", 16000, 20000);
+        assert_eq!(match_duration(&input, &short, &limits).unwrap(), 1.6);
+        assert_eq!(for_duration(&input, 4.0, &limits).unwrap(), Some(0.5));
+        assert_eq!(for_bpm(&input, 120.0, &limits).unwrap(), None);
+        let beat = write("beat.wav", 16000, 0);
+        let clicks = (0..16000 * 12).map(|i| if i % 9600 < 40 { 0.8 } else { 0.0 });
+        let clicks = AudioBuffer::new(AudioFormat::default(), clicks.collect());
+        crate::write_wav(&beat, &clicks).unwrap();
+        let tempo = for_bpm(&beat, 120.0, &limits).unwrap().unwrap();
+        assert!((tempo - 1.2).abs() < 0.03, "100 BPM needs {tempo} for 120");
+        let music = write("music.wav", 44100, 44100);
+        let err = match_duration(&input, &music, &limits).unwrap_err();
+        assert!(
+            err.to_string().contains("its reference is 44100 Hz"),
+            "{err}"
+        );
+    }
+}
@@ -0,0 +1,195 @@
+//! Parsing and display of the durations and byte sizes that command-line
+//! arguments take and messages print.
+
+use crate::error::{Error, Result};
+use std::time::Duration;
+
+/// Parses a duration: bare seconds (`90`, `1.5`), units largest first
+/// (`90s`, `1m30s`, `1h`, `250ms`), or a clock (`01:30`, `00:01:30.500`).
+pub fn parse_duration(s: &str) -> Result<Duration> {
+    let s = s.trim();
+    let secs = if s.contains(':') { clock(s) } else { units(s) };
+    secs.and_then(|secs| Duration::try_from_secs_f64(secs).ok())
+        .ok_or_else(|| {
+            Error::invalid(format!(
+                "Invalid duration {s:?}: expected seconds or a form like 90s, 1m30s, 250ms, or 00:01:30.500"
+            ))
+        })
+}
+
+/// Like [`parse_duration`], in seconds.
+pub fn parse_seconds(s: &str) -> Result<f64> {
+    parse_duration(s).map(|d| d.as_secs_f64())
+}
+
+/// `[[HH:]MM:]SS[.fff]`, with every field but the first below 60.
+fn clock(s: &str) -> Option<f64> {
+    let fields: Vec<&str> = s.split(':').collect();
+    if fields.len() > 3 {
+        return None;
+    }
+    let mut secs = 0.0;
+    for (i, field) in fields.iter().enumerate() {
+        let last = i + 1 == fields.len();
+        let digits = field
+            .bytes()
+            .all(|b| b.is_ascii_digit() || (last && b == b'.'));
+        if field.is_empty() || !digits {
+            return None;
+        }
+        let value: f64 = field.parse().ok()?;
+        if i > 0 && value >= 60.0 {
+            return None;
+        }
+        secs = secs * 60.0 + value;
+    }
+    Some(secs)
+}
+
+/// A bare number of seconds, or numbers each followed by a unit, every unit
+/// smaller than the one before.
+fn units(s: &str) -> Option<f64> {
+    if let Ok(secs) = s.parse::<f64>() {
+        return Some(secs);
+    }
+    let mut rest = s;
+    let mut total = 0.0;
+    let mut previous = f64::INFINITY;
+    while !rest.is_empty() {
+        let split = rest.find(|c: char| c.is_ascii_alphabetic())?;
+        let (number, tail) = rest.split_at(split);
+        let end = tail
+            .find(|c: char| !c.is_ascii_alphabetic())
+            .unwrap_or(tail.len());
+        let (unit, next) = tail.split_at(end);
+        let scale = match unit.to_ascii_lowercase().as_str() {
+            "ms" => 0.001,
+            "s" => 1.0,
+            "m" => 60.0,
+            "h" => 3600.0,
+            _ => return None,
+        };
+        let value: f64 = number.trim().parse().ok()?;
+        if scale >= previous || value.is_sign_negative() {
+            return None;
+        }
+        total += value * scale;
+        previous = scale;
+        rest = next.trim_start();
+    }
+    previous.is_finite().then_some(total)
+}
+
+/// `secs` as compact text such as `0.25s`, `1m30s`, or `1h02m03.5s`, to the
+/// millisecond.
+pub fn format_duration(secs: f64) -> String {
+    if !(secs.is_finite() && secs >= 0.0) {
+        return format!("{secs}s");
+    }
+    let millis = (secs * 1000.0).round() as u64;
+    let (hours, minutes) = (millis / 3_600_000, millis / 60_000 % 60);
+    let seconds = format!("{:.3}", (millis % 60_000) as f64 / 1000.0);
+    let seconds = seconds.trim_end_matches('0').trim_end_matches('.');
+    let pad = if seconds.len() == 1 || seconds.as_bytes().get(1) == Some(&b'.') {
+        "0"
+    } else {
+        ""
+    };
+    match (hours, minutes) {
+        (0, 0) => format!("{seconds}s"),
+        (0, _) => format!("{minutes}m{pad}{seconds}s"),
+        _ => format!("{hours}h{minutes:02}m{pad}{seconds}s"),
+    }
+}
+
+/// Parses a byte size, with an optional `K`, `M`, or `G` binary suffix.
+pub fn parse_byte_size(s: &str) -> Result<u64> {
+    let s = s.trim();
+    let (digits, shift) = match s.char_indices().last() {
+        Some((i, 'k' | 'K')) => (&s[..i], 10),
+        Some((i, 'm' | 'M')) => (&s[..i], 20),
+        Some((i, 'g' | 'G')) => (&s[..i], 30),
+        _ => (s, 0),
+    };
+    digits
+        .trim()
+        .parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(1 << shift))
+        .ok_or_else(|| {
+            Error::invalid(format!(
+                "Invalid byte size {s:?}: expected a whole number of bytes, optionally with a K, M, or G suffix"
+            ))
+        })
+}
+
+/// `bytes` in the largest binary unit that keeps the number at least 1,
+/// e.g. `512 bytes`, `1.5 KiB`, or `4 GiB`.
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{bytes} bytes");
+    }
+    let mut value = bytes as f64;
+    let mut unit = "bytes";
+    for next in UNITS {
+        if value < 1024.0 {
+            break;
+        }
+        value /= 1024.0;
+        unit = next;
+    }
+    let text = format!("{value:.2}");
+    format!(
+        "{} {unit}",
+        text.trim_end_matches('0').trim_end_matches('.')
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_durations_in_every_form() {
+        let secs = |s| parse_duration(s).unwrap().as_secs_f64();
+        assert_eq!(secs("90"), 90.0);
+        assert_eq!(secs("300s"), 300.0);
+        assert_eq!(secs("1.5m"), 90.0);
+        assert_eq!(secs("1m30s"), 90.0);
+        assert_eq!(secs("1h 2m 3s"), 3723.0);
+        assert_eq!(secs("250ms"), 0.25);
+        assert_eq!(secs("00:01:30.500"), 90.5);
+        assert_eq!(secs("2:05"), 125.0);
+        for bad in [
+            "", "5 days", "-1s", "30s1m", "1m-5s", "1:75", "1::2", "a:b", "1:2:3:4",
+        ] {
+            let message = parse_duration(bad).unwrap_err().to_string();
+            assert!(message.contains("expected seconds"), "{bad:?}: {message}");
+        }
+        assert_eq!(parse_seconds("1m").unwrap(), 60.0);
+    }
+
+    #[test]
+    fn test_format_round_trips() {
+        assert_eq!(format_duration(0.25), "0.25s");
+        assert_eq!(format_duration(90.0), "1m30s");
+        assert_eq!(format_duration(65.5), "1m05.5s");
+        assert_eq!(format_duration(3723.5), "1h02m03.5s");
+        assert_eq!(format_duration(3600.0), "1h00m00s");
+        for secs in [0.25, 90.0, 65.5, 3723.5] {
+            assert_eq!(parse_seconds(&format_duration(secs)).unwrap(), secs);
+        }
+        assert_eq!(format_bytes(512), "512 bytes");
+        assert_eq!(format_bytes(1536), "1.5 KiB");
+        assert_eq!(format_bytes(4 << 30), "4 GiB");
+    }
+
+    #[test]
+    fn test_byte_sizes() {
+        assert_eq!(parse_byte_size("512K").unwrap(), 512 << 10);
+        assert_eq!(parse_byte_size("2g").unwrap(), 2 << 30);
+        assert_eq!(parse_byte_size("1048576").unwrap(), 1 << 20);
+        assert!(parse_byte_size("lots").is_err());
+    }
+}
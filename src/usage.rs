@@ -0,0 +1,96 @@
+//! Resource usage of the whole process, for comparing configurations.
+//!
+//! Read from `/proc/self` on Linux; other platforms report nothing.
+
+use crate::json::Json;
+use std::fs;
+
+/// Kernel clock ticks per second in `/proc/self/stat`, fixed at 100 on Linux.
+const TICKS_PER_SEC: f64 = 100.0;
+
+/// Totals for the process so far, each `None` where the platform doesn't
+/// expose it.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ResourceUsage {
+    pub peak_rss_bytes: Option<u64>,
+    /// User plus system CPU time over all threads.
+    pub cpu_secs: Option<f64>,
+    /// Bytes passed to read and write calls, including cache hits.
+    pub read_bytes: Option<u64>,
+    pub written_bytes: Option<u64>,
+}
+
+impl ResourceUsage {
+    pub fn current() -> Self {
+        let read = |name: &str| fs::read_to_string(format!("/proc/self/{name}")).ok();
+        let io = read("io");
+        Self {
+            peak_rss_bytes: read("status").and_then(|s| peak_rss(&s)),
+            cpu_secs: read("stat").and_then(|s| cpu_secs(&s)),
+            read_bytes: io.as_deref().and_then(|s| io_field(s, "rchar")),
+            written_bytes: io.as_deref().and_then(|s| io_field(s, "wchar")),
+        }
+    }
+
+    /// The known totals as a JSON object.
+    pub fn to_json(&self) -> Json {
+        let fields = [
+            ("peak_rss_bytes", self.peak_rss_bytes.map(|b| b as f64)),
+            ("cpu_secs", self.cpu_secs),
+            ("read_bytes", self.read_bytes.map(|b| b as f64)),
+            ("written_bytes", self.written_bytes.map(|b| b as f64)),
+        ];
+        Json::Object(
+            fields
+                .into_iter()
+                .filter_map(|(key, value)| Some((key.to_string(), Json::Number(value?))))
+                .collect(),
+        )
+    }
+}
+
+/// `VmHWM` of `/proc/self/status`, which is given in KiB.
+fn peak_rss(status: &str) -> Option<u64> {
+    let line = status.lines().find(|l| l.starts_with("VmHWM:"))?;
+    let kib: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kib * 1024)
+}
+
+/// `utime + stime` of `/proc/self/stat`, in seconds.
+fn cpu_secs(stat: &str) -> Option<f64> {
+    // The command name may contain spaces, so count fields after its ')'.
+    let fields: Vec<&str> = stat.rsplit_once(')')?.1.split_whitespace().collect();
+    let ticks = |i: usize| fields.get(i)?.parse::<u64>().ok();
+    Some((ticks(11)? + ticks(12)?) as f64 / TICKS_PER_SEC)
+}
+
+fn io_field(io: &str, key: &str) -> Option<u64> {
+    io.lines()
+        .find_map(|l| l.strip_prefix(key)?.strip_prefix(':'))?
+        .trim()
+        .parse()
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parses_proc_files() {
+        assert_eq!(peak_rss("Name:\tx\nVmHWM:\t    2048 kB\n"), Some(2 << 20));
+        let stat = "42 (wav tempo) R 1 42 42 0 -1 4194304 100 0 0 0 250 50 0 0 20 0 4";
+        assert_eq!(cpu_secs(stat), Some(3.0));
+        let io = "rchar: 1000\nwchar: 2000\nsyscr: 5\n";
+        assert_eq!(io_field(io, "wchar"), Some(2000));
+        let usage = ResourceUsage {
+            cpu_secs: Some(1.5),
+            written_bytes: Some(10),
+            ..ResourceUsage::default()
+        };
+        assert_eq!(
+            usage.to_json().to_string(),
+            r#"{"cpu_secs": 1.5, "written_bytes": 10}"#
+        );
+    }
+}
@@ -0,0 +1,176 @@
+//! Format compliance checks shared by processing and the `check` subcommand.
+
+use crate::error::{Error, Result};
+use crate::format::{AudioFormat, SampleType};
+use crate::units::format_duration;
+use std::fmt;
+
+/// Format a file must match; `max_duration` is optional.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Profile {
+    pub format: AudioFormat,
+    pub max_duration: Option<f64>,
+}
+
+impl From<AudioFormat> for Profile {
+    /// Profile requiring exactly `format`, with no duration limit.
+    fn from(format: AudioFormat) -> Self {
+        Self {
+            format,
+            max_duration: None,
+        }
+    }
+}
+
+/// A single way in which a file departs from its profile.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Violation {
+    SampleRate {
+        found: u32,
+        expected: u32,
+    },
+    Channels {
+        found: u16,
+        expected: u16,
+    },
+    SampleType {
+        found: SampleType,
+        expected: SampleType,
+    },
+    Duration {
+        found: f64,
+        max: f64,
+    },
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::SampleRate { found, expected } => {
+                write!(f, "sample rate {found} Hz (expected {expected} Hz)")
+            }
+            Self::Channels { found, expected } => {
+                write!(f, "{found} channels (expected {expected})")
+            }
+            Self::SampleType { found, expected } => {
+                write!(f, "{found} samples (expected {expected})")
+            }
+            Self::Duration { found, max } => {
+                write!(
+                    f,
+                    "duration {} (max {})",
+                    format_duration(*found),
+                    format_duration(*max)
+                )
+            }
+        }
+    }
+}
+
+impl Profile {
+    /// Every violation of this profile by a file in `format` lasting `duration` seconds.
+    pub fn violations(&self, format: &AudioFormat, duration: f64) -> Vec<Violation> {
+        let mut found = self.format_violations(format);
+        if let Some(max) = self.max_duration
+            && duration > max
+        {
+            found.push(Violation::Duration {
+                found: duration,
+                max,
+            });
+        }
+        found
+    }
+
+    /// Fails with [`Error::UnsupportedFormat`] on any format violation, ignoring duration.
+    pub fn check_format(&self, format: &AudioFormat) -> Result<()> {
+        if self.format_violations(format).is_empty() {
+            return Ok(());
+        }
+        Err(Error::UnsupportedFormat {
+            found: *format,
+            expected: self.format,
+        })
+    }
+
+    fn format_violations(&self, format: &AudioFormat) -> Vec<Violation> {
+        let expected = &self.format;
+        let mut found = Vec::new();
+        if format.sample_rate != expected.sample_rate {
+            found.push(Violation::SampleRate {
+                found: format.sample_rate,
+                expected: expected.sample_rate,
+            });
+        }
+        if format.channels != expected.channels {
+            found.push(Violation::Channels {
+                found: format.channels,
+                expected: expected.channels,
+            });
+        }
+        if format.sample_type != expected.sample_type {
+            found.push(Violation::SampleType {
+                found: format.sample_type,
+                expected: expected.sample_type,
+            });
+        }
+        found
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format(channels: u16, sample_rate: u32) -> AudioFormat {
+        AudioFormat {
+            sample_rate,
+            channels,
+            sample_type: SampleType::Int(16),
+        }
+    }
+
+    #[test]
+    fn test_default_profile_accepts_mono_16k() {
+        assert!(Profile::default().check_format(&format(1, 16000)).is_ok());
+        assert!(
+            Profile::default()
+                .violations(&format(1, 16000), 3600.0)
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_violations_list_every_mismatch() {
+        let profile = Profile {
+            max_duration: Some(10.0),
+            ..Profile::default()
+        };
+        let found = profile.violations(&format(2, 44100), 12.5);
+        assert_eq!(
+            found,
+            vec![
+                Violation::SampleRate {
+                    found: 44100,
+                    expected: 16000
+                },
+                Violation::Channels {
+                    found: 2,
+                    expected: 1
+                },
+                Violation::Duration {
+                    found: 12.5,
+                    max: 10.0
+                },
+            ]
+        );
+        let err = profile
+            .check_format(&format(2, 44100))
+            .unwrap_err()
+            .to_string();
+        assert_eq!(
+            err,
+            "Unsupported format: sample rate 44100 Hz (expected 16000 Hz), 2 channels (expected 1)"
+        );
+    }
+}
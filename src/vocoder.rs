@@ -0,0 +1,201 @@
+//! Pure-Rust phase vocoder, an alternative to the Signalsmith engine.
+
+use crate::fft::{Complex, fft, hann};
+use std::f32::consts::PI;
+
+/// Bin magnitude below which a bin counts as silent.
+const SILENT_BIN: f32 = 1e-4;
+
+/// Time-stretches `input` by the tempo `tempo_at` returns for each frame's
+/// position (as a fraction of the file), keeping pitch.
+///
+/// `fft_size` must be a power of two and a multiple of `overlap`, which sets
+/// the synthesis hop to `fft_size / overlap`.
+pub fn stretch(
+    input: &[f32],
+    fft_size: usize,
+    overlap: usize,
+    tempo_at: impl Fn(f32) -> f32,
+) -> Vec<f32> {
+    if input.is_empty() {
+        return Vec::new();
+    }
+    let n = fft_size;
+    let bins = n / 2 + 1;
+    let synthesis_hop = n / overlap;
+    let window = hann(n);
+
+    // Pad so the first and last frames see the signal edges fully.
+    let mut padded = vec![0.0; n];
+    padded.extend_from_slice(input);
+    padded.extend(std::iter::repeat_n(0.0, n));
+
+    let mut output: Vec<f32> = Vec::new();
+    let mut weight: Vec<f32> = Vec::new();
+    let mut previous_phase = vec![0.0f32; bins];
+    let mut previous_magnitude = vec![0.0f32; bins];
+    let mut synthesis_phase = vec![0.0f32; bins];
+    let mut buf = vec![Complex::default(); n];
+    let mut position = 0.0f64;
+    let mut out_pos = 0usize;
+
+    while (position as usize) + n <= padded.len() {
+        let start = position as usize;
+        let progress = (start.saturating_sub(n) as f32 / input.len() as f32).min(1.0);
+        let analysis_hop = synthesis_hop as f64 * tempo_at(progress) as f64;
+
+        for ((b, &s), &w) in buf.iter_mut().zip(&padded[start..start + n]).zip(&window) {
+            *b = Complex::new(s * w, 0.0);
+        }
+        fft(&mut buf, false);
+
+        let magnitude: Vec<f32> = buf[..bins].iter().map(|c| c.norm()).collect();
+        let phase: Vec<f32> = buf[..bins].iter().map(|c| c.arg()).collect();
+
+        // Identity phase locking: only spectral peaks are phase-propagated;
+        // the bins around each peak keep their analysis phase offset from it,
+        // so the partial stays coherent across its main lobe.
+        let peaks = peaks(&magnitude);
+        for &k in &peaks {
+            if previous_magnitude[k] < SILENT_BIN {
+                synthesis_phase[k] = phase[k];
+            } else {
+                let expected = 2.0 * PI * k as f32 * analysis_hop as f32 / n as f32;
+                let deviation = wrap(phase[k] - previous_phase[k] - expected);
+                let frequency = 2.0 * PI * k as f32 / n as f32 + deviation / analysis_hop as f32;
+                synthesis_phase[k] = wrap(synthesis_phase[k] + frequency * synthesis_hop as f32);
+            }
+        }
+        let mut region = 0;
+        for k in 0..bins {
+            if peaks.is_empty() {
+                break;
+            }
+            // Advance to the peak this bin is closest to.
+            while region + 1 < peaks.len()
+                && peaks[region + 1].abs_diff(k) < peaks[region].abs_diff(k)
+            {
+                region += 1;
+            }
+            let peak = peaks[region];
+            if peak != k {
+                synthesis_phase[k] = synthesis_phase[peak] + phase[k] - phase[peak];
+            }
+        }
+        for k in 0..bins {
+            buf[k] = Complex::from_polar(magnitude[k], synthesis_phase[k]);
+        }
+        previous_phase.copy_from_slice(&phase);
+        previous_magnitude.copy_from_slice(&magnitude);
+        // Mirror the bins so the inverse transform is real.
+        for k in bins..n {
+            let mirror = buf[n - k];
+            buf[k] = Complex::new(mirror.re, -mirror.im);
+        }
+        fft(&mut buf, true);
+
+        if output.len() < out_pos + n {
+            output.resize(out_pos + n, 0.0);
+            weight.resize(out_pos + n, 0.0);
+        }
+        for i in 0..n {
+            output[out_pos + i] += buf[i].re * window[i];
+            weight[out_pos + i] += window[i] * window[i];
+        }
+
+        position += analysis_hop;
+        out_pos += synthesis_hop;
+    }
+
+    for (s, &w) in output.iter_mut().zip(&weight) {
+        if w > 1e-3 {
+            *s /= w;
+        }
+    }
+    // Drop the padding frame and trim to the stretched length.
+    let expected_len = expected_length(input.len(), synthesis_hop, &tempo_at);
+    output
+        .into_iter()
+        .skip(n)
+        .chain(std::iter::repeat(0.0))
+        .take(expected_len)
+        .collect()
+}
+
+/// Local maxima of a magnitude spectrum, in ascending bin order.
+fn peaks(magnitude: &[f32]) -> Vec<usize> {
+    (0..magnitude.len())
+        .filter(|&k| {
+            let left = if k == 0 { 0.0 } else { magnitude[k - 1] };
+            let right = magnitude.get(k + 1).copied().unwrap_or(0.0);
+            magnitude[k] > left && magnitude[k] >= right
+        })
+        .collect()
+}
+
+/// Output length implied by integrating `1 / tempo` over the input.
+fn expected_length(len: usize, step: usize, tempo_at: &impl Fn(f32) -> f32) -> usize {
+    let mut total = 0.0f64;
+    let mut pos = 0;
+    while pos < len {
+        let block = step.min(len - pos);
+        let mid = (pos + block / 2) as f32 / len as f32;
+        total += block as f64 / tempo_at(mid) as f64;
+        pos += block;
+    }
+    total as usize
+}
+
+/// Wraps a phase into [-PI, PI).
+fn wrap(phase: f32) -> f32 {
+    (phase + PI).rem_euclid(2.0 * PI) - PI
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(freq: f32, rate: f32, len: usize) -> Vec<f32> {
+        (0..len)
+            .map(|i| (2.0 * PI * freq * i as f32 / rate).sin() * 0.5)
+            .collect()
+    }
+
+    /// Dominant frequency of `samples` via zero crossings.
+    fn crossings_hz(samples: &[f32], rate: f32) -> f32 {
+        let crossings = samples
+            .windows(2)
+            .filter(|w| w[0] < 0.0 && w[1] >= 0.0)
+            .count();
+        crossings as f32 * rate / samples.len() as f32
+    }
+
+    #[test]
+    fn test_stretch_keeps_pitch_and_scales_length() {
+        let input = sine(440.0, 16000.0, 16000);
+        for tempo in [0.5, 1.5] {
+            let output = stretch(&input, 2048, 4, |_| tempo);
+            assert_eq!(output.len(), (16000.0 / tempo) as usize);
+            let middle = &output[2048..output.len() - 2048];
+            let hz = crossings_hz(middle, 16000.0);
+            assert!((hz - 440.0).abs() < 5.0, "tempo {tempo}: {hz} Hz");
+            let rms = (middle.iter().map(|s| s * s).sum::<f32>() / middle.len() as f32).sqrt();
+            assert!(
+                (rms - 0.5 / 2f32.sqrt()).abs() < 0.05,
+                "tempo {tempo}: rms {rms}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_identity_tempo_reconstructs_input() {
+        let input = sine(300.0, 16000.0, 8000);
+        let output = stretch(&input, 1024, 4, |_| 1.0);
+        assert_eq!(output.len(), input.len());
+        let error = input[1024..7000]
+            .iter()
+            .zip(&output[1024..7000])
+            .fold(0.0f32, |m, (a, b)| m.max((a - b).abs()));
+        assert!(error < 1e-3, "max error {error}");
+    }
+}
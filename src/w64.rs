@@ -0,0 +1,199 @@
+//! Sony Wave64 (`.w64`): RIFF/WAVE with GUID chunk ids and 64-bit sizes, for
+//! recordings beyond the 4 GiB RIFF limit.
+//!
+//! Chunks go in and out as [`Chunk`]s under their RIFF four-character ids;
+//! the Wave64 GUIDs of the standard chunks start with the same four bytes.
+
+use crate::error::{Error, Result};
+use crate::riff::{self, Chunk};
+use std::fs;
+use std::ops::Range;
+use std::path::Path;
+
+const RIFF_GUID: &[u8; 16] = b"riff\x2e\x91\xcf\x11\xa5\xd6\x28\xdb\x04\xc1\x00\x00";
+const WAVE_GUID: &[u8; 16] = b"wave\xf3\xac\xd3\x11\x8c\xd1\x00\xc0\x4f\x8e\xdb\x8a";
+const LIST_GUID: &[u8; 16] = b"list\x2f\x91\xcf\x11\xa5\xd6\x28\xdb\x04\xc1\x00\x00";
+
+/// What follows the four-character id in the GUID of `fmt `, `data`,
+/// `fact`, and most other chunks.
+const CHUNK_SUFFIX: &[u8; 12] = b"\xf3\xac\xd3\x11\x8c\xd1\x00\xc0\x4f\x8e\xdb\x8a";
+
+/// Chunk header size: the GUID plus a 64-bit size that includes the header.
+const HEADER: usize = 24;
+
+/// Whether `bytes` starts like a Wave64 file.
+pub fn is_w64(bytes: &[u8]) -> bool {
+    bytes.starts_with(RIFF_GUID)
+}
+
+/// Whether `path` names a Wave64 file by its extension.
+pub fn is_w64_path(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("w64"))
+}
+
+/// Parses the top-level chunks of a Wave64 byte buffer like
+/// [`riff::parse_chunks`]. Chunks whose GUID has no RIFF id are skipped.
+pub fn parse_chunks(bytes: &[u8]) -> Result<Vec<Chunk>> {
+    Ok(chunk_spans(bytes)?
+        .into_iter()
+        .map(|(id, range)| Chunk {
+            id,
+            data: bytes[range].to_vec(),
+        })
+        .collect())
+}
+
+/// RIFF id and data range of each top-level chunk of a Wave64 byte buffer
+/// that has one. Truncated trailing chunks keep whatever data is present.
+pub(crate) fn chunk_spans(bytes: &[u8]) -> Result<Vec<([u8; 4], Range<usize>)>> {
+    if !(bytes.len() >= 40 && is_w64(bytes) && &bytes[24..40] == WAVE_GUID) {
+        return Err(Error::decode(
+            "Failed to parse Wave64 chunks",
+            hound::Error::FormatError("no riff/wave GUID header"),
+        ));
+    }
+    Ok(spans(bytes)
+        .into_iter()
+        .filter_map(|(guid, start, end)| Some((fourcc(&guid)?, start..end.min(bytes.len()))))
+        .collect())
+}
+
+/// Writes `chunks` as a complete Wave64 file, aligning each to 8 bytes.
+pub fn write_chunks(path: &Path, chunks: &[Chunk]) -> Result<()> {
+    let out = encode_chunks(chunks);
+    fs::write(path, out).map_err(|e| Error::io("Failed to write output Wave64", e))
+}
+
+/// The bytes [`write_chunks`] writes for `chunks`.
+pub fn encode_chunks(chunks: &[Chunk]) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.extend_from_slice(RIFF_GUID);
+    out.extend_from_slice(&[0; 8]);
+    out.extend_from_slice(WAVE_GUID);
+    for chunk in chunks {
+        out.extend_from_slice(&guid(&chunk.id));
+        out.extend_from_slice(&((HEADER + chunk.data.len()) as u64).to_le_bytes());
+        out.extend_from_slice(&chunk.data);
+        out.resize(out.len().next_multiple_of(8), 0);
+    }
+    let len = out.len() as u64;
+    out[16..24].copy_from_slice(&len.to_le_bytes());
+    out
+}
+
+/// Every way the header of the Wave64 file in `bytes` departs from the
+/// spec, with the same `fmt `/`data`/`fact` checks as
+/// [`riff::header_problems`].
+pub fn header_problems(bytes: &[u8]) -> Vec<String> {
+    if !(bytes.len() >= 40 && is_w64(bytes) && &bytes[24..40] == WAVE_GUID) {
+        return vec!["no riff/wave GUID header".to_string()];
+    }
+    let mut problems = Vec::new();
+    let size = u64_at(bytes, 16);
+    if size != bytes.len() as u64 {
+        problems.push(format!("riff size {size}, expected {}", bytes.len()));
+    }
+    let mut chunks: Vec<(&[u8], &[u8])> = Vec::new();
+    for (guid, start, end) in spans(bytes) {
+        let name = String::from_utf8_lossy(&guid[..4]).into_owned();
+        if end > bytes.len() {
+            problems.push(format!("{name:?} chunk overruns the file"));
+            break;
+        }
+        if end.next_multiple_of(8) > bytes.len() {
+            problems.push(format!("{name:?} chunk is missing its alignment padding"));
+        }
+        if fourcc(&guid).is_some() {
+            chunks.push((
+                &bytes[start - HEADER..start - HEADER + 4],
+                &bytes[start..end],
+            ));
+        }
+    }
+    problems.extend(riff::format_problems(&chunks));
+    problems
+}
+
+/// GUID, data offset, and declared data end of each top-level chunk.
+fn spans(bytes: &[u8]) -> Vec<([u8; 16], usize, usize)> {
+    let mut spans = Vec::new();
+    let mut pos = 40;
+    while pos + HEADER <= bytes.len() {
+        let guid: [u8; 16] = std::array::from_fn(|k| bytes[pos + k]);
+        let size = usize::try_from(u64_at(bytes, pos + 16)).unwrap_or(usize::MAX);
+        let start = pos + HEADER;
+        // A size below the header would loop forever; treat it as empty.
+        let end = pos.saturating_add(size).max(start);
+        spans.push((guid, start, end));
+        pos = end.min(bytes.len()).next_multiple_of(8);
+    }
+    spans
+}
+
+fn fourcc(guid: &[u8; 16]) -> Option<[u8; 4]> {
+    if guid == LIST_GUID {
+        Some(*b"LIST")
+    } else if &guid[4..] == CHUNK_SUFFIX {
+        Some([guid[0], guid[1], guid[2], guid[3]])
+    } else {
+        None
+    }
+}
+
+fn guid(id: &[u8; 4]) -> [u8; 16] {
+    if id == b"LIST" {
+        return *LIST_GUID;
+    }
+    let mut guid = [0; 16];
+    guid[..4].copy_from_slice(id);
+    guid[4..].copy_from_slice(CHUNK_SUFFIX);
+    guid
+}
+
+fn u64_at(bytes: &[u8], i: usize) -> u64 {
+    u64::from_le_bytes(std::array::from_fn(|k| bytes[i + k]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::{AudioBuffer, AudioFormat, SampleType};
+    use crate::{decode_wav, write_wav};
+
+    #[test]
+    fn test_round_trip_through_wave64() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("long.w64");
+        let format = AudioFormat {
+            channels: 2,
+            sample_type: SampleType::Int(24),
+            ..AudioFormat::default()
+        };
+        // 5 frames of 6 bytes leave the data chunk off the 8-byte grid.
+        let buffer = AudioBuffer::new(
+            format,
+            vec![0.5, -0.5, 0.25, 0.0, -1.0, 0.125, 0.0, 0.0, 0.5, 0.5],
+        );
+        write_wav(&path, &buffer)?;
+        riff::append_chunks(
+            &path,
+            &[Chunk {
+                id: *b"LIST",
+                data: b"INFOINAM\x04\x00\x00\x00Take".to_vec(),
+            }],
+        )?;
+
+        let bytes = fs::read(&path)?;
+        assert!(is_w64(&bytes));
+        assert_eq!(bytes.len() % 8, 0);
+        assert_eq!(header_problems(&bytes), Vec::<String>::new());
+        let decoded = decode_wav(&path)?;
+        assert_eq!(decoded.format, format);
+        assert_eq!(decoded.samples, buffer.samples);
+        let ids: Vec<[u8; 4]> = parse_chunks(&bytes)?.iter().map(|c| c.id).collect();
+        assert_eq!(ids, [*b"fmt ", *b"data", *b"LIST"]);
+        assert!(parse_chunks(b"RIFF\0\0\0\0WAVE").is_err());
+        Ok(())
+    }
+}
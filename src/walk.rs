@@ -0,0 +1,608 @@
+//! Input discovery: maps WAV files under one or more roots to output paths.
+//!
+//! [`discover`] lists directories on several threads at once, which is what
+//! keeps startup short on network storage where every listing and stat is
+//! a round trip; its jobs arrive as they're found, so processing can begin
+//! before the walk ends.
+
+use crate::error::{Error, Result};
+use crate::glob::Filter;
+use std::collections::HashSet;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{Receiver, Sender, channel};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use walkdir::WalkDir;
+
+/// One input file and the output path it is written to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Job {
+    pub input: PathBuf,
+    pub output: PathBuf,
+}
+
+/// Output base directory for each input root.
+///
+/// A single root maps straight onto `output_dir`. With several roots each
+/// one gets a subdirectory named after it, unless `merge` resolves every
+/// root's relative paths into the same tree.
+pub fn output_bases(roots: &[PathBuf], output_dir: &Path, merge: bool) -> Result<Vec<PathBuf>> {
+    if roots.len() == 1 || merge {
+        return Ok(vec![output_dir.to_path_buf(); roots.len()]);
+    }
+    let mut seen = HashSet::new();
+    roots
+        .iter()
+        .map(|root| {
+            let name = root_name(root)?;
+            if !seen.insert(name.clone()) {
+                return Err(Error::invalid(format!(
+                    "Input roots share the name {:?}; use --merge-roots or rename one",
+                    name
+                )));
+            }
+            Ok(output_dir.join(name))
+        })
+        .collect()
+}
+
+/// How far below each root a walk goes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Traversal {
+    /// Deepest level files are taken from, 1 being the root itself; `None`
+    /// for no limit.
+    pub max_depth: Option<usize>,
+    /// Follow symbolic links to files and directories instead of skipping
+    /// them. A link back to a directory the walk is already in is skipped.
+    pub follow_symlinks: bool,
+}
+
+impl Traversal {
+    /// Whether a directory `depth` levels below its root is listed.
+    fn enters(&self, depth: usize) -> bool {
+        self.max_depth.is_none_or(|max| depth < max)
+    }
+}
+
+/// In a walk's extensions, every file, whether it has an extension or not.
+pub const ANY_EXTENSION: &str = "*";
+
+/// Extensions of the audio files the tool reads.
+pub const AUDIO_EXTENSIONS: [&str; 2] = ["wav", "w64"];
+
+/// Recursively collects files with one of `extensions`, in any case, that
+/// `filter` accepts under every root, as deep as `traversal` goes, listing
+/// `threads` directories at once and creating output subdirs, sorted by
+/// input path.
+///
+/// `output_dir` must already exist. When it lies inside an input root it is
+/// left out of the traversal, so repeated runs never re-stretch their own
+/// outputs.
+pub fn collect(
+    roots: &[PathBuf],
+    output_dir: &Path,
+    merge: bool,
+    threads: usize,
+    extensions: &[&str],
+    filter: &Filter,
+    traversal: Traversal,
+) -> Result<Vec<Job>> {
+    let found = discover(
+        roots, output_dir, merge, threads, extensions, filter, traversal,
+    )?;
+    let mut jobs = found.into_iter().collect::<Result<Vec<_>>>()?;
+    jobs.sort_by(|a, b| a.input.cmp(&b.input));
+    Ok(jobs)
+}
+
+/// Like [`collect`], but walks in the background and hands over each job,
+/// in no particular order, as soon as it is found. A problem with the roots
+/// fails up front; one found during the walk ends it as the last item.
+pub fn discover(
+    roots: &[PathBuf],
+    output_dir: &Path,
+    merge: bool,
+    threads: usize,
+    extensions: &[&str],
+    filter: &Filter,
+    traversal: Traversal,
+) -> Result<Receiver<Result<Job>>> {
+    let bases = output_bases(roots, output_dir, merge)?;
+    let output_canonical = output_dir
+        .canonicalize()
+        .map_err(|e| Error::io("Failed to resolve output directory", e))?;
+    let mut pending = Vec::new();
+    let mut excluded = None;
+    for (root, base) in roots.iter().zip(bases) {
+        let root_canonical = root
+            .canonicalize()
+            .map_err(|e| Error::io(format!("Failed to resolve input root {:?}", root), e))?;
+        if root_canonical == output_canonical {
+            return Err(Error::invalid(format!(
+                "Output directory {:?} is the same as input root {:?}",
+                output_dir, root
+            )));
+        }
+        if output_canonical.starts_with(&root_canonical) {
+            excluded = Some(output_canonical.clone());
+        }
+        let root = Arc::new((root.clone(), base));
+        pending.push((root, PathBuf::new()));
+    }
+    let walk = Arc::new(Walk {
+        queue: Mutex::new((pending, 0)),
+        changed: Condvar::new(),
+        excluded,
+        extensions: extensions.iter().map(|e| e.to_string()).collect(),
+        filter: filter.clone(),
+        traversal,
+        outputs: Mutex::new(HashSet::new()),
+    });
+    let (tx, rx) = channel();
+    for _ in 0..threads.max(1) {
+        let (walk, tx) = (Arc::clone(&walk), tx.clone());
+        thread::spawn(move || walk.work(&tx));
+    }
+    Ok(rx)
+}
+
+/// An input root and the output base its files map under.
+type Root = Arc<(PathBuf, PathBuf)>;
+
+/// The state [`discover`]'s threads share.
+struct Walk {
+    /// Directories still to list, relative to their root, and how many are
+    /// being listed right now.
+    queue: Mutex<(Vec<(Root, PathBuf)>, usize)>,
+    changed: Condvar,
+    /// The canonical output directory, when it lies inside a root.
+    excluded: Option<PathBuf>,
+    extensions: Vec<String>,
+    filter: Filter,
+    traversal: Traversal,
+    outputs: Mutex<HashSet<PathBuf>>,
+}
+
+impl Walk {
+    /// Lists directories until none are left or the receiver hangs up.
+    fn work(&self, tx: &Sender<Result<Job>>) {
+        while let Some((root, dir)) = self.next() {
+            let mut subdirs = Vec::new();
+            let listed = self.list(&root, &dir, &mut subdirs, tx);
+            let Ok(mut queue) = self.queue.lock() else {
+                return;
+            };
+            queue.1 -= 1;
+            if listed {
+                queue
+                    .0
+                    .extend(subdirs.into_iter().map(|d| (Arc::clone(&root), d)));
+            } else {
+                // Nobody is listening any more, or the walk failed.
+                queue.0.clear();
+            }
+            self.changed.notify_all();
+        }
+    }
+
+    fn next(&self) -> Option<(Root, PathBuf)> {
+        let mut queue = self.queue.lock().ok()?;
+        loop {
+            if let Some(next) = queue.0.pop() {
+                queue.1 += 1;
+                return Some(next);
+            }
+            if queue.1 == 0 {
+                return None;
+            }
+            queue = self.changed.wait(queue).ok()?;
+        }
+    }
+
+    /// Sends the jobs in one directory and collects its subdirectories.
+    /// `false` means the walk should stop.
+    fn list(
+        &self,
+        root: &Root,
+        dir: &Path,
+        subdirs: &mut Vec<PathBuf>,
+        tx: &Sender<Result<Job>>,
+    ) -> bool {
+        let (root_path, base) = &**root;
+        // Unreadable directories are skipped, as they always were.
+        let Ok(entries) = fs::read_dir(root_path.join(dir)) else {
+            return true;
+        };
+        for entry in entries.filter_map(std::result::Result::ok) {
+            let Ok(mut file_type) = entry.file_type() else {
+                continue;
+            };
+            if file_type.is_symlink() && self.traversal.follow_symlinks {
+                // Broken links are skipped.
+                let Ok(metadata) = fs::metadata(entry.path()) else {
+                    continue;
+                };
+                file_type = metadata.file_type();
+                if file_type.is_dir() && loops_back(root_path, dir, &entry.path()) {
+                    continue;
+                }
+            }
+            let rel_path = dir.join(entry.file_name());
+            if file_type.is_dir() {
+                let skip = !self.traversal.enters(rel_path.components().count())
+                    || self
+                        .excluded
+                        .as_deref()
+                        .is_some_and(|out| is_same_dir(&entry.path(), out))
+                    || self.filter.skips_dir(&rel_path);
+                if !skip {
+                    subdirs.push(rel_path);
+                }
+                continue;
+            }
+            let wanted = self.extensions.iter().any(|e| e == ANY_EXTENSION)
+                || rel_path
+                    .extension()
+                    .and_then(|ext| ext.to_str())
+                    .is_some_and(|ext| self.extensions.iter().any(|e| e.eq_ignore_ascii_case(ext)));
+            if !(file_type.is_file() && wanted && self.filter.accepts(&rel_path)) {
+                continue;
+            }
+            // Stat here, in parallel, so a file that vanished is never queued
+            // and the decoder's own size check finds the metadata cached.
+            if entry.metadata().is_err() {
+                continue;
+            }
+            let job = self.job(entry.path(), base.join(&rel_path));
+            let failed = job.is_err();
+            if tx.send(job).is_err() || failed {
+                return false;
+            }
+        }
+        true
+    }
+
+    fn job(&self, input: PathBuf, output: PathBuf) -> Result<Job> {
+        let fresh = self
+            .outputs
+            .lock()
+            .map_err(|_| Error::invalid("Input walk failed"))?
+            .insert(output.clone());
+        if !fresh {
+            return Err(Error::invalid(format!(
+                "Several inputs map to {:?}; merged roots must not share relative paths",
+                output
+            )));
+        }
+        fs::create_dir_all(output.parent().unwrap_or_else(|| Path::new(".")))
+            .map_err(|e| Error::io("Failed to create output subdir", e))?;
+        Ok(Job { input, output })
+    }
+}
+
+/// Recursively lists WAV (and Wave64 `.w64`) files under `root`, in any
+/// case, skipping the canonical directory `exclude`.
+pub fn wav_files<'a>(root: &Path, exclude: Option<&'a Path>) -> impl Iterator<Item = PathBuf> + 'a {
+    WalkDir::new(root)
+        .into_iter()
+        .filter_entry(move |e| {
+            !exclude.is_some_and(|dir| e.file_type().is_dir() && is_same_dir(e.path(), dir))
+        })
+        .filter_map(Result::ok)
+        .filter(|e| {
+            let ext = e.path().extension().and_then(|ext| ext.to_str());
+            e.file_type().is_file()
+                && ext
+                    .is_some_and(|ext| AUDIO_EXTENSIONS.iter().any(|e| e.eq_ignore_ascii_case(ext)))
+        })
+        .map(|e| e.into_path())
+}
+
+/// `output` with `suffix` added to its file stem, e.g. `a/talk_t0.9.wav` for
+/// `a/talk.wav` and `_t0.9`.
+pub fn with_suffix(output: &Path, suffix: &str) -> PathBuf {
+    let mut name = output.file_stem().unwrap_or_default().to_os_string();
+    name.push(suffix);
+    if let Some(ext) = output.extension() {
+        name.push(".");
+        name.push(ext);
+    }
+    output.with_file_name(name)
+}
+
+/// Whether the directory `link`, found in `dir` under `root`, resolves to
+/// `dir` or one above it, so following it would walk in circles.
+fn loops_back(root: &Path, dir: &Path, link: &Path) -> bool {
+    let Ok(target) = link.canonicalize() else {
+        return true;
+    };
+    dir.ancestors()
+        .any(|above| is_same_dir(&root.join(above), &target))
+}
+
+/// Whether `path` resolves to the (already canonical) directory `dir`.
+fn is_same_dir(path: &Path, dir: &Path) -> bool {
+    path.canonicalize().is_ok_and(|p| p == dir)
+}
+
+/// Last path component of a root, resolving `.`/`..` via the filesystem.
+fn root_name(root: &Path) -> Result<OsString> {
+    if let Some(name) = root.file_name() {
+        return Ok(name.to_os_string());
+    }
+    let canonical = root
+        .canonicalize()
+        .map_err(|e| Error::io(format!("Failed to resolve input root {:?}", root), e))?;
+    canonical
+        .file_name()
+        .map(|n| n.to_os_string())
+        .ok_or_else(|| {
+            Error::invalid(format!(
+                "Input root {:?} has no name to prefix outputs with",
+                root
+            ))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_output_bases_prefix_each_root() -> Result<()> {
+        let roots = vec![PathBuf::from("/data/dirA"), PathBuf::from("/mnt/dirB")];
+        let bases = output_bases(&roots, Path::new("out"), false)?;
+        assert_eq!(
+            bases,
+            vec![PathBuf::from("out/dirA"), PathBuf::from("out/dirB")]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_output_bases_single_or_merged() -> Result<()> {
+        let single = output_bases(&[PathBuf::from("/data/a")], Path::new("out"), false)?;
+        assert_eq!(single, vec![PathBuf::from("out")]);
+        let roots = vec![PathBuf::from("/x/a"), PathBuf::from("/y/a")];
+        assert!(output_bases(&roots, Path::new("out"), false).is_err());
+        let merged = output_bases(&roots, Path::new("out"), true)?;
+        assert_eq!(merged, vec![PathBuf::from("out"); 2]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_discover_walks_deep_trees_in_parallel() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let (a, b, out) = (
+            dir.path().join("a"),
+            dir.path().join("b"),
+            dir.path().join("out"),
+        );
+        let mut expected = Vec::new();
+        for i in 0..20 {
+            let sub = a.join(format!("{}/{}", i % 3, i));
+            fs::create_dir_all(&sub)?;
+            fs::write(sub.join("x.wav"), b"")?;
+            fs::write(sub.join("notes.txt"), b"")?;
+            expected.push(sub.join("x.wav"));
+        }
+        fs::create_dir_all(&out)?;
+        let roots = std::slice::from_ref(&a);
+        let mut found: Vec<PathBuf> = discover(
+            roots,
+            &out,
+            false,
+            4,
+            &["wav"],
+            &Filter::default(),
+            Traversal::default(),
+        )?
+        .into_iter()
+        .map(|job| job.map(|job| job.input))
+        .collect::<Result<_>>()?;
+        found.sort();
+        expected.sort();
+        assert_eq!(found, expected);
+        let notes = discover(
+            roots,
+            &out,
+            false,
+            4,
+            &["txt", "md"],
+            &Filter::default(),
+            Traversal::default(),
+        )?
+        .into_iter();
+        assert_eq!(notes.filter(|job| job.is_ok()).count(), 20);
+
+        fs::create_dir_all(b.join("1/7"))?;
+        fs::write(b.join("1/7/x.wav"), b"")?;
+        let err = collect(
+            &[a, b],
+            &out,
+            true,
+            4,
+            &["wav"],
+            &Filter::default(),
+            Traversal::default(),
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("Several inputs map to"), "{err}");
+        Ok(())
+    }
+
+    #[test]
+    fn test_with_suffix_keeps_extension() {
+        let suffixed = with_suffix(Path::new("out/talk.v2.wav"), "_t0.9");
+        assert_eq!(suffixed, PathBuf::from("out/talk.v2_t0.9.wav"));
+        assert_eq!(
+            with_suffix(Path::new("noext"), "_t1.1"),
+            PathBuf::from("noext_t1.1")
+        );
+    }
+
+    #[test]
+    fn test_collect_skips_output_nested_in_input() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let input = dir.path().join("in");
+        let output = input.join("out");
+        fs::create_dir_all(&output)?;
+        fs::write(input.join("a.wav"), b"")?;
+        fs::write(input.join("B.WAV"), b"")?;
+        fs::write(input.join("notes.txt"), b"")?;
+        fs::write(input.join("README"), b"")?;
+        fs::write(output.join("a.wav"), b"")?;
+
+        let jobs = collect(
+            std::slice::from_ref(&input),
+            &output,
+            false,
+            4,
+            &AUDIO_EXTENSIONS,
+            &Filter::default(),
+            Traversal::default(),
+        )?;
+        assert_eq!(
+            jobs,
+            vec![
+                Job {
+                    input: input.join("B.WAV"),
+                    output: output.join("B.WAV"),
+                },
+                Job {
+                    input: input.join("a.wav"),
+                    output: output.join("a.wav"),
+                },
+            ]
+        );
+        let everything = collect(
+            std::slice::from_ref(&input),
+            &output,
+            false,
+            4,
+            &[ANY_EXTENSION],
+            &Filter::default(),
+            Traversal::default(),
+        )?;
+        assert_eq!(everything.len(), 4);
+        let roots = std::slice::from_ref(&output);
+        assert!(
+            collect(
+                roots,
+                &output,
+                false,
+                4,
+                &AUDIO_EXTENSIONS,
+                &Filter::default(),
+                Traversal::default()
+            )
+            .is_err()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_extensions_match_in_any_case() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let (input, output) = (dir.path().join("in"), dir.path().join("out"));
+        fs::create_dir_all(input.join("sub"))?;
+        fs::create_dir_all(&output)?;
+        for name in [
+            "a.wav",
+            "b.WAV",
+            "c.Wav",
+            "sub/d.wave",
+            "sub/E.W64",
+            "f.txt",
+        ] {
+            fs::write(input.join(name), b"")?;
+        }
+        let inputs = |extensions: &[&str]| -> anyhow::Result<Vec<PathBuf>> {
+            let jobs = collect(
+                std::slice::from_ref(&input),
+                &output,
+                false,
+                4,
+                extensions,
+                &Filter::default(),
+                Traversal::default(),
+            )?;
+            Ok(jobs.into_iter().map(|job| job.input).collect())
+        };
+        let audio: Vec<PathBuf> = ["a.wav", "b.WAV", "c.Wav", "sub/E.W64"]
+            .iter()
+            .map(|name| input.join(name))
+            .collect();
+        let mut found = inputs(&AUDIO_EXTENSIONS)?;
+        found.sort();
+        let mut expected = audio.clone();
+        expected.sort();
+        assert_eq!(found, expected);
+        let mut listed: Vec<PathBuf> = wav_files(&input, None).collect();
+        listed.sort();
+        assert_eq!(listed, expected);
+
+        // An extra extension, as --extensions adds, is picked up in any case too.
+        let mut found = inputs(&["wav", "w64", "WAVE"])?;
+        found.sort();
+        expected.push(input.join("sub/d.wave"));
+        expected.sort();
+        assert_eq!(found, expected);
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_traversal_depth_and_symlinks() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let (input, shard, out) = (
+            dir.path().join("in"),
+            dir.path().join("shard"),
+            dir.path().join("out"),
+        );
+        fs::create_dir_all(input.join("deep/er"))?;
+        fs::create_dir_all(&shard)?;
+        fs::create_dir_all(&out)?;
+        for path in ["top.wav", "deep/mid.wav", "deep/er/low.wav"] {
+            fs::write(input.join(path), b"")?;
+        }
+        fs::write(shard.join("s.wav"), b"")?;
+        std::os::unix::fs::symlink(&shard, input.join("shard"))?;
+        std::os::unix::fs::symlink(&input, input.join("deep/loop"))?;
+
+        let found = |max_depth, follow_symlinks| -> Result<Vec<PathBuf>> {
+            let traversal = Traversal {
+                max_depth,
+                follow_symlinks,
+            };
+            let roots = std::slice::from_ref(&input);
+            let jobs = collect(
+                roots,
+                &out,
+                false,
+                2,
+                &["wav"],
+                &Filter::default(),
+                traversal,
+            )?;
+            Ok(jobs
+                .into_iter()
+                .map(|job| job.input.strip_prefix(&input).unwrap().to_path_buf())
+                .collect())
+        };
+        let paths = |paths: &[&str]| paths.iter().map(PathBuf::from).collect::<Vec<_>>();
+        assert_eq!(
+            found(None, false)?,
+            paths(&["deep/er/low.wav", "deep/mid.wav", "top.wav"])
+        );
+        assert_eq!(found(Some(1), false)?, paths(&["top.wav"]));
+        assert_eq!(found(Some(2), false)?, paths(&["deep/mid.wav", "top.wav"]));
+        assert_eq!(
+            found(None, true)?,
+            paths(&["deep/er/low.wav", "deep/mid.wav", "shard/s.wav", "top.wav"])
+        );
+        Ok(())
+    }
+}
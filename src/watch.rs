@@ -0,0 +1,455 @@
+//! Hot-folder operation: polling the inputs for new or changed files, with a
+//! small HTTP status endpoint so supervisors can tell the service is alive.
+//!
+//! The poller hands files to the processor through a bounded [`JobQueue`],
+//! whose [`Overflow`] policy decides what a burst of uploads does once it
+//! fills, so memory stays flat however far polling runs ahead.
+
+use crate::error::{Error, Result};
+use crate::json::Json;
+use crate::run::{self, Run, unix_now};
+use crate::walk::Job;
+use std::collections::{HashMap, VecDeque};
+use std::fs::{self, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+/// Failures kept for the status endpoint, newest last.
+const RECENT_ERRORS: usize = 10;
+
+/// Which inputs are due, across polls.
+#[derive(Debug)]
+pub struct Watcher {
+    settle: Duration,
+    /// Input modification times as of their last attempt, so a file that
+    /// failed isn't retried until it changes.
+    attempted: HashMap<PathBuf, SystemTime>,
+}
+
+impl Watcher {
+    /// A watcher that leaves files alone until they have gone `settle`
+    /// without being modified, so uploads still in progress aren't read.
+    pub fn new(settle: Duration) -> Self {
+        Self {
+            settle,
+            attempted: HashMap::new(),
+        }
+    }
+
+    /// The jobs to process now, marked as attempted, and how many more wait
+    /// for their input to settle.
+    pub fn poll(&mut self, jobs: Vec<Job>) -> (Vec<Job>, usize) {
+        let now = SystemTime::now();
+        let (mut ready, mut settling) = (Vec::new(), 0);
+        for job in jobs {
+            let Some(changed) = modified(&job.input) else {
+                continue;
+            };
+            let written = modified(&job.output).is_some_and(|out| out >= changed);
+            if written || self.attempted.get(&job.input) == Some(&changed) {
+                continue;
+            }
+            if now.duration_since(changed).unwrap_or_default() < self.settle {
+                settling += 1;
+                continue;
+            }
+            self.attempted.insert(job.input.clone(), changed);
+            ready.push(job);
+        }
+        (ready, settling)
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// What [`JobQueue::push`] does when the queue is full.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Overflow {
+    /// Wait until the processor takes what is queued.
+    Block,
+    /// Drop the oldest queued job to make room.
+    DropOldest,
+    /// Append the job to this list file, read back as room frees up.
+    Spill(PathBuf),
+}
+
+/// Jobs found by the poller and not yet taken by the processor.
+#[derive(Debug)]
+pub struct JobQueue {
+    capacity: usize,
+    overflow: Overflow,
+    state: Mutex<Queued>,
+    changed: Condvar,
+}
+
+#[derive(Debug, Default)]
+struct Queued {
+    jobs: VecDeque<Job>,
+    /// Jobs in the spill file.
+    spilled: usize,
+}
+
+impl JobQueue {
+    /// An empty queue holding at most `capacity` jobs in memory. A spill
+    /// file left by an earlier run is picked up again.
+    pub fn new(capacity: usize, overflow: Overflow) -> Result<Self> {
+        if capacity == 0 {
+            return Err(Error::invalid("Queue capacity must be at least 1"));
+        }
+        let spilled = match &overflow {
+            Overflow::Spill(path) => read_spill(path)?.len(),
+            _ => 0,
+        };
+        Ok(Self {
+            capacity,
+            overflow,
+            state: Mutex::new(Queued {
+                jobs: VecDeque::new(),
+                spilled,
+            }),
+            changed: Condvar::new(),
+        })
+    }
+
+    /// Queues `job`, following the overflow policy when the queue is full.
+    /// Returns the job dropped to make room, if any.
+    pub fn push(&self, job: Job) -> Result<Option<Job>> {
+        let mut state = self.lock();
+        let mut dropped = None;
+        match &self.overflow {
+            _ if state.jobs.len() < self.capacity && state.spilled == 0 => {
+                state.jobs.push_back(job)
+            }
+            Overflow::Block => {
+                while state.jobs.len() >= self.capacity {
+                    state = self.changed.wait(state).unwrap_or_else(|e| e.into_inner());
+                }
+                state.jobs.push_back(job);
+            }
+            Overflow::DropOldest => {
+                dropped = state.jobs.pop_front();
+                state.jobs.push_back(job);
+            }
+            Overflow::Spill(path) => {
+                let mut file = OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .map_err(|e| Error::io(format!("Failed to open {:?}", path), e))?;
+                writeln!(file, "{}\t{}", job.input.display(), job.output.display())
+                    .map_err(|e| Error::io(format!("Failed to write {:?}", path), e))?;
+                state.spilled += 1;
+            }
+        }
+        self.changed.notify_all();
+        Ok(dropped)
+    }
+
+    /// Waits for at least one job, then takes all that are queued in memory
+    /// and, after them, up to the capacity from the spill file.
+    pub fn take(&self) -> Result<Vec<Job>> {
+        let mut state = self.lock();
+        while state.jobs.is_empty() && state.spilled == 0 {
+            state = self.changed.wait(state).unwrap_or_else(|e| e.into_inner());
+        }
+        let mut jobs: Vec<Job> = state.jobs.drain(..).collect();
+        if let Overflow::Spill(path) = &self.overflow
+            && state.spilled > 0
+        {
+            let mut spilled = read_spill(path)?;
+            // Everything queued after the first spill went to disk too, so
+            // the spilled jobs are the newer ones.
+            let rest = spilled.split_off(spilled.len().min(self.capacity));
+            let text: String = rest
+                .iter()
+                .map(|job| format!("{}\t{}\n", job.input.display(), job.output.display()))
+                .collect();
+            let written = if rest.is_empty() {
+                fs::remove_file(path)
+            } else {
+                fs::write(path, text)
+            };
+            written.map_err(|e| Error::io(format!("Failed to write {:?}", path), e))?;
+            state.spilled = rest.len();
+            jobs.append(&mut spilled);
+        }
+        self.changed.notify_all();
+        Ok(jobs)
+    }
+
+    /// Jobs waiting, in memory and spilled.
+    pub fn len(&self) -> usize {
+        let state = self.lock();
+        state.jobs.len() + state.spilled
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Queued> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// The jobs in spill file `path`, one `input<TAB>output` line each.
+fn read_spill(path: &Path) -> Result<Vec<Job>> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(Error::io(format!("Failed to read {:?}", path), e)),
+    };
+    text.lines()
+        .map(|line| {
+            let (input, output) = line
+                .split_once('\t')
+                .ok_or_else(|| Error::invalid(format!("Invalid line in {:?}: {line}", path)))?;
+            Ok(Job {
+                input: input.into(),
+                output: output.into(),
+            })
+        })
+        .collect()
+}
+
+/// What the service has done so far, shared with the status endpoint.
+#[derive(Debug)]
+pub struct Status {
+    run_id: String,
+    started: u64,
+    state: Mutex<State>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    pending: usize,
+    processed: usize,
+    failed: usize,
+    dropped: usize,
+    /// The latest file finished, whether it succeeded, and when.
+    last: Option<(PathBuf, bool, u64)>,
+    errors: Vec<(PathBuf, String, u64)>,
+}
+
+impl Status {
+    /// The status of `run`, with nothing done yet.
+    pub fn new(run: &Run) -> Self {
+        Self {
+            run_id: run.id.clone(),
+            started: run.started,
+            state: Mutex::default(),
+        }
+    }
+
+    /// Sets how many files are waiting to be processed.
+    pub fn set_pending(&self, pending: usize) {
+        if let Ok(mut state) = self.state.lock() {
+            state.pending = pending;
+        }
+    }
+
+    /// Records that `file` was dropped from a full queue.
+    pub fn drop_job(&self, file: &Path) {
+        if let Ok(mut state) = self.state.lock() {
+            state.dropped += 1;
+            let error = "dropped from the full watch queue".to_string();
+            state.errors.push((file.to_path_buf(), error, unix_now()));
+            let excess = state.errors.len().saturating_sub(RECENT_ERRORS);
+            state.errors.drain(..excess);
+        }
+    }
+
+    /// Records that `file` finished, with its error if it failed.
+    pub fn finish(&self, file: &Path, error: Option<String>) {
+        let Ok(mut state) = self.state.lock() else {
+            return;
+        };
+        state.pending = state.pending.saturating_sub(1);
+        state.last = Some((file.to_path_buf(), error.is_none(), unix_now()));
+        match error {
+            None => state.processed += 1,
+            Some(error) => {
+                state.failed += 1;
+                state.errors.push((file.to_path_buf(), error, unix_now()));
+                let excess = state.errors.len().saturating_sub(RECENT_ERRORS);
+                state.errors.drain(..excess);
+            }
+        }
+    }
+
+    /// The current state as a JSON object.
+    pub fn to_json(&self) -> Json {
+        let state = match self.state.lock() {
+            Ok(state) => state,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        let path = |p: &Path| Json::from(p.display().to_string());
+        let last = match &state.last {
+            Some((file, ok, at)) => Json::Object(vec![
+                ("file".to_string(), path(file)),
+                ("ok".to_string(), Json::Bool(*ok)),
+                ("at".to_string(), run::timestamp(*at).into()),
+            ]),
+            None => Json::Null,
+        };
+        let errors = state
+            .errors
+            .iter()
+            .map(|(file, error, at)| {
+                Json::Object(vec![
+                    ("file".to_string(), path(file)),
+                    ("error".to_string(), error.as_str().into()),
+                    ("at".to_string(), run::timestamp(*at).into()),
+                ])
+            })
+            .collect();
+        Json::Object(vec![
+            ("status".to_string(), "ok".into()),
+            ("pending".to_string(), state.pending.into()),
+            ("processed".to_string(), state.processed.into()),
+            ("failed".to_string(), state.failed.into()),
+            ("last_processed".to_string(), last),
+            ("dropped".to_string(), state.dropped.into()),
+            ("errors".to_string(), Json::Array(errors)),
+            ("run_id".to_string(), self.run_id.as_str().into()),
+            ("started".to_string(), run::timestamp(self.started).into()),
+            ("updated".to_string(), run::now().into()),
+        ])
+    }
+}
+
+/// Serves `status` as JSON on `GET /health` and `GET /status` at `addr`
+/// from a background thread, returning the address bound (useful with port
+/// 0). Every other request gets a 404.
+pub fn serve(addr: &str, status: Arc<Status>) -> Result<SocketAddr> {
+    let listener =
+        TcpListener::bind(addr).map_err(|e| Error::io(format!("Failed to listen on {addr}"), e))?;
+    let local = listener
+        .local_addr()
+        .map_err(|e| Error::io(format!("Failed to listen on {addr}"), e))?;
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            // A client that misbehaves only loses its own answer.
+            let _ = respond(stream, &status);
+        }
+    });
+    Ok(local)
+}
+
+fn respond(mut stream: TcpStream, status: &Status) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(2)))?;
+    let mut line = String::new();
+    BufReader::new(&stream).read_line(&mut line)?;
+    let mut parts = line.split_whitespace();
+    let (method, target) = (parts.next(), parts.next().unwrap_or(""));
+    let path = target.split('?').next().unwrap_or("");
+    let (code, body) = match (method, path) {
+        (Some("GET"), "/health" | "/status") => ("200 OK", format!("{}\n", status.to_json())),
+        _ => ("404 Not Found", "{\"error\": \"not found\"}\n".to_string()),
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {code}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )?;
+    stream.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    #[test]
+    fn test_poll_skips_written_and_failed_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let job = |name: &str| Job {
+            input: dir.path().join(name),
+            output: dir.path().join(format!("out-{name}")),
+        };
+        let jobs = vec![job("a.wav"), job("b.wav")];
+        fs::write(&jobs[0].input, b"a").unwrap();
+        fs::write(&jobs[1].input, b"b").unwrap();
+        fs::write(&jobs[1].output, b"done").unwrap();
+
+        let mut settling = Watcher::new(Duration::from_secs(3600));
+        assert_eq!(settling.poll(jobs.clone()), (vec![], 1));
+        let mut watcher = Watcher::new(Duration::ZERO);
+        assert_eq!(watcher.poll(jobs.clone()), (vec![jobs[0].clone()], 0));
+        // a.wav failed and has no output, but isn't retried until it changes.
+        assert_eq!(watcher.poll(jobs), (vec![], 0));
+    }
+
+    #[test]
+    fn test_queue_overflow_policies() {
+        let job = |n: usize| Job {
+            input: PathBuf::from(format!("in/{n}.wav")),
+            output: PathBuf::from(format!("out/{n}.wav")),
+        };
+        let inputs =
+            |jobs: Vec<Job>| -> Vec<PathBuf> { jobs.into_iter().map(|j| j.input).collect() };
+
+        let dropping = JobQueue::new(2, Overflow::DropOldest).unwrap();
+        assert_eq!(dropping.push(job(1)).unwrap(), None);
+        assert_eq!(dropping.push(job(2)).unwrap(), None);
+        assert_eq!(dropping.push(job(3)).unwrap(), Some(job(1)));
+        assert_eq!(
+            inputs(dropping.take().unwrap()),
+            inputs(vec![job(2), job(3)])
+        );
+
+        let dir = tempfile::tempdir().unwrap();
+        let spill = dir.path().join("spill.tsv");
+        let spilling = JobQueue::new(2, Overflow::Spill(spill.clone())).unwrap();
+        for n in 1..=5 {
+            assert_eq!(spilling.push(job(n)).unwrap(), None);
+        }
+        assert_eq!(spilling.len(), 5);
+        // Once a job spills, later ones queue behind it on disk, in order.
+        let first = spilling.take().unwrap();
+        assert_eq!(inputs(first), inputs(vec![job(1), job(2), job(3), job(4)]));
+        drop(spilling);
+        let reopened = JobQueue::new(2, Overflow::Spill(spill)).unwrap();
+        assert_eq!(reopened.take().unwrap(), vec![job(5)]);
+        assert!(reopened.is_empty());
+
+        let blocking = Arc::new(JobQueue::new(1, Overflow::Block).unwrap());
+        blocking.push(job(1)).unwrap();
+        let pusher = {
+            let blocking = Arc::clone(&blocking);
+            thread::spawn(move || blocking.push(job(2)).unwrap())
+        };
+        assert_eq!(blocking.take().unwrap(), vec![job(1)]);
+        pusher.join().unwrap();
+        assert_eq!(blocking.take().unwrap(), vec![job(2)]);
+    }
+
+    #[test]
+    fn test_status_endpoint() {
+        let status = Arc::new(Status::new(&Run::start()));
+        status.set_pending(2);
+        status.finish(Path::new("in/a.wav"), None);
+        status.finish(Path::new("in/b.wav"), Some("bad header".to_string()));
+        let addr = serve("127.0.0.1:0", Arc::clone(&status)).unwrap();
+        let get = |path: &str| {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            write!(stream, "GET {path} HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        };
+        let health = get("/health");
+        assert!(health.starts_with("HTTP/1.1 200 OK\r\n"), "{health}");
+        assert!(health.contains(
+            r#""pending": 0, "processed": 1, "failed": 1, "last_processed": {"file": "in/b.wav", "ok": false, "#
+        ));
+        assert!(health.contains(r#""error": "bad header""#));
+        assert!(get("/other").starts_with("HTTP/1.1 404"));
+    }
+}
@@ -0,0 +1,244 @@
+//! A RIFF/WAVE writer that streams samples straight to disk.
+//!
+//! [`WavStream`] writes the header up front with placeholder sizes, appends
+//! each block of samples as it is produced, and patches the sizes once the
+//! last one is in, so no more than a block is ever held in memory. Outputs
+//! of unknown length reserve room for a `ds64` chunk and become RF64 files
+//! if they outgrow the 4 GiB RIFF limit.
+
+use crate::error::{Error, Result};
+use crate::format::{self, AudioFormat, SampleType};
+use crate::riff::{self, Chunk};
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+
+/// Largest RIFF or `data` size a plain RIFF/WAVE header can hold.
+const RIFF_LIMIT: u64 = u32::MAX as u64;
+
+/// Body size of a `ds64` chunk without a table: RIFF size, data size, and
+/// sample count as 64-bit fields, then the table length.
+const DS64_LEN: usize = 28;
+
+/// A WAV file being written, with its sizes still to be patched.
+pub struct WavStream {
+    file: BufWriter<File>,
+    format: AudioFormat,
+    /// Whether a `JUNK` chunk holds the place of a `ds64` chunk.
+    reserved: bool,
+    /// Offsets of the `data` chunk's size and, for float, the `fact` body.
+    data_at: u64,
+    fact_at: Option<u64>,
+    data_len: u64,
+    bytes: Vec<u8>,
+}
+
+impl WavStream {
+    /// Creates `path` for samples in `format`. With the data size known up
+    /// front as `data_len`, the room for RF64 sizes is only reserved when it
+    /// will be needed; `None` always reserves it.
+    pub fn create(path: &Path, format: AudioFormat, data_len: Option<u64>) -> Result<Self> {
+        let reserved = data_len.is_none_or(|len| len + 1024 > RIFF_LIMIT);
+        let file = File::create(path).map_err(|e| Error::io("Failed to create output WAV", e))?;
+        let fmt = riff::fmt_body(format);
+        let mut header = b"RIFF\0\0\0\0WAVE".to_vec();
+        if reserved {
+            header.extend_from_slice(b"JUNK");
+            header.extend_from_slice(&(DS64_LEN as u32).to_le_bytes());
+            header.extend_from_slice(&[0; DS64_LEN]);
+        }
+        header.extend_from_slice(b"fmt ");
+        header.extend_from_slice(&(fmt.len() as u32).to_le_bytes());
+        header.extend_from_slice(&fmt);
+        let mut fact_at = None;
+        if let SampleType::Float(_) = format.sample_type {
+            header.extend_from_slice(b"fact\x04\0\0\0");
+            fact_at = Some(header.len() as u64);
+            header.extend_from_slice(&[0; 4]);
+        }
+        header.extend_from_slice(b"data");
+        let data_at = header.len() as u64;
+        header.extend_from_slice(&[0; 4]);
+        let mut stream = Self {
+            file: BufWriter::new(file),
+            format,
+            reserved,
+            data_at,
+            fact_at,
+            data_len: 0,
+            bytes: Vec::new(),
+        };
+        stream.put(&header)?;
+        Ok(stream)
+    }
+
+    /// Appends interleaved samples in [-1.0, 1.0], quantized to the format's
+    /// sample type.
+    pub fn write(&mut self, samples: &[f32]) -> Result<()> {
+        let mut bytes = std::mem::take(&mut self.bytes);
+        bytes.clear();
+        for &s in samples {
+            push_sample(&mut bytes, s, self.format.sample_type);
+        }
+        self.put(&bytes)?;
+        self.data_len += bytes.len() as u64;
+        self.bytes = bytes;
+        Ok(())
+    }
+
+    /// Pads the `data` chunk and patches every size in the header.
+    pub fn finish(self) -> Result<()> {
+        self.finish_with(&[])
+    }
+
+    /// Like [`finish`](Self::finish), with `chunks` such as metadata written
+    /// after the samples.
+    pub fn finish_with(mut self, chunks: &[Chunk]) -> Result<()> {
+        if self.data_len % 2 == 1 {
+            self.put(&[0])?;
+        }
+        for chunk in chunks {
+            self.put(&chunk.id)?;
+            self.put(&(chunk.data.len() as u32).to_le_bytes())?;
+            self.put(&chunk.data)?;
+            if chunk.data.len() % 2 == 1 {
+                self.put(&[0])?;
+            }
+        }
+        let width = u64::from(self.format.sample_type.bits().div_ceil(8));
+        let frames = self.data_len / (width * u64::from(self.format.channels.max(1)));
+        let mut file = self
+            .file
+            .into_inner()
+            .map_err(|e| Error::io("Failed to write output WAV", e.into_error()))?;
+        let len = file
+            .stream_position()
+            .map_err(|e| Error::io("Failed to write output WAV", e))?;
+        let sizes = Sizes {
+            riff: len - 8,
+            data: self.data_len,
+            frames,
+        };
+        for (at, bytes) in sizes.patches(self.data_at, self.fact_at, self.reserved, RIFF_LIMIT)? {
+            file.seek(SeekFrom::Start(at))
+                .and_then(|_| file.write_all(&bytes))
+                .map_err(|e| Error::io("Failed to patch output WAV header", e))?;
+        }
+        Ok(())
+    }
+
+    fn put(&mut self, bytes: &[u8]) -> Result<()> {
+        self.file
+            .write_all(bytes)
+            .map_err(|e| Error::io("Failed to write output WAV", e))
+    }
+}
+
+/// The sizes a finished file's header records.
+struct Sizes {
+    riff: u64,
+    data: u64,
+    frames: u64,
+}
+
+impl Sizes {
+    /// The bytes to write at each header offset: plain 32-bit sizes while
+    /// they fit under `limit`, and otherwise an RF64 header whose `ds64`
+    /// chunk replaces the reserved `JUNK` one.
+    fn patches(
+        &self,
+        data_at: u64,
+        fact_at: Option<u64>,
+        reserved: bool,
+        limit: u64,
+    ) -> Result<Vec<(u64, Vec<u8>)>> {
+        let le32 = |n: u64| (n as u32).to_le_bytes().to_vec();
+        let mut patches = Vec::new();
+        if self.riff <= limit {
+            patches.push((4, le32(self.riff)));
+            patches.push((data_at, le32(self.data)));
+            patches.extend(fact_at.map(|at| (at, le32(self.frames))));
+            return Ok(patches);
+        }
+        if !reserved {
+            return Err(Error::invalid("Output exceeds the RIFF size limit"));
+        }
+        let mut ds64 = b"ds64".to_vec();
+        ds64.extend_from_slice(&(DS64_LEN as u32).to_le_bytes());
+        for size in [self.riff, self.data, self.frames] {
+            ds64.extend_from_slice(&size.to_le_bytes());
+        }
+        ds64.extend_from_slice(&0u32.to_le_bytes());
+        patches.push((0, b"RF64".to_vec()));
+        patches.push((4, le32(RIFF_LIMIT)));
+        patches.push((12, ds64));
+        patches.push((data_at, le32(RIFF_LIMIT)));
+        patches.extend(fact_at.map(|at| (at, le32(RIFF_LIMIT))));
+        Ok(patches)
+    }
+}
+
+/// Appends the little-endian bytes of one normalized sample as `sample_type`.
+pub(crate) fn push_sample(bytes: &mut Vec<u8>, sample: f32, sample_type: SampleType) {
+    match sample_type {
+        SampleType::Int(8) => bytes.push((format::f32_to_int(sample, 8) + 128) as u8),
+        SampleType::Int(bits) => {
+            let width = bits.div_ceil(8) as usize;
+            bytes.extend_from_slice(&format::f32_to_int(sample, bits).to_le_bytes()[..width]);
+        }
+        SampleType::Float(_) => bytes.extend_from_slice(&sample.to_le_bytes()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_streamed_file_passes_strict_header() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("out.wav");
+        for sample_type in [
+            SampleType::Int(8),
+            SampleType::Int(24),
+            SampleType::Float(32),
+        ] {
+            let format = AudioFormat {
+                sample_type,
+                ..AudioFormat::default()
+            };
+            let samples = [0.25, -0.5, 0.125];
+            for data_len in [None, Some(0)] {
+                let mut stream = WavStream::create(&path, format, data_len)?;
+                stream.write(&samples[..2])?;
+                stream.write(&samples[2..])?;
+                stream.finish()?;
+                riff::check_header(&path)?;
+                let read = crate::read_wav(&path)?;
+                assert_eq!((read.format, read.frames()), (format, 3));
+                assert!((read.samples[1] + 0.5).abs() < 0.01, "{sample_type:?}");
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_oversized_output_becomes_rf64() {
+        let sizes = Sizes {
+            riff: 5000,
+            data: 4000,
+            frames: 1000,
+        };
+        let patches = sizes.patches(80, Some(72), true, 4096).unwrap();
+        assert_eq!(patches[0], (0, b"RF64".to_vec()));
+        let (at, ds64) = &patches[2];
+        assert_eq!((*at, &ds64[..8]), (12, &b"ds64\x1c\0\0\0"[..]));
+        assert_eq!(ds64[8..16], 5000u64.to_le_bytes());
+        assert_eq!(ds64[24..32], 1000u64.to_le_bytes());
+        for at in [4, 80, 72] {
+            assert!(patches.contains(&(at, vec![0xff; 4])), "{at}");
+        }
+        assert!(sizes.patches(80, None, false, 4096).is_err());
+        assert_eq!(sizes.patches(80, None, false, 8192).unwrap().len(), 2);
+    }
+}